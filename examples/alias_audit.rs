@@ -0,0 +1,110 @@
+use std::{env, process::exit};
+
+use futures::{
+    future::{self, Either},
+    Future, Stream,
+};
+use ruma_client::{self, AliasStatus, Client};
+use tokio_core::reactor::Core;
+use url::Url;
+
+fn alias_audit(
+    homeserver_url: Url,
+    user: String,
+    password: String,
+    clean_up: bool,
+) -> impl Future<Item = (), Error = ruma_client::Error> {
+    let client = Client::https(homeserver_url, None).unwrap();
+    let audit_client = client.clone();
+
+    client
+        .log_in(user, password, None, Some("alias_audit".to_owned()))
+        .and_then(move |_| {
+            // The first sync fills the store with the rooms of the user.
+            client
+                .sync(None, None, false)
+                .into_future()
+                .map_err(|(error, _)| error)
+        })
+        .and_then(move |_| {
+            audit_client
+                .audit_aliases()
+                .map(move |aliases| (audit_client, aliases))
+        })
+        .and_then(move |(client, audit)| {
+            for (room_id, error) in &audit.failed_rooms {
+                eprintln!("Failed to list the aliases of {}: {:?}", room_id, error);
+            }
+            for (alias, error) in &audit.failed_aliases {
+                eprintln!("Failed to resolve {}: {:?}", alias, error);
+            }
+
+            let aliases = audit.aliases;
+            for audited in &aliases {
+                let status = match audited.status {
+                    AliasStatus::Valid => "ok".to_owned(),
+                    AliasStatus::Missing => "missing".to_owned(),
+                    AliasStatus::OtherRoom(ref room_id) => format!("points to {}", room_id),
+                };
+
+                println!(
+                    "{} {} ({:?}): {}",
+                    audited.room_id, audited.alias, audited.sources, status
+                );
+            }
+
+            let dangling = aliases
+                .iter()
+                .filter(|audited| audited.is_dangling())
+                .count();
+            println!("{} aliases, {} dangling", aliases.len(), dangling);
+
+            if clean_up {
+                Either::A(client.clean_up_aliases(aliases).map(|cleanup| {
+                    for room_id in cleanup.updated {
+                        println!("Removed dangling aliases from {}", room_id);
+                    }
+                    for (room_id, error) in cleanup.failed {
+                        eprintln!("Failed to clean up {}: {:?}", room_id, error);
+                    }
+                }))
+            } else {
+                Either::B(future::ok(()))
+            }
+        })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let clean_up = args.iter().any(|arg| arg == "--clean-up");
+    let positional: Vec<&String> = args
+        .iter()
+        .skip(1)
+        .filter(|arg| *arg != "--clean-up")
+        .collect();
+
+    let (homeserver_url, user, password) = match positional.as_slice() {
+        [homeserver_url, user, password] => (
+            homeserver_url.to_string(),
+            user.to_string(),
+            password.to_string(),
+        ),
+        _ => {
+            eprintln!(
+                "Usage: {} [--clean-up] <homeserver_url> <user> <password>",
+                args[0]
+            );
+            exit(1)
+        }
+    };
+
+    Core::new()
+        .unwrap()
+        .run(alias_audit(
+            homeserver_url.parse().unwrap(),
+            user,
+            password,
+            clean_up,
+        ))
+        .unwrap();
+}
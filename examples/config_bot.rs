@@ -0,0 +1,51 @@
+use std::{env, process::exit};
+
+use futures::{future, Future};
+use ruma_client::{self, Config};
+use tokio_core::reactor::Core;
+
+/// Log in as configured in a TOML file and greet the configured rooms.
+///
+/// The configuration can be overridden with environment variables starting with `CONFIG_BOT_`,
+/// e.g. `CONFIG_BOT_CREDENTIALS__PASSWORD`.
+fn config_bot(config: Config) -> impl Future<Item = (), Error = ruma_client::Error> {
+    let client = match config.client_builder() {
+        Ok(builder) => builder.build_https().unwrap(),
+        Err(error) => return future::Either::A(future::err(error)),
+    };
+    let greeting = config
+        .get::<String>("greeting.text")
+        .ok()
+        .and_then(|text| text)
+        .unwrap_or_else(|| "Hello!".to_owned());
+
+    future::Either::B(
+        config
+            .log_in(&client)
+            .and_then(move |_| config.join_rooms(&client))
+            .and_then(move |rooms| {
+                future::join_all(
+                    rooms
+                        .into_iter()
+                        .map(move |room| room.send_text(greeting.clone())),
+                )
+            })
+            .map(|event_ids| println!("Greeted {} rooms", event_ids.len())),
+    )
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: {} <config.toml>", env::args().next().unwrap());
+            exit(1)
+        }
+    };
+    let config = Config::load(path, "CONFIG_BOT").unwrap_or_else(|error| {
+        eprintln!("Invalid configuration: {:?}", error);
+        exit(1)
+    });
+
+    Core::new().unwrap().run(config_bot(config)).unwrap();
+}
@@ -4,10 +4,6 @@ use std::{convert::TryFrom, env, process::exit};
 
 use futures::Future;
 use ruma_client::{self, api::r0, Client};
-use ruma_events::{
-    room::message::{MessageEventContent, MessageType, TextMessageEventContent},
-    EventType,
-};
 use ruma_identifiers::RoomAliasId;
 use tokio_core::reactor::Core;
 use url::Url;
@@ -31,25 +27,15 @@ fn hello_world(
     let client = Client::https(homeserver_url, None).unwrap();
 
     client.register_guest().and_then(clone!(client => move |_| {
-        r0::alias::get_alias::call(client, r0::alias::get_alias::Request {
-            room_alias: RoomAliasId::try_from(&room[..]).unwrap(),
-        })
-    })).and_then(clone!(client => move |response| {
-        let room_id = response.room_id;
+        client.resolve_alias(RoomAliasId::try_from(&room[..]).unwrap())
+    })).and_then(clone!(client => move |resolved| {
+        let (room_id, _servers) = resolved;
 
         r0::membership::join_room_by_id::call(client.clone(), r0::membership::join_room_by_id::Request {
             room_id: room_id.clone(),
             third_party_signed: None,
         }).and_then(move |_| {
-            r0::send::send_message_event::call(client, r0::send::send_message_event::Request {
-                room_id: room_id,
-                event_type: EventType::RoomMessage,
-                txn_id: "1".to_owned(),
-                data: MessageEventContent::Text(TextMessageEventContent {
-                    body: "Hello World!".to_owned(),
-                    msgtype: MessageType::Text,
-                }),
-            })
+            client.room(room_id).send_text("Hello World!".to_owned())
         })
     })).map(|_| ())
 }
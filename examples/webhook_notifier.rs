@@ -0,0 +1,123 @@
+#![feature(try_from)]
+
+use std::{
+    convert::TryFrom,
+    env,
+    io::{self, BufRead, BufReader, Read},
+    process::exit,
+};
+
+use futures::{stream, Future, Stream};
+use ruma_client::{self, ClientBuilder};
+use ruma_events::room::message::{MessageEventContent, MessageType, NoticeMessageEventContent};
+use ruma_identifiers::RoomIdOrAliasId;
+use tokio_core::reactor::Core;
+use url::Url;
+
+/// The longest message that is sent, in bytes. The rest of a longer line is dropped.
+const MAX_LINE_LENGTH: u64 = 4096;
+
+/// The lines of `reader`, cut off at `MAX_LINE_LENGTH` bytes so that a line without an end
+/// doesn't have to fit into memory.
+fn bounded_lines<R: BufRead>(mut reader: R) -> impl Iterator<Item = io::Result<String>> {
+    let mut line = Vec::new();
+
+    std::iter::from_fn(move || {
+        line.clear();
+
+        let read = match (&mut reader)
+            .take(MAX_LINE_LENGTH)
+            .read_until(b'\n', &mut line)
+        {
+            Ok(0) => return None,
+            Ok(read) => read,
+            Err(error) => return Some(Err(error)),
+        };
+
+        // Skip the rest of a line that was cut off.
+        if read as u64 == MAX_LINE_LENGTH && line.last() != Some(&b'\n') {
+            let mut rest = Vec::new();
+
+            loop {
+                rest.clear();
+
+                match (&mut reader)
+                    .take(MAX_LINE_LENGTH)
+                    .read_until(b'\n', &mut rest)
+                {
+                    Ok(0) => break,
+                    Ok(_) if rest.last() == Some(&b'\n') => break,
+                    Ok(_) => {}
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+        }
+
+        let text = String::from_utf8_lossy(&line);
+
+        Some(Ok(text.trim_end_matches(&['\r', '\n'][..]).to_owned()))
+    })
+}
+
+/// Post every line read from stdin as a notice to the room, without ever syncing.
+///
+/// Lines are read one at a time while the previous one is sent, so memory use doesn't depend on
+/// the amount of input.
+fn webhook_notifier(
+    homeserver_url: Url,
+    user: String,
+    password: String,
+    room: RoomIdOrAliasId,
+) -> impl Future<Item = (), Error = ruma_client::Error> {
+    let client = ClientBuilder::new(homeserver_url)
+        .lightweight(true)
+        .build_https()
+        .unwrap();
+    let lines = stream::iter_result(bounded_lines(BufReader::new(io::stdin())))
+        .map_err(ruma_client::Error::from);
+
+    client
+        .log_in(user, password, None, Some("webhook_notifier".to_owned()))
+        .and_then(move |_| {
+            lines.for_each(move |body| {
+                let content = MessageEventContent::Notice(NoticeMessageEventContent {
+                    body,
+                    msgtype: MessageType::Notice,
+                });
+
+                client
+                    .send_to(room.clone(), content)
+                    .map(|event_id| println!("Sent {}", event_id))
+            })
+        })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let (homeserver_url, user, password, room) = match args.as_slice() {
+        [_, homeserver_url, user, password, room] => (
+            homeserver_url.clone(),
+            user.clone(),
+            password.clone(),
+            room.clone(),
+        ),
+        _ => {
+            eprintln!(
+                "Usage: {} <homeserver_url> <user> <password> <room>",
+                args[0]
+            );
+            exit(1)
+        }
+    };
+
+    Core::new()
+        .unwrap()
+        .run(webhook_notifier(
+            homeserver_url.parse().unwrap(),
+            user,
+            password,
+            RoomIdOrAliasId::try_from(&room[..]).unwrap(),
+        ))
+        .unwrap();
+}
@@ -0,0 +1,62 @@
+//! Managing the logged-in user's account.
+
+use futures::Future;
+use hyper::client::connect::Connect;
+
+use crate::{Client, Error};
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Change the logged-in user's password.
+    ///
+    /// The homeserver usually asks for the current password with the user-interactive
+    /// authentication API, which this method answers. If it asks for more, this fails with
+    /// `Error::InteractiveAuthRequired`. With `logout_devices`, all other devices of the user are
+    /// logged out, so their access tokens stop working; the current session stays valid either
+    /// way.
+    pub fn change_password(
+        &self,
+        current_password: String,
+        new_password: String,
+        logout_devices: bool,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::account::change_password;
+
+        self.request_with_password::<change_password::Endpoint, _>(current_password, move |auth| {
+            change_password::Request {
+                new_password: new_password.clone(),
+                logout_devices: Some(logout_devices),
+                auth,
+            }
+        })
+        .map(|_| ())
+    }
+
+    /// Deactivate the logged-in user's account, so that nobody can log in to it anymore.
+    ///
+    /// This can't be undone. The current password is used to answer the user-interactive
+    /// authentication like with `change_password`. With `erase`, homeservers that support it also
+    /// hide the messages sent by the user from users joining rooms later. Once the account is
+    /// deactivated, the client's session is removed.
+    pub fn deactivate(
+        &self,
+        password: String,
+        erase: bool,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::account::deactivate;
+
+        let data = self.0.clone();
+
+        self.request_with_password::<deactivate::Endpoint, _>(password, move |auth| {
+            deactivate::Request {
+                auth,
+                erase: Some(erase),
+            }
+        })
+        .map(move |_| {
+            *data.session.borrow_mut() = None;
+        })
+    }
+}
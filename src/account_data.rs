@@ -0,0 +1,497 @@
+//! Account data conveniences.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    rc::Rc,
+};
+
+use futures::{
+    future::{self, Either, Future, Loop},
+    stream, Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_identifiers::{RoomId, UserId};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{error::not_found_as_none, Client, Error, Room};
+
+/// The type of the account data event listing the direct chats of the user.
+pub const DIRECT_EVENT_TYPE: &str = "m.direct";
+
+/// The type of the account data event listing the users the user ignores.
+pub const IGNORED_USER_LIST_EVENT_TYPE: &str = "m.ignored_user_list";
+
+/// How often `Client::migrate_account_data` starts over when the account data changes while it
+/// migrates it.
+const MIGRATION_ATTEMPTS: usize = 3;
+
+/// The version field used by `AccountDataMigrations` unless another one is set.
+const DEFAULT_VERSION_FIELD: &str = "version";
+
+/// The content of an `m.ignored_user_list` account data event.
+#[derive(Deserialize)]
+struct IgnoredUserListEventContent {
+    /// The ignored users, each with an empty object.
+    #[serde(default)]
+    ignored_users: BTreeMap<String, Value>,
+}
+
+/// Parse the ignored users from the content of an `m.ignored_user_list` account data event.
+pub(crate) fn ignored_users_from_content(content: &Value) -> Vec<UserId> {
+    serde_json::from_value::<IgnoredUserListEventContent>(content.clone())
+        .map(|content| {
+            content
+                .ignored_users
+                .keys()
+                .filter_map(|user_id| UserId::try_from(user_id.as_str()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A migration of account data from one version of its schema to the next.
+type Migration = Box<dyn Fn(Value) -> Result<Value, String>>;
+
+/// The migrations of the schema of an account data event type, run by
+/// `Client::migrate_account_data`.
+///
+/// The version of the account data is stored in a field of its content, `version` by default.
+/// Account data without that field has version 0. The first migration added with
+/// `add_migration` upgrades the data from version 0 to 1, the second from 1 to 2 and so on.
+pub struct AccountDataMigrations {
+    event_type: String,
+    version_field: String,
+    migrations: Vec<Migration>,
+}
+
+/// The outcome of `Client::migrate_account_data`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MigrationOutcome {
+    /// The account data was migrated from the first to the second version.
+    Migrated(u64, u64),
+    /// The account data already had the given version, which is at least the latest one.
+    UpToDate(u64),
+    /// There is no account data of the event type.
+    Missing,
+}
+
+impl AccountDataMigrations {
+    /// Creates an empty list of migrations for the given account data event type.
+    pub fn new(event_type: String) -> Self {
+        AccountDataMigrations {
+            event_type,
+            version_field: DEFAULT_VERSION_FIELD.to_owned(),
+            migrations: Vec::new(),
+        }
+    }
+
+    /// Store the version in the given field instead of `version`.
+    pub fn version_field(mut self, version_field: String) -> Self {
+        self.version_field = version_field;
+        self
+    }
+
+    /// Add the migration to the next version.
+    ///
+    /// The migration gets the content of the account data and returns the migrated content, or
+    /// a description of why it can't be migrated. It doesn't need to update the version field,
+    /// but the migrated content has to be a JSON object to hold it.
+    pub fn add_migration<F>(mut self, migration: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value, String> + 'static,
+    {
+        self.migrations.push(Box::new(migration));
+        self
+    }
+
+    /// The version the migrations lead to.
+    pub fn latest_version(&self) -> u64 {
+        self.migrations.len() as u64
+    }
+
+    /// The version of the given content.
+    fn version(&self, content: &Value) -> u64 {
+        content
+            .get(&self.version_field)
+            .and_then(Value::as_u64)
+            .unwrap_or(0)
+    }
+
+    /// Run the migrations from the version of `content` to the latest version.
+    fn migrate(&self, mut content: Value) -> Result<Value, Error> {
+        let from = self.version(&content);
+
+        for (version, migration) in self.migrations.iter().enumerate().skip(from as usize) {
+            content = migration(content).map_err(|reason| Error::AccountDataMigration {
+                from_version: version as u64,
+                reason,
+            })?;
+
+            match &mut content {
+                Value::Object(fields) => {
+                    fields.insert(self.version_field.clone(), Value::from(version as u64 + 1));
+                }
+                _ => {
+                    return Err(Error::AccountDataMigration {
+                        from_version: version as u64,
+                        reason: "the migrated content isn't a JSON object".to_owned(),
+                    })
+                }
+            }
+        }
+
+        Ok(content)
+    }
+}
+
+impl Debug for AccountDataMigrations {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("AccountDataMigrations")
+            .field("event_type", &self.event_type)
+            .field("version_field", &self.version_field)
+            .field("latest_version", &self.latest_version())
+            .finish()
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Bring the logged-in user's account data of the type of `migrations` to the latest
+    /// version, e.g. when an application starts.
+    ///
+    /// The Matrix API has no atomic compare-and-set for account data, so the account data is
+    /// fetched again right before the migrated data is written. If another client changed it
+    /// in the meantime, the migration starts over, up to three times, after which it fails with
+    /// `Error::AccountDataChanged`. Two clients can still both write migrated data if their
+    /// requests interleave exactly, but since both migrate the same data to the same version,
+    /// that only matters for non-deterministic migrations.
+    pub fn migrate_account_data(
+        &self,
+        migrations: AccountDataMigrations,
+    ) -> impl Future<Item = MigrationOutcome, Error = Error> {
+        self.run_migrations(Rc::new(migrations))
+    }
+
+    /// Run `migrations` with `migrate_account_data` before the next sync request of
+    /// `Client::sync`, so that the application only sees account data of the latest version.
+    ///
+    /// If a migration fails, the sync stream fails with its error, and the next call of `sync`
+    /// tries again.
+    pub fn migrate_account_data_on_startup(&self, migrations: AccountDataMigrations) {
+        self.0
+            .startup_migrations
+            .borrow_mut()
+            .push(Rc::new(migrations));
+    }
+
+    /// Run the migrations added with `migrate_account_data_on_startup` that didn't succeed yet.
+    pub(crate) fn run_startup_migrations(&self) -> impl Future<Item = (), Error = Error> {
+        let client = self.clone();
+        let pending = self.0.startup_migrations.borrow().clone();
+
+        stream::iter_ok(pending).for_each(move |migrations| {
+            let data = client.0.clone();
+
+            client.run_migrations(migrations.clone()).map(move |_| {
+                data.startup_migrations
+                    .borrow_mut()
+                    .retain(|pending| !Rc::ptr_eq(pending, &migrations));
+            })
+        })
+    }
+
+    fn run_migrations(
+        &self,
+        migrations: Rc<AccountDataMigrations>,
+    ) -> impl Future<Item = MigrationOutcome, Error = Error> {
+        let client = self.clone();
+
+        future::loop_fn(0, move |attempt| {
+            let client = client.clone();
+            let migrations = migrations.clone();
+
+            if attempt == MIGRATION_ATTEMPTS {
+                return Either::A(future::err(Error::AccountDataChanged(
+                    migrations.event_type.clone(),
+                )));
+            }
+
+            Either::B(
+                client
+                    .global_account_data(&migrations.event_type)
+                    .and_then(move |content| {
+                        let content = match content {
+                            Some(content) => content,
+                            None => {
+                                return Either::A(future::ok(Loop::Break(
+                                    MigrationOutcome::Missing,
+                                )))
+                            }
+                        };
+                        let from = migrations.version(&content);
+
+                        if from >= migrations.latest_version() {
+                            return Either::A(future::ok(Loop::Break(MigrationOutcome::UpToDate(
+                                from,
+                            ))));
+                        }
+
+                        let migrated = match migrations.migrate(content.clone()) {
+                            Ok(migrated) => migrated,
+                            Err(error) => return Either::A(future::err(error)),
+                        };
+                        let to = migrations.latest_version();
+                        let event_type = migrations.event_type.clone();
+
+                        Either::B(client.global_account_data(&event_type).and_then(
+                            move |current| {
+                                if current.as_ref() != Some(&content) {
+                                    return Either::A(future::ok(Loop::Continue(attempt + 1)));
+                                }
+
+                                Either::B(
+                                    client.set_global_account_data(&event_type, migrated).map(
+                                        move |()| Loop::Break(MigrationOutcome::Migrated(from, to)),
+                                    ),
+                                )
+                            },
+                        ))
+                    }),
+            )
+        })
+    }
+
+    /// Get the logged-in user's global account data of the given type from the homeserver.
+    ///
+    /// Resolves to `None` if there is no such account data. The account data received with sync
+    /// responses is available from `Store::account_data` without a request.
+    pub fn account_data<T>(&self, event_type: &str) -> impl Future<Item = Option<T>, Error = Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.global_account_data(event_type)
+            .and_then(|content| match content {
+                Some(content) => Ok(Some(serde_json::from_value(content)?)),
+                None => Ok(None),
+            })
+    }
+
+    /// Replace the logged-in user's global account data of the given type.
+    pub fn set_account_data<T>(
+        &self,
+        event_type: &str,
+        data: &T,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        T: Serialize,
+    {
+        match serde_json::to_value(data) {
+            Ok(data) => Either::A(self.set_global_account_data(event_type, data)),
+            Err(error) => Either::B(future::err(Error::from(error))),
+        }
+    }
+
+    /// Get the rooms the logged-in user considers direct chats, keyed by the other user, from the
+    /// homeserver.
+    pub fn direct_rooms(&self) -> impl Future<Item = HashMap<UserId, Vec<RoomId>>, Error = Error> {
+        self.account_data(DIRECT_EVENT_TYPE)
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Replace the rooms the logged-in user considers direct chats.
+    pub fn set_direct_rooms(
+        &self,
+        direct_rooms: &HashMap<UserId, Vec<RoomId>>,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.set_account_data(DIRECT_EVENT_TYPE, direct_rooms)
+    }
+
+    /// Get the users the logged-in user ignores from the homeserver.
+    pub fn ignored_users(&self) -> impl Future<Item = Vec<UserId>, Error = Error> {
+        self.global_account_data(IGNORED_USER_LIST_EVENT_TYPE)
+            .map(|content| {
+                content
+                    .as_ref()
+                    .map(ignored_users_from_content)
+                    .unwrap_or_default()
+            })
+    }
+
+    /// Replace the users the logged-in user ignores.
+    ///
+    /// The homeserver stops sending events from ignored users, and sends the complete history
+    /// of rooms again with the next sync.
+    pub fn set_ignored_users(&self, user_ids: &[UserId]) -> impl Future<Item = (), Error = Error> {
+        let ignored_users: Map<String, Value> = user_ids
+            .iter()
+            .map(|user_id| (user_id.to_string(), json!({})))
+            .collect();
+
+        self.set_global_account_data(
+            IGNORED_USER_LIST_EVENT_TYPE,
+            json!({ "ignored_users": ignored_users }),
+        )
+    }
+
+    /// The content of the logged-in user's account data of the given type, or `None` if there
+    /// is none.
+    pub(crate) fn global_account_data(
+        &self,
+        event_type: &str,
+    ) -> impl Future<Item = Option<Value>, Error = Error> {
+        use crate::api::r0::config::get_global_account_data;
+
+        let request = self
+            .logged_in_user_id()
+            .map(|user_id| get_global_account_data::Request {
+                user_id,
+                event_type: event_type.to_owned(),
+            });
+        let client = self.clone();
+
+        future::result(request)
+            .and_then(move |request| get_global_account_data::call(client, request))
+            .then(|result| not_found_as_none(result).map(|response| response.map(|r| r.data)))
+    }
+
+    /// Replace the content of the logged-in user's account data of the given type.
+    pub(crate) fn set_global_account_data(
+        &self,
+        event_type: &str,
+        data: Value,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::config::set_global_account_data;
+
+        let request = self
+            .logged_in_user_id()
+            .map(|user_id| set_global_account_data::Request {
+                data,
+                event_type: event_type.to_owned(),
+                user_id,
+            });
+        let client = self.clone();
+
+        future::result(request)
+            .and_then(move |request| set_global_account_data::call(client, request))
+            .map(|_| ())
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the logged-in user's account data of the given type for this room from the
+    /// homeserver.
+    ///
+    /// Resolves to `None` if there is no such account data. The account data received with sync
+    /// responses is available from `Store::room_account_data` without a request.
+    pub fn account_data<T>(&self, event_type: &str) -> impl Future<Item = Option<T>, Error = Error>
+    where
+        T: DeserializeOwned,
+    {
+        use crate::api::r0::config::get_room_account_data;
+
+        let request =
+            self.client()
+                .logged_in_user_id()
+                .map(|user_id| get_room_account_data::Request {
+                    user_id,
+                    room_id: self.room_id().clone(),
+                    event_type: event_type.to_owned(),
+                });
+        let client = self.client().clone();
+
+        future::result(request)
+            .and_then(move |request| get_room_account_data::call(client, request))
+            .then(|result| match not_found_as_none(result)? {
+                Some(response) => Ok(Some(serde_json::from_value(response.data)?)),
+                None => Ok(None),
+            })
+    }
+
+    /// Replace the logged-in user's account data of the given type for this room.
+    pub fn set_account_data<T>(
+        &self,
+        event_type: &str,
+        data: &T,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        T: Serialize,
+    {
+        use crate::api::r0::config::set_room_account_data;
+
+        let request = self.client().logged_in_user_id().and_then(|user_id| {
+            Ok(set_room_account_data::Request {
+                data: serde_json::to_value(data)?,
+                event_type: event_type.to_owned(),
+                room_id: self.room_id().clone(),
+                user_id,
+            })
+        });
+        let client = self.client().clone();
+
+        future::result(request)
+            .and_then(move |request| set_room_account_data::call(client, request))
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::AccountDataMigrations;
+    use crate::Error;
+
+    fn migrations() -> AccountDataMigrations {
+        AccountDataMigrations::new("org.example.settings".to_owned())
+            .add_migration(|mut content| {
+                content["theme"] = content["colour"].take();
+                Ok(content)
+            })
+            .add_migration(|content| match content["theme"].as_str() {
+                Some(_) => Ok(content),
+                None => Err("no theme".to_owned()),
+            })
+    }
+
+    #[test]
+    fn migrates_from_the_stored_version() {
+        let migrated = migrations().migrate(json!({ "colour": "dark" })).unwrap();
+        assert_eq!(
+            migrated,
+            json!({ "theme": "dark", "colour": null, "version": 2 })
+        );
+
+        let migrated = migrations()
+            .migrate(json!({ "theme": "light", "version": 1 }))
+            .unwrap();
+        assert_eq!(migrated, json!({ "theme": "light", "version": 2 }));
+    }
+
+    #[test]
+    fn reports_the_failing_version() {
+        match migrations().migrate(json!({ "version": 1 })) {
+            Err(Error::AccountDataMigration { from_version, .. }) => assert_eq!(from_version, 1),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn migrated_content_has_to_be_an_object() {
+        let migrations = AccountDataMigrations::new("org.example.settings".to_owned())
+            .add_migration(|_| Ok(Value::Null));
+
+        match migrations.migrate(json!({})) {
+            Err(Error::AccountDataMigration { from_version, .. }) => assert_eq!(from_version, 0),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
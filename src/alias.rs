@@ -0,0 +1,392 @@
+//! Managing the aliases of rooms.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryFrom,
+};
+
+use futures::{
+    future::{self, Either},
+    stream, Future, Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_events::{collections::only, EventType};
+use ruma_identifiers::{RoomAliasId, RoomId, UserId};
+use serde_json::Value;
+
+use crate::{error::not_found_as_none, Client, Error, Room, StoreSnapshot};
+
+/// How many rooms or aliases `Client::audit_aliases` looks up at the same time.
+const AUDIT_CONCURRENCY: usize = 4;
+
+/// An alias of a room the user administers, found by `Client::audit_aliases`.
+#[derive(Clone, Debug)]
+pub struct AuditedAlias {
+    /// The alias.
+    pub alias: RoomAliasId,
+    /// The room that lists the alias.
+    pub room_id: RoomId,
+    /// Where the room lists the alias.
+    pub sources: Vec<AliasSource>,
+    /// What the alias resolves to in the room directory.
+    pub status: AliasStatus,
+}
+
+/// Where a room lists one of its aliases.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AliasSource {
+    /// The `alias` of the room's `m.room.canonical_alias` event.
+    CanonicalAlias,
+    /// One of the `alt_aliases` of the room's `m.room.canonical_alias` event.
+    AltAlias,
+    /// The aliases of the room on the user's homeserver.
+    LocalDirectory,
+}
+
+/// What an alias resolves to in the room directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AliasStatus {
+    /// The alias points to the room that lists it.
+    Valid,
+    /// The alias doesn't exist.
+    Missing,
+    /// The alias points to another room.
+    OtherRoom(RoomId),
+}
+
+/// The outcome of `Client::audit_aliases`.
+#[derive(Debug, Default)]
+pub struct AliasAudit {
+    /// The aliases that were resolved.
+    pub aliases: Vec<AuditedAlias>,
+    /// The rooms whose aliases couldn't be listed, with the error.
+    pub failed_rooms: Vec<(RoomId, Error)>,
+    /// The aliases that couldn't be resolved, with the error.
+    pub failed_aliases: Vec<(RoomAliasId, Error)>,
+}
+
+/// The outcome of `Client::clean_up_aliases`.
+#[derive(Debug, Default)]
+pub struct AliasCleanup {
+    /// The rooms whose `m.room.canonical_alias` event was updated.
+    pub updated: Vec<RoomId>,
+    /// The rooms whose `m.room.canonical_alias` event couldn't be updated, with the error.
+    pub failed: Vec<(RoomId, Error)>,
+}
+
+impl AuditedAlias {
+    /// Whether the alias doesn't point to the room that lists it.
+    pub fn is_dangling(&self) -> bool {
+        self.status != AliasStatus::Valid
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Look up the room an alias points to, without joining it.
+    ///
+    /// This doesn't require a session. The returned future resolves to the ID of the room and the
+    /// servers that know about the room, which can be used to join it over federation.
+    pub fn resolve_alias(
+        &self,
+        room_alias: RoomAliasId,
+    ) -> impl Future<Item = (RoomId, Vec<String>), Error = Error> {
+        use crate::api::r0::alias::get_alias;
+
+        get_alias::call(self.clone(), get_alias::Request { room_alias })
+            .map(|response| (response.room_id, response.servers))
+    }
+
+    /// Remove an alias from the room it points to.
+    ///
+    /// This requires being the creator of the alias, or a high enough power level in the room.
+    pub fn delete_alias(&self, room_alias: RoomAliasId) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::alias::delete_alias;
+
+        delete_alias::call(self.clone(), delete_alias::Request { room_alias }).map(|_| ())
+    }
+
+    /// List the aliases of all rooms in the `Store` whose aliases the logged-in user can change,
+    /// and check what each alias resolves to.
+    ///
+    /// The aliases of a room are taken from its `m.room.canonical_alias` event and from the
+    /// aliases the user's homeserver has for it. The store has to be synced for the rooms to be
+    /// known. Rooms whose aliases couldn't be listed and aliases that couldn't be resolved are
+    /// reported in the result instead of failing the whole audit.
+    pub fn audit_aliases(&self) -> impl Future<Item = AliasAudit, Error = Error> {
+        let user_id = match self.logged_in_user_id() {
+            Ok(user_id) => user_id,
+            Err(error) => return Either::A(future::err(error)),
+        };
+
+        let snapshot = self.store().snapshot();
+        let rooms: Vec<_> = snapshot
+            .room_ids()
+            .filter(|room_id| can_change_aliases(&snapshot, room_id, &user_id))
+            .cloned()
+            .collect();
+
+        let client = self.clone();
+        let resolving_client = self.clone();
+
+        Either::B(
+            stream::iter_ok(rooms)
+                .map(move |room_id| {
+                    client
+                        .room(room_id.clone())
+                        .listed_aliases()
+                        .then(move |result| Ok::<_, Error>((room_id, result)))
+                })
+                .buffer_unordered(AUDIT_CONCURRENCY)
+                .fold(
+                    (Vec::new(), AliasAudit::default()),
+                    |(mut listed, mut audit), (room_id, result)| {
+                        match result {
+                            Ok(aliases) => listed.extend(aliases),
+                            Err(error) => audit.failed_rooms.push((room_id, error)),
+                        }
+
+                        Ok::<_, Error>((listed, audit))
+                    },
+                )
+                .and_then(move |(listed, audit)| {
+                    stream::iter_ok(listed)
+                        .map(move |(room_id, alias, sources)| {
+                            resolving_client
+                                .resolve_alias(alias.clone())
+                                .then(move |result| {
+                                    let status = match result {
+                                        Ok((ref target, _)) if *target == room_id => {
+                                            AliasStatus::Valid
+                                        }
+                                        Ok((target, _)) => AliasStatus::OtherRoom(target),
+                                        Err(ref error) if error.is_not_found() => {
+                                            AliasStatus::Missing
+                                        }
+                                        Err(error) => return Ok(Err((alias, error))),
+                                    };
+
+                                    Ok::<_, Error>(Ok(AuditedAlias {
+                                        alias,
+                                        room_id,
+                                        sources,
+                                        status,
+                                    }))
+                                })
+                        })
+                        .buffer_unordered(AUDIT_CONCURRENCY)
+                        .fold(audit, |mut audit, result| {
+                            match result {
+                                Ok(audited) => audit.aliases.push(audited),
+                                Err(failed) => audit.failed_aliases.push(failed),
+                            }
+
+                            Ok::<_, Error>(audit)
+                        })
+                }),
+        )
+    }
+
+    /// Remove the dangling aliases among `aliases` from the `m.room.canonical_alias` events of
+    /// their rooms.
+    ///
+    /// `aliases` are usually the `aliases` of the result of `audit_aliases`; valid aliases are
+    /// ignored. Aliases on the homeserver that point to nowhere can't exist, so only the state of
+    /// the rooms is changed. The updates are spaced out by the client's `RateLimiter`, so the returned future
+    /// has to be run on a runtime providing a `tokio_timer` timer.
+    pub fn clean_up_aliases(
+        &self,
+        aliases: Vec<AuditedAlias>,
+    ) -> impl Future<Item = AliasCleanup, Error = Error> {
+        let mut dangling: HashMap<RoomId, HashSet<RoomAliasId>> = HashMap::new();
+        for audited in aliases {
+            let in_state = audited
+                .sources
+                .iter()
+                .any(|source| *source != AliasSource::LocalDirectory);
+
+            if audited.is_dangling() && in_state {
+                dangling
+                    .entry(audited.room_id)
+                    .or_default()
+                    .insert(audited.alias);
+            }
+        }
+
+        let client = self.clone();
+
+        stream::iter_ok(dangling)
+            .and_then(move |(room_id, aliases)| {
+                let room = client.room(room_id.clone());
+
+                client
+                    .rate_limited(move || room.remove_canonical_aliases(&aliases))
+                    .then(move |result| Ok((room_id, result)))
+            })
+            .fold(AliasCleanup::default(), |mut cleanup, (room_id, result)| {
+                match result {
+                    Ok(true) => cleanup.updated.push(room_id),
+                    Ok(false) => {}
+                    Err(error) => cleanup.failed.push((room_id, error)),
+                }
+
+                Ok::<_, Error>(cleanup)
+            })
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Make an alias point to this room.
+    ///
+    /// Fails if the alias already exists. This doesn't change the canonical alias of the room.
+    pub fn create_alias(&self, room_alias: RoomAliasId) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::alias::create_alias;
+
+        create_alias::call(
+            self.client().clone(),
+            create_alias::Request {
+                room_alias,
+                room_id: self.room_id().clone(),
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// The aliases the user's homeserver has for this room.
+    ///
+    /// Homeservers that don't support listing the aliases of a room are treated as having none.
+    pub fn local_aliases(&self) -> impl Future<Item = Vec<RoomAliasId>, Error = Error> {
+        use crate::api::r0::alias::get_room_aliases;
+
+        get_room_aliases::call(
+            self.client().clone(),
+            get_room_aliases::Request {
+                room_id: self.room_id().clone(),
+            },
+        )
+        .then(|result| {
+            not_found_as_none(result)
+                .map(|response| response.map_or_else(Vec::new, |response| response.aliases))
+        })
+    }
+
+    /// The aliases listed by this room's `m.room.canonical_alias` event and the homeserver, with
+    /// where each alias is listed.
+    fn listed_aliases(
+        &self,
+    ) -> impl Future<Item = Vec<(RoomId, RoomAliasId, Vec<AliasSource>)>, Error = Error> {
+        let room_id = self.room_id().clone();
+
+        self.state_event::<Value>(EventType::RoomCanonicalAlias, "")
+            .join(self.local_aliases())
+            .map(move |(canonical_alias, local_aliases)| {
+                let mut listed: BTreeMap<String, Vec<AliasSource>> = BTreeMap::new();
+                let content = canonical_alias.unwrap_or(Value::Null);
+
+                if let Some(alias) = content.get("alias").and_then(Value::as_str) {
+                    listed
+                        .entry(alias.to_owned())
+                        .or_default()
+                        .push(AliasSource::CanonicalAlias);
+                }
+                let alt_aliases = content
+                    .get("alt_aliases")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str);
+                for alias in alt_aliases {
+                    listed
+                        .entry(alias.to_owned())
+                        .or_default()
+                        .push(AliasSource::AltAlias);
+                }
+                for alias in local_aliases {
+                    listed
+                        .entry(alias.to_string())
+                        .or_default()
+                        .push(AliasSource::LocalDirectory);
+                }
+
+                listed
+                    .into_iter()
+                    .filter_map(|(alias, sources)| {
+                        RoomAliasId::try_from(alias.as_str())
+                            .ok()
+                            .map(|alias| (room_id.clone(), alias, sources))
+                    })
+                    .collect()
+            })
+    }
+
+    /// Remove `aliases` from this room's `m.room.canonical_alias` event, keeping the fields
+    /// `ruma_events` doesn't know about.
+    ///
+    /// Resolves to whether the event was changed.
+    fn remove_canonical_aliases(
+        &self,
+        aliases: &HashSet<RoomAliasId>,
+    ) -> impl Future<Item = bool, Error = Error> {
+        let room = self.clone();
+        let aliases: HashSet<String> = aliases.iter().map(ToString::to_string).collect();
+
+        self.state_event::<Value>(EventType::RoomCanonicalAlias, "")
+            .and_then(move |content| {
+                let mut content = match content {
+                    Some(Value::Object(content)) => content,
+                    _ => return Either::A(future::ok(false)),
+                };
+                let mut changed = false;
+
+                let remove_alias = match content.get("alias").and_then(Value::as_str) {
+                    Some(alias) => aliases.contains(alias),
+                    None => false,
+                };
+                if remove_alias {
+                    content.remove("alias");
+                    changed = true;
+                }
+                if let Some(Value::Array(alt_aliases)) = content.get_mut("alt_aliases") {
+                    let count = alt_aliases.len();
+                    alt_aliases.retain(|alias| match alias.as_str() {
+                        Some(alias) => !aliases.contains(alias),
+                        None => true,
+                    });
+                    changed |= alt_aliases.len() != count;
+                }
+
+                if !changed {
+                    return Either::A(future::ok(false));
+                }
+
+                Either::B(
+                    room.send_state_event(
+                        EventType::RoomCanonicalAlias,
+                        "",
+                        &Value::Object(content),
+                    )
+                    .map(|_| true),
+                )
+            })
+    }
+}
+
+/// Whether `user_id` has a high enough power level to change the canonical alias of a room.
+fn can_change_aliases(snapshot: &StoreSnapshot, room_id: &RoomId, user_id: &UserId) -> bool {
+    let required = match snapshot.state_event(room_id, &EventType::RoomPowerLevels, "") {
+        Some(only::StateEvent::RoomPowerLevels(event)) => event
+            .content
+            .events
+            .get(&EventType::RoomCanonicalAlias)
+            .cloned()
+            .unwrap_or(event.content.state_default),
+        _ => 0,
+    };
+
+    snapshot.power_level(room_id, user_id) >= required
+}
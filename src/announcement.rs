@@ -0,0 +1,170 @@
+//! Announcements to many rooms, e.g. of maintenance.
+
+use std::collections::HashSet;
+
+use futures::{
+    future::{self, Either, Future},
+    Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_events::{
+    room::{
+        message::{MessageEventContent, MessageType, NoticeMessageEventContent},
+        pinned_events::PinnedEventsContent,
+    },
+    EventType,
+};
+use ruma_identifiers::{EventId, RoomId};
+use serde_json::Value;
+
+use crate::{message::render_template, BroadcastProgress, Client, Error, Room};
+
+/// A way to select the rooms an announcement is sent to.
+#[derive(Clone, Debug)]
+pub enum RoomSelector {
+    /// The rooms with the given tag, e.g. `FAVOURITE_TAG`.
+    Tag(String),
+    /// The children of the given space.
+    Space(RoomId),
+    /// The given room.
+    Room(RoomId),
+}
+
+/// The outcome of sending an announcement to one room.
+#[derive(Debug)]
+pub struct AnnouncementProgress {
+    /// The outcome of sending the message.
+    pub broadcast: BroadcastProgress,
+    /// The outcome of pinning the message, or `None` if it wasn't pinned, either because pinning
+    /// wasn't requested or because the message couldn't be sent.
+    pub pin_result: Option<Result<(), Error>>,
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// The IDs of the rooms in the `Store` that match any of the selectors, without duplicates.
+    ///
+    /// Rooms selected by tag are ordered like `tagged_rooms`; the store has to be synced for the
+    /// tags and the children of spaces to be known.
+    pub fn select_rooms(&self, selectors: &[RoomSelector]) -> Vec<RoomId> {
+        let mut seen = HashSet::new();
+        let mut rooms = Vec::new();
+
+        for selector in selectors {
+            let selected = match selector {
+                RoomSelector::Tag(tag) => self.tagged_rooms(tag),
+                RoomSelector::Space(space_id) => self.store().space_children(space_id),
+                RoomSelector::Room(room_id) => vec![room_id.clone()],
+            };
+
+            for room_id in selected {
+                if seen.insert(room_id.clone()) {
+                    rooms.push(room_id);
+                }
+            }
+        }
+
+        rooms
+    }
+
+    /// Send an announcement as a notice to all rooms matching the selectors, and optionally pin
+    /// it in each room.
+    ///
+    /// The `{{name}}` placeholders of `template` are replaced by the values with these names in
+    /// `values`, e.g. `json!({ "start": "10:00 UTC" })`. The announcement is sent with
+    /// `broadcast`, so the same requirements apply, and the returned stream yields the outcome
+    /// for each room.
+    pub fn announce(
+        &self,
+        selectors: &[RoomSelector],
+        template: &str,
+        values: &Value,
+        pin: bool,
+    ) -> impl Stream<Item = AnnouncementProgress, Error = Error> {
+        let client = self.clone();
+        let content = MessageEventContent::Notice(NoticeMessageEventContent {
+            body: render_template(template, values),
+            msgtype: MessageType::Notice,
+        });
+
+        self.broadcast(self.select_rooms(selectors), content)
+            .and_then(move |broadcast| {
+                let event_id = match broadcast.result {
+                    Ok(ref event_id) if pin => event_id.clone(),
+                    _ => {
+                        return Either::A(future::ok(AnnouncementProgress {
+                            broadcast,
+                            pin_result: None,
+                        }));
+                    }
+                };
+
+                Either::B(
+                    client
+                        .room(broadcast.room_id.clone())
+                        .pin_event(event_id)
+                        .then(move |pin_result| {
+                            Ok(AnnouncementProgress {
+                                broadcast,
+                                pin_result: Some(pin_result),
+                            })
+                        }),
+                )
+            })
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Add an event to the pinned events of this room.
+    pub fn pin_event(&self, event_id: EventId) -> impl Future<Item = (), Error = Error> {
+        let room = self.clone();
+
+        self.pinned_events().and_then(move |mut pinned| {
+            if pinned.contains(&event_id) {
+                return Either::A(future::ok(()));
+            }
+
+            pinned.push(event_id);
+            Either::B(room.set_pinned_events(pinned))
+        })
+    }
+
+    /// Remove an event from the pinned events of this room.
+    pub fn unpin_event(&self, event_id: EventId) -> impl Future<Item = (), Error = Error> {
+        let room = self.clone();
+
+        self.pinned_events().and_then(move |mut pinned| {
+            let count = pinned.len();
+            pinned.retain(|pinned| *pinned != event_id);
+
+            if pinned.len() == count {
+                return Either::A(future::ok(()));
+            }
+
+            Either::B(room.set_pinned_events(pinned))
+        })
+    }
+
+    /// Get the pinned events of this room from the homeserver.
+    pub fn pinned_events(&self) -> impl Future<Item = Vec<EventId>, Error = Error> {
+        self.state_event(EventType::RoomPinnedEvents, "").map(
+            |content: Option<PinnedEventsContent>| {
+                content.map(|content| content.pinned).unwrap_or_default()
+            },
+        )
+    }
+
+    fn set_pinned_events(&self, pinned: Vec<EventId>) -> impl Future<Item = (), Error = Error> {
+        self.send_state_event(
+            EventType::RoomPinnedEvents,
+            "",
+            &PinnedEventsContent { pinned },
+        )
+        .map(|_| ())
+    }
+}
@@ -1,16 +1,66 @@
+/// Generate the `call` and `call_with_extensions` functions of an endpoint.
+///
+/// This makes endpoints of custom homeserver modules work like the ones in `api`. Define the
+/// endpoint in a module of its own with the `ruma_api!` macro from
+/// `ruma_client::reexports::ruma_api_macros`, then invoke `ruma_client::endpoint_calls!()` in
+/// that module. The module then has a `call` function taking a `Client` and the endpoint's
+/// `Request`, and a `call_with_extensions` function additionally taking `Extensions`. Requests
+/// are made like those of built-in endpoints, e.g. with the client's access token,
+/// `AccessTokenOverride` and deadlines.
+///
+/// The code generated by `ruma_api!` refers to the crates it uses, like `http`, `ruma_api`,
+/// `serde_json` and `url`, by absolute paths, which this macro can't redirect. All of them are
+/// in `ruma_client::reexports`: on the 2015 edition, `pub use` them at the root of the
+/// application crate; on the 2018 edition, absolute paths only name dependencies, so depend on
+/// the versions re-exported there.
+#[macro_export]
+macro_rules! endpoint_calls {
+    () => {
+        /// Make a request to this API endpoint.
+        pub fn call<C>(
+            client: $crate::Client<C>,
+            request: Request,
+        ) -> impl $crate::reexports::futures::Future<Item = Response, Error = $crate::Error>
+        where
+            C: $crate::reexports::hyper::client::connect::Connect + 'static,
+        {
+            client.request::<Endpoint>(request)
+        }
+
+        /// Make a request to this API endpoint, with extension data attached.
+        pub fn call_with_extensions<C>(
+            client: $crate::Client<C>,
+            request: Request,
+            extensions: $crate::Extensions,
+        ) -> impl $crate::reexports::futures::Future<Item = Response, Error = $crate::Error>
+        where
+            C: $crate::reexports::hyper::client::connect::Connect + 'static,
+        {
+            client.request_with_extensions::<Endpoint>(request, extensions)
+        }
+    };
+}
+
 macro_rules! endpoint {
     // No reexports besides `Request` and `Response`.
-    ($(#[$attr:meta])+ [$($outer_mod:ident),*], $inner_mod:ident) => {
-        endpoint!($(#[$attr])+ [$($outer_mod),*], $inner_mod, []);
+    (@[$($root:tt)+] $(#[$attr:meta])+ [$($outer_mod:ident),*], $inner_mod:ident) => {
+        endpoint!(@[$($root)+] $(#[$attr])+ [$($outer_mod),*], $inner_mod, []);
     };
 
     // No imports from super.
-    ($(#[$attr:meta])+ [$($outer_mod:ident),*], $inner_mod:ident, [$($import:ident),*]) => {
-        endpoint!($(#[$attr])+ [$($outer_mod),*], $inner_mod, [$($import),*], []);
+    (
+        @[$($root:tt)+]
+        $(#[$attr:meta])+
+        [$($outer_mod:ident),*],
+        $inner_mod:ident,
+        [$($import:ident),*]
+    ) => {
+        endpoint!(@[$($root)+] $(#[$attr])+ [$($outer_mod),*], $inner_mod, [$($import),*], []);
     };
 
     // Explicit case.
     (
+        @[$($root:tt)+]
         $(#[$attr:meta])+
         [$($outer_mod:ident),*],
         $inner_mod:ident,
@@ -19,49 +69,51 @@ macro_rules! endpoint {
     ) => {
         #[$($attr)+]
         pub mod $inner_mod {
-            use futures::Future;
-            use hyper::client::connect::Connect;
-            use ruma_client_api::$($outer_mod::)*$inner_mod::Endpoint;
+            pub(crate) use $($root)+::$($outer_mod::)*$inner_mod::Endpoint;
             $(use super::$super_import;)*
-            pub use ruma_client_api::$($outer_mod::)*$inner_mod::{
+            pub use $($root)+::$($outer_mod::)*$inner_mod::{
                 Request,
                 Response,
                 $($import),*
             };
 
-            use crate::{Client, Error};
-
-            /// Make a request to this API endpoint.
-            pub fn call<C>(
-                client: Client<C>,
-                request: Request,
-            ) -> impl Future<Item = Response, Error = Error>
-            where
-                C: Connect + 'static,
-            {
-                client.request::<Endpoint>(request)
-            }
+            crate::endpoint_calls!();
         }
     };
+
+    // Endpoint defined in `api::local` because `ruma_client_api` doesn't provide it.
+    (local $($args:tt)+) => {
+        endpoint!(@[crate::api::local] $($args)+);
+    };
+
+    // Endpoint defined in `ruma_client_api`.
+    ($($args:tt)+) => {
+        endpoint!(@[ruma_client_api] $($args)+);
+    };
 }
 
+pub(crate) mod local;
+
 /// Endpoints for the r0.x.x versions of the client API specification.
 pub mod r0 {
     /// Account registration and management.
     pub mod account {
         endpoint!(
+            local
             /// Change the password for an account on this homeserver.
             [r0, account],
             change_password
         );
 
         endpoint!(
+            local
             /// Deactivate the user's account, removing all ability for the user to log in again.
             [r0, account],
             deactivate
         );
 
         endpoint!(
+            local
             /// Register for an account on this homeserver.
             [r0, account],
             register,
@@ -75,10 +127,25 @@ pub mod r0 {
         );
 
         endpoint!(
-            /// Request an account registration token by email.
+            local
+            /// Request a phone number validation token for registration by SMS.
+            [r0, account],
+            request_register_msisdn_token
+        );
+
+        endpoint!(
+            local
+            /// Request an email address validation token for registration by email.
             [r0, account],
             request_register_token
         );
+
+        endpoint!(
+            local
+            /// Get the user ID of the owner of the access token.
+            [r0, account],
+            whoami
+        );
     }
 
     /// Room aliases.
@@ -96,14 +163,52 @@ pub mod r0 {
         );
 
         endpoint!(
+            local
             /// Resolve a room alias to the corresponding room ID.
             [r0, alias],
             get_alias
         );
+
+        endpoint!(
+            local
+            /// Get the aliases of a room that the homeserver maintains.
+            [r0, alias],
+            get_room_aliases
+        );
+    }
+
+    /// Server capabilities.
+    pub mod capabilities {
+        endpoint!(
+            local
+            /// Get the capabilities of the homeserver.
+            [r0, capabilities],
+            get_capabilities,
+            [
+                Capabilities,
+                ChangePasswordCapability,
+                RoomVersionStability,
+                RoomVersionsCapability
+            ]
+        );
     }
 
     /// Client configuration.
     pub mod config {
+        endpoint!(
+            local
+            /// Get account data for the user.
+            [r0, config],
+            get_global_account_data
+        );
+
+        endpoint!(
+            local
+            /// Get account data scoped to a room for the user.
+            [r0, config],
+            get_room_account_data
+        );
+
         endpoint!(
             /// Set account data for the user.
             [r0, config],
@@ -119,6 +224,20 @@ pub mod r0 {
 
     /// Account contact information.
     pub mod contact {
+        endpoint!(
+            local
+            /// Add a validated third party identifier to the user's account.
+            [r0, contact],
+            add_3pid
+        );
+
+        endpoint!(
+            local
+            /// Bind a third party identifier to the user's Matrix ID on an identity server.
+            [r0, contact],
+            bind_3pid
+        );
+
         endpoint!(
             /// Add contact information to the user's account.
             [r0, contact],
@@ -127,6 +246,14 @@ pub mod r0 {
         );
 
         endpoint!(
+            local
+            /// Remove a third party identifier from the user's account.
+            [r0, contact],
+            delete_3pid
+        );
+
+        endpoint!(
+            local
             /// Get a list of the third party identifiers that the homeserver has associated with the user's account.
             [r0, contact],
             get_contacts,
@@ -134,29 +261,110 @@ pub mod r0 {
         );
 
         endpoint!(
+            local
             /// Request an email address verification token by email.
             [r0, contact],
             request_contact_verification_token
         );
+
+        endpoint!(
+            local
+            /// Request a phone number verification token by SMS.
+            [r0, contact],
+            request_msisdn_verification_token
+        );
+
+        endpoint!(
+            local
+            /// Unbind a third party identifier from the user's Matrix ID on an identity server.
+            [r0, contact],
+            unbind_3pid
+        );
     }
 
     /// Event context.
     pub mod context {
         endpoint!(
+            local
             /// Get a number of events that happened just before and after a given event.
             [r0, context],
             get_context
         );
     }
 
+    /// Device management.
+    pub mod device {
+        pub use crate::api::local::r0::device::Device;
+
+        endpoint!(
+            local
+            /// Delete a device of the logged-in user.
+            [r0, device],
+            delete_device
+        );
+
+        endpoint!(
+            local
+            /// Delete several devices of the logged-in user at once.
+            [r0, device],
+            delete_devices
+        );
+
+        endpoint!(
+            local
+            /// Get a single device of the logged-in user.
+            [r0, device],
+            get_device
+        );
+
+        endpoint!(
+            local
+            /// Get the devices of the logged-in user.
+            [r0, device],
+            get_devices
+        );
+
+        endpoint!(
+            local
+            /// Update the display name of a device of the logged-in user.
+            [r0, device],
+            update_device
+        );
+    }
+
     /// The public room directory.
     pub mod directory {
         endpoint!(
-            /// Get a number of events that happened just before and after a given event.
+            local
+            /// Get the list of rooms in a homeserver's public directory.
             [r0, directory],
             get_public_rooms,
             [PublicRoomsChunk]
         );
+
+        endpoint!(
+            local
+            /// Search the public room directory of a homeserver.
+            [r0, directory],
+            get_public_rooms_filtered,
+            [Filter, PublicRoomsChunk]
+        );
+
+        endpoint!(
+            local
+            /// Get the visibility of a room in the public room directory.
+            [r0, directory],
+            get_room_visibility,
+            [Visibility]
+        );
+
+        endpoint!(
+            local
+            /// Publish a room in the public room directory, or remove it from it.
+            [r0, directory],
+            set_room_visibility,
+            [Visibility]
+        );
     }
 
     /// Event filters.
@@ -178,9 +386,43 @@ pub mod r0 {
         );
     }
 
+    /// Keys for end-to-end encryption.
+    pub mod keys {
+        pub use crate::api::local::r0::keys::DeviceKeys;
+
+        endpoint!(
+            local
+            /// Publish end-to-end encryption keys for the device.
+            [r0, keys],
+            upload_keys
+        );
+
+        endpoint!(
+            local
+            /// Claim one-time keys of devices, to start Olm sessions with them.
+            [r0, keys],
+            claim_keys
+        );
+
+        endpoint!(
+            local
+            /// Get the identity keys of devices.
+            [r0, keys],
+            get_keys
+        );
+
+        endpoint!(
+            local
+            /// Get the users whose devices changed between two sync tokens.
+            [r0, keys],
+            get_key_changes
+        );
+    }
+
     /// Media repository.
     pub mod media {
         endpoint!(
+            local
             /// Upload media to the media repository.
             [r0, media],
             create_content
@@ -198,11 +440,25 @@ pub mod r0 {
             get_content_thumbnail,
             [Method]
         );
+
+        endpoint!(
+            local
+            /// Get the configuration of the media repository, e.g. the maximum upload size.
+            [r0, media],
+            get_media_config
+        );
+
+        endpoint!(
+            local
+            /// Get an OpenGraph preview of a URL.
+            [r0, media],
+            get_url_preview
+        );
     }
 
     /// Room membership.
     pub mod membership {
-        pub use ruma_client_api::r0::membership::ThirdPartySigned;
+        pub use crate::api::local::r0::membership::ThirdPartySigned;
 
         endpoint!(
             /// Ban a user from a room.
@@ -217,23 +473,41 @@ pub mod r0 {
         );
 
         endpoint!(
+            local
             /// Invite a user to a room.
             [r0, membership],
             invite_user
         );
 
         endpoint!(
+            local
             /// Join a room using its ID.
             [r0, membership],
             join_room_by_id
         );
 
         endpoint!(
+            local
             /// Join a room using its ID or an alias.
             [r0, membership],
             join_room_by_id_or_alias
         );
 
+        endpoint!(
+            local
+            /// Get the joined members of a room with their display names and avatars.
+            [r0, membership],
+            joined_members,
+            [RoomMember]
+        );
+
+        endpoint!(
+            local
+            /// Ask to be invited to a room.
+            [r0, membership],
+            knock_room
+        );
+
         endpoint!(
             /// Kick a user from a room.
             [r0, membership],
@@ -247,12 +521,23 @@ pub mod r0 {
         );
 
         endpoint!(
+            local
             /// Unban a user from a room.
             [r0, membership],
             unban_user
         );
     }
 
+    /// Peeking into rooms without joining them.
+    pub mod peeking {
+        endpoint!(
+            local
+            /// Listen on the event stream of a room.
+            [r0, peeking],
+            get_events
+        );
+    }
+
     /// User presence.
     pub mod presence {
         endpoint!(
@@ -316,6 +601,16 @@ pub mod r0 {
     /// Push notifications.
     pub mod push {}
 
+    /// Read markers.
+    pub mod read_marker {
+        endpoint!(
+            local
+            /// Set the position of the fully-read marker, and optionally the read receipt.
+            [r0, read_marker],
+            set_read_marker
+        );
+    }
+
     /// Event receipts.
     pub mod receipt {
         endpoint!(
@@ -335,13 +630,52 @@ pub mod r0 {
         );
     }
 
-    /// Room creation.
+    /// Event relations.
+    pub mod relations {
+        endpoint!(
+            local
+            /// Get the events that relate to an event.
+            [r0, relations],
+            get_relating_events
+        );
+
+        endpoint!(
+            local
+            /// Get the events that relate to an event with a given relation type.
+            [r0, relations],
+            get_relating_events_with_rel_type
+        );
+
+        endpoint!(
+            local
+            /// Get the events of a given type that relate to an event with a given relation type.
+            [r0, relations],
+            get_relating_events_with_rel_type_and_event_type
+        );
+    }
+
+    /// Room creation and retrieval of single room events.
     pub mod room {
         endpoint!(
+            local
             /// Create a room.
             [r0, room],
             create_room,
-            [CreationContent, RoomPreset, Visibility]
+            [CreationContent, InitialStateEvent, RoomPreset, Visibility]
+        );
+
+        endpoint!(
+            local
+            /// Get a single event of a room as JSON.
+            [r0, room],
+            get_room_event
+        );
+
+        endpoint!(
+            local
+            /// Upgrade a room to a new room version.
+            [r0, room],
+            upgrade_room
         );
     }
 
@@ -378,6 +712,13 @@ pub mod r0 {
             send_message_event
         );
 
+        endpoint!(
+            local
+            /// Send a message with arbitrary JSON content to a room.
+            [r0, send],
+            send_raw_message_event
+        );
+
         endpoint!(
             /// Send a state event with an empty state key.
             [r0, send],
@@ -404,6 +745,7 @@ pub mod r0 {
     /// User session management.
     pub mod session {
         endpoint!(
+            local
             /// Log in to an account, creating an access token.
             [r0, session],
             login,
@@ -420,12 +762,15 @@ pub mod r0 {
     /// Getting and synchronizing events.
     pub mod sync {
         endpoint!(
+            local
             /// Get the list of members for a room.
             [r0, sync],
-            get_member_events
+            get_member_events,
+            [MembershipState]
         );
 
         endpoint!(
+            local
             /// Get message and state events for a room.
             [r0, sync],
             get_message_events,
@@ -451,22 +796,28 @@ pub mod r0 {
         );
 
         endpoint!(
+            local
             /// Synchronize the client's state with the latest state on the homeserver.
             [r0, sync],
             sync_events,
             [
                 AccountData,
+                DeviceLists,
                 Ephemeral,
                 Filter,
                 InviteState,
                 InvitedRoom,
                 JoinedRoom,
+                KnockState,
+                KnockedRoom,
                 LeftRoom,
                 Presence,
                 Rooms,
                 SetPresence,
                 State,
                 Timeline,
+                ToDevice,
+                ToDeviceEvent,
                 UnreadNotificationsCount
             ]
         );
@@ -474,19 +825,24 @@ pub mod r0 {
 
     /// Tagging rooms.
     pub mod tag {
+        pub use crate::api::local::r0::tag::TagInfo;
+
         endpoint!(
+            local
             /// Create a tag on a room.
             [r0, tag],
             create_tag
         );
 
         endpoint!(
+            local
             /// Delete a tag on a room.
             [r0, tag],
             delete_tag
         );
 
         endpoint!(
+            local
             /// Get the user's tags for a room.
             [r0, tag],
             get_tags
@@ -502,6 +858,16 @@ pub mod r0 {
         );
     }
 
+    /// Sending events directly to devices.
+    pub mod to_device {
+        endpoint!(
+            local
+            /// Send an event to devices.
+            [r0, to_device],
+            send_event_to_device
+        );
+    }
+
     /// Voice over IP.
     pub mod voip {
         endpoint!(
@@ -514,9 +880,36 @@ pub mod r0 {
 
 /// Endpoints that cannot change with new versions of the Matrix specification.
 pub mod unversioned {
+    endpoint!(
+        local
+        /// Get the homeserver a domain delegates its client API to.
+        [unversioned],
+        discover_homeserver,
+        [HomeserverInfo]
+    );
+
     endpoint!(
         /// Get the versions of the specification supported by this homeserver.
         [unversioned],
         get_supported_versions
     );
 }
+
+/// Administration endpoints specific to the Synapse homeserver.
+#[cfg(feature = "synapse-admin")]
+pub mod synapse_admin {
+    endpoint!(
+        local
+        /// Purge room history up to a given event.
+        [synapse_admin],
+        purge_history
+    );
+
+    endpoint!(
+        local
+        /// Get the status of a room history purge.
+        [synapse_admin],
+        purge_history_status,
+        [PurgeStatus]
+    );
+}
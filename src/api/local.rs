@@ -0,0 +1,11 @@
+//! Endpoint definitions that `ruma_client_api` doesn't provide (yet).
+//!
+//! The modules here mirror the layout of `ruma_client_api` so that the `endpoint!` macro can
+//! expose them the same way as the upstream ones.
+
+pub mod content_scanner;
+pub mod identity;
+pub mod r0;
+#[cfg(feature = "synapse-admin")]
+pub mod synapse_admin;
+pub mod unversioned;
@@ -0,0 +1,35 @@
+//! Endpoints of the [matrix-content-scanner](https://github.com/matrix-org/matrix-content-scanner)
+//! service.
+
+/// [GET /_matrix/media_proxy/unstable/scan/{serverName}/{mediaId}](https://github.com/matrix-org/matrix-content-scanner#api)
+pub mod scan {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Scan media from the media repository for malware.",
+            method: GET,
+            name: "scan",
+            path: "/_matrix/media_proxy/unstable/scan/:server_name/:media_id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The server name from the mxc:// URI (the authoritory component).
+            #[ruma_api(path)]
+            pub server_name: String,
+            /// The media ID from the mxc:// URI (the path component).
+            #[ruma_api(path)]
+            pub media_id: String,
+        }
+
+        response {
+            /// Whether the media was found to be clean.
+            pub clean: bool,
+            /// Human-readable information about the result of the scan.
+            pub info: String,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Endpoints of the identity service API.
+
+/// [POST /_matrix/identity/api/v1/sign-ed25519](https://matrix.org/docs/spec/identity_service/r0.1.0.html#post-matrix-identity-api-v1-sign-ed25519)
+// The generated response body type is only ever deserialized.
+#[allow(dead_code)]
+pub mod sign_ed25519 {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+
+    use crate::api::local::r0::membership::ThirdPartySigned;
+
+    ruma_api! {
+        metadata {
+            description: "Sign invitation details.",
+            method: POST,
+            name: "sign_ed25519",
+            path: "/_matrix/identity/api/v1/sign-ed25519",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The Matrix ID of the user accepting the invitation.
+            pub mxid: UserId,
+            /// The token from the call to store-invite.
+            pub token: String,
+            /// The private key, encoded as unpadded base64.
+            pub private_key: String,
+        }
+
+        response {
+            /// The signed invitation details.
+            #[ruma_api(body)]
+            pub signed: ThirdPartySigned,
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Endpoints for the r0.x.x versions of the client API specification.
+
+pub mod account;
+pub mod alias;
+pub mod capabilities;
+pub mod config;
+pub mod contact;
+pub mod context;
+pub mod device;
+pub mod directory;
+pub mod keys;
+pub mod media;
+pub mod membership;
+pub mod peeking;
+pub mod read_marker;
+pub mod relations;
+pub mod room;
+pub mod send;
+pub mod session;
+pub mod sync;
+pub mod tag;
+pub mod to_device;
@@ -0,0 +1,298 @@
+//! Endpoints for account registration and management.
+
+/// [GET /_matrix/client/r0/account/whoami](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-account-whoami)
+pub mod whoami {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get information about the owner of the access token.",
+            method: GET,
+            name: "whoami",
+            path: "/_matrix/client/r0/account/whoami",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {}
+
+        response {
+            /// The user ID that owns the access token.
+            pub user_id: UserId,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/account/password](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-account-password)
+///
+/// The definition in `ruma_client_api` lacks the `auth` field.
+pub mod change_password {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Change the password of the current user's account.",
+            method: POST,
+            name: "change_password",
+            path: "/_matrix/client/r0/account/password",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The new password for the account.
+            pub new_password: String,
+            /// Whether the other devices of the user should be logged out.
+            ///
+            /// Homeservers log them out if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub logout_devices: Option<bool>,
+            /// Additional authentication information for the user-interactive authentication API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub auth: Option<Value>,
+        }
+
+        response {}
+    }
+}
+
+/// [POST /_matrix/client/r0/account/deactivate](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-deactivate)
+///
+/// The definition in `ruma_client_api` lacks the request parameters.
+pub mod deactivate {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Deactivate the current user's account.",
+            method: POST,
+            name: "deactivate",
+            path: "/_matrix/client/r0/account/deactivate",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// Additional authentication information for the user-interactive authentication API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub auth: Option<Value>,
+            /// Whether the homeserver should also forget the messages sent by the user, as far
+            /// as possible.
+            ///
+            /// This is a Synapse extension of the specification.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub erase: Option<bool>,
+        }
+
+        response {
+            /// Whether the homeserver unbound the user's third-party identifiers from the
+            /// identity server, either `success` or `no-support`.
+            #[serde(default)]
+            pub id_server_unbind_result: Option<String>,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/register](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-register)
+///
+/// The definition in `ruma_client_api` doesn't allow constructing the `auth` field.
+pub mod register {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Register an account on this homeserver.",
+            method: POST,
+            name: "register",
+            path: "/_matrix/client/r0/register",
+            rate_limited: true,
+            requires_authentication: false,
+        }
+
+        request {
+            /// If true, the server binds the email used for authentication
+            /// to the Matrix ID with the ID Server.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub bind_email: Option<bool>,
+            /// The desired password for the account.
+            ///
+            /// Should only be empty for guest accounts.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub password: Option<String>,
+            /// local part of the desired Matrix ID.
+            ///
+            /// If omitted, the homeserver MUST generate a Matrix ID local part.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub username: Option<String>,
+            /// ID of the client device.
+            ///
+            /// If this does not correspond to a known client device, a new device will be created.
+            /// The server will auto-generate a device_id if this is not specified.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub device_id: Option<String>,
+            /// A display name to assign to the newly-created device.
+            ///
+            /// Ignored if `device_id` corresponds to a known device.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub initial_device_display_name: Option<String>,
+            /// Additional authentication information for the user-interactive authentication API.
+            ///
+            /// This authenticates the register call itself, e.g. with a validated email address,
+            /// and is omitted until a response with status code 401 asked for it.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub auth: Option<Value>,
+            /// Kind of account to register
+            ///
+            /// Defaults to `User` if omitted.
+            #[ruma_api(query)]
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub kind: Option<RegistrationKind>,
+        }
+
+        response {
+            /// An access token for the account.
+            ///
+            /// This access token can then be used to authorize other requests.
+            pub access_token: String,
+            /// The hostname of the homeserver on which the account has been registered.
+            #[serde(default)]
+            pub home_server: Option<String>,
+            /// The fully-qualified Matrix ID that has been registered.
+            pub user_id: UserId,
+            /// ID of the registered device.
+            ///
+            /// Will be the same as the corresponding parameter in the request, if one was specified.
+            pub device_id: String,
+        }
+    }
+
+    /// The auth data type of the definition in `ruma_client_api`.
+    ///
+    /// Its fields are private, so `auth` takes JSON instead, which a deserialized
+    /// `AuthenticationData` can be converted to with `serde_json::to_value`.
+    pub use ruma_client_api::r0::account::register::AuthenticationData;
+
+    /// The kind of account being registered.
+    #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+    pub enum RegistrationKind {
+        /// A guest account
+        ///
+        /// These accounts may have limited permissions and may not be supported by all servers.
+        #[serde(rename = "guest")]
+        Guest,
+        /// A regular user account
+        #[serde(rename = "user")]
+        User,
+    }
+}
+
+/// [POST /_matrix/client/r0/register/email/requestToken](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-register-email-requesttoken)
+///
+/// The definition in `ruma_client_api` requires authentication and lacks the session ID in the
+/// response.
+pub mod request_register_token {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Ask for a token validating an email address for registration.",
+            method: POST,
+            name: "request_register_token",
+            path: "/_matrix/client/r0/register/email/requestToken",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// Client-generated secret string used to protect this session.
+            pub client_secret: String,
+            /// The email address.
+            pub email: String,
+            /// Used to distinguish protocol level retries from requests to re-send the email.
+            pub send_attempt: u64,
+            /// Where the user is redirected to after validating the address.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub next_link: Option<String>,
+            /// The identity server to send the onward request to, as a hostname with an optional
+            /// port.
+            ///
+            /// The homeserver sends the email itself if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_server: Option<String>,
+            /// An access token previously registered with the identity server.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_access_token: Option<String>,
+        }
+
+        response {
+            /// The session ID, to be passed back in the `m.login.email.identity` stage of the
+            /// registration.
+            pub sid: String,
+            /// Where the token sent to the user can be submitted, if the homeserver accepts it
+            /// directly.
+            #[serde(default)]
+            pub submit_url: Option<String>,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/register/msisdn/requestToken](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-register-msisdn-requesttoken)
+pub mod request_register_msisdn_token {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Ask for a token validating a phone number for registration.",
+            method: POST,
+            name: "request_register_msisdn_token",
+            path: "/_matrix/client/r0/register/msisdn/requestToken",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// Client-generated secret string used to protect this session.
+            pub client_secret: String,
+            /// The two-letter ISO 3166-1 alpha-2 code of the country the number should be parsed
+            /// as if it's dialled from.
+            pub country: String,
+            /// The phone number.
+            pub phone_number: String,
+            /// Used to distinguish protocol level retries from requests to re-send the SMS.
+            pub send_attempt: u64,
+            /// Where the user is redirected to after validating the number.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub next_link: Option<String>,
+            /// The identity server to send the onward request to, as a hostname with an optional
+            /// port.
+            ///
+            /// The homeserver sends the SMS itself if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_server: Option<String>,
+            /// An access token previously registered with the identity server.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_access_token: Option<String>,
+        }
+
+        response {
+            /// The session ID, to be passed back in the `m.login.msisdn` stage of the
+            /// registration.
+            pub sid: String,
+            /// Where the token sent to the user can be submitted, if the homeserver accepts it
+            /// directly.
+            #[serde(default)]
+            pub submit_url: Option<String>,
+        }
+    }
+}
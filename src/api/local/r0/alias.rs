@@ -0,0 +1,63 @@
+//! Endpoints for room aliases.
+
+/// [GET /_matrix/client/r0/directory/room/{roomAlias}](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-directory-room-roomalias)
+///
+/// The definition in `ruma_client_api` requires authentication.
+pub mod get_alias {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomAliasId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Resolve a room alias to a room ID.",
+            method: GET,
+            name: "get_alias",
+            path: "/_matrix/client/r0/directory/room/:room_alias",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The room alias.
+            #[ruma_api(path)]
+            pub room_alias: RoomAliasId,
+        }
+
+        response {
+            /// The room ID for this room alias.
+            pub room_id: RoomId,
+            /// A list of servers that are aware of this room ID.
+            pub servers: Vec<String>,
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/rooms/{roomId}/aliases](https://matrix.org/docs/spec/client_server/r0.6.1#get-matrix-client-r0-rooms-roomid-aliases)
+pub mod get_room_aliases {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomAliasId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the aliases of a room that the homeserver maintains.",
+            method: GET,
+            name: "get_room_aliases",
+            path: "/_matrix/client/r0/rooms/:room_id/aliases",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the room.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+        }
+
+        response {
+            /// The aliases of the room on the homeserver.
+            pub aliases: Vec<RoomAliasId>,
+        }
+    }
+}
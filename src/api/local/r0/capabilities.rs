@@ -0,0 +1,67 @@
+//! Endpoints for server capabilities.
+
+/// [GET /_matrix/client/r0/capabilities](https://matrix.org/docs/spec/client_server/r0.5.0.html#get-matrix-client-r0-capabilities)
+pub mod get_capabilities {
+    use std::collections::BTreeMap;
+
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get information about the server's supported feature set and other relevant capabilities.",
+            method: GET,
+            name: "get_capabilities",
+            path: "/_matrix/client/r0/capabilities",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {}
+
+        response {
+            /// The capabilities the server supports.
+            pub capabilities: Capabilities,
+        }
+    }
+
+    /// The capabilities a server supports.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct Capabilities {
+        /// Whether the user can change their password.
+        #[serde(rename = "m.change_password")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub change_password: Option<ChangePasswordCapability>,
+        /// The room versions the server supports.
+        #[serde(rename = "m.room_versions")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub room_versions: Option<RoomVersionsCapability>,
+    }
+
+    /// Whether the user can change their password.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ChangePasswordCapability {
+        /// `true` if the user can change their password.
+        pub enabled: bool,
+    }
+
+    /// The room versions the server supports.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct RoomVersionsCapability {
+        /// The version the server uses for new rooms.
+        pub default: String,
+        /// The stability of the supported room versions, keyed by version.
+        pub available: BTreeMap<String, RoomVersionStability>,
+    }
+
+    /// The stability of a room version.
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    pub enum RoomVersionStability {
+        /// The version is defined by the specification.
+        #[serde(rename = "stable")]
+        Stable,
+        /// The version is experimental.
+        #[serde(rename = "unstable")]
+        Unstable,
+    }
+}
@@ -0,0 +1,76 @@
+//! Endpoints for client configuration.
+
+/// [GET /_matrix/client/r0/user/{userId}/account_data/{type}](https://matrix.org/docs/spec/client_server/r0.5.0.html#get-matrix-client-r0-user-userid-account-data-type)
+// The generated response body type is only ever deserialized.
+#[allow(dead_code)]
+pub mod get_global_account_data {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Gets global account data.",
+            method: GET,
+            name: "get_global_account_data",
+            path: "/_matrix/client/r0/user/:user_id/account_data/:event_type",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the user to get account data for.
+            #[ruma_api(path)]
+            pub user_id: UserId,
+            /// The event type of the account data to get.
+            #[ruma_api(path)]
+            pub event_type: String,
+        }
+
+        response {
+            /// The content of the account data.
+            #[ruma_api(body)]
+            pub data: Value,
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/user/{userId}/rooms/{roomId}/account_data/{type}](https://matrix.org/docs/spec/client_server/r0.5.0.html#get-matrix-client-r0-user-userid-rooms-roomid-account-data-type)
+// The generated response body type is only ever deserialized.
+#[allow(dead_code)]
+pub mod get_room_account_data {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Gets account data scoped to a room.",
+            method: GET,
+            name: "get_room_account_data",
+            path: "/_matrix/client/r0/user/:user_id/rooms/:room_id/account_data/:event_type",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the user to get account data for.
+            #[ruma_api(path)]
+            pub user_id: UserId,
+            /// The ID of the room the account data is scoped to.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The event type of the account data to get.
+            #[ruma_api(path)]
+            pub event_type: String,
+        }
+
+        response {
+            /// The content of the account data.
+            #[ruma_api(body)]
+            pub data: Value,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Endpoints for account contact information.
+
+/// [GET /_matrix/client/r0/account/3pid](https://matrix.org/docs/spec/client_server/r0.6.0.html#get-matrix-client-r0-account-3pid)
+///
+/// The definition in `ruma_client_api` lacks phone numbers and the timestamps of identifiers.
+pub mod get_contacts {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get a list of 3rd party contacts associated with the user's account.",
+            method: GET,
+            name: "get_contacts",
+            path: "/_matrix/client/r0/account/3pid",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {}
+
+        response {
+            /// A list of third party identifiers the homeserver has associated with the user's
+            /// account.
+            pub threepids: Vec<ThirdPartyIdentifier>,
+        }
+    }
+
+    /// The medium of a third party identifier.
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+    pub enum Medium {
+        /// An email address.
+        #[serde(rename = "email")]
+        Email,
+        /// A phone number.
+        #[serde(rename = "msisdn")]
+        Msisdn,
+    }
+
+    /// An identifier external to Matrix.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ThirdPartyIdentifier {
+        /// The third party identifier address.
+        pub address: String,
+        /// The medium of third party identifier.
+        pub medium: Medium,
+        /// When the identifier was validated, in milliseconds since the Unix epoch.
+        #[serde(default)]
+        pub validated_at: Option<u64>,
+        /// When the identifier was added to the account, in milliseconds since the Unix epoch.
+        #[serde(default)]
+        pub added_at: Option<u64>,
+    }
+}
+
+/// [POST /_matrix/client/r0/account/3pid/add](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-3pid-add)
+pub mod add_3pid {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Add a validated third party identifier to the user's account.",
+            method: POST,
+            name: "add_3pid",
+            path: "/_matrix/client/r0/account/3pid/add",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The client secret used in the session in which the identifier was validated.
+            pub client_secret: String,
+            /// The session identifier given by the homeserver when the validation was requested.
+            pub sid: String,
+            /// Additional authentication information for the user-interactive authentication API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub auth: Option<Value>,
+        }
+
+        response {}
+    }
+}
+
+/// [POST /_matrix/client/r0/account/3pid/bind](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-3pid-bind)
+pub mod bind_3pid {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Bind a third party identifier to the user's Matrix ID on an identity server.",
+            method: POST,
+            name: "bind_3pid",
+            path: "/_matrix/client/r0/account/3pid/bind",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The client secret used in the session with the identity server.
+            pub client_secret: String,
+            /// The identity server to use, as a hostname with an optional port.
+            pub id_server: String,
+            /// An access token previously registered with the identity server.
+            pub id_access_token: String,
+            /// The session identifier given by the identity server.
+            pub sid: String,
+        }
+
+        response {}
+    }
+}
+
+/// [POST /_matrix/client/r0/account/3pid/delete](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-3pid-delete)
+pub mod delete_3pid {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::get_contacts::Medium;
+
+    ruma_api! {
+        metadata {
+            description: "Remove a third party identifier from the user's account.",
+            method: POST,
+            name: "delete_3pid",
+            path: "/_matrix/client/r0/account/3pid/delete",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The identity server to unbind the identifier from.
+            ///
+            /// Homeservers use the one the identifier was bound with if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_server: Option<String>,
+            /// The medium of the identifier.
+            pub medium: Medium,
+            /// The address of the identifier.
+            pub address: String,
+        }
+
+        response {
+            /// Whether the homeserver unbound the identifier from the identity server, either
+            /// `success` or `no-support`.
+            pub id_server_unbind_result: String,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/account/3pid/unbind](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-3pid-unbind)
+pub mod unbind_3pid {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::get_contacts::Medium;
+
+    ruma_api! {
+        metadata {
+            description: "Unbind a third party identifier from the user's Matrix ID on an identity server.",
+            method: POST,
+            name: "unbind_3pid",
+            path: "/_matrix/client/r0/account/3pid/unbind",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The identity server to unbind the identifier from.
+            ///
+            /// Homeservers use the one the identifier was bound with if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_server: Option<String>,
+            /// The medium of the identifier.
+            pub medium: Medium,
+            /// The address of the identifier.
+            pub address: String,
+        }
+
+        response {
+            /// Whether the homeserver unbound the identifier from the identity server, either
+            /// `success` or `no-support`.
+            pub id_server_unbind_result: String,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/account/3pid/email/requestToken](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-3pid-email-requesttoken)
+///
+/// The definition in `ruma_client_api` lacks the session ID in the response.
+pub mod request_contact_verification_token {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Ask for a verification token for an email address.",
+            method: POST,
+            name: "request_contact_verification_token",
+            path: "/_matrix/client/r0/account/3pid/email/requestToken",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// Client-generated secret string used to protect this session.
+            pub client_secret: String,
+            /// The email address.
+            pub email: String,
+            /// Used to distinguish protocol level retries from requests to re-send the email.
+            pub send_attempt: u64,
+            /// Where the user is redirected to after validating the address.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub next_link: Option<String>,
+            /// The identity server to send the onward request to, as a hostname with an optional
+            /// port.
+            ///
+            /// The homeserver sends the email itself if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_server: Option<String>,
+            /// An access token previously registered with the identity server.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_access_token: Option<String>,
+        }
+
+        response {
+            /// The session ID, to be passed to `add_3pid` once the address is validated.
+            pub sid: String,
+            /// Where the token sent to the user can be submitted, if the homeserver accepts it
+            /// directly.
+            #[serde(default)]
+            pub submit_url: Option<String>,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/account/3pid/msisdn/requestToken](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-account-3pid-msisdn-requesttoken)
+pub mod request_msisdn_verification_token {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Ask for a verification token for a phone number.",
+            method: POST,
+            name: "request_msisdn_verification_token",
+            path: "/_matrix/client/r0/account/3pid/msisdn/requestToken",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// Client-generated secret string used to protect this session.
+            pub client_secret: String,
+            /// The two-letter ISO 3166-1 alpha-2 code of the country the number should be parsed
+            /// as if it's dialled from.
+            pub country: String,
+            /// The phone number.
+            pub phone_number: String,
+            /// Used to distinguish protocol level retries from requests to re-send the SMS.
+            pub send_attempt: u64,
+            /// Where the user is redirected to after validating the number.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub next_link: Option<String>,
+            /// The identity server to send the onward request to, as a hostname with an optional
+            /// port.
+            ///
+            /// The homeserver sends the SMS itself if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_server: Option<String>,
+            /// An access token previously registered with the identity server.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub id_access_token: Option<String>,
+        }
+
+        response {
+            /// The session ID, to be passed to `add_3pid` once the number is validated.
+            pub sid: String,
+            /// Where the token sent to the user can be submitted, if the homeserver accepts it
+            /// directly.
+            #[serde(default)]
+            pub submit_url: Option<String>,
+        }
+    }
+}
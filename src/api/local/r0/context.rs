@@ -0,0 +1,55 @@
+//! Endpoints for event context.
+
+/// [GET /_matrix/client/r0/rooms/{roomId}/context/{eventId}](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-rooms-roomid-context-eventid)
+///
+/// The definition in `ruma_client_api` requires the limit and can't deserialize state events
+/// among the returned timeline events.
+pub mod get_context {
+    use ruma_api_macros::ruma_api;
+    use ruma_events::collections::{all, only};
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the events immediately preceding and following a given event.",
+            method: GET,
+            name: "get_context",
+            path: "/_matrix/client/r0/rooms/:room_id/context/:event_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to get events from.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The event to get context around.
+            #[ruma_api(path)]
+            pub event_id: EventId,
+            /// The maximum number of events to return.
+            ///
+            /// Default: 10.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub limit: Option<u64>,
+        }
+
+        response {
+            /// A token that can be used to paginate backwards with.
+            pub start: String,
+            /// A token that can be used to paginate forwards with.
+            pub end: String,
+            /// A list of room events that happened just before the requested event, in
+            /// reverse-chronological order.
+            pub events_before: Vec<all::RoomEvent>,
+            /// Details of the requested event.
+            pub event: all::RoomEvent,
+            /// A list of room events that happened just after the requested event, in
+            /// chronological order.
+            pub events_after: Vec<all::RoomEvent>,
+            /// The state of the room at the last event returned.
+            pub state: Vec<only::StateEvent>,
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Endpoints for managing the devices of the logged-in user.
+
+use serde_derive::{Deserialize, Serialize};
+
+/// A device of a user.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Device {
+    /// The ID of the device.
+    pub device_id: String,
+    /// The display name of the device, set by the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The IP address the device was last seen at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen_ip: Option<String>,
+    /// When the device was last seen, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen_ts: Option<u64>,
+}
+
+/// [GET /_matrix/client/r0/devices](https://matrix.org/docs/spec/client_server/r0.6.0.html#get-matrix-client-r0-devices)
+pub mod get_devices {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::Device;
+
+    ruma_api! {
+        metadata {
+            description: "Get the devices of the current user.",
+            method: GET,
+            name: "get_devices",
+            path: "/_matrix/client/r0/devices",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {}
+
+        response {
+            /// The devices of the current user.
+            pub devices: Vec<Device>,
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/devices/{deviceId}](https://matrix.org/docs/spec/client_server/r0.6.0.html#get-matrix-client-r0-devices-deviceid)
+pub mod get_device {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::Device;
+
+    ruma_api! {
+        metadata {
+            description: "Get a single device of the current user.",
+            method: GET,
+            name: "get_device",
+            path: "/_matrix/client/r0/devices/:device_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the device.
+            #[ruma_api(path)]
+            pub device_id: String,
+        }
+
+        response {
+            /// The device.
+            #[serde(flatten)]
+            pub device: Device,
+        }
+    }
+}
+
+/// [PUT /_matrix/client/r0/devices/{deviceId}](https://matrix.org/docs/spec/client_server/r0.6.0.html#put-matrix-client-r0-devices-deviceid)
+pub mod update_device {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Update the metadata of a device of the current user.",
+            method: PUT,
+            name: "update_device",
+            path: "/_matrix/client/r0/devices/:device_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the device.
+            #[ruma_api(path)]
+            pub device_id: String,
+            /// The new display name of the device.
+            ///
+            /// The display name is left unchanged if this is omitted.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub display_name: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+/// [DELETE /_matrix/client/r0/devices/{deviceId}](https://matrix.org/docs/spec/client_server/r0.6.0.html#delete-matrix-client-r0-devices-deviceid)
+pub mod delete_device {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Delete a device of the current user, invalidating its access token.",
+            method: DELETE,
+            name: "delete_device",
+            path: "/_matrix/client/r0/devices/:device_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the device.
+            #[ruma_api(path)]
+            pub device_id: String,
+            /// Additional authentication information for the user-interactive authentication API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub auth: Option<Value>,
+        }
+
+        response {}
+    }
+}
+
+/// [POST /_matrix/client/r0/delete_devices](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-delete-devices)
+pub mod delete_devices {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Delete several devices of the current user, invalidating their access tokens.",
+            method: POST,
+            name: "delete_devices",
+            path: "/_matrix/client/r0/delete_devices",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The IDs of the devices.
+            pub devices: Vec<String>,
+            /// Additional authentication information for the user-interactive authentication API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub auth: Option<Value>,
+        }
+
+        response {}
+    }
+}
@@ -0,0 +1,190 @@
+//! Endpoints for the public room directory.
+
+/// [GET /_matrix/client/r0/directory/list/room/{roomId}](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-directory-list-room-roomid)
+pub mod get_room_visibility {
+    use ruma_api_macros::ruma_api;
+    pub use ruma_client_api::r0::room::create_room::Visibility;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the visibility of a room in the public room directory.",
+            method: GET,
+            name: "get_room_visibility",
+            path: "/_matrix/client/r0/directory/list/room/:room_id",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The ID of the room.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+        }
+
+        response {
+            /// Whether the room is listed in the public room directory.
+            pub visibility: Visibility,
+        }
+    }
+}
+
+/// [PUT /_matrix/client/r0/directory/list/room/{roomId}](https://matrix.org/docs/spec/client_server/r0.4.0.html#put-matrix-client-r0-directory-list-room-roomid)
+pub mod set_room_visibility {
+    use ruma_api_macros::ruma_api;
+    pub use ruma_client_api::r0::room::create_room::Visibility;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Publish a room in the public room directory, or remove it from it.",
+            method: PUT,
+            name: "set_room_visibility",
+            path: "/_matrix/client/r0/directory/list/room/:room_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the room.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// Whether the room should be listed in the public room directory.
+            pub visibility: Visibility,
+        }
+
+        response {}
+    }
+}
+
+/// [GET /_matrix/client/r0/publicRooms](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-publicrooms)
+///
+/// The definition in `ruma_client_api` lacks the pagination parameters and the `server`.
+pub mod get_public_rooms {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomAliasId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the list of rooms in a homeserver's public directory.",
+            method: GET,
+            name: "get_public_rooms",
+            path: "/_matrix/client/r0/publicRooms",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The maximum number of rooms to return.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub limit: Option<u64>,
+            /// The `next_batch` or `prev_batch` token of an earlier response, to get the next
+            /// page.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub since: Option<String>,
+            /// The server to get the directory of. Defaults to the user's homeserver.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub server: Option<String>,
+        }
+
+        response {
+            /// A page of public rooms.
+            pub chunk: Vec<PublicRoomsChunk>,
+            /// A pagination token for the next page, if there is one.
+            pub next_batch: Option<String>,
+            /// A pagination token for the previous page, if there is one.
+            pub prev_batch: Option<String>,
+            /// An estimate of the total number of public rooms.
+            pub total_room_count_estimate: Option<u64>,
+        }
+    }
+
+    /// A room in the public room directory.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct PublicRoomsChunk {
+        /// Aliases of the room.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub aliases: Vec<RoomAliasId>,
+        /// The canonical alias of the room, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub canonical_alias: Option<RoomAliasId>,
+        /// The name of the room, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub name: Option<String>,
+        /// The number of members joined to the room.
+        pub num_joined_members: u64,
+        /// The ID of the room.
+        pub room_id: RoomId,
+        /// The topic of the room, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub topic: Option<String>,
+        /// Whether the room may be viewed by guest users without joining.
+        pub world_readable: bool,
+        /// Whether guest users may join the room and participate in it.
+        pub guest_can_join: bool,
+        /// The URL for the room's avatar, if one is set.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub avatar_url: Option<String>,
+    }
+}
+
+/// [POST /_matrix/client/r0/publicRooms](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-publicrooms)
+pub mod get_public_rooms_filtered {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    pub use super::get_public_rooms::PublicRoomsChunk;
+
+    ruma_api! {
+        metadata {
+            description: "Search the public room directory of a homeserver.",
+            method: POST,
+            name: "get_public_rooms_filtered",
+            path: "/_matrix/client/r0/publicRooms",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The server to search the directory of. Defaults to the user's homeserver.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub server: Option<String>,
+            /// The maximum number of rooms to return.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub limit: Option<u64>,
+            /// The `next_batch` or `prev_batch` token of an earlier response, to get the next
+            /// page.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub since: Option<String>,
+            /// The filter to apply to the rooms.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub filter: Option<Filter>,
+        }
+
+        response {
+            /// A page of public rooms.
+            pub chunk: Vec<PublicRoomsChunk>,
+            /// A pagination token for the next page, if there is one.
+            pub next_batch: Option<String>,
+            /// A pagination token for the previous page, if there is one.
+            pub prev_batch: Option<String>,
+            /// An estimate of the total number of public rooms.
+            pub total_room_count_estimate: Option<u64>,
+        }
+    }
+
+    /// A filter for the public room directory.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct Filter {
+        /// Only return rooms whose name, topic or canonical alias contains this term.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub generic_search_term: Option<String>,
+    }
+}
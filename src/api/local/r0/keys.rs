@@ -0,0 +1,181 @@
+//! Endpoints for the keys of end-to-end encryption.
+
+use std::collections::BTreeMap;
+
+use ruma_identifiers::UserId;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The identity keys of a device, signed by the device.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeviceKeys {
+    /// The user the device belongs to.
+    pub user_id: UserId,
+    /// The ID of the device.
+    pub device_id: String,
+    /// The encryption algorithms the device supports.
+    pub algorithms: Vec<String>,
+    /// The public identity keys of the device, by `{algorithm}:{device_id}`.
+    pub keys: BTreeMap<String, String>,
+    /// The signatures of the keys, by user ID and then by `{algorithm}:{key_id}`.
+    pub signatures: BTreeMap<String, BTreeMap<String, String>>,
+    /// Information about the device added by the homeserver, which isn't signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unsigned: Option<Value>,
+}
+
+/// [POST /_matrix/client/r0/keys/upload](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-keys-upload)
+pub mod upload_keys {
+    use std::collections::BTreeMap;
+
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use super::DeviceKeys;
+
+    ruma_api! {
+        metadata {
+            description: "Publish end-to-end encryption keys for the device.",
+            method: POST,
+            name: "upload_keys",
+            path: "/_matrix/client/r0/keys/upload",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The identity keys of the device, if they weren't uploaded yet.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub device_keys: Option<DeviceKeys>,
+            /// New one-time keys, by `{algorithm}:{key_id}`.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub one_time_keys: Option<BTreeMap<String, Value>>,
+        }
+
+        response {
+            /// How many unclaimed one-time keys of each algorithm the homeserver has for the
+            /// device.
+            pub one_time_key_counts: BTreeMap<String, u64>,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/keys/claim](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-keys-claim)
+pub mod claim_keys {
+    use std::collections::{BTreeMap, HashMap};
+
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Claim one-time keys of devices, to start Olm sessions with them.",
+            method: POST,
+            name: "claim_keys",
+            path: "/_matrix/client/r0/keys/claim",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// How long to wait for remote homeservers, in milliseconds.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub timeout: Option<u64>,
+            /// The algorithm of the key to claim, by user and device ID.
+            pub one_time_keys: HashMap<UserId, BTreeMap<String, String>>,
+        }
+
+        response {
+            /// The errors of remote homeservers that couldn't be reached, by server name.
+            #[serde(default)]
+            pub failures: BTreeMap<String, Value>,
+            /// The claimed keys by user and device ID, and then by `{algorithm}:{key_id}`.
+            #[serde(default)]
+            pub one_time_keys: HashMap<UserId, BTreeMap<String, BTreeMap<String, Value>>>,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/keys/query](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-keys-query)
+pub mod get_keys {
+    use std::collections::{BTreeMap, HashMap};
+
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Get the identity keys of devices.",
+            method: POST,
+            name: "get_keys",
+            path: "/_matrix/client/r0/keys/query",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// How long to wait for remote homeservers, in milliseconds.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub timeout: Option<u64>,
+            /// The devices to get the keys of, by user. An empty list means all devices of the
+            /// user.
+            pub device_keys: HashMap<UserId, Vec<String>>,
+            /// The `next_batch` token of the sync response that told the client the keys changed.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub token: Option<String>,
+        }
+
+        response {
+            /// The errors of remote homeservers that couldn't be reached, by server name.
+            #[serde(default)]
+            pub failures: BTreeMap<String, Value>,
+            /// The identity keys of the devices, by user and device ID.
+            ///
+            /// These are the JSON objects as the homeserver sent them, because their signatures
+            /// may cover fields `DeviceKeys` doesn't know.
+            #[serde(default)]
+            pub device_keys: HashMap<UserId, BTreeMap<String, Value>>,
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/keys/changes](https://matrix.org/docs/spec/client_server/r0.6.0.html#get-matrix-client-r0-keys-changes)
+pub mod get_key_changes {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the users whose devices changed between two sync tokens.",
+            method: GET,
+            name: "get_key_changes",
+            path: "/_matrix/client/r0/keys/changes",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The `next_batch` token of the earlier sync response.
+            #[ruma_api(query)]
+            pub from: String,
+            /// The `next_batch` token of the later sync response.
+            #[ruma_api(query)]
+            pub to: String,
+        }
+
+        response {
+            /// The users whose devices changed, who share an encrypted room with the user.
+            #[serde(default)]
+            pub changed: Vec<UserId>,
+            /// The users who no longer share an encrypted room with the user.
+            #[serde(default)]
+            pub left: Vec<UserId>,
+        }
+    }
+}
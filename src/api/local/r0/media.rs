@@ -0,0 +1,205 @@
+//! Endpoints for the media repository.
+
+/// [POST /_matrix/media/r0/upload](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-media-r0-upload)
+///
+/// The definition in `ruma_client_api` can't send the file. `ruma_api!` only supports JSON
+/// bodies, so the conversions are implemented by hand.
+pub mod create_content {
+    use std::convert::TryFrom;
+
+    use futures::{
+        future::{self, FutureFrom},
+        Future, IntoFuture, Stream,
+    };
+    use http::{header::CONTENT_TYPE, Method};
+    use hyper::Body;
+    use ruma_api::{Endpoint as RumaApiEndpoint, Error, Metadata};
+    use serde_derive::{Deserialize, Serialize};
+    use url::Url;
+
+    /// The API endpoint.
+    #[derive(Debug)]
+    pub struct Endpoint;
+
+    impl RumaApiEndpoint for Endpoint {
+        type Request = Request;
+        type Response = Response;
+
+        const METADATA: Metadata = Metadata {
+            description: "Upload content to the media store.",
+            method: Method::POST,
+            name: "create_media_content",
+            path: "/_matrix/media/r0/upload",
+            rate_limited: true,
+            requires_authentication: true,
+        };
+    }
+
+    /// Data for a request to the `create_media_content` API endpoint.
+    #[derive(Clone, Debug)]
+    pub struct Request {
+        /// The content type of the file being uploaded.
+        pub content_type: String,
+        /// The name of the file being uploaded.
+        pub filename: Option<String>,
+        /// The file contents to upload.
+        pub file: Vec<u8>,
+    }
+
+    /// Data in the response from the `create_media_content` API endpoint.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct Response {
+        /// The MXC URI for the uploaded content.
+        pub content_uri: String,
+    }
+
+    impl TryFrom<Request> for http::Request<Body> {
+        type Error = Error;
+
+        fn try_from(request: Request) -> Result<Self, Self::Error> {
+            // The homeserver URL is filled in by the client.
+            let mut url = Url::parse("http://invalid-host-please-change/").unwrap();
+            url.set_path(Endpoint::METADATA.path);
+
+            if let Some(filename) = &request.filename {
+                url.query_pairs_mut().append_pair("filename", filename);
+            }
+
+            Ok(http::Request::builder()
+                .method(Endpoint::METADATA.method)
+                .uri(url.into_string())
+                .header(CONTENT_TYPE, request.content_type)
+                .body(Body::from(request.file))?)
+        }
+    }
+
+    impl FutureFrom<http::Request<Body>> for Request {
+        type Future = Box<dyn Future<Item = Self, Error = Self::Error> + Send>;
+        type Error = Error;
+
+        fn future_from(request: http::Request<Body>) -> Self::Future {
+            let (parts, body) = request.into_parts();
+            let content_type = parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("application/octet-stream")
+                .to_owned();
+            let url = Url::parse("http://invalid-host-please-change/")
+                .unwrap()
+                .join(&parts.uri.to_string());
+            let filename = url.ok().and_then(|url| {
+                url.query_pairs()
+                    .find(|(key, _)| key == "filename")
+                    .map(|(_, value)| value.into_owned())
+            });
+
+            Box::new(
+                body.concat2()
+                    .map_err(Error::from)
+                    .map(move |file| Request {
+                        content_type,
+                        filename,
+                        file: file.to_vec(),
+                    }),
+            )
+        }
+    }
+
+    impl TryFrom<Response> for http::Response<Body> {
+        type Error = Error;
+
+        fn try_from(response: Response) -> Result<Self, Self::Error> {
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&response)?))?)
+        }
+    }
+
+    impl FutureFrom<http::Response<Body>> for Response {
+        type Future = Box<dyn Future<Item = Self, Error = Self::Error> + Send>;
+        type Error = Error;
+
+        fn future_from(response: http::Response<Body>) -> Self::Future {
+            if !response.status().is_success() {
+                return Box::new(future::err(Error::StatusCode(response.status())));
+            }
+
+            Box::new(
+                response
+                    .into_body()
+                    .concat2()
+                    .map_err(Error::from)
+                    .and_then(|body| {
+                        serde_json::from_slice(&body)
+                            .map_err(Error::from)
+                            .into_future()
+                    }),
+            )
+        }
+    }
+}
+
+/// [GET /_matrix/media/r0/config](https://matrix.org/docs/spec/client_server/r0.6.0.html#get-matrix-media-r0-config)
+pub mod get_media_config {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the configuration of the content repository.",
+            method: GET,
+            name: "get_media_config",
+            path: "/_matrix/media/r0/config",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {}
+
+        response {
+            /// The maximum size of an upload in bytes, if the homeserver has a limit.
+            #[serde(rename = "m.upload.size")]
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub upload_size: Option<u64>,
+        }
+    }
+}
+
+/// [GET /_matrix/media/r0/preview_url](https://matrix.org/docs/spec/client_server/r0.6.0.html#get-matrix-media-r0-preview-url)
+pub mod get_url_preview {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::{Map, Value};
+
+    ruma_api! {
+        metadata {
+            description: "Get information about a URL for the client.",
+            method: GET,
+            name: "get_url_preview",
+            path: "/_matrix/media/r0/preview_url",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The URL to get a preview of.
+            #[ruma_api(query)]
+            pub url: String,
+            /// The preferred point in time to return a preview for, in milliseconds since the Unix
+            /// epoch.
+            ///
+            /// The homeserver may return a newer version if it doesn't have the requested one.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub ts: Option<u64>,
+        }
+
+        response {
+            /// The OpenGraph data of the URL, e.g. `og:title` and `og:image`, which is an `mxc://`
+            /// URI.
+            #[serde(flatten)]
+            pub data: Map<String, Value>,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Endpoints for room membership.
+
+use std::collections::HashMap;
+
+use ruma_identifiers::UserId;
+use serde_derive::{Deserialize, Serialize};
+
+/// A signature of an `m.third_party_invite` token to prove that this user owns a third party
+/// identity which has been invited to the room.
+///
+/// The identity server that sent the invite creates it, see `Client::join_with_email_invite`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ThirdPartySigned {
+    /// The Matrix ID of the user who issued the invite.
+    pub sender: UserId,
+    /// The Matrix ID of the invitee.
+    pub mxid: UserId,
+    /// The state key of the `m.third_party_invite` event.
+    pub token: String,
+    /// The signatures of this object, keyed by server name and then by signing key ID.
+    pub signatures: HashMap<String, HashMap<String, String>>,
+}
+
+/// [POST /_matrix/client/r0/join/{roomIdOrAlias}](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-join-roomidoralias)
+///
+/// The definition in `ruma_client_api` uses an untyped `ThirdPartySigned`.
+pub mod join_room_by_id_or_alias {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, RoomIdOrAliasId};
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::ThirdPartySigned;
+
+    ruma_api! {
+        metadata {
+            description: "Join a room using its ID or one of its aliases.",
+            method: POST,
+            name: "join_room_by_id_or_alias",
+            path: "/_matrix/client/r0/join/:room_id_or_alias",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room where the user should be invited.
+            #[ruma_api(path)]
+            pub room_id_or_alias: RoomIdOrAliasId,
+            /// The signature of a `m.third_party_invite` token to prove that this user owns a
+            /// third party identity which has been invited to the room.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub third_party_signed: Option<ThirdPartySigned>,
+        }
+
+        response {
+            /// The room that the user joined.
+            pub room_id: RoomId,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/rooms/{roomId}/join](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-rooms-roomid-join)
+///
+/// The definition in `ruma_client_api` uses an untyped `ThirdPartySigned`.
+pub mod join_room_by_id {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::ThirdPartySigned;
+
+    ruma_api! {
+        metadata {
+            description: "Join a room using its ID.",
+            method: POST,
+            name: "join_room_by_id",
+            path: "/_matrix/client/r0/rooms/:room_id/join",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room where the user should be invited.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The signature of a `m.third_party_invite` token to prove that this user owns a
+            /// third party identity which has been invited to the room.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub third_party_signed: Option<ThirdPartySigned>,
+        }
+
+        response {
+            /// The room that the user joined.
+            pub room_id: RoomId,
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/rooms/{roomId}/joined_members](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-rooms-roomid-joined-members)
+pub mod joined_members {
+    use std::collections::HashMap;
+
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the joined members of a room with their profile.",
+            method: GET,
+            name: "joined_members",
+            path: "/_matrix/client/r0/rooms/:room_id/joined_members",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to get the members of.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+        }
+
+        response {
+            /// The joined members, keyed by user ID.
+            pub joined: HashMap<UserId, RoomMember>,
+        }
+    }
+
+    /// The profile of a joined member of a room.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct RoomMember {
+        /// The display name of the user in the room, if they set one.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub display_name: Option<String>,
+        /// The `mxc://` URL of the avatar of the user in the room, if they set one.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub avatar_url: Option<String>,
+    }
+}
+
+/// [POST /_matrix/client/r0/rooms/{roomId}/invite](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-rooms-roomid-invite)
+///
+/// The definition in `ruma_client_api` lacks the reason.
+pub mod invite_user {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Invite a user to a room.",
+            method: POST,
+            name: "invite_user",
+            path: "/_matrix/client/r0/rooms/:room_id/invite",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room where the user should be invited.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The user to invite.
+            pub user_id: UserId,
+            /// The reason for inviting the user.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reason: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+/// [POST /_matrix/client/r0/rooms/{roomId}/unban](https://matrix.org/docs/spec/client_server/r0.6.0.html#post-matrix-client-r0-rooms-roomid-unban)
+///
+/// The definition in `ruma_client_api` lacks the reason.
+pub mod unban_user {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Unban a user from a room.",
+            method: POST,
+            name: "unban_user",
+            path: "/_matrix/client/r0/rooms/:room_id/unban",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to unban the user from.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The user to unban.
+            pub user_id: UserId,
+            /// The reason for unbanning the user.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reason: Option<String>,
+        }
+
+        response {}
+    }
+}
+
+/// [POST /_matrix/client/r0/knock/{roomIdOrAlias}](https://spec.matrix.org/v1.1/client-server-api/#post_matrixclientv3knockroomidoralias)
+pub mod knock_room {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, RoomIdOrAliasId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Ask to be invited to a room.",
+            method: POST,
+            name: "knock_room",
+            path: "/_matrix/client/r0/knock/:room_id_or_alias",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to knock on.
+            #[ruma_api(path)]
+            pub room_id_or_alias: RoomIdOrAliasId,
+            /// The reason for knocking, shown to the members of the room.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reason: Option<String>,
+        }
+
+        response {
+            /// The room that the user knocked on.
+            pub room_id: RoomId,
+        }
+    }
+}
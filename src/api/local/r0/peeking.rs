@@ -0,0 +1,47 @@
+//! Endpoints for peeking into rooms without joining them.
+
+/// [GET /_matrix/client/r0/events](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-events)
+pub mod get_events {
+    use ruma_api_macros::ruma_api;
+    use ruma_events::collections::all;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Listen on the event stream of a particular room.",
+            method: GET,
+            name: "get_events",
+            path: "/_matrix/client/r0/events",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The token to stream from.
+            ///
+            /// This token is either from a previous request to this API or from the initial sync
+            /// API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub from: Option<String>,
+            /// The maximum time to wait for an event, in milliseconds.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub timeout: Option<u64>,
+            /// The room ID for which events should be returned.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub room_id: Option<RoomId>,
+        }
+
+        response {
+            /// A token which correlates to the first value in `chunk`.
+            pub start: String,
+            /// A token which correlates to the last value in `chunk`.
+            pub end: String,
+            /// The events received, which may be none.
+            pub chunk: Vec<all::RoomEvent>,
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Endpoints for read markers.
+
+/// [POST /_matrix/client/r0/rooms/{roomId}/read_markers](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-rooms-roomid-read-markers)
+pub mod set_read_marker {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Set the position of the read marker for a room, and optionally the read receipt's location.",
+            method: POST,
+            name: "set_read_marker",
+            path: "/_matrix/client/r0/rooms/:room_id/read_markers",
+            rate_limited: true,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room ID to set the read marker in for the user.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The event ID the read marker should be located at.
+            #[serde(rename = "m.fully_read")]
+            pub fully_read: EventId,
+            /// The event ID to set the read receipt location at.
+            #[serde(rename = "m.read")]
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub read_receipt: Option<EventId>,
+        }
+
+        response {}
+    }
+}
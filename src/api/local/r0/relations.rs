@@ -0,0 +1,183 @@
+//! Endpoints for event relations.
+
+/// [GET /_matrix/client/v1/rooms/{roomId}/relations/{eventId}](https://spec.matrix.org/v1.4/client-server-api/#get_matrixclientv1roomsroomidrelationseventid)
+pub mod get_relating_events {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Get the events that relate to an event.",
+            method: GET,
+            name: "get_relating_events",
+            path: "/_matrix/client/v1/rooms/:room_id/relations/:event_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room containing the parent event.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The ID of the parent event whose child events are to be returned.
+            #[ruma_api(path)]
+            pub event_id: EventId,
+            /// The pagination token to start returning results from.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub from: Option<String>,
+            /// The pagination token to stop returning results at.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub to: Option<String>,
+            /// The maximum number of results to return in a single chunk.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub limit: Option<u64>,
+        }
+
+        response {
+            /// The child events, newest first, as JSON.
+            ///
+            /// `ruma_events` drops the relations of the events it parses, which is what callers
+            /// are usually interested in.
+            pub chunk: Vec<Value>,
+            /// The token to fetch the next page of older child events.
+            ///
+            /// `None` if there are no more events.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub next_batch: Option<String>,
+            /// The token to fetch the previous page of newer child events.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub prev_batch: Option<String>,
+        }
+    }
+}
+
+/// [GET /_matrix/client/v1/rooms/{roomId}/relations/{eventId}/{relType}](https://spec.matrix.org/v1.4/client-server-api/#get_matrixclientv1roomsroomidrelationseventidreltype)
+pub mod get_relating_events_with_rel_type {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use crate::message::RelationType;
+
+    ruma_api! {
+        metadata {
+            description: "Get the events that relate to an event with the given relation type.",
+            method: GET,
+            name: "get_relating_events_with_rel_type",
+            path: "/_matrix/client/v1/rooms/:room_id/relations/:event_id/:rel_type",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room containing the parent event.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The ID of the parent event whose child events are to be returned.
+            #[ruma_api(path)]
+            pub event_id: EventId,
+            /// The type of relation of the child events.
+            #[ruma_api(path)]
+            pub rel_type: RelationType,
+            /// The pagination token to start returning results from.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub from: Option<String>,
+            /// The pagination token to stop returning results at.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub to: Option<String>,
+            /// The maximum number of results to return in a single chunk.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub limit: Option<u64>,
+        }
+
+        response {
+            /// The child events, newest first, as JSON.
+            ///
+            /// `ruma_events` drops the relations of the events it parses, which is what callers
+            /// are usually interested in.
+            pub chunk: Vec<Value>,
+            /// The token to fetch the next page of older child events.
+            ///
+            /// `None` if there are no more events.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub next_batch: Option<String>,
+            /// The token to fetch the previous page of newer child events.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub prev_batch: Option<String>,
+        }
+    }
+}
+
+/// [GET /_matrix/client/v1/rooms/{roomId}/relations/{eventId}/{relType}/{eventType}](https://spec.matrix.org/v1.4/client-server-api/#get_matrixclientv1roomsroomidrelationseventidreltypeeventtype)
+pub mod get_relating_events_with_rel_type_and_event_type {
+    use ruma_api_macros::ruma_api;
+    use ruma_events::EventType;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    use crate::message::RelationType;
+
+    ruma_api! {
+        metadata {
+            description: "Get the events of the given type that relate to an event with the given relation type.",
+            method: GET,
+            name: "get_relating_events_with_rel_type_and_event_type",
+            path: "/_matrix/client/v1/rooms/:room_id/relations/:event_id/:rel_type/:event_type",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room containing the parent event.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The ID of the parent event whose child events are to be returned.
+            #[ruma_api(path)]
+            pub event_id: EventId,
+            /// The type of relation of the child events.
+            #[ruma_api(path)]
+            pub rel_type: RelationType,
+            /// The type of the child events.
+            #[ruma_api(path)]
+            pub event_type: EventType,
+            /// The pagination token to start returning results from.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub from: Option<String>,
+            /// The pagination token to stop returning results at.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub to: Option<String>,
+            /// The maximum number of results to return in a single chunk.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub limit: Option<u64>,
+        }
+
+        response {
+            /// The child events, newest first, as JSON.
+            ///
+            /// `ruma_events` drops the relations of the events it parses, which is what callers
+            /// are usually interested in.
+            pub chunk: Vec<Value>,
+            /// The token to fetch the next page of older child events.
+            ///
+            /// `None` if there are no more events.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub next_batch: Option<String>,
+            /// The token to fetch the previous page of newer child events.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub prev_batch: Option<String>,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Endpoints for rooms.
+
+/// [POST /_matrix/client/r0/createRoom](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-createroom)
+///
+/// The definition in `ruma_client_api` lacks `initial_state` and `is_direct`.
+pub mod create_room {
+    use ruma_api_macros::ruma_api;
+    pub use ruma_client_api::r0::room::create_room::{CreationContent, RoomPreset, Visibility};
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Create a new room.",
+            method: POST,
+            name: "create_room",
+            path: "/_matrix/client/r0/createRoom",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// Extra keys to be added to the content of the `m.room.create`.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub creation_content: Option<CreationContent>,
+            /// State events to send to the room after the events of the preset.
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            #[serde(default)]
+            pub initial_state: Vec<InitialStateEvent>,
+            /// A list of user IDs to invite to the room.
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            #[serde(default)]
+            pub invite: Vec<UserId>,
+            /// Whether the room is a direct chat, which sets `is_direct` in the invites.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub is_direct: Option<bool>,
+            /// If this is included, an `m.room.name` event will be sent into the room to indicate
+            /// the name of the room.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub name: Option<String>,
+            /// Convenience parameter for setting various default state events based on a preset.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub preset: Option<RoomPreset>,
+            /// The desired room alias local part.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub room_alias_name: Option<String>,
+            /// If this is included, an `m.room.topic` event will be sent into the room to indicate
+            /// the topic for the room.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub topic: Option<String>,
+            /// Whether the room is shown in the published room list. Rooms default to private
+            /// visibility.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub visibility: Option<Visibility>,
+        }
+
+        response {
+            /// The created room's ID.
+            pub room_id: RoomId,
+        }
+    }
+
+    /// A state event to send to a new room.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct InitialStateEvent {
+        /// The type of the event.
+        #[serde(rename = "type")]
+        pub event_type: String,
+        /// The state key of the event.
+        pub state_key: String,
+        /// The content of the event.
+        pub content: Value,
+    }
+}
+
+/// [GET /_matrix/client/r0/rooms/{roomId}/event/{eventId}](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-rooms-roomid-event-eventid)
+///
+/// The event is returned as JSON, because the event collections of `ruma_events` drop the
+/// content they don't know about, like relations.
+// The generated response body type is only ever deserialized.
+#[allow(dead_code)]
+pub mod get_room_event {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Get a single event based on roomId/eventId",
+            method: GET,
+            name: "get_room_event",
+            path: "/_matrix/client/r0/rooms/:room_id/event/:event_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the room the event is in.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The ID of the event.
+            #[ruma_api(path)]
+            pub event_id: EventId,
+        }
+
+        response {
+            /// The event.
+            #[ruma_api(body)]
+            pub event: Value,
+        }
+    }
+}
+
+/// [POST /_matrix/client/r0/rooms/{roomId}/upgrade](https://matrix.org/docs/spec/client_server/r0.5.0.html#post-matrix-client-r0-rooms-roomid-upgrade)
+pub mod upgrade_room {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Upgrade a room to a new room version.",
+            method: POST,
+            name: "upgrade_room",
+            path: "/_matrix/client/r0/rooms/:room_id/upgrade",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the room to upgrade.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The version of the new room.
+            pub new_version: String,
+        }
+
+        response {
+            /// The ID of the new room.
+            pub replacement_room: RoomId,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Endpoints for sending events.
+
+/// [PUT /_matrix/client/r0/rooms/{roomId}/send/{eventType}/{txnId}](https://matrix.org/docs/spec/client_server/r0.4.0.html#put-matrix-client-r0-rooms-roomid-send-eventtype-txnid)
+pub mod send_raw_message_event {
+    use ruma_api_macros::ruma_api;
+    use ruma_events::EventType;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Send a message event with arbitrary content to a room.",
+            method: PUT,
+            name: "send_raw_message_event",
+            path: "/_matrix/client/r0/rooms/:room_id/send/:event_type/:txn_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to send the event to.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The type of event to send.
+            #[ruma_api(path)]
+            pub event_type: EventType,
+            /// The transaction ID for this event.
+            ///
+            /// Clients should generate an ID unique across requests with the
+            /// same access token; it will be used by the server to ensure
+            /// idempotency of requests.
+            #[ruma_api(path)]
+            pub txn_id: String,
+            /// The event's content.
+            #[ruma_api(body)]
+            pub data: Value,
+        }
+
+        response {
+            /// A unique identifier for the event.
+            pub event_id: EventId,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Endpoints for user session management.
+
+/// [POST /_matrix/client/r0/login](https://matrix.org/docs/spec/client_server/r0.4.0.html#post-matrix-client-r0-login)
+///
+/// The definition in `ruma_client_api` lacks the `initial_device_display_name` field.
+pub mod login {
+    use ruma_api_macros::ruma_api;
+    pub use ruma_client_api::r0::session::login::{LoginType, Medium};
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Login to the homeserver.",
+            method: POST,
+            name: "login",
+            path: "/_matrix/client/r0/login",
+            rate_limited: true,
+            requires_authentication: false,
+        }
+
+        request {
+            /// The user's password.
+            pub password: String,
+            /// When logging in using a third party identifier, the medium of the identifier.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub medium: Option<Medium>,
+            /// The authentication mechanism.
+            #[serde(rename = "type")]
+            pub login_type: LoginType,
+            /// The fully qualified user ID or just local part of the user ID.
+            pub user: String,
+            /// Third party identifier for the user.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub address: Option<String>,
+            /// ID of the client device
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub device_id: Option<String>,
+            /// A display name to assign to the newly-created device.
+            ///
+            /// Ignored if `device_id` corresponds to a known device.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub initial_device_display_name: Option<String>,
+        }
+
+        response {
+            /// An access token for the account.
+            pub access_token: String,
+            /// The hostname of the homeserver on which the account has been registered.
+            pub home_server: String,
+            /// A refresh token may be exchanged for a new access token using the /tokenrefresh API
+            /// endpoint.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub refresh_token: Option<String>,
+            /// The fully-qualified Matrix ID that has been registered.
+            pub user_id: UserId,
+            /// ID of the logged-in device.
+            ///
+            /// Will be the same as the corresponging parameter in the request, if one was
+            /// specified.
+            pub device_id: String,
+        }
+    }
+}
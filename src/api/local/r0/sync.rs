@@ -0,0 +1,250 @@
+//! Endpoints for getting and synchronizing events.
+
+/// [GET /_matrix/client/r0/rooms/{roomId}/messages](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-rooms-roomid-messages)
+///
+/// The definition in `ruma_client_api` sends the pagination parameters in the body and can't
+/// deserialize state events in `chunk`.
+pub mod get_message_events {
+    use ruma_api_macros::ruma_api;
+    pub use ruma_client_api::r0::sync::get_message_events::Direction;
+    use ruma_events::collections::all;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get message events for a room.",
+            method: GET,
+            name: "get_message_events",
+            path: "/_matrix/client/r0/rooms/:room_id/messages",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to get events from.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The token to start returning events from.
+            ///
+            /// This token can be obtained from a prev_batch token returned for each room by the
+            /// sync API, or from a start or end token returned by a previous request to this
+            /// endpoint.
+            #[ruma_api(query)]
+            pub from: String,
+            /// The token to stop returning events at.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub to: Option<String>,
+            /// The direction to return events from.
+            #[ruma_api(query)]
+            pub dir: Direction,
+            /// The maximum number of events to return.
+            ///
+            /// Default: 10.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub limit: Option<u64>,
+        }
+
+        response {
+            /// The token the pagination starts from.
+            pub start: String,
+            /// A list of room events, ordered in the direction of the pagination.
+            pub chunk: Vec<all::RoomEvent>,
+            /// The token the pagination ends at.
+            ///
+            /// `None` if there are no more events to return in this direction.
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            pub end: Option<String>,
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/rooms/{roomId}/members](https://matrix.org/docs/spec/client_server/r0.5.0.html#get-matrix-client-r0-rooms-roomid-members)
+///
+/// The definition in `ruma_client_api` lacks the filters.
+pub mod get_member_events {
+    use ruma_api_macros::ruma_api;
+    use ruma_events::room::member::MemberEvent;
+    pub use ruma_events::room::member::MembershipState;
+    use ruma_identifiers::RoomId;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get membership events for a room.",
+            method: GET,
+            name: "get_member_events",
+            path: "/_matrix/client/r0/rooms/:room_id/members",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to get the member events for.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The point in time to return the members for, as a pagination token, e.g. the
+            /// `prev_batch` token of a sync response's timeline.
+            ///
+            /// Defaults to the current state of the room.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub at: Option<String>,
+            /// Only return members with this membership.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub membership: Option<MembershipState>,
+            /// Don't return members with this membership.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub not_membership: Option<MembershipState>,
+        }
+
+        response {
+            /// A list of member events.
+            pub chunk: Vec<MemberEvent>
+        }
+    }
+}
+
+/// [GET /_matrix/client/r0/sync](https://matrix.org/docs/spec/client_server/r0.6.1.html#get-matrix-client-r0-sync)
+///
+/// The definition in `ruma_client_api` lacks the rooms the user knocked on and the global account
+/// data.
+pub mod sync_events {
+    use std::collections::{BTreeMap, HashMap};
+
+    use ruma_api_macros::ruma_api;
+    pub use ruma_client_api::r0::sync::sync_events::{
+        AccountData, Ephemeral, Filter, InviteState, InvitedRoom, JoinedRoom, LeftRoom, Presence,
+        SetPresence, State, Timeline, UnreadNotificationsCount,
+    };
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Get all new events from all rooms since the last sync or a given point of time.",
+            method: GET,
+            name: "sync",
+            path: "/_matrix/client/r0/sync",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// A filter represented either as its full JSON definition or the ID of a saved filter.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub filter: Option<Filter>,
+            /// A point in time to continue a sync from.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub since: Option<String>,
+            /// Controls whether to include the full state for all rooms the user is a member of.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub full_state: Option<bool>,
+            /// Controls whether the client is automatically marked as online by polling this API.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub set_presence: Option<SetPresence>,
+            /// The maximum time to poll in milliseconds before returning this request.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            #[ruma_api(query)]
+            pub timeout: Option<u64>,
+        }
+
+        response {
+            /// The batch token to supply in the `since` param of the next `/sync` request.
+            pub next_batch: String,
+            /// Updates to rooms.
+            #[serde(default)]
+            pub rooms: Rooms,
+            /// Updates to the presence status of other users.
+            pub presence: Presence,
+            /// The global private data created by this user.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub account_data: Option<AccountData>,
+            /// The users whose devices changed, for end-to-end encryption.
+            #[serde(default)]
+            pub device_lists: DeviceLists,
+            /// Messages sent directly to this device.
+            #[serde(default)]
+            pub to_device: ToDevice,
+            /// How many one-time keys the homeserver has for this device, by algorithm.
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub device_one_time_keys_count: Option<BTreeMap<String, u64>>,
+        }
+    }
+
+    /// Messages sent directly to this device.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct ToDevice {
+        /// The to-device events.
+        #[serde(default)]
+        pub events: Vec<ToDeviceEvent>,
+    }
+
+    /// An event sent directly to this device, e.g. an `m.room_key` event.
+    ///
+    /// `ruma_events` has no types for to-device events, so the content is JSON.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ToDeviceEvent {
+        /// The user who sent the event.
+        pub sender: UserId,
+        /// The type of the event.
+        #[serde(rename = "type")]
+        pub event_type: String,
+        /// The content of the event.
+        pub content: Value,
+    }
+
+    /// The users whose devices changed since the last sync.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct DeviceLists {
+        /// The users whose devices changed, who share an encrypted room with the user.
+        #[serde(default)]
+        pub changed: Vec<UserId>,
+        /// The users who no longer share an encrypted room with the user.
+        #[serde(default)]
+        pub left: Vec<UserId>,
+    }
+
+    /// Updates to rooms.
+    #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+    pub struct Rooms {
+        /// The rooms that the user has left or been banned from.
+        #[serde(default)]
+        pub leave: HashMap<RoomId, LeftRoom>,
+        /// The rooms that the user has joined.
+        #[serde(default)]
+        pub join: HashMap<RoomId, JoinedRoom>,
+        /// The rooms that the user has been invited to.
+        #[serde(default)]
+        pub invite: HashMap<RoomId, InvitedRoom>,
+        /// The rooms that the user has knocked on.
+        #[serde(default)]
+        pub knock: HashMap<RoomId, KnockedRoom>,
+    }
+
+    /// Updates to a room that the user has knocked on.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct KnockedRoom {
+        /// The state of the room at the time of the knock.
+        pub knock_state: KnockState,
+    }
+
+    /// The state of a room that the user has knocked on.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct KnockState {
+        /// Stripped state events, i.e. only their `type`, `state_key`, `sender` and `content`.
+        ///
+        /// The events are JSON, because `ruma_events` can't deserialize stripped state events of
+        /// types it doesn't know, like `m.room.encryption`.
+        pub events: Vec<Value>,
+    }
+}
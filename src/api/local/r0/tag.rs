@@ -0,0 +1,122 @@
+//! Endpoints for tagging rooms.
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Information about a tag.
+///
+/// `ruma_events::tag::TagInfo` expects the order to be a string, but it is a number.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct TagInfo {
+    /// The position of the room among the rooms with this tag, between 0 and 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<f64>,
+}
+
+/// [PUT /_matrix/client/r0/user/{userId}/rooms/{roomId}/tags/{tag}](https://matrix.org/docs/spec/client_server/r0.4.0.html#put-matrix-client-r0-user-userid-rooms-roomid-tags-tag)
+///
+/// The definition in `ruma_client_api` uses `ruma_events::tag::TagInfo`.
+pub mod create_tag {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::TagInfo;
+
+    ruma_api! {
+        metadata {
+            description: "Add a new tag to a room.",
+            method: PUT,
+            name: "create_tag",
+            path: "/_matrix/client/r0/user/:user_id/rooms/:room_id/tags/:tag",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID of the user creating the tag.
+            #[ruma_api(path)]
+            pub user_id: UserId,
+            /// The room to tag.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The name of the tag to create.
+            #[ruma_api(path)]
+            pub tag: String,
+            /// Info about the tag.
+            #[ruma_api(body)]
+            pub tag_info: TagInfo,
+        }
+
+        response {}
+    }
+}
+
+/// [GET /_matrix/client/r0/user/{userId}/rooms/{roomId}/tags](https://matrix.org/docs/spec/client_server/r0.4.0.html#get-matrix-client-r0-user-userid-rooms-roomid-tags)
+///
+/// The definition in `ruma_client_api` expects the tags to be nested twice.
+pub mod get_tags {
+    use std::collections::HashMap;
+
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::TagInfo;
+
+    ruma_api! {
+        metadata {
+            description: "Get the tags associated with a room.",
+            method: GET,
+            name: "get_tags",
+            path: "/_matrix/client/r0/user/:user_id/rooms/:room_id/tags",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The user whose tags will be retrieved.
+            #[ruma_api(path)]
+            pub user_id: UserId,
+            /// The room from which tags will be retrieved.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+        }
+
+        response {
+            /// The user's tags for the room.
+            pub tags: HashMap<String, TagInfo>,
+        }
+    }
+}
+
+/// [DELETE /_matrix/client/r0/user/{userId}/rooms/{roomId}/tags/{tag}](https://matrix.org/docs/spec/client_server/r0.4.0.html#delete-matrix-client-r0-user-userid-rooms-roomid-tags-tag)
+pub mod delete_tag {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Remove a tag from a room.",
+            method: DELETE,
+            name: "delete_tag",
+            path: "/_matrix/client/r0/user/:user_id/rooms/:room_id/tags/:tag",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The user whose tag will be deleted.
+            #[ruma_api(path)]
+            pub user_id: UserId,
+            /// The tagged room.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The name of the tag to delete.
+            #[ruma_api(path)]
+            pub tag: String,
+        }
+
+        response {}
+    }
+}
@@ -0,0 +1,36 @@
+//! Endpoints for sending events directly to devices.
+
+/// [PUT /_matrix/client/r0/sendToDevice/{eventType}/{txnId}](https://matrix.org/docs/spec/client_server/r0.6.0.html#put-matrix-client-r0-sendtodevice-eventtype-txnid)
+pub mod send_event_to_device {
+    use std::collections::{BTreeMap, HashMap};
+
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::UserId;
+    use serde_derive::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    ruma_api! {
+        metadata {
+            description: "Send an event to devices.",
+            method: PUT,
+            name: "send_event_to_device",
+            path: "/_matrix/client/r0/sendToDevice/:event_type/:txn_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The type of the event to send.
+            #[ruma_api(path)]
+            pub event_type: String,
+            /// The transaction ID for this event.
+            #[ruma_api(path)]
+            pub txn_id: String,
+            /// The content of the event for each device, by user and device ID. The device ID
+            /// `*` stands for all devices of the user.
+            pub messages: HashMap<UserId, BTreeMap<String, Value>>,
+        }
+
+        response {}
+    }
+}
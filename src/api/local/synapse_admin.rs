@@ -0,0 +1,78 @@
+//! Administration endpoints specific to the Synapse homeserver.
+
+/// [POST /_synapse/admin/v1/purge_history/{roomId}](https://github.com/matrix-org/synapse/blob/master/docs/admin_api/purge_history_api.rst)
+pub mod purge_history {
+    use ruma_api_macros::ruma_api;
+    use ruma_identifiers::{EventId, RoomId};
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Purge historic events from a room, starting with the oldest.",
+            method: POST,
+            name: "purge_history",
+            path: "/_synapse/admin/v1/purge_history/:room_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The room to purge history from.
+            #[ruma_api(path)]
+            pub room_id: RoomId,
+            /// The most recent event to purge. Everything older than it is purged as well.
+            pub purge_up_to_event_id: EventId,
+            /// Whether events sent by users of this homeserver should be purged too.
+            ///
+            /// If false, only events sent by remote users are purged.
+            pub delete_local_events: bool,
+        }
+
+        response {
+            /// An opaque ID that can be used to query the status of the purge.
+            pub purge_id: String,
+        }
+    }
+}
+
+/// [GET /_synapse/admin/v1/purge_history_status/{purgeId}](https://github.com/matrix-org/synapse/blob/master/docs/admin_api/purge_history_api.rst)
+pub mod purge_history_status {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get the status of a room history purge.",
+            method: GET,
+            name: "purge_history_status",
+            path: "/_synapse/admin/v1/purge_history_status/:purge_id",
+            rate_limited: false,
+            requires_authentication: true,
+        }
+
+        request {
+            /// The ID returned when the purge was started.
+            #[ruma_api(path)]
+            pub purge_id: String,
+        }
+
+        response {
+            /// The current status of the purge.
+            pub status: PurgeStatus,
+        }
+    }
+
+    /// The status of a room history purge.
+    #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    pub enum PurgeStatus {
+        /// The purge is still running.
+        #[serde(rename = "active")]
+        Active,
+        /// The purge finished successfully.
+        #[serde(rename = "complete")]
+        Complete,
+        /// The purge failed.
+        #[serde(rename = "failed")]
+        Failed,
+    }
+}
@@ -0,0 +1,33 @@
+//! Endpoints that cannot change with new versions of the Matrix specification.
+
+/// [GET /.well-known/matrix/client](https://matrix.org/docs/spec/client_server/r0.5.0.html#get-well-known-matrix-client)
+pub mod discover_homeserver {
+    use ruma_api_macros::ruma_api;
+    use serde_derive::{Deserialize, Serialize};
+
+    ruma_api! {
+        metadata {
+            description: "Get discovery information about the domain.",
+            method: GET,
+            name: "discover_homeserver",
+            path: "/.well-known/matrix/client",
+            rate_limited: false,
+            requires_authentication: false,
+        }
+
+        request {}
+
+        response {
+            /// Information about the homeserver to connect to.
+            #[serde(rename = "m.homeserver")]
+            pub homeserver: HomeserverInfo,
+        }
+    }
+
+    /// Information about the homeserver to connect to.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct HomeserverInfo {
+        /// The base URL for the homeserver for client-server connections.
+        pub base_url: String,
+    }
+}
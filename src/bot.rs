@@ -0,0 +1,173 @@
+//! Per-room configuration of bots.
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_events::EventType;
+use ruma_identifiers::UserId;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tokio_sync::watch;
+
+use crate::{error::not_found_as_none, Error, Room};
+
+/// The type of the state events holding the configuration of bots.
+pub const BOT_OPTIONS_EVENT_TYPE: &str = "m.room.bot.options";
+
+/// Where a bot keeps its configuration for a room.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BotConfigLocation {
+    /// An `m.room.bot.options` state event with the state key `_{bot's user ID}`.
+    ///
+    /// Everybody in the room can read it, and room moderators can change it.
+    State,
+    /// The bot's account data for the room, with the given event type.
+    ///
+    /// Only the bot can read and change it.
+    AccountData(String),
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the configuration of the logged-in bot for this room.
+    ///
+    /// Resolves to `None` if there is no configuration yet.
+    pub fn bot_config<T>(
+        &self,
+        location: &BotConfigLocation,
+    ) -> impl Future<Item = Option<T>, Error = Error>
+    where
+        T: DeserializeOwned,
+    {
+        use crate::api::r0::{config::get_room_account_data, sync::get_state_events_for_key};
+
+        let user_id = match self.client().logged_in_user_id() {
+            Ok(user_id) => user_id,
+            Err(error) => return Either::A(future::err(error)),
+        };
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+
+        let content: Box<dyn Future<Item = Value, Error = Error>> = match location {
+            BotConfigLocation::State => Box::new(
+                get_state_events_for_key::call(
+                    client,
+                    get_state_events_for_key::Request {
+                        room_id,
+                        event_type: EventType::Custom(BOT_OPTIONS_EVENT_TYPE.to_owned()),
+                        state_key: bot_state_key(&user_id),
+                    },
+                )
+                .map(|response| response.content),
+            ),
+            BotConfigLocation::AccountData(event_type) => Box::new(
+                get_room_account_data::call(
+                    client,
+                    get_room_account_data::Request {
+                        user_id,
+                        room_id,
+                        event_type: event_type.clone(),
+                    },
+                )
+                .map(|response| response.data),
+            ),
+        };
+
+        Either::B(content.then(|result| match not_found_as_none(result)? {
+            Some(content) => Ok(Some(serde_json::from_value(content)?)),
+            None => Ok(None),
+        }))
+    }
+
+    /// Change the configuration of the logged-in bot for this room.
+    pub fn set_bot_config<T>(
+        &self,
+        location: &BotConfigLocation,
+        config: &T,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        T: Serialize,
+    {
+        use crate::api::r0::{config::set_room_account_data, send::send_state_event_for_key};
+
+        let (user_id, data) = match self
+            .client()
+            .logged_in_user_id()
+            .and_then(|user_id| Ok((user_id, serde_json::to_value(config)?)))
+        {
+            Ok(request_data) => request_data,
+            Err(error) => return Either::A(future::err(error)),
+        };
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+
+        Either::B(match location {
+            BotConfigLocation::State => Either::A(
+                send_state_event_for_key::call(
+                    client,
+                    send_state_event_for_key::Request {
+                        room_id,
+                        event_type: EventType::Custom(BOT_OPTIONS_EVENT_TYPE.to_owned()),
+                        state_key: bot_state_key(&user_id),
+                        data,
+                    },
+                )
+                .map(|_| ()),
+            ),
+            BotConfigLocation::AccountData(event_type) => Either::B(
+                set_room_account_data::call(
+                    client,
+                    set_room_account_data::Request {
+                        data,
+                        event_type: event_type.clone(),
+                        room_id,
+                        user_id,
+                    },
+                )
+                .map(|_| ()),
+            ),
+        })
+    }
+
+    /// Watch the configuration of the logged-in bot for this room, as received by
+    /// `Client::sync`.
+    ///
+    /// The receiver yields the current configuration first, then every new one. Configurations
+    /// that can't be deserialized as `T` are yielded as `None`.
+    pub fn watch_bot_config<T>(
+        &self,
+        location: &BotConfigLocation,
+    ) -> Result<watch::Receiver<Option<T>>, Error>
+    where
+        T: DeserializeOwned + Clone + PartialEq + 'static,
+    {
+        let user_id = self.client().logged_in_user_id()?;
+        let store = self.client().store();
+
+        Ok(match location.clone() {
+            BotConfigLocation::State => {
+                let state_key = bot_state_key(&user_id);
+
+                store.watch(self.room_id(), move |room| {
+                    room.state_content(
+                        EventType::Custom(BOT_OPTIONS_EVENT_TYPE.to_owned()),
+                        &state_key,
+                    )
+                    .and_then(|content| serde_json::from_value(content).ok())
+                })
+            }
+            BotConfigLocation::AccountData(event_type) => {
+                store.watch(self.room_id(), move |room| {
+                    room.account_data(&event_type)
+                        .and_then(|content| serde_json::from_value(content.clone()).ok())
+                })
+            }
+        })
+    }
+}
+
+/// The state key of the `m.room.bot.options` event of a bot.
+fn bot_state_key(user_id: &UserId) -> String {
+    format!("_{}", user_id)
+}
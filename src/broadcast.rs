@@ -0,0 +1,220 @@
+//! Sending the same message to many rooms.
+
+use std::{cell::RefCell, rc::Rc};
+
+use futures::{stream, Future, Stream};
+use hyper::client::connect::Connect;
+use ruma_events::{room::message::MessageEventContent, EventType};
+use ruma_identifiers::{EventId, RoomId};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Client, Error};
+
+/// A message being sent to many rooms by `Client::broadcast`.
+///
+/// The `remaining` broadcast of the latest `BroadcastProgress` can be saved and handed to
+/// `Client::resume_broadcast` to continue a broadcast that was interrupted, e.g. by a restart.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Broadcast {
+    /// The ID of the broadcast, which the transaction IDs of its messages are derived from.
+    ///
+    /// Thanks to these transaction IDs, a message that was sent right before the broadcast was
+    /// interrupted isn't sent to the same room again when resuming, as long as the same session
+    /// is used.
+    pub id: String,
+    /// The content of the message.
+    pub content: MessageEventContent,
+    /// The rooms the message still has to be sent to, in order.
+    pub pending: Vec<RoomId>,
+    /// The rooms the message couldn't be sent to.
+    pub failed: Vec<RoomId>,
+}
+
+/// The outcome of sending a broadcast message to one room.
+#[derive(Debug)]
+pub struct BroadcastProgress {
+    /// The room the message was sent to.
+    pub room_id: RoomId,
+    /// The ID of the sent event, or the error that prevented sending it.
+    pub result: Result<EventId, Error>,
+    state: Rc<BroadcastState>,
+    /// How many of the rooms of `state` were done after this room.
+    done: usize,
+    /// How many of the failed rooms of `state` had failed after this room.
+    failed: usize,
+}
+
+impl BroadcastProgress {
+    /// The part of the broadcast that is left after this room.
+    ///
+    /// This copies the pending rooms, so it's meant for saving the state of the broadcast from
+    /// time to time rather than for every room.
+    pub fn remaining(&self) -> Broadcast {
+        Broadcast {
+            id: self.state.id.clone(),
+            content: self.state.content.clone(),
+            pending: self.state.rooms[self.done..].to_vec(),
+            failed: self.state.failed.borrow()[..self.failed].to_vec(),
+        }
+    }
+}
+
+/// The state of a broadcast shared by its `BroadcastProgress`es.
+///
+/// Rooms are sent to in order and failed rooms are only ever appended, so each progress only
+/// has to remember how far the broadcast got.
+#[derive(Debug)]
+struct BroadcastState {
+    id: String,
+    content: MessageEventContent,
+    rooms: Vec<RoomId>,
+    failed: RefCell<Vec<RoomId>>,
+}
+
+impl Broadcast {
+    /// Send the message again to the rooms it couldn't be sent to, after the pending rooms.
+    pub fn retry_failed(&mut self) {
+        self.pending.append(&mut self.failed);
+    }
+}
+
+impl BroadcastState {
+    /// The transaction ID of the message sent to `room_id`.
+    fn txn_id(&self, room_id: &RoomId) -> String {
+        format!("{}.{}", self.id, room_id)
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Send the same message to each of `rooms`, one room after the other.
+    ///
+    /// The messages are spaced out by the client's `RateLimiter`, so the returned stream has to be
+    /// run on a runtime providing a `tokio_timer` timer. Messages the homeserver rate-limits
+    /// anyway are retried after the delay it asks for. The stream yields the outcome for each
+    /// room. A failure to send to one room doesn't stop the broadcast; the room is added to the
+    /// `failed` rooms of the remaining broadcast instead.
+    pub fn broadcast(
+        &self,
+        rooms: Vec<RoomId>,
+        content: MessageEventContent,
+    ) -> impl Stream<Item = BroadcastProgress, Error = Error> {
+        self.resume_broadcast(Broadcast {
+            id: self.next_txn_id(),
+            content,
+            pending: rooms,
+            failed: Vec::new(),
+        })
+    }
+
+    /// Continue sending a broadcast to its pending rooms.
+    ///
+    /// See `broadcast` for details.
+    pub fn resume_broadcast(
+        &self,
+        broadcast: Broadcast,
+    ) -> impl Stream<Item = BroadcastProgress, Error = Error> {
+        use crate::api::r0::send::send_message_event;
+
+        let client = self.clone();
+        let Broadcast {
+            id,
+            content,
+            pending,
+            failed,
+        } = broadcast;
+        let state = Rc::new(BroadcastState {
+            id,
+            content,
+            rooms: pending,
+            failed: RefCell::new(failed),
+        });
+
+        stream::iter_ok(0..state.rooms.len()).and_then(move |index| {
+            let state = state.clone();
+            let room_id = state.rooms[index].clone();
+            let request = send_message_event::Request {
+                room_id: room_id.clone(),
+                event_type: EventType::RoomMessage,
+                txn_id: state.txn_id(&room_id),
+                data: state.content.clone(),
+            };
+            let sending_client = client.clone();
+
+            client
+                .rate_limited(move || {
+                    send_message_event::call(sending_client.clone(), request.clone())
+                })
+                .map(|response| response.event_id)
+                .then(move |result| {
+                    if result.is_err() {
+                        state.failed.borrow_mut().push(room_id.clone());
+                    }
+
+                    let failed = state.failed.borrow().len();
+
+                    Ok(BroadcastProgress {
+                        room_id,
+                        result,
+                        state,
+                        done: index + 1,
+                        failed,
+                    })
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+    use ruma_events::room::message::{MessageEventContent, MessageType, TextMessageEventContent};
+    use ruma_identifiers::RoomId;
+
+    use super::{BroadcastProgress, BroadcastState};
+    use crate::Error;
+
+    fn room(name: &str) -> RoomId {
+        RoomId::try_from(&format!("!{}:example.org", name)[..]).unwrap()
+    }
+
+    fn progress(state: &Rc<BroadcastState>, done: usize) -> BroadcastProgress {
+        BroadcastProgress {
+            room_id: state.rooms[done - 1].clone(),
+            result: Err(Error::DeadlineExceeded),
+            state: state.clone(),
+            done,
+            failed: state.failed.borrow().len(),
+        }
+    }
+
+    #[test]
+    fn remaining_broadcasts_are_snapshots() {
+        let state = Rc::new(BroadcastState {
+            id: "broadcast".to_owned(),
+            content: MessageEventContent::Text(TextMessageEventContent {
+                body: "hello".to_owned(),
+                msgtype: MessageType::Text,
+            }),
+            rooms: vec![room("a"), room("b"), room("c")],
+            failed: RefCell::new(vec![room("old")]),
+        });
+
+        state.failed.borrow_mut().push(room("a"));
+        let first = progress(&state, 1);
+        state.failed.borrow_mut().push(room("b"));
+        let second = progress(&state, 2);
+
+        let remaining = first.remaining();
+        assert_eq!(remaining.pending, vec![room("b"), room("c")]);
+        assert_eq!(remaining.failed, vec![room("old"), room("a")]);
+
+        let remaining = second.remaining();
+        assert_eq!(remaining.pending, vec![room("c")]);
+        assert_eq!(remaining.failed, vec![room("old"), room("a"), room("b")]);
+        assert_eq!(remaining.id, "broadcast");
+    }
+}
@@ -0,0 +1,205 @@
+//! Configuration of new clients.
+
+use std::rc::Rc;
+
+use hyper::{
+    client::{connect::Connect, HttpConnector},
+    Client as HyperClient,
+};
+#[cfg(feature = "hyper-tls")]
+use hyper_tls::HttpsConnector;
+#[cfg(feature = "hyper-tls")]
+use native_tls::Error as NativeTlsError;
+use rand::{rngs::StdRng, FromEntropy};
+use url::Url;
+
+use crate::{
+    environment::{Clock, Rng, SystemClock},
+    Client, ClientData, Session,
+};
+
+/// A builder for clients with more options than `Client::new`, `Client::https` and
+/// `Client::custom`.
+#[derive(Debug)]
+pub struct ClientBuilder {
+    pub(crate) homeserver_url: Url,
+    pub(crate) media_url: Option<Url>,
+    pub(crate) session: Option<Session>,
+    pub(crate) device_id: Option<String>,
+    pub(crate) persistent_device_id: bool,
+    pub(crate) lightweight: bool,
+    pub(crate) max_response_size: Option<u64>,
+    pub(crate) clock: Rc<dyn Clock>,
+    pub(crate) rng: Box<dyn Rng>,
+}
+
+impl ClientBuilder {
+    /// Creates a builder for a client of the given homeserver.
+    pub fn new(homeserver_url: Url) -> Self {
+        ClientBuilder {
+            homeserver_url,
+            media_url: None,
+            session: None,
+            device_id: None,
+            persistent_device_id: false,
+            lightweight: false,
+            max_response_size: None,
+            clock: Rc::new(SystemClock),
+            rng: Box::new(StdRng::from_entropy()),
+        }
+    }
+
+    /// Download files and thumbnails from the media repository at the given URL instead of the
+    /// homeserver URL.
+    ///
+    /// This is for deployments serving media from another host, e.g. a CDN. The access token is
+    /// never sent there, and all other requests, including uploads, still go to the homeserver
+    /// URL. Both hosts share the same `hyper::Client`, so its connection pool and TLS
+    /// configuration are used for both. A path of the URL is put in front of the paths of the
+    /// media endpoints.
+    pub fn media_url(mut self, media_url: Url) -> Self {
+        self.media_url = Some(media_url);
+        self
+    }
+
+    /// Use the given session, e.g. one saved from an earlier login.
+    ///
+    /// Implies `persistent_device_id(true)`: logins that don't specify a device ID, e.g. after
+    /// the homeserver stopped accepting the session's access token, continue the session's
+    /// device instead of creating a new one.
+    pub fn session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self.persistent_device_id = true;
+        self
+    }
+
+    /// Use the given session, if any, like `session`.
+    pub(crate) fn optional_session(self, session: Option<Session>) -> Self {
+        match session {
+            Some(session) => self.session(session),
+            None => ClientBuilder {
+                session: None,
+                ..self
+            },
+        }
+    }
+
+    /// Reuse the given device ID, e.g. one saved from `Client::device_id`, for logins that don't
+    /// specify one.
+    ///
+    /// Implies `persistent_device_id(true)`.
+    pub fn device_id(mut self, device_id: String) -> Self {
+        self.device_id = Some(device_id);
+        self.persistent_device_id = true;
+        self
+    }
+
+    /// Whether logins that don't specify a device ID use the same device every time.
+    ///
+    /// Without this, the homeserver creates a new device for each such login. With it, the client
+    /// generates a device ID on the first login unless one is known from the session or from
+    /// `device_id`, and reuses it for later logins.
+    pub fn persistent_device_id(mut self, persistent: bool) -> Self {
+        self.persistent_device_id = persistent;
+        self
+    }
+
+    /// Whether the client runs in lightweight mode, for bots that only send messages.
+    ///
+    /// A lightweight client never syncs: `Client::sync` and the methods built on it fail with
+    /// `Error::SyncDisabled`, so the `Store` stays empty and memory use doesn't grow with the
+    /// number or size of the user's rooms. Use `Client::whoami` to check a restored
+    /// session, and `Client::send_to` to send to rooms by ID or alias.
+    ///
+    /// Responses are read into memory up to `LIGHTWEIGHT_MAX_RESPONSE_SIZE` bytes unless
+    /// `max_response_size` sets another limit.
+    pub fn lightweight(mut self, lightweight: bool) -> Self {
+        self.lightweight = lightweight;
+        self
+    }
+
+    /// Fail requests whose responses are larger than `max_size` bytes with
+    /// `Error::ResponseTooLarge`, instead of reading them into memory.
+    ///
+    /// This bounds the memory a single response takes, including files downloaded with
+    /// `Client::download`. `Client::download_stream` hands out the body without reading it, so
+    /// it isn't limited.
+    pub fn max_response_size(mut self, max_size: u64) -> Self {
+        self.max_response_size = Some(max_size);
+        self
+    }
+
+    /// Use the given clock instead of `SystemClock`.
+    pub fn clock<T: Clock + 'static>(mut self, clock: T) -> Self {
+        self.clock = Rc::new(clock);
+        self
+    }
+
+    /// Use the given source of randomness instead of one seeded from the operating system.
+    pub fn rng<R: Rng + 'static>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Creates a client for making HTTP requests.
+    pub fn build(self) -> Client<HttpConnector> {
+        self.build_custom(HyperClient::builder().keep_alive(true).build_http())
+    }
+
+    /// Creates a client for making HTTPS requests.
+    #[cfg(feature = "tls")]
+    pub fn build_https(self) -> Result<Client<HttpsConnector<HttpConnector>>, NativeTlsError> {
+        let connector = HttpsConnector::new(4)?;
+
+        Ok(self.build_custom(HyperClient::builder().keep_alive(true).build(connector)))
+    }
+
+    /// Creates a client using the given `hyper::Client`.
+    pub fn build_custom<C: Connect>(self, hyper_client: HyperClient<C>) -> Client<C> {
+        Client(Rc::new(ClientData::new(self, hyper_client)), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use ruma_identifiers::UserId;
+    use url::Url;
+
+    use super::ClientBuilder;
+    use crate::{environment::TestClock, Session};
+
+    fn client(clock: &TestClock) -> crate::Client<hyper::client::HttpConnector> {
+        ClientBuilder::new(Url::parse("https://example.org").unwrap())
+            .clock(clock.clone())
+            .rng(StdRng::from_seed([7; 32]))
+            .build()
+    }
+
+    #[test]
+    fn restored_sessions_keep_their_device() {
+        let session = Session::new(
+            "token".to_owned(),
+            UserId::try_from("@alice:example.org").unwrap(),
+            "DEVICE".to_owned(),
+        );
+        let restored = ClientBuilder::new(Url::parse("https://example.org").unwrap())
+            .session(session)
+            .build();
+
+        assert_eq!(restored.device_id().as_deref(), Some("DEVICE"));
+    }
+
+    #[test]
+    fn clock_and_rng_make_transaction_ids_reproducible() {
+        let clock = TestClock::new();
+        let first = client(&clock);
+        let second = client(&clock);
+
+        assert_eq!(first.next_txn_id(), second.next_txn_id());
+        assert_eq!(first.next_txn_id(), second.next_txn_id());
+        assert_ne!(first.next_txn_id(), first.next_txn_id());
+    }
+}
@@ -0,0 +1,189 @@
+//! Checking whether the homeserver and a room support a feature.
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_events::EventType;
+use ruma_identifiers::RoomId;
+use serde_derive::Deserialize;
+use serde_json::json;
+
+use crate::{
+    api::r0::capabilities::get_capabilities::{self, Capabilities},
+    error::not_found_as_none,
+    Client, Error, Room,
+};
+
+/// The version of rooms whose `m.room.create` event doesn't specify one.
+const DEFAULT_ROOM_VERSION: &str = "1";
+
+/// A feature that is only available in newer room versions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RoomFeature {
+    /// Asking to be invited to a room with the `knock` join rule.
+    Knocking,
+    /// The `restricted` join rule, which lets members of other rooms join.
+    RestrictedJoinRule,
+}
+
+/// The field of the content of an `m.room.create` event that `ruma_events` doesn't know about.
+#[derive(Deserialize)]
+struct CreateContent {
+    room_version: Option<String>,
+}
+
+impl RoomFeature {
+    /// The first room version that supports this feature.
+    pub fn required_room_version(self) -> &'static str {
+        match self {
+            RoomFeature::Knocking => "7",
+            RoomFeature::RestrictedJoinRule => "8",
+        }
+    }
+
+    /// Whether the given room version supports this feature.
+    ///
+    /// Experimental room versions, whose names aren't numbers, are assumed not to support it.
+    pub fn is_supported_by(self, room_version: &str) -> bool {
+        let required: u64 = self.required_room_version().parse().unwrap();
+
+        room_version
+            .parse::<u64>()
+            .map(|version| version >= required)
+            .unwrap_or(false)
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the capabilities of the homeserver.
+    ///
+    /// Homeservers that don't implement the endpoint answer with 404 Not Found, which resolves to
+    /// the defaults of the specification: no capabilities reported.
+    pub fn capabilities(&self) -> impl Future<Item = Capabilities, Error = Error> {
+        get_capabilities::call(self.clone(), get_capabilities::Request {}).then(|result| {
+            not_found_as_none(result)
+                .map(|response| response.map(|r| r.capabilities).unwrap_or_default())
+        })
+    }
+
+    /// Check that the homeserver supports a room version with the given feature.
+    ///
+    /// Fails with `Error::UnsupportedByServer` if it doesn't. Homeservers that don't report the
+    /// room versions they support are assumed to support the feature.
+    pub fn ensure_server_supports(
+        &self,
+        feature: RoomFeature,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.capabilities().and_then(move |capabilities| {
+            if server_supports(&capabilities, feature) {
+                Ok(())
+            } else {
+                Err(Error::UnsupportedByServer {
+                    required_version: feature.required_room_version().to_owned(),
+                })
+            }
+        })
+    }
+}
+
+/// Whether a homeserver with the given capabilities supports a room version with `feature`.
+fn server_supports(capabilities: &Capabilities, feature: RoomFeature) -> bool {
+    match capabilities.room_versions {
+        Some(ref room_versions) => room_versions
+            .available
+            .keys()
+            .any(|version| feature.is_supported_by(version)),
+        None => true,
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the version of this room from its `m.room.create` event.
+    pub fn room_version(&self) -> impl Future<Item = String, Error = Error> {
+        self.state_event(EventType::RoomCreate, "")
+            .map(|content: Option<CreateContent>| {
+                content
+                    .and_then(|content| content.room_version)
+                    .unwrap_or_else(|| DEFAULT_ROOM_VERSION.to_owned())
+            })
+    }
+
+    /// Check that this room and the homeserver support the given feature.
+    ///
+    /// Fails with `Error::UnsupportedByRoomVersion` or `Error::UnsupportedByServer` if they
+    /// don't.
+    pub fn ensure_supports(&self, feature: RoomFeature) -> impl Future<Item = (), Error = Error> {
+        let client = self.client().clone();
+
+        self.room_version().and_then(move |room_version| {
+            if feature.is_supported_by(&room_version) {
+                Either::A(client.ensure_server_supports(feature))
+            } else {
+                Either::B(future::err(Error::UnsupportedByRoomVersion {
+                    room_version,
+                    required_version: feature.required_room_version().to_owned(),
+                }))
+            }
+        })
+    }
+
+    /// Let the members of any of the rooms `allowed_rooms` join this room without an invite.
+    ///
+    /// Fails with `Error::UnsupportedByRoomVersion` or `Error::UnsupportedByServer` before
+    /// changing anything if the room's version doesn't support the `restricted` join rule.
+    pub fn set_restricted_join_rule(
+        &self,
+        allowed_rooms: Vec<RoomId>,
+    ) -> impl Future<Item = (), Error = Error> {
+        let room = self.clone();
+        let content = json!({
+            "join_rule": "restricted",
+            "allow": allowed_rooms
+                .iter()
+                .map(|room_id| json!({ "type": "m.room_membership", "room_id": room_id }))
+                .collect::<Vec<_>>(),
+        });
+
+        self.ensure_supports(RoomFeature::RestrictedJoinRule)
+            .and_then(move |()| room.send_state_event(EventType::RoomJoinRules, "", &content))
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{server_supports, RoomFeature};
+    use crate::api::r0::capabilities::get_capabilities::Capabilities;
+
+    #[test]
+    fn reported_room_versions_decide_support() {
+        let capabilities: Capabilities = serde_json::from_value(json!({
+            "m.room_versions": {
+                "default": "6",
+                "available": { "6": "stable", "7": "stable" },
+            },
+        }))
+        .unwrap();
+
+        assert!(server_supports(&capabilities, RoomFeature::Knocking));
+        assert!(!server_supports(
+            &capabilities,
+            RoomFeature::RestrictedJoinRule
+        ));
+    }
+
+    #[test]
+    fn default_capabilities_support_everything() {
+        assert!(server_supports(
+            &Capabilities::default(),
+            RoomFeature::RestrictedJoinRule
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+//! Adapting requests and responses to the version of the specification the homeserver speaks.
+//!
+//! The endpoint definitions follow a single version of each endpoint. A few endpoints changed the
+//! shape of their JSON in ways that break either old or new homeservers, so the bodies of their
+//! requests and responses are rewritten on the way, depending on the versions the homeserver
+//! supports. They are asked for before the first request to such an endpoint, or with
+//! `Client::negotiate_spec_versions`.
+
+use futures::{
+    future::{self, Either, Future},
+    Stream,
+};
+use http::{header::CONTENT_LENGTH, Method};
+use hyper::{client::connect::Connect, Body};
+use serde_json::{json, Map, Value};
+
+use crate::{api::unversioned::get_supported_versions, Client, Error};
+
+/// A change of the JSON body of an endpoint between versions of the specification.
+struct Shim {
+    method: Method,
+    /// The path of the endpoint, as in its metadata.
+    path: &'static str,
+    /// Whether the shim applies to a homeserver supporting the given versions.
+    applies: fn(&[String]) -> bool,
+    /// Rewrites the body of requests.
+    request: Option<fn(&mut Map<String, Value>)>,
+    /// Rewrites the body of successful responses.
+    response: Option<fn(&mut Map<String, Value>)>,
+}
+
+/// All shims, in the order they are applied.
+fn shims() -> Vec<Shim> {
+    vec![
+        // r0.4.0 replaced `user`, `medium` and `address` with `identifier`, and newer homeservers
+        // only accept the latter.
+        Shim {
+            method: Method::POST,
+            path: "/_matrix/client/r0/login",
+            applies: |versions| supports(versions, "r0.4.0"),
+            request: Some(login_identifier),
+            response: None,
+        },
+        // `home_server` was deprecated in r0.4.0 and newer homeservers leave it out.
+        Shim {
+            method: Method::POST,
+            path: "/_matrix/client/r0/login",
+            applies: |_| true,
+            request: None,
+            response: Some(home_server_from_user_id),
+        },
+        Shim {
+            method: Method::POST,
+            path: "/_matrix/client/r0/register",
+            applies: |_| true,
+            request: None,
+            response: Some(home_server_from_user_id),
+        },
+        // Lazy-loading of members came with r0.5.0, older homeservers reject filters asking for
+        // it. Filters built with the types of `api::r0::filter` never do, but custom endpoints
+        // can.
+        Shim {
+            method: Method::POST,
+            path: "/_matrix/client/r0/user/:user_id/filter",
+            applies: |versions| !supports(versions, "r0.5.0"),
+            request: Some(filter_without_lazy_loading),
+            response: None,
+        },
+    ]
+}
+
+/// Whether a shim applies to requests to the endpoint with the given method and path for some
+/// versions.
+fn has_shims(method: &Method, path: &str) -> bool {
+    shims()
+        .iter()
+        .any(|shim| shim.method == *method && shim.path == path)
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Ask the homeserver which versions of the specification it supports, and adapt requests
+    /// and responses of endpoints that changed between versions to them from now on.
+    ///
+    /// This happens automatically before the first request to such an endpoint, so calling this
+    /// is only needed to ask again, e.g. after the homeserver was upgraded. Resolves to the
+    /// supported versions.
+    pub fn negotiate_spec_versions(&self) -> impl Future<Item = Vec<String>, Error = Error> {
+        let data = self.0.clone();
+
+        get_supported_versions::call(self.clone(), get_supported_versions::Request {}).map(
+            move |response| {
+                *data.spec_versions.borrow_mut() = response.versions.clone();
+                response.versions
+            },
+        )
+    }
+
+    /// The versions of the specification the homeserver reported, empty until they were asked
+    /// for.
+    pub fn spec_versions(&self) -> Vec<String> {
+        self.0.spec_versions.borrow().clone()
+    }
+}
+
+/// The versions of the specification the homeserver supports, asked for first if a request to
+/// the endpoint with the given method and path may need to be adapted to them.
+///
+/// If the homeserver doesn't tell, requests are made as the oldest supported version defines
+/// them. The future is boxed, because asking for the versions is a request as well.
+pub(crate) fn spec_versions_for<C>(
+    client: Client<C>,
+    method: &Method,
+    path: &str,
+) -> Box<dyn Future<Item = Vec<String>, Error = Error>>
+where
+    C: Connect + 'static,
+{
+    let versions = client.spec_versions();
+
+    if !versions.is_empty() || !has_shims(method, path) {
+        return Box::new(future::ok(versions));
+    }
+
+    Box::new(client.negotiate_spec_versions().or_else(|_| Ok(Vec::new())))
+}
+
+/// Rewrite the body of a request to the endpoint with the given method and path, if a shim
+/// applies to it.
+pub(crate) fn adapt_request(
+    versions: &[String],
+    method: &Method,
+    path: &str,
+    request: http::Request<Body>,
+) -> impl Future<Item = http::Request<Body>, Error = Error> {
+    let rewrites: Vec<_> = shims()
+        .into_iter()
+        .filter(|shim| shim.method == *method && shim.path == path && (shim.applies)(versions))
+        .filter_map(|shim| shim.request)
+        .collect();
+
+    if rewrites.is_empty() {
+        return Either::A(future::ok(request));
+    }
+
+    let (mut parts, body) = request.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Either::B(body.concat2().map_err(Error::from).and_then(move |body| {
+        let body = rewrite(&body, &rewrites)?;
+
+        Ok(http::Request::from_parts(parts, body))
+    }))
+}
+
+/// Rewrite the body of a successful response from the endpoint with the given method and path,
+/// if a shim applies to it.
+pub(crate) fn adapt_response(
+    versions: &[String],
+    method: &Method,
+    path: &str,
+    response: http::Response<Body>,
+) -> impl Future<Item = http::Response<Body>, Error = Error> {
+    let rewrites: Vec<_> = shims()
+        .into_iter()
+        .filter(|shim| shim.method == *method && shim.path == path && (shim.applies)(versions))
+        .filter_map(|shim| shim.response)
+        .collect();
+
+    if rewrites.is_empty() || !response.status().is_success() {
+        return Either::A(future::ok(response));
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Either::B(body.concat2().map_err(Error::from).and_then(move |body| {
+        let body = rewrite(&body, &rewrites)?;
+
+        Ok(http::Response::from_parts(parts, body))
+    }))
+}
+
+/// Apply rewrites to a JSON object, leaving bodies that aren't objects as they are.
+fn rewrite(body: &[u8], rewrites: &[fn(&mut Map<String, Value>)]) -> Result<Body, Error> {
+    match serde_json::from_slice(body) {
+        Ok(Value::Object(mut object)) => {
+            for rewrite in rewrites {
+                rewrite(&mut object);
+            }
+
+            Ok(Body::from(serde_json::to_vec(&object)?))
+        }
+        _ => Ok(Body::from(body.to_vec())),
+    }
+}
+
+/// Whether the homeserver supports `version` or a later version.
+fn supports(versions: &[String], version: &str) -> bool {
+    let key = |version: &str| -> Option<(u64, u64, u64)> {
+        // The versions after r0.6.1 are named `v1.1`, `v1.2` and so on.
+        if let Some(minor) = version.strip_prefix("v1.") {
+            return Some((1, minor.parse().ok()?, 0));
+        }
+
+        let mut parts = version.strip_prefix('r')?.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        let patch = parts.next()?.parse().ok()?;
+
+        Some((major, minor, patch))
+    };
+    let required = match key(version) {
+        Some(required) => required,
+        None => return false,
+    };
+
+    versions
+        .iter()
+        .filter_map(|supported| key(supported))
+        .any(|supported| supported >= required)
+}
+
+/// Move the user or third party identifier of a login request into `identifier`.
+fn login_identifier(request: &mut Map<String, Value>) {
+    if request.contains_key("identifier") {
+        return;
+    }
+
+    let identifier = match (
+        request.remove("user"),
+        request.remove("medium"),
+        request.remove("address"),
+    ) {
+        (_, Some(medium), Some(address)) => json!({
+            "type": "m.id.thirdparty",
+            "medium": medium,
+            "address": address,
+        }),
+        (Some(user), _, _) => json!({ "type": "m.id.user", "user": user }),
+        _ => return,
+    };
+
+    request.insert("identifier".to_owned(), identifier);
+}
+
+/// Remove the lazy-loading options from the room event filters of a filter definition.
+fn filter_without_lazy_loading(filter: &mut Map<String, Value>) {
+    let room = match filter.get_mut("room").and_then(Value::as_object_mut) {
+        Some(room) => room,
+        None => return,
+    };
+
+    for key in &["state", "timeline"] {
+        if let Some(Value::Object(room_event_filter)) = room.get_mut(*key) {
+            room_event_filter.remove("lazy_load_members");
+            room_event_filter.remove("include_redundant_members");
+        }
+    }
+}
+
+/// Fill in the deprecated `home_server` of a response from the server name of its `user_id`.
+fn home_server_from_user_id(response: &mut Map<String, Value>) {
+    if response.contains_key("home_server") {
+        return;
+    }
+
+    let server_name = response
+        .get("user_id")
+        .and_then(Value::as_str)
+        .and_then(|user_id| user_id.split_once(':'))
+        .map(|(_, server_name)| server_name.to_owned());
+
+    if let Some(server_name) = server_name {
+        response.insert("home_server".to_owned(), Value::String(server_name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Method;
+    use serde_json::{json, Map, Value};
+
+    use super::{
+        filter_without_lazy_loading, has_shims, home_server_from_user_id, login_identifier,
+        supports,
+    };
+
+    fn versions(versions: &[&str]) -> Vec<String> {
+        versions.iter().map(|version| version.to_string()).collect()
+    }
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(object) => object,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn supports_compares_versions() {
+        let old = versions(&["r0.0.1", "r0.3.0"]);
+        let new = versions(&["r0.6.1", "v1.1", "v1.10"]);
+
+        assert!(supports(&old, "r0.3.0"));
+        assert!(!supports(&old, "r0.4.0"));
+        assert!(supports(&new, "r0.4.0"));
+        assert!(supports(&new, "v1.2"));
+        assert!(!supports(&new, "v1.11"));
+        assert!(!supports(&versions(&[]), "r0.0.1"));
+        assert!(!supports(&versions(&["unstable", "r0.x"]), "r0.0.1"));
+        assert!(!supports(&new, "not a version"));
+    }
+
+    #[test]
+    fn login_identifier_moves_user_ids() {
+        let mut request = object(json!({ "type": "m.login.password", "user": "alice" }));
+        login_identifier(&mut request);
+
+        assert_eq!(
+            Value::Object(request),
+            json!({
+                "type": "m.login.password",
+                "identifier": { "type": "m.id.user", "user": "alice" },
+            })
+        );
+    }
+
+    #[test]
+    fn login_identifier_moves_third_party_ids() {
+        let mut request = object(json!({ "medium": "email", "address": "alice@example.org" }));
+        login_identifier(&mut request);
+
+        assert_eq!(
+            Value::Object(request),
+            json!({
+                "identifier": {
+                    "type": "m.id.thirdparty",
+                    "medium": "email",
+                    "address": "alice@example.org",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn login_identifier_keeps_existing_identifiers() {
+        let original = json!({ "identifier": { "type": "m.id.user", "user": "alice" } });
+        let mut request = object(original.clone());
+        login_identifier(&mut request);
+
+        assert_eq!(Value::Object(request), original);
+    }
+
+    #[test]
+    fn home_server_is_taken_from_the_user_id() {
+        let mut response = object(json!({ "user_id": "@alice:example.org:8448" }));
+        home_server_from_user_id(&mut response);
+        assert_eq!(response["home_server"], "example.org:8448");
+
+        let mut response = object(json!({ "user_id": "@alice:a.org", "home_server": "b.org" }));
+        home_server_from_user_id(&mut response);
+        assert_eq!(response["home_server"], "b.org");
+
+        let mut response = object(json!({}));
+        home_server_from_user_id(&mut response);
+        assert!(!response.contains_key("home_server"));
+    }
+
+    #[test]
+    fn lazy_loading_is_removed_from_filters() {
+        let mut filter = object(json!({
+            "room": {
+                "state": { "lazy_load_members": true, "types": ["m.room.member"] },
+                "timeline": { "include_redundant_members": true, "limit": 10 },
+            },
+        }));
+        filter_without_lazy_loading(&mut filter);
+
+        assert_eq!(
+            Value::Object(filter),
+            json!({
+                "room": {
+                    "state": { "types": ["m.room.member"] },
+                    "timeline": { "limit": 10 },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn only_some_endpoints_have_shims() {
+        assert!(has_shims(&Method::POST, "/_matrix/client/r0/login"));
+        assert!(has_shims(
+            &Method::POST,
+            "/_matrix/client/r0/user/:user_id/filter"
+        ));
+        assert!(!has_shims(&Method::GET, "/_matrix/client/r0/login"));
+        assert!(!has_shims(&Method::GET, "/_matrix/client/r0/sync"));
+    }
+}
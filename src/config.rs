@@ -0,0 +1,657 @@
+//! Configuration files for bots.
+//!
+//! Only the part of TOML that configuration files commonly use is supported: tables, bare keys,
+//! strings, integers, floats, booleans and arrays of them, each on a single line.
+
+use std::{collections::HashMap, convert::TryFrom, env, fs, io, path::Path, path::PathBuf};
+
+use futures::{
+    future::{self, Either},
+    stream, Future, Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_identifiers::RoomIdOrAliasId;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use serde_json::{Map, Number, Value};
+use url::Url;
+
+use crate::{Client, ClientBuilder, Error, Room, Session};
+
+/// The configuration of a bot, loaded from a TOML file with `Config::load`.
+///
+/// ```toml
+/// homeserver_url = "https://matrix.example.org"
+/// rooms = ["#lobby:example.org", "!ops:example.org"]
+///
+/// [credentials]
+/// user = "@bot:example.org"
+/// password = "hunter2"
+/// session_path = "session.json"
+///
+/// [features]
+/// greet_new_members = true
+/// ```
+///
+/// Tables and keys not listed here are kept and can be read with `Config::get`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The URL of the homeserver.
+    pub homeserver_url: Url,
+    /// How the bot logs in.
+    pub credentials: Credentials,
+    /// The IDs or aliases of the rooms the bot serves.
+    pub rooms: Vec<String>,
+    /// Features of the bot that can be switched on or off.
+    pub features: HashMap<String, bool>,
+    /// The whole configuration, after applying overrides from the environment.
+    values: Value,
+}
+
+/// The `credentials` table of a `Config`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Credentials {
+    /// The user ID or localpart to log in with.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// The password to log in with.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Where the bot keeps its session between runs.
+    #[serde(default)]
+    pub session_path: Option<PathBuf>,
+}
+
+/// The keys of a `Config` with a fixed meaning.
+#[derive(Deserialize)]
+struct KnownKeys {
+    homeserver_url: String,
+    #[serde(default)]
+    credentials: Credentials,
+    #[serde(default)]
+    rooms: Vec<String>,
+    #[serde(default)]
+    features: HashMap<String, bool>,
+}
+
+impl Config {
+    /// Load the configuration from the TOML file at `path`, with overrides from environment
+    /// variables starting with `{env_prefix}_`.
+    ///
+    /// The rest of the name of such a variable is the lowercase path of the key, with tables
+    /// separated by two underscores. With the prefix `MYBOT`, `MYBOT_HOMESERVER_URL` overrides
+    /// `homeserver_url` and `MYBOT_CREDENTIALS__PASSWORD` overrides `password` in the
+    /// `credentials` table. Values are parsed like TOML values, except that keys holding a string
+    /// in the file take the value of the variable as it is.
+    pub fn load<P>(path: P, env_prefix: &str) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut values = parse(&fs::read_to_string(path)?)?;
+        apply_overrides(&mut values, env_prefix, env::vars())?;
+
+        Config::from_values(values)
+    }
+
+    /// Parse a configuration in TOML, without overrides from the environment.
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        Config::from_values(parse(toml)?)
+    }
+
+    /// Whether the feature `name` is switched on in the `features` table.
+    pub fn feature(&self, name: &str) -> bool {
+        self.features.get(name).cloned().unwrap_or(false)
+    }
+
+    /// Get the value of a key that has no fixed meaning, e.g. `greeting.text` for `text` in the
+    /// `greeting` table.
+    ///
+    /// Resolves to `None` if the key doesn't exist.
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let value = key
+            .split('.')
+            .try_fold(&self.values, |value, key| value.get(key));
+
+        match value {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// A builder for a client of the configured homeserver.
+    ///
+    /// If a session was saved at the configured `session_path` by `Config::log_in`, the client
+    /// starts out with it.
+    pub fn client_builder(&self) -> Result<ClientBuilder, Error> {
+        let builder = ClientBuilder::new(self.homeserver_url.clone());
+
+        let path = match self.credentials.session_path {
+            Some(ref path) => path,
+            None => return Ok(builder),
+        };
+        let session = match fs::read(path) {
+            Ok(session) => session,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(builder),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(builder.session(serde_json::from_slice(&session)?))
+    }
+
+    /// Log `client` in with the configured credentials, unless it already has a session.
+    ///
+    /// The new session is saved at the configured `session_path`, if any, so that
+    /// `Config::client_builder` can restore it on the next run.
+    pub fn log_in<C>(&self, client: &Client<C>) -> impl Future<Item = Session, Error = Error>
+    where
+        C: Connect + 'static,
+    {
+        if let Some(session) = client.session() {
+            return Either::A(future::ok(session));
+        }
+
+        let (user, password) = match self.credentials {
+            Credentials {
+                user: Some(ref user),
+                password: Some(ref password),
+                ..
+            } => (user.clone(), password.clone()),
+            _ => {
+                return Either::A(future::err(Error::Config(
+                    "credentials need a user and a password to log in".to_owned(),
+                )))
+            }
+        };
+        let session_path = self.credentials.session_path.clone();
+
+        Either::B(
+            client
+                .log_in(user, password, None, None)
+                .and_then(move |session| {
+                    if let Some(path) = session_path {
+                        fs::write(path, serde_json::to_vec(&session)?)?;
+                    }
+
+                    Ok(session)
+                }),
+        )
+    }
+
+    /// Join the configured rooms with `client`, in the order of the configuration.
+    pub fn join_rooms<C>(
+        &self,
+        client: &Client<C>,
+    ) -> impl Future<Item = Vec<Room<C>>, Error = Error>
+    where
+        C: Connect + 'static,
+    {
+        use crate::api::r0::membership::join_room_by_id_or_alias;
+
+        let rooms = match self
+            .rooms
+            .iter()
+            .map(|room| {
+                RoomIdOrAliasId::try_from(&room[..])
+                    .map_err(|_| Error::Config(format!("{} is no room ID or alias", room)))
+            })
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(rooms) => rooms,
+            Err(error) => return Either::A(future::err(error)),
+        };
+        let client = client.clone();
+
+        Either::B(
+            stream::iter_ok(rooms)
+                .and_then(move |room_id_or_alias| {
+                    let client = client.clone();
+
+                    join_room_by_id_or_alias::call(
+                        client.clone(),
+                        join_room_by_id_or_alias::Request {
+                            room_id_or_alias,
+                            third_party_signed: None,
+                        },
+                    )
+                    .map(move |response| client.room(response.room_id))
+                })
+                .collect(),
+        )
+    }
+
+    fn from_values(values: Value) -> Result<Self, Error> {
+        let known: KnownKeys = serde_json::from_value(values.clone())?;
+
+        Ok(Config {
+            homeserver_url: Url::parse(&known.homeserver_url)?,
+            credentials: known.credentials,
+            rooms: known.rooms,
+            features: known.features,
+            values,
+        })
+    }
+}
+
+/// Parse a TOML document into a JSON object.
+fn parse(toml: &str) -> Result<Value, Error> {
+    let mut root = Map::new();
+    let mut table_path = Vec::new();
+
+    for (index, line) in toml.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(line).trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if line.starts_with("[[") || !line.ends_with(']') {
+                return Err(syntax_error(line_number, "invalid table header"));
+            }
+
+            table_path = line[1..line.len() - 1]
+                .split('.')
+                .map(|key| key.trim().to_owned())
+                .collect();
+
+            if !table_path.iter().all(|key| is_bare_key(key)) {
+                return Err(syntax_error(line_number, "invalid table name"));
+            }
+            if table(&mut root, &table_path).is_none() {
+                return Err(syntax_error(line_number, "table name is already a key"));
+            }
+
+            continue;
+        }
+
+        let equals = line
+            .find('=')
+            .ok_or_else(|| syntax_error(line_number, "expected `key = value`"))?;
+        let key = line[..equals].trim();
+
+        if !is_bare_key(key) {
+            return Err(syntax_error(line_number, "invalid key"));
+        }
+
+        let value = parse_value(line[equals + 1..].trim())
+            .ok_or_else(|| syntax_error(line_number, "invalid value"))?;
+        let table = table(&mut root, &table_path)
+            .ok_or_else(|| syntax_error(line_number, "table name is already a key"))?;
+
+        if table.insert(key.to_owned(), value).is_some() {
+            return Err(syntax_error(line_number, "duplicate key"));
+        }
+    }
+
+    Ok(Value::Object(root))
+}
+
+/// Override the values of a parsed configuration with environment variables.
+fn apply_overrides<I>(values: &mut Value, env_prefix: &str, vars: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let prefix = format!("{}_", env_prefix);
+
+    for (name, raw) in vars {
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+
+        let mut path: Vec<_> = name[prefix.len()..]
+            .to_lowercase()
+            .split("__")
+            .map(ToOwned::to_owned)
+            .collect();
+        let key = path.pop().unwrap_or_default();
+
+        // Other programs may use variables with the same prefix that can't name a key.
+        if !path.iter().chain(Some(&key)).all(|key| is_bare_key(key)) {
+            continue;
+        }
+
+        let invalid = || Error::Config(format!("environment variable {} names no key", name));
+        let root = values.as_object_mut().ok_or_else(invalid)?;
+        let table = table(root, &path).ok_or_else(invalid)?;
+        let value = match table.get(&key) {
+            Some(Value::String(_)) => Value::String(raw),
+            _ => parse_value(&raw).unwrap_or(Value::String(raw)),
+        };
+
+        table.insert(key, value);
+    }
+
+    Ok(())
+}
+
+/// The table at `path`, created if it doesn't exist, or `None` if a key on the way holds
+/// something else.
+fn table<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+) -> Option<&'a mut Map<String, Value>> {
+    let mut current = root;
+
+    for key in path {
+        current = match current
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            Value::Object(table) => table,
+            _ => return None,
+        };
+    }
+
+    Some(current)
+}
+
+/// A line without its comment, if any.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..index],
+            None => {}
+        }
+    }
+
+    line
+}
+
+/// Whether `key` is a bare key, i.e. made of ASCII letters and digits, `_` and `-`.
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parse a value, which has to make up all of `input`.
+fn parse_value(input: &str) -> Option<Value> {
+    let (value, rest) = value(input)?;
+
+    if rest.trim().is_empty() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Parse the value at the start of `input`, returning it and the rest of the input.
+fn value(input: &str) -> Option<(Value, &str)> {
+    let input = input.trim_start();
+
+    match input.chars().next()? {
+        '"' => basic_string(&input[1..]),
+        '\'' => {
+            let end = input[1..].find('\'')?;
+
+            Some((Value::String(input[1..=end].to_owned()), &input[end + 2..]))
+        }
+        '[' => array(&input[1..]),
+        _ => {
+            let end = input
+                .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+                .unwrap_or(input.len());
+
+            Some((scalar(&input[..end])?, &input[end..]))
+        }
+    }
+}
+
+/// Parse the rest of a string in double quotes, with escapes.
+fn basic_string(input: &str) -> Option<(Value, &str)> {
+    let mut string = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((Value::String(string), &input[index + 1..])),
+            '\\' => string.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '"' => '"',
+                '\\' => '\\',
+                _ => return None,
+            }),
+            c => string.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parse the rest of an array.
+fn array(mut input: &str) -> Option<(Value, &str)> {
+    let mut elements = Vec::new();
+
+    loop {
+        input = input.trim_start();
+
+        if let Some(rest) = input.strip_prefix(']') {
+            return Some((Value::Array(elements), rest));
+        }
+
+        let (element, rest) = value(input)?;
+        elements.push(element);
+        input = rest.trim_start();
+
+        if let Some(rest) = input.strip_prefix(',') {
+            input = rest;
+        } else if !input.starts_with(']') {
+            return None;
+        }
+    }
+}
+
+/// Parse a boolean or a number.
+fn scalar(input: &str) -> Option<Value> {
+    match input {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+
+    let number = input.replace('_', "");
+
+    if let Ok(integer) = number.parse::<i64>() {
+        return Some(Value::Number(integer.into()));
+    }
+
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+}
+
+fn syntax_error(line_number: usize, reason: &str) -> Error {
+    Error::Config(format!("line {}: {}", line_number, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{apply_overrides, parse, Config};
+
+    const EXAMPLE: &str = r##"
+        # The homeserver.
+        homeserver_url = "https://matrix.example.org"
+        rooms = ["#lobby:example.org", "!ops:example.org"] # Served rooms.
+
+        [credentials]
+        user = "@bot:example.org"
+        password = "hunter2"
+
+        [features]
+        greet_new_members = true
+
+        [greeting]
+        text = "Welcome # to the lobby"
+        delay_secs = 5
+    "##;
+
+    fn overrides(vars: &[(&str, &str)]) -> serde_json::Value {
+        let mut values = parse(EXAMPLE).unwrap();
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        apply_overrides(&mut values, "MYBOT", vars).unwrap();
+        values
+    }
+
+    #[test]
+    fn loads_known_and_other_keys() {
+        let config = Config::from_toml(EXAMPLE).unwrap();
+
+        assert_eq!(
+            config.homeserver_url.as_str(),
+            "https://matrix.example.org/"
+        );
+        assert_eq!(config.rooms, vec!["#lobby:example.org", "!ops:example.org"]);
+        assert_eq!(config.credentials.user.as_deref(), Some("@bot:example.org"));
+        assert_eq!(config.credentials.session_path, None);
+        assert!(config.feature("greet_new_members"));
+        assert!(!config.feature("unknown"));
+        assert_eq!(
+            config.get::<String>("greeting.text").unwrap().as_deref(),
+            Some("Welcome # to the lobby")
+        );
+        assert_eq!(config.get::<u64>("greeting.delay_secs").unwrap(), Some(5));
+        assert_eq!(config.get::<u64>("greeting.missing").unwrap(), None);
+        assert!(config.get::<u64>("greeting.text").is_err());
+    }
+
+    #[test]
+    fn parses_values() {
+        let values = parse(
+            r#"
+            basic = "tab\tquote\" backslash\\"
+            literal = 'C:\path # not a comment'
+            integer = -1_000
+            float = 2.5
+            exponent = 1e3
+            yes = true
+            no = false
+            empty = []
+            nested = [[1, 2], ["a"], ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            values,
+            json!({
+                "basic": "tab\tquote\" backslash\\",
+                "literal": "C:\\path # not a comment",
+                "integer": -1000,
+                "float": 2.5,
+                "exponent": 1000.0,
+                "yes": true,
+                "no": false,
+                "empty": [],
+                "nested": [[1, 2], ["a"]],
+            })
+        );
+    }
+
+    #[test]
+    fn parses_nested_tables() {
+        let values = parse("[a.b]\nc = 1\n[ a ]\nd = 2\n").unwrap();
+
+        assert_eq!(values, json!({ "a": { "b": { "c": 1 }, "d": 2 } }));
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        let invalid = [
+            "key",
+            "key = ",
+            "key = value",
+            "key = \"unterminated",
+            "key = \"bad \\q escape\"",
+            "key = 1 2",
+            "key = [1, 2",
+            "key = [1 2]",
+            "\"quoted\" = 1",
+            "dotted.key = 1",
+            "key = 1\nkey = 2",
+            "key = 1\n[key]",
+            "[table]\n[table.key]\nx = 1\n[table]\nkey = 1",
+            "[[array]]",
+            "[unclosed",
+            "[in valid]",
+        ];
+
+        for toml in &invalid {
+            assert!(parse(toml).is_err(), "{:?} should be rejected", toml);
+        }
+    }
+
+    #[test]
+    fn reports_the_line_of_syntax_errors() {
+        let error = format!("{:?}", parse("a = 1\n\n# comment\nb = nope").unwrap_err());
+
+        assert!(error.contains("line 4"), "{}", error);
+    }
+
+    #[test]
+    fn overrides_keys_from_the_environment() {
+        let values = overrides(&[
+            ("MYBOT_HOMESERVER_URL", "https://other.example.org"),
+            ("MYBOT_CREDENTIALS__PASSWORD", "12345"),
+            ("MYBOT_GREETING__DELAY_SECS", "10"),
+            ("MYBOT_FEATURES__NEW_FEATURE", "true"),
+            ("MYBOT_NEW__KEY", "[1, 2]"),
+            ("MYBOT_PLAIN", "not toml"),
+            ("OTHER_HOMESERVER_URL", "https://ignored.example.org"),
+        ]);
+
+        assert_eq!(values["homeserver_url"], "https://other.example.org");
+        // Keys holding strings keep the value as it is.
+        assert_eq!(values["credentials"]["password"], "12345");
+        assert_eq!(values["greeting"]["delay_secs"], 10);
+        assert_eq!(values["features"]["new_feature"], true);
+        assert_eq!(values["new"]["key"], json!([1, 2]));
+        assert_eq!(values["plain"], "not toml");
+    }
+
+    #[test]
+    fn ignores_variables_that_name_no_key() {
+        let values = overrides(&[
+            ("MYBOT_LOG.LEVEL", "debug"),
+            ("MYBOT_", "empty"),
+            ("MYBOT_CREDENTIALS__", "empty"),
+        ]);
+
+        assert_eq!(values, parse(EXAMPLE).unwrap());
+    }
+
+    #[test]
+    fn rejects_overrides_through_non_tables() {
+        let mut values = parse(EXAMPLE).unwrap();
+        let vars = vec![("MYBOT_ROOMS__FIRST".to_owned(), "x".to_owned())];
+
+        assert!(apply_overrides(&mut values, "MYBOT", vars).is_err());
+    }
+
+    #[test]
+    fn requires_a_valid_homeserver_url() {
+        assert!(Config::from_toml("rooms = []").is_err());
+        assert!(Config::from_toml("homeserver_url = \"not a url\"").is_err());
+    }
+}
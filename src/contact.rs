@@ -0,0 +1,68 @@
+//! Managing the email addresses and phone numbers attached to the logged-in user's account.
+
+use futures::Future;
+use hyper::client::connect::Connect;
+
+use crate::{
+    api::r0::contact::get_contacts::{Medium, ThirdPartyIdentifier},
+    Client, Error,
+};
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the email addresses and phone numbers attached to the logged-in user's account.
+    pub fn third_party_ids(&self) -> impl Future<Item = Vec<ThirdPartyIdentifier>, Error = Error> {
+        use crate::api::r0::contact::get_contacts;
+
+        get_contacts::call(self.clone(), get_contacts::Request {})
+            .map(|response| response.threepids)
+    }
+
+    /// Attach a validated email address or phone number to the logged-in user's account.
+    ///
+    /// The validation is started with `request_contact_verification_token` or
+    /// `request_msisdn_verification_token`, which return the `sid` of the session; the
+    /// `client_secret` is the one passed to them. The user's password is used to answer the
+    /// user-interactive authentication like with `change_password`.
+    pub fn add_third_party_id(
+        &self,
+        client_secret: String,
+        sid: String,
+        password: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::contact::add_3pid;
+
+        self.request_with_password::<add_3pid::Endpoint, _>(password, move |auth| {
+            add_3pid::Request {
+                client_secret: client_secret.clone(),
+                sid: sid.clone(),
+                auth,
+            }
+        })
+        .map(|_| ())
+    }
+
+    /// Remove an email address or phone number from the logged-in user's account.
+    ///
+    /// The homeserver also unbinds it from the identity server it was bound with. Returns
+    /// whether that worked, which fails if the identity server doesn't support it.
+    pub fn remove_third_party_id(
+        &self,
+        medium: Medium,
+        address: String,
+    ) -> impl Future<Item = bool, Error = Error> {
+        use crate::api::r0::contact::delete_3pid;
+
+        delete_3pid::call(
+            self.clone(),
+            delete_3pid::Request {
+                id_server: None,
+                medium,
+                address,
+            },
+        )
+        .map(|response| response.id_server_unbind_result == "success")
+    }
+}
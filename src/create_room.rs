@@ -0,0 +1,135 @@
+//! Creating rooms.
+
+use futures::future::Future;
+use hyper::client::connect::Connect;
+use ruma_events::EventType;
+use ruma_identifiers::UserId;
+use serde::Serialize;
+
+use crate::{
+    api::r0::room::create_room::{
+        self, CreationContent, InitialStateEvent, RoomPreset, Visibility,
+    },
+    Client, Error, Room,
+};
+
+/// The options of a new room, created with `Client::create_room`.
+#[derive(Clone, Debug, Default)]
+pub struct CreateRoomBuilder {
+    creation_content: Option<CreationContent>,
+    initial_state: Vec<InitialStateEvent>,
+    invite: Vec<UserId>,
+    is_direct: Option<bool>,
+    name: Option<String>,
+    preset: Option<RoomPreset>,
+    room_alias_name: Option<String>,
+    topic: Option<String>,
+    visibility: Option<Visibility>,
+}
+
+impl CreateRoomBuilder {
+    /// Creates a builder for a room with the homeserver's default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the room is shown in the published room list.
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    /// Give the room the alias `#{localpart}:{homeserver}`.
+    pub fn alias(mut self, localpart: String) -> Self {
+        self.room_alias_name = Some(localpart);
+        self
+    }
+
+    /// Set the name of the room.
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Set the topic of the room.
+    pub fn topic(mut self, topic: String) -> Self {
+        self.topic = Some(topic);
+        self
+    }
+
+    /// Invite a user to the room.
+    pub fn invite(mut self, user_id: UserId) -> Self {
+        self.invite.push(user_id);
+        self
+    }
+
+    /// Set the join rules, history visibility and power levels of the room with a preset.
+    pub fn preset(mut self, preset: RoomPreset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Set whether users on other homeservers can join the room.
+    pub fn federate(mut self, federate: bool) -> Self {
+        self.creation_content = Some(CreationContent {
+            federate: Some(federate),
+        });
+        self
+    }
+
+    /// Mark the room as a direct chat with the invited users.
+    pub fn is_direct(mut self, is_direct: bool) -> Self {
+        self.is_direct = Some(is_direct);
+        self
+    }
+
+    /// Send a state event of any type to the room when it is created.
+    ///
+    /// Initial state overrides the state set by the preset, but not the name and topic.
+    pub fn initial_state<T>(
+        mut self,
+        event_type: EventType,
+        state_key: String,
+        content: &T,
+    ) -> Result<Self, Error>
+    where
+        T: Serialize,
+    {
+        self.initial_state.push(InitialStateEvent {
+            event_type: event_type.to_string(),
+            state_key,
+            content: serde_json::to_value(content)?,
+        });
+
+        Ok(self)
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Create a room, returning a handle for it.
+    pub fn create_room(
+        &self,
+        options: CreateRoomBuilder,
+    ) -> impl Future<Item = Room<C>, Error = Error> {
+        let client = self.clone();
+
+        create_room::call(
+            self.clone(),
+            create_room::Request {
+                creation_content: options.creation_content,
+                initial_state: options.initial_state,
+                invite: options.invite,
+                is_direct: options.is_direct,
+                name: options.name,
+                preset: options.preset,
+                room_alias_name: options.room_alias_name,
+                topic: options.topic,
+                visibility: options.visibility,
+            },
+        )
+        .map(move |response| client.room(response.room_id))
+    }
+}
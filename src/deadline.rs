@@ -0,0 +1,247 @@
+//! Deadlines bounding requests, including their retries.
+
+use std::{
+    cmp,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{self, Either, IntoFuture, Loop},
+    Async, Future, Poll, Stream,
+};
+use http::{Request, Response, StatusCode};
+use hyper::{client::connect::Connect, Body};
+use serde_json::Value;
+
+use crate::{environment::ClockDelay, Client, ClientData, Clock, Error};
+
+/// How long to wait before retrying a rate-limited request if the homeserver doesn't say.
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A future failing with `Error::DeadlineExceeded` once a `Clock` reached a deadline.
+struct Bounded<F> {
+    future: F,
+    delay: ClockDelay,
+}
+
+impl<F> Future for Bounded<F>
+where
+    F: Future<Error = Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<F::Item, Error> {
+        if let Async::Ready(item) = self.future.poll()? {
+            return Ok(Async::Ready(item));
+        }
+
+        match self.delay.poll()? {
+            Async::Ready(()) => Err(Error::DeadlineExceeded),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// Bound `future` to finish before `clock` reaches `deadline`.
+fn bounded<F>(clock: &dyn Clock, future: F, deadline: Instant) -> Bounded<F> {
+    Bounded {
+        future,
+        delay: clock.delay_until(deadline),
+    }
+}
+
+/// A future bounded by a deadline, see `Client::with_deadline`.
+struct WithDeadline<C: Connect, F> {
+    client: Client<C>,
+    deadline: Instant,
+    future: Bounded<F>,
+}
+
+impl<C, F> Future for WithDeadline<C, F>
+where
+    C: Connect,
+    F: Future<Error = Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<F::Item, Error> {
+        // Requests are only made while their future is polled, so making the deadline visible
+        // for the duration of the poll is enough for `send_request` to pick it up.
+        let outer = self.client.0.deadline.get();
+        let deadline = match outer {
+            Some(outer) => cmp::min(outer, self.deadline),
+            None => self.deadline,
+        };
+
+        self.client.0.deadline.set(Some(deadline));
+        let result = self.future.poll();
+        self.client.0.deadline.set(outer);
+
+        result
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Bound `future` to finish before `deadline`, an instant of the client's clock.
+    ///
+    /// Fails with `Error::DeadlineExceeded` once the deadline has passed, as told by the clock's
+    /// `delay_until`. All requests made by
+    /// the future know the deadline: rate-limited requests are only retried if the retry can
+    /// still finish in time, and a request is abandoned as soon as the deadline passes. This
+    /// makes it possible to bound a whole action, e.g. `Room::join_replacement`, at ten seconds
+    /// including all of its requests and retries. Nested deadlines are combined, the earliest
+    /// one wins.
+    pub fn with_deadline<F>(
+        &self,
+        deadline: Instant,
+        future: F,
+    ) -> impl Future<Item = F::Item, Error = Error>
+    where
+        F: IntoFuture<Error = Error>,
+    {
+        WithDeadline {
+            client: self.clone(),
+            deadline,
+            future: bounded(&*self.0.clock, future.into_future(), deadline),
+        }
+    }
+}
+
+/// Send a request, retrying it while the homeserver rate-limits it and the retry can still be
+/// made before `deadline`.
+///
+/// A rate-limited response is returned as it is once there's no time for another attempt.
+pub(crate) fn send_until<C>(
+    data: Rc<ClientData<C>>,
+    request: Request<Body>,
+    deadline: Instant,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    C: Connect + 'static,
+{
+    let (parts, body) = request.into_parts();
+
+    body.concat2().map_err(Error::from).and_then(move |body| {
+        let body = body.into_bytes();
+
+        future::loop_fn((), move |()| {
+            if data.clock.instant() >= deadline {
+                return Either::A(future::err(Error::DeadlineExceeded));
+            }
+
+            let mut request = Request::new(Body::from(body.clone()));
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = parts.uri.clone();
+            *request.version_mut() = parts.version;
+            *request.headers_mut() = parts.headers.clone();
+
+            let data = data.clone();
+
+            let response = data.hyper.request(request).map_err(Error::from);
+
+            Either::B(
+                bounded(&*data.clock, response, deadline).and_then(move |response| {
+                    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                        return Either::A(future::ok(Loop::Break(response)));
+                    }
+
+                    Either::B(retry_delay(response).and_then(move |(response, delay)| {
+                        let retry_at = data.clock.instant() + delay;
+
+                        if retry_at >= deadline {
+                            Either::A(future::ok(Loop::Break(response)))
+                        } else {
+                            Either::B(
+                                data.clock
+                                    .delay_until(retry_at)
+                                    .map(|()| Loop::Continue(())),
+                            )
+                        }
+                    }))
+                }),
+            )
+        })
+    })
+}
+
+/// Send a request without retrying it, abandoning it once `deadline` has passed, if any.
+pub(crate) fn send_once<C>(
+    data: Rc<ClientData<C>>,
+    request: Request<Body>,
+    deadline: Option<Instant>,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    C: Connect + 'static,
+{
+    let response = data.hyper.request(request).map_err(Error::from);
+
+    match deadline {
+        Some(deadline) => Either::A(bounded(&*data.clock, response, deadline)),
+        None => Either::B(response),
+    }
+}
+
+/// The earlier of two optional deadlines.
+pub(crate) fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(cmp::min(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Read how long the homeserver wants to be left alone from a rate-limited response.
+///
+/// The body is read to find out and put back into the returned response.
+pub(crate) fn retry_delay(
+    response: Response<Body>,
+) -> impl Future<Item = (Response<Body>, Duration), Error = Error> {
+    let (parts, body) = response.into_parts();
+
+    body.concat2().map_err(Error::from).map(move |body| {
+        let delay = serde_json::from_slice::<Value>(&body)
+            .ok()
+            .and_then(|body| body.get("retry_after_ms").and_then(Value::as_u64))
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_RETRY_DELAY);
+
+        (Response::from_parts(parts, Body::from(body)), delay)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{future, Async, Future};
+
+    use super::bounded;
+    use crate::{environment::TestClock, Clock, Error};
+
+    #[test]
+    fn bounded_futures_follow_the_clock() {
+        let clock = TestClock::new();
+        let deadline = clock.instant() + Duration::from_secs(10);
+        let mut pending = bounded(&clock, future::empty::<(), Error>(), deadline);
+
+        assert!(matches!(pending.poll(), Ok(Async::NotReady)));
+
+        clock.advance(Duration::from_secs(10));
+
+        assert!(matches!(pending.poll(), Err(Error::DeadlineExceeded)));
+    }
+
+    #[test]
+    fn finished_futures_win_over_a_passed_deadline() {
+        let clock = TestClock::new();
+        let deadline = clock.instant();
+        let mut finished = bounded(&clock, future::ok::<_, Error>(1), deadline);
+
+        assert!(matches!(finished.poll(), Ok(Async::Ready(1))));
+    }
+}
@@ -0,0 +1,143 @@
+//! Managing the devices, i.e. the sessions, of the logged-in user.
+
+use futures::{
+    future::{self, Either},
+    Future,
+};
+use hyper::client::connect::Connect;
+
+use crate::{api::r0::device::Device, Client, Error};
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the devices of the logged-in user.
+    pub fn devices(&self) -> impl Future<Item = Vec<Device>, Error = Error> {
+        use crate::api::r0::device::get_devices;
+
+        get_devices::call(self.clone(), get_devices::Request {}).map(|response| response.devices)
+    }
+
+    /// Get a single device of the logged-in user.
+    pub fn device(&self, device_id: String) -> impl Future<Item = Device, Error = Error> {
+        use crate::api::r0::device::get_device;
+
+        get_device::call(self.clone(), get_device::Request { device_id })
+            .map(|response| response.device)
+    }
+
+    /// Change the display name of a device of the logged-in user.
+    pub fn rename_device(
+        &self,
+        device_id: String,
+        display_name: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::device::update_device;
+
+        update_device::call(
+            self.clone(),
+            update_device::Request {
+                device_id,
+                display_name: Some(display_name),
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Delete a device of the logged-in user, logging it out.
+    ///
+    /// The user's password is used to answer the user-interactive authentication like with
+    /// `change_password`.
+    pub fn delete_device(
+        &self,
+        device_id: String,
+        password: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::device::delete_device;
+
+        self.request_with_password::<delete_device::Endpoint, _>(password, move |auth| {
+            delete_device::Request {
+                device_id: device_id.clone(),
+                auth,
+            }
+        })
+        .map(|_| ())
+    }
+
+    /// Delete several devices of the logged-in user at once, logging them out.
+    ///
+    /// The user's password is used to answer the user-interactive authentication like with
+    /// `change_password`.
+    pub fn delete_devices(
+        &self,
+        device_ids: Vec<String>,
+        password: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::device::delete_devices;
+
+        self.request_with_password::<delete_devices::Endpoint, _>(password, move |auth| {
+            delete_devices::Request {
+                devices: device_ids.clone(),
+                auth,
+            }
+        })
+        .map(|_| ())
+    }
+
+    /// Log out all devices of the logged-in user except the one of the client's session.
+    ///
+    /// The user's password is used to answer the user-interactive authentication like with
+    /// `change_password`. The returned future resolves to the IDs of the deleted devices.
+    pub fn log_out_other_devices(
+        &self,
+        password: String,
+    ) -> impl Future<Item = Vec<String>, Error = Error> {
+        let own_device_id = match self.session() {
+            Some(session) => session.device_id().to_owned(),
+            None => return Either::A(future::err(Error::AuthenticationRequired)),
+        };
+        let client = self.clone();
+
+        Either::B(self.devices().and_then(move |devices| {
+            let device_ids: Vec<_> = devices
+                .into_iter()
+                .map(|device| device.device_id)
+                .filter(|device_id| *device_id != own_device_id)
+                .collect();
+
+            if device_ids.is_empty() {
+                return Either::A(future::ok(device_ids));
+            }
+
+            Either::B(
+                client
+                    .delete_devices(device_ids.clone(), password)
+                    .map(move |()| device_ids),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{Future, FutureFrom};
+    use hyper::Body;
+
+    use crate::api::r0::device::get_device;
+
+    #[test]
+    fn device_is_read_from_the_whole_body() {
+        let body =
+            r#"{"device_id":"QBUAZIFURK","display_name":"android","last_seen_ts":1474491775024}"#;
+        let response = http::Response::new(Body::from(body));
+        let device = get_device::Response::future_from(response)
+            .wait()
+            .unwrap()
+            .device;
+
+        assert_eq!(device.device_id, "QBUAZIFURK");
+        assert_eq!(device.display_name.as_deref(), Some("android"));
+        assert_eq!(device.last_seen_ts, Some(1_474_491_775_024));
+    }
+}
@@ -0,0 +1,319 @@
+//! Keeping track of the devices of users sharing encrypted rooms with the user.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+};
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_events::{collections::only, room::member::MembershipState};
+use ruma_identifiers::UserId;
+use serde_json::Value;
+
+use crate::{
+    api::r0::{
+        keys::{get_key_changes, get_keys, DeviceKeys},
+        sync::sync_events::Response,
+    },
+    encryption::{verify_json, OlmAccount},
+    Client, Error, StoreSnapshot,
+};
+
+/// How long `/keys/query` waits for remote homeservers, in milliseconds.
+const QUERY_TIMEOUT: u64 = 10_000;
+
+/// The known devices of users sharing encrypted rooms with the user.
+///
+/// A user is tracked once they share an encrypted room with the user. Their devices are
+/// outdated until they are queried with `Client::update_devices`, and again whenever a sync
+/// response lists them in `device_lists.changed`.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceTracker {
+    /// The devices of tracked users, by device ID.
+    devices: HashMap<UserId, BTreeMap<String, DeviceKeys>>,
+    /// The tracked users whose devices have to be queried, with the generation they were marked
+    /// outdated in.
+    outdated: HashMap<UserId, u64>,
+    /// Counts how often users were marked outdated, to tell which marks a query covers.
+    generation: u64,
+    /// The `next_batch` token of the latest sync response.
+    sync_token: Option<String>,
+}
+
+impl DeviceTracker {
+    /// Update the tracked users from a sync response, after it was applied to the store.
+    pub(crate) fn apply_sync(&mut self, response: &Response, store: &StoreSnapshot) {
+        self.apply_changes(&response.device_lists.changed, &response.device_lists.left);
+
+        // New members of encrypted rooms and members of newly encrypted rooms only show up in
+        // the rooms of the response.
+        for room_id in response.rooms.join.keys() {
+            if !store.is_encrypted(room_id) {
+                continue;
+            }
+
+            for event in store.state(room_id) {
+                if let only::StateEvent::RoomMember(member) = event {
+                    let shares_room = matches!(
+                        member.content.membership,
+                        MembershipState::Join | MembershipState::Invite
+                    );
+
+                    if let Ok(user_id) = UserId::try_from(member.state_key.as_str()) {
+                        if shares_room && !self.devices.contains_key(&user_id) {
+                            self.devices.insert(user_id.clone(), BTreeMap::new());
+                            self.mark_outdated(user_id);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.sync_token = Some(response.next_batch.clone());
+    }
+
+    /// The devices of a tracked user, by device ID.
+    pub(crate) fn user_devices(&self, user_id: &UserId) -> Option<&BTreeMap<String, DeviceKeys>> {
+        self.devices.get(user_id)
+    }
+
+    /// The keys of a device of a tracked user.
+    pub(crate) fn device(&self, user_id: &UserId, device_id: &str) -> Option<&DeviceKeys> {
+        self.devices.get(user_id)?.get(device_id)
+    }
+
+    /// Track a device with the given keys, as if a query returned them.
+    #[cfg(test)]
+    pub(crate) fn insert_device(&mut self, keys: DeviceKeys) {
+        self.devices
+            .entry(keys.user_id.clone())
+            .or_default()
+            .insert(keys.device_id.clone(), keys);
+    }
+
+    /// Mark the devices of tracked users as outdated, and stop tracking users who left.
+    fn apply_changes(&mut self, changed: &[UserId], left: &[UserId]) {
+        for user_id in changed {
+            if self.devices.contains_key(user_id) {
+                self.mark_outdated(user_id.clone());
+            }
+        }
+
+        for user_id in left {
+            self.devices.remove(user_id);
+            self.outdated.remove(user_id);
+        }
+    }
+
+    fn mark_outdated(&mut self, user_id: UserId) {
+        self.generation += 1;
+        self.outdated.insert(user_id, self.generation);
+    }
+
+    /// Replace the devices of users with the valid ones of a `/keys/query` response to a query
+    /// started in the given generation.
+    ///
+    /// Users on homeservers that couldn't be reached stay outdated, and so do users whose
+    /// devices changed again while the query was running.
+    fn apply_query(
+        &mut self,
+        account: &dyn OlmAccount,
+        response: get_keys::Response,
+        generation: u64,
+    ) {
+        for (user_id, devices) in response.device_keys {
+            let known = match self.devices.get_mut(&user_id) {
+                Some(known) => known,
+                // The user left all encrypted rooms while the query was running.
+                None => continue,
+            };
+
+            let mut updated = BTreeMap::new();
+
+            for (device_id, object) in devices {
+                let keys: DeviceKeys = match serde_json::from_value(object.clone()) {
+                    Ok(keys) => keys,
+                    Err(_) => {
+                        log::warn!(
+                            "ignoring invalid keys of {} for device {}",
+                            user_id,
+                            device_id
+                        );
+                        continue;
+                    }
+                };
+
+                if keys.user_id != user_id || keys.device_id != device_id {
+                    log::warn!("ignoring keys of {} for device {}", user_id, device_id);
+                    continue;
+                }
+
+                if !is_self_signed(account, &keys, object) {
+                    log::warn!(
+                        "ignoring keys of device {} of {} with a bad signature",
+                        device_id,
+                        user_id
+                    );
+                    continue;
+                }
+
+                // A device's Ed25519 key never changes, a new one is a sign of an attack.
+                let previous = known.get(&device_id).and_then(|keys| ed25519_key(keys));
+
+                if previous.is_some() && previous != ed25519_key(&keys) {
+                    log::warn!(
+                        "ignoring new Ed25519 key of device {} of {}",
+                        device_id,
+                        user_id
+                    );
+                    updated.insert(device_id.clone(), known[&device_id].clone());
+                    continue;
+                }
+
+                updated.insert(device_id, keys);
+            }
+
+            *known = updated;
+
+            if matches!(self.outdated.get(&user_id), Some(&marked) if marked <= generation) {
+                self.outdated.remove(&user_id);
+            }
+        }
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// The known devices of a user sharing an encrypted room with the user, by device ID.
+    ///
+    /// Returns `None` if the user isn't tracked. The devices may be outdated, see
+    /// `update_devices`.
+    pub fn user_devices(&self, user_id: &UserId) -> Option<BTreeMap<String, DeviceKeys>> {
+        self.0
+            .encryption
+            .borrow()
+            .devices
+            .devices
+            .get(user_id)
+            .cloned()
+    }
+
+    /// The tracked users whose devices have changed since they were last queried.
+    pub fn outdated_device_lists(&self) -> Vec<UserId> {
+        self.0
+            .encryption
+            .borrow()
+            .devices
+            .outdated
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Query the keys of all devices of the tracked users whose devices changed.
+    ///
+    /// Users are tracked from the sync responses of `sync`. Devices whose keys aren't signed by
+    /// themselves are left out, which requires the Olm account set with `enable_encryption`.
+    pub fn update_devices(&self) -> impl Future<Item = (), Error = Error> {
+        let data = self.0.clone();
+        let (device_keys, token, generation) = {
+            let encryption = self.0.encryption.borrow();
+
+            if encryption.account.is_none() {
+                return Either::A(future::err(Error::EncryptionDisabled));
+            }
+
+            let device_keys: HashMap<_, _> = encryption
+                .devices
+                .outdated
+                .keys()
+                .map(|user_id| (user_id.clone(), Vec::new()))
+                .collect();
+
+            (
+                device_keys,
+                encryption.devices.sync_token.clone(),
+                encryption.devices.generation,
+            )
+        };
+
+        if device_keys.is_empty() {
+            return Either::A(future::ok(()));
+        }
+
+        Either::B(
+            get_keys::call(
+                self.clone(),
+                get_keys::Request {
+                    timeout: Some(QUERY_TIMEOUT),
+                    device_keys,
+                    token,
+                },
+            )
+            .map(move |response| {
+                if !response.failures.is_empty() {
+                    log::warn!(
+                        "failed to query keys from {:?}",
+                        response.failures.keys().collect::<Vec<_>>()
+                    );
+                }
+
+                let mut encryption = data.encryption.borrow_mut();
+                let encryption = &mut *encryption;
+
+                if let Some(ref account) = encryption.account {
+                    encryption
+                        .devices
+                        .apply_query(&**account, response, generation);
+                }
+            }),
+        )
+    }
+
+    /// Find out whose devices changed between two sync responses, e.g. while the client wasn't
+    /// running, and mark them as outdated.
+    ///
+    /// `from` and `to` are `next_batch` tokens of sync responses.
+    pub fn key_changes(
+        &self,
+        from: String,
+        to: String,
+    ) -> impl Future<Item = get_key_changes::Response, Error = Error> {
+        let data = self.0.clone();
+
+        get_key_changes::call(self.clone(), get_key_changes::Request { from, to }).map(
+            move |response| {
+                data.encryption
+                    .borrow_mut()
+                    .devices
+                    .apply_changes(&response.changed, &response.left);
+
+                response
+            },
+        )
+    }
+}
+
+/// The Ed25519 key of a device.
+pub(crate) fn ed25519_key(keys: &DeviceKeys) -> Option<&String> {
+    keys.keys.get(&format!("ed25519:{}", keys.device_id))
+}
+
+/// The Curve25519 identity key of a device.
+pub(crate) fn curve25519_key(keys: &DeviceKeys) -> Option<&String> {
+    keys.keys.get(&format!("curve25519:{}", keys.device_id))
+}
+
+/// Whether the keys of a device, as the JSON object the homeserver sent, are signed with its own
+/// Ed25519 key.
+fn is_self_signed(account: &dyn OlmAccount, keys: &DeviceKeys, object: Value) -> bool {
+    match ed25519_key(keys) {
+        Some(ed25519_key) => {
+            verify_json(account, &keys.user_id, &keys.device_id, ed25519_key, object)
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,81 @@
+//! Browsing the public room directory.
+
+use futures::{
+    future::{Either, Future},
+    stream::{self, Stream},
+};
+use hyper::client::connect::Connect;
+
+use crate::{
+    api::r0::directory::{
+        get_public_rooms::{self, PublicRoomsChunk},
+        get_public_rooms_filtered::{self, Filter},
+    },
+    Client, Error,
+};
+
+/// How many rooms `Client::public_rooms` requests per page.
+const PUBLIC_ROOMS_PAGE_SIZE: u64 = 100;
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// All rooms in the public room directory of `server`, or of the user's homeserver.
+    ///
+    /// With a `search_term`, only rooms whose name, topic or canonical alias contains it are
+    /// returned, which requires a session. The directory is fetched page by page as the stream is
+    /// polled.
+    pub fn public_rooms(
+        &self,
+        server: Option<String>,
+        search_term: Option<String>,
+    ) -> impl Stream<Item = PublicRoomsChunk, Error = Error> {
+        let client = self.clone();
+
+        // `None` after the last page, otherwise the `since` token of the next page, which the
+        // first page is requested without.
+        stream::unfold(Some(None), move |since: Option<Option<String>>| {
+            let since = since?;
+            let limit = Some(PUBLIC_ROOMS_PAGE_SIZE);
+
+            let page = match search_term {
+                Some(ref search_term) => Either::A(
+                    get_public_rooms_filtered::call(
+                        client.clone(),
+                        get_public_rooms_filtered::Request {
+                            server: server.clone(),
+                            limit,
+                            since,
+                            filter: Some(Filter {
+                                generic_search_term: Some(search_term.clone()),
+                            }),
+                        },
+                    )
+                    .map(|response| (response.chunk, response.next_batch)),
+                ),
+                None => Either::B(
+                    get_public_rooms::call(
+                        client.clone(),
+                        get_public_rooms::Request {
+                            limit,
+                            since,
+                            server: server.clone(),
+                        },
+                    )
+                    .map(|response| (response.chunk, response.next_batch)),
+                ),
+            };
+
+            Some(page.map(|(chunk, next_batch)| {
+                let next = match next_batch {
+                    Some(next_batch) if !chunk.is_empty() => Some(Some(next_batch)),
+                    _ => None,
+                };
+
+                (stream::iter_ok(chunk), next)
+            }))
+        })
+        .flatten()
+    }
+}
@@ -0,0 +1,380 @@
+//! Sorting the events of sync responses into categories and passing them to handlers.
+
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    panic::{self, AssertUnwindSafe},
+};
+
+use ruma_events::{
+    collections::{all, only},
+    presence::PresenceEvent,
+    receipt::ReceiptEvent,
+    typing::TypingEvent,
+    CustomEvent,
+};
+use ruma_identifiers::RoomId;
+use serde_json::Value;
+
+use crate::{
+    api::r0::sync::sync_events::{Response, Rooms, ToDeviceEvent},
+    events::TimelineEvent,
+    Error,
+};
+
+/// An event from a sync response, along with the room it belongs to.
+#[derive(Clone, Debug)]
+pub enum SyncEvent {
+    /// A non-state event from a room's timeline, e.g. a message, redaction or call event.
+    Message {
+        /// The room the event was sent in.
+        room_id: RoomId,
+        /// The event.
+        event: only::RoomEvent,
+    },
+    /// A state event, either from a room's state or its timeline.
+    State {
+        /// The room the state belongs to.
+        room_id: RoomId,
+        /// The event.
+        event: only::StateEvent,
+    },
+    /// A state event of a room the user has been invited to.
+    InviteState {
+        /// The room the user has been invited to.
+        room_id: RoomId,
+        /// The event.
+        event: only::StateEvent,
+    },
+    /// A stripped state event of a room the user has knocked on, as JSON.
+    KnockState {
+        /// The room the user has knocked on.
+        room_id: RoomId,
+        /// The event.
+        event: Value,
+    },
+    /// A presence update of another user.
+    Presence(PresenceEvent),
+    /// Read receipts for events in a room.
+    Receipt {
+        /// The room the receipts belong to.
+        room_id: RoomId,
+        /// The event.
+        event: ReceiptEvent,
+    },
+    /// The list of users currently typing in a room.
+    Typing {
+        /// The room the users are typing in.
+        room_id: RoomId,
+        /// The event.
+        event: TypingEvent,
+    },
+    /// Private data the user has attached to a room.
+    AccountData {
+        /// The room the data is attached to.
+        room_id: RoomId,
+        /// The event.
+        event: only::Event,
+    },
+    /// An event sent directly to this device.
+    ToDevice(ToDeviceEvent),
+    /// An ephemeral or presence event whose type is not part of the specification.
+    Custom {
+        /// The room the event belongs to, if it was sent to a room.
+        room_id: Option<RoomId>,
+        /// The event.
+        event: CustomEvent,
+    },
+}
+
+impl SyncEvent {
+    /// Sort all events of a sync response into `SyncEvent`s.
+    ///
+    /// For each room, state events come before timeline events, followed by ephemeral events and
+    /// account data. Presence events come after the rooms, followed by to-device events.
+    pub fn from_response(response: Response) -> Vec<SyncEvent> {
+        let mut events = Vec::new();
+        let Rooms {
+            join,
+            leave,
+            invite,
+            knock,
+        } = response.rooms;
+
+        for (room_id, room) in join {
+            events.extend(state_events(&room_id, room.state.events));
+            events.extend(timeline_events(&room_id, room.timeline.events));
+            events.extend(
+                room.ephemeral
+                    .events
+                    .into_iter()
+                    .filter_map(|event| ephemeral_event(&room_id, event)),
+            );
+            events.extend(room.account_data.events.into_iter().map(|event| {
+                SyncEvent::AccountData {
+                    room_id: room_id.clone(),
+                    event,
+                }
+            }));
+        }
+
+        for (room_id, room) in leave {
+            events.extend(state_events(&room_id, room.state.events));
+            events.extend(timeline_events(&room_id, room.timeline.events));
+        }
+
+        for (room_id, room) in invite {
+            events.extend(room.invite_state.events.into_iter().map(|event| {
+                SyncEvent::InviteState {
+                    room_id: room_id.clone(),
+                    event,
+                }
+            }));
+        }
+
+        for (room_id, room) in knock {
+            events.extend(
+                room.knock_state
+                    .events
+                    .into_iter()
+                    .map(|event| SyncEvent::KnockState {
+                        room_id: room_id.clone(),
+                        event,
+                    }),
+            );
+        }
+
+        events.extend(
+            response
+                .presence
+                .events
+                .into_iter()
+                .filter_map(|event| match event {
+                    only::Event::Presence(event) => Some(SyncEvent::Presence(event)),
+                    only::Event::Custom(event) => Some(SyncEvent::Custom {
+                        room_id: None,
+                        event,
+                    }),
+                    // Other event types are never sent as presence updates.
+                    _ => None,
+                }),
+        );
+
+        events.extend(
+            response
+                .to_device
+                .events
+                .into_iter()
+                .map(SyncEvent::ToDevice),
+        );
+
+        events
+    }
+
+    /// The room this event belongs to, if any.
+    pub fn room_id(&self) -> Option<&RoomId> {
+        match self {
+            SyncEvent::Message { room_id, .. }
+            | SyncEvent::State { room_id, .. }
+            | SyncEvent::InviteState { room_id, .. }
+            | SyncEvent::KnockState { room_id, .. }
+            | SyncEvent::Receipt { room_id, .. }
+            | SyncEvent::Typing { room_id, .. }
+            | SyncEvent::AccountData { room_id, .. } => Some(room_id),
+            SyncEvent::Custom { room_id, .. } => room_id.as_ref(),
+            SyncEvent::Presence(_) | SyncEvent::ToDevice(_) => None,
+        }
+    }
+}
+
+/// Receives the events of sync responses, sorted into `SyncEvent`s.
+///
+/// This is implemented for all closures taking a `SyncEvent` and returning a `Result`.
+pub trait EventHandler {
+    /// The error the handler can fail with.
+    type Error: Debug;
+
+    /// Handle a single event.
+    fn handle(&mut self, event: SyncEvent) -> Result<(), Self::Error>;
+}
+
+impl<F, E> EventHandler for F
+where
+    F: FnMut(SyncEvent) -> Result<(), E>,
+    E: Debug,
+{
+    type Error = E;
+
+    fn handle(&mut self, event: SyncEvent) -> Result<(), E> {
+        self(event)
+    }
+}
+
+/// The ways an `EventHandler` can fail.
+#[derive(Debug)]
+pub enum HandlerFailure<E> {
+    /// The handler returned an error.
+    Error(E),
+    /// The handler panicked, with the given message.
+    Panic(String),
+}
+
+/// What `Client::sync_with_handler` does when its `EventHandler` fails.
+pub enum ErrorPolicy<E> {
+    /// Log the failure and continue with the next event.
+    LogAndContinue,
+    /// Stop syncing, resolving the future returned by `Client::sync_with_handler` with
+    /// `Error::EventHandler`.
+    Stop,
+    /// Pass the failure, along with the event the handler failed on, to the given error handler
+    /// and continue with the next event.
+    Route(Box<dyn FnMut(HandlerFailure<E>, SyncEvent)>),
+}
+
+impl<E> Debug for ErrorPolicy<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ErrorPolicy::LogAndContinue => f.write_str("LogAndContinue"),
+            ErrorPolicy::Stop => f.write_str("Stop"),
+            ErrorPolicy::Route(_) => f.write_str("Route(..)"),
+        }
+    }
+}
+
+/// Pass an event to `handler`, applying `policy` if it fails.
+///
+/// Returns an error if the sync loop should stop.
+pub(crate) fn dispatch<H>(
+    handler: &mut H,
+    policy: &mut ErrorPolicy<H::Error>,
+    event: SyncEvent,
+) -> Result<(), Error>
+where
+    H: EventHandler,
+{
+    // The event is only needed again if it has to be passed to the error handler.
+    let routed_event = match policy {
+        ErrorPolicy::Route(_) => Some(event.clone()),
+        _ => None,
+    };
+
+    let failure = match panic::catch_unwind(AssertUnwindSafe(|| handler.handle(event))) {
+        Ok(Ok(())) => return Ok(()),
+        Ok(Err(error)) => HandlerFailure::Error(error),
+        Err(payload) => HandlerFailure::Panic(panic_message(payload)),
+    };
+
+    match policy {
+        ErrorPolicy::LogAndContinue => {
+            log::error!("event handler failed: {:?}", failure);
+            Ok(())
+        }
+        ErrorPolicy::Stop => Err(Error::EventHandler(format!("{:?}", failure))),
+        ErrorPolicy::Route(route) => {
+            if let Some(event) = routed_event {
+                route(failure, event);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// The message a panic was started with, if it is a string.
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast_ref::<&str>() {
+            Some(message) => (*message).to_owned(),
+            None => "unknown panic payload".to_owned(),
+        },
+    }
+}
+
+fn state_events<'a>(
+    room_id: &'a RoomId,
+    events: Vec<only::StateEvent>,
+) -> impl Iterator<Item = SyncEvent> + 'a {
+    events.into_iter().map(move |event| SyncEvent::State {
+        room_id: room_id.clone(),
+        event,
+    })
+}
+
+fn timeline_events<'a>(
+    room_id: &'a RoomId,
+    events: Vec<all::RoomEvent>,
+) -> impl Iterator<Item = SyncEvent> + 'a {
+    events
+        .into_iter()
+        .map(move |event| timeline_event(room_id.clone(), event))
+}
+
+fn timeline_event(room_id: RoomId, event: all::RoomEvent) -> SyncEvent {
+    match TimelineEvent::from(event) {
+        TimelineEvent::Message(event) => SyncEvent::Message { room_id, event },
+        TimelineEvent::State(event) => SyncEvent::State { room_id, event },
+    }
+}
+
+fn ephemeral_event(room_id: &RoomId, event: only::Event) -> Option<SyncEvent> {
+    let room_id = room_id.clone();
+
+    match event {
+        only::Event::Receipt(event) => Some(SyncEvent::Receipt { room_id, event }),
+        only::Event::Typing(event) => Some(SyncEvent::Typing { room_id, event }),
+        only::Event::Custom(event) => Some(SyncEvent::Custom {
+            room_id: Some(room_id),
+            event,
+        }),
+        // Other event types are never sent as ephemeral events.
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::SyncEvent;
+    use crate::api::r0::sync::sync_events::{Presence, Response, Rooms, ToDevice};
+
+    #[test]
+    fn to_device_events_are_dispatched() {
+        let response = Response {
+            next_batch: "next".to_owned(),
+            rooms: Rooms {
+                leave: HashMap::new(),
+                join: HashMap::new(),
+                invite: HashMap::new(),
+                knock: HashMap::new(),
+            },
+            presence: Presence { events: Vec::new() },
+            account_data: None,
+            device_lists: Default::default(),
+            to_device: ToDevice {
+                events: vec![serde_json::from_value(json!({
+                    "sender": "@alice:example.org",
+                    "type": "m.room_key",
+                    "content": { "algorithm": "m.megolm.v1.aes-sha2" },
+                }))
+                .unwrap()],
+            },
+            device_one_time_keys_count: None,
+        };
+
+        let events = SyncEvent::from_response(response);
+
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            SyncEvent::ToDevice(event) => {
+                assert_eq!(event.sender.to_string(), "@alice:example.org");
+                assert_eq!(event.event_type, "m.room_key");
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+        assert!(events[0].room_id().is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! The history of edits of messages.
+
+use futures::{
+    future::{self, Either},
+    Future, Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_events::{
+    collections::all,
+    room::message::{MessageEvent, MessageEventContent},
+    EventType,
+};
+use ruma_identifiers::{EventId, UserId};
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    error::not_found_as_none,
+    message::{RelationType, ReplacementEventContent},
+    Error, Room,
+};
+
+/// One edit of a message.
+#[derive(Clone, Debug)]
+pub struct Edit {
+    /// The ID of the edit event.
+    pub event_id: EventId,
+    /// When the edit was sent, in milliseconds since the Unix epoch.
+    pub origin_server_ts: u64,
+    /// The content of the message after the edit.
+    pub content: MessageEventContent,
+}
+
+/// The edits of a message, created by `Room::edit_history`.
+#[derive(Clone, Debug)]
+pub struct EditHistory {
+    /// The message as it was sent.
+    pub original: MessageEvent,
+    /// The edits of the message by its sender, oldest first.
+    pub edits: Vec<Edit>,
+}
+
+/// An edit event as returned by the relations endpoint.
+#[derive(Deserialize)]
+struct EditEvent {
+    event_id: EventId,
+    sender: UserId,
+    origin_server_ts: u64,
+    content: ReplacementEventContent,
+}
+
+impl EditHistory {
+    /// The current content of the message, i.e. the content of the latest edit, or of the
+    /// original if it wasn't edited.
+    pub fn current_content(&self) -> &MessageEventContent {
+        match self.edits.last() {
+            Some(edit) => &edit.content,
+            None => &self.original.content,
+        }
+    }
+
+    /// Whether the message was edited.
+    pub fn is_edited(&self) -> bool {
+        !self.edits.is_empty()
+    }
+
+    /// When the message was edited the last time, in milliseconds since the Unix epoch.
+    pub fn last_edited(&self) -> Option<u64> {
+        self.edits.last().map(|edit| edit.origin_server_ts)
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the edits of the message `event_id`.
+    ///
+    /// The original message is taken from the client's `Store` if it's there and fetched
+    /// otherwise. The edits are fetched with `Room::relations`. The edit the homeserver bundled
+    /// with the original message is included as well, which keeps the latest edit known if the
+    /// homeserver doesn't support the relations endpoint. Edits by other users than the sender of
+    /// the message are left out, as clients must not apply them.
+    pub fn edit_history(
+        &self,
+        event_id: EventId,
+    ) -> impl Future<Item = EditHistory, Error = Error> {
+        let room = self.clone();
+
+        self.original_message(event_id.clone())
+            .and_then(move |original| {
+                room.relations_json(
+                    event_id,
+                    Some(RelationType::Replace),
+                    Some(EventType::RoomMessage),
+                )
+                .collect()
+                .then(|result| not_found_as_none(result).map(Option::unwrap_or_default))
+                .map(move |events| (original, events))
+            })
+            .map(|(original, mut events)| {
+                events.extend(bundled_edit(&original));
+
+                let mut edits: Vec<_> = events
+                    .into_iter()
+                    .filter_map(|event| serde_json::from_value::<EditEvent>(event).ok())
+                    .filter(|event| {
+                        event.sender == original.sender
+                            && event.content.relates_to.rel_type == Some(RelationType::Replace)
+                            && event.content.relates_to.event_id.as_ref()
+                                == Some(&original.event_id)
+                    })
+                    .map(|event| Edit {
+                        event_id: event.event_id,
+                        origin_server_ts: event.origin_server_ts,
+                        content: event.content.new_content,
+                    })
+                    .collect();
+
+                // Edits with the same timestamp are ordered by their ID, like the specification
+                // requires for picking the latest one.
+                edits.sort_by(|a, b| {
+                    (a.origin_server_ts, a.event_id.to_string())
+                        .cmp(&(b.origin_server_ts, b.event_id.to_string()))
+                });
+                edits.dedup_by(|a, b| a.event_id == b.event_id);
+
+                EditHistory { original, edits }
+            })
+    }
+
+    /// The message event `event_id`, from the store or the homeserver.
+    fn original_message(
+        &self,
+        event_id: EventId,
+    ) -> impl Future<Item = MessageEvent, Error = Error> {
+        use crate::api::r0::room::get_room_event;
+
+        let stored = self
+            .client()
+            .store()
+            .snapshot()
+            .timeline(self.room_id())
+            .iter()
+            .find_map(|event| match event {
+                all::RoomEvent::RoomMessage(event) if event.event_id == event_id => {
+                    Some(event.clone())
+                }
+                _ => None,
+            });
+
+        match stored {
+            Some(event) => Either::A(future::ok(event)),
+            None => Either::B(
+                get_room_event::call(
+                    self.client().clone(),
+                    get_room_event::Request {
+                        room_id: self.room_id().clone(),
+                        event_id,
+                    },
+                )
+                .and_then(|response| {
+                    serde_json::from_value::<MessageEvent>(response.event).map_err(Error::from)
+                }),
+            ),
+        }
+    }
+}
+
+/// The edit event bundled in the `unsigned` data of `original` by the homeserver, if any.
+///
+/// Homeservers following newer versions of the specification only bundle the ID of the edit,
+/// which is left out.
+fn bundled_edit(original: &MessageEvent) -> Option<Value> {
+    original
+        .unsigned
+        .as_ref()?
+        .pointer("/m.relations/m.replace")
+        .filter(|replacement| replacement.get("content").is_some())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma_events::room::message::MessageEvent;
+    use serde_json::{json, Value};
+
+    use super::bundled_edit;
+
+    fn message(replacement: Value) -> MessageEvent {
+        serde_json::from_value(json!({
+            "type": "m.room.message",
+            "event_id": "$original:example.org",
+            "room_id": "!room:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1,
+            "content": { "msgtype": "m.text", "body": "Hello" },
+            "unsigned": { "m.relations": { "m.replace": replacement } },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn only_complete_bundled_edits_are_used() {
+        let edit = json!({
+            "event_id": "$edit:example.org",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 2,
+            "content": {},
+        });
+
+        assert_eq!(bundled_edit(&message(edit.clone())), Some(edit));
+        assert_eq!(
+            bundled_edit(&message(json!({ "event_id": "$edit:example.org" }))),
+            None
+        );
+    }
+}
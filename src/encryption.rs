@@ -0,0 +1,852 @@
+//! End-to-end encryption with Olm.
+//!
+//! The cryptography is left to an Olm library chosen by the application, e.g. the `olm-rs`
+//! binding of libolm, which is plugged in by implementing `OlmAccount`. This module takes care
+//! of the protocol around it: signing and publishing keys, and saving the account.
+
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_identifiers::{RoomId, UserId};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    api::r0::{
+        keys::{upload_keys, DeviceKeys},
+        sync::sync_events::Response,
+    },
+    device_tracking::DeviceTracker,
+    megolm::OutboundGroup,
+    Client, Error, StoreSnapshot,
+};
+
+/// The algorithm of Olm-encrypted to-device messages.
+pub const OLM_ALGORITHM: &str = "m.olm.v1.curve25519-aes-sha2";
+
+/// The algorithm of Megolm-encrypted room messages.
+pub const MEGOLM_ALGORITHM: &str = "m.megolm.v1.aes-sha2";
+
+/// The algorithm of one-time keys signed by the device.
+const SIGNED_CURVE25519: &str = "signed_curve25519";
+
+/// The public identity keys of an Olm account, in unpadded base64.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IdentityKeys {
+    /// The Curve25519 key used to establish Olm sessions.
+    pub curve25519: String,
+    /// The Ed25519 key used to sign.
+    pub ed25519: String,
+}
+
+/// An Olm account, implemented with an Olm library.
+///
+/// Install one with `Client::enable_encryption`. Keys and signatures are in unpadded base64,
+/// like libolm returns them.
+pub trait OlmAccount: Debug {
+    /// The public identity keys of the account.
+    fn identity_keys(&self) -> IdentityKeys;
+
+    /// Sign `message` with the Ed25519 key of the account.
+    fn sign(&self, message: &str) -> String;
+
+    /// Whether `signature` is a valid signature of `message` by the Ed25519 key `key`, e.g. with
+    /// the utility functions of libolm.
+    fn verify(&self, key: &str, message: &str, signature: &str) -> bool;
+
+    /// How many one-time keys the account can hold.
+    fn max_one_time_keys(&self) -> usize;
+
+    /// Generate `count` new one-time keys.
+    fn generate_one_time_keys(&mut self, count: usize);
+
+    /// The public Curve25519 one-time keys that weren't published yet, by key ID.
+    fn one_time_keys(&self) -> BTreeMap<String, String>;
+
+    /// Mark all one-time keys as published.
+    fn mark_keys_as_published(&mut self);
+
+    /// Serialize the account, encrypted with `pickle_key`.
+    fn pickle(&self, pickle_key: &[u8]) -> String;
+
+    /// Start an Olm session with the device with the Curve25519 identity key `identity_key`,
+    /// using one of its one-time keys.
+    ///
+    /// Returns `None` if the keys are invalid.
+    fn create_outbound_session(
+        &self,
+        identity_key: &str,
+        one_time_key: &str,
+    ) -> Option<Box<dyn OlmSession>>;
+
+    /// Start a new outbound Megolm session for encrypting room messages.
+    fn create_outbound_group_session(&self) -> Box<dyn OutboundGroupSession>;
+}
+
+/// An Olm session with another device, implemented with an Olm library.
+pub trait OlmSession: Debug {
+    /// The ID of the session.
+    fn session_id(&self) -> String;
+
+    /// Encrypt `plaintext`, returning the type of the message, 0 for pre-key messages and 1 for
+    /// normal ones, and the ciphertext in unpadded base64.
+    fn encrypt(&mut self, plaintext: &str) -> (u64, String);
+
+    /// Serialize the session, encrypted with `pickle_key`.
+    fn pickle(&self, pickle_key: &[u8]) -> String;
+}
+
+/// An outbound Megolm session encrypting the messages of a room, implemented with an Olm
+/// library.
+pub trait OutboundGroupSession: Debug {
+    /// The ID of the session.
+    fn session_id(&self) -> String;
+
+    /// The key other devices need to decrypt the messages from the current one on, in unpadded
+    /// base64.
+    fn session_key(&self) -> String;
+
+    /// Encrypt `plaintext`, returning the ciphertext in unpadded base64.
+    fn encrypt(&mut self, plaintext: &str) -> String;
+}
+
+/// An Olm account and its Olm sessions saved by `Client::save_olm_account`.
+///
+/// The account is restored by unpickling `pickle` with the Olm library, and passing it to
+/// `Client::enable_encryption` of a client logged in as the same device. Its sessions are
+/// restored the same way with `Client::restore_olm_session`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PickledOlmAccount {
+    /// The user the account belongs to.
+    pub user_id: UserId,
+    /// The device the account belongs to.
+    pub device_id: String,
+    /// The account, as serialized by `OlmAccount::pickle`.
+    pub pickle: String,
+    /// The Olm sessions with other devices as serialized by `OlmSession::pickle`, by the
+    /// Curve25519 identity key of the device.
+    #[serde(default)]
+    pub sessions: BTreeMap<String, String>,
+}
+
+impl PickledOlmAccount {
+    /// Read an account from a JSON file written by `Client::save_olm_account`.
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+}
+
+/// The end-to-end encryption state of a client.
+#[derive(Debug, Default)]
+pub(crate) struct EncryptionState {
+    pub(crate) account: Option<Box<dyn OlmAccount>>,
+    /// Whether the identity keys of the account were uploaded by this client.
+    device_keys_uploaded: Cell<bool>,
+    /// How many one-time keys the homeserver has for the device, as reported by the latest key
+    /// upload or sync.
+    one_time_key_count: Cell<Option<u64>>,
+    /// The devices of users sharing encrypted rooms with the user.
+    pub(crate) devices: DeviceTracker,
+    /// The Olm sessions with other devices, by their Curve25519 identity key.
+    pub(crate) sessions: HashMap<String, Box<dyn OlmSession>>,
+    /// The outbound Megolm session of each encrypted room.
+    pub(crate) group_sessions: HashMap<RoomId, OutboundGroup>,
+}
+
+impl EncryptionState {
+    /// Update the tracked devices and the number of one-time keys on the homeserver with a sync
+    /// response.
+    pub(crate) fn apply_sync(&mut self, response: &Response, store: &StoreSnapshot) {
+        self.devices.apply_sync(response, store);
+
+        if let Some(ref counts) = response.device_one_time_keys_count {
+            let count = counts.get(SIGNED_CURVE25519).cloned().unwrap_or(0);
+
+            self.one_time_key_count.set(Some(count));
+        }
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Use the given Olm account for end-to-end encryption.
+    ///
+    /// The account has to belong to the device of the client's session. Publish its keys with
+    /// `upload_keys`.
+    pub fn enable_encryption<A>(&self, account: A)
+    where
+        A: OlmAccount + 'static,
+    {
+        let mut encryption = self.0.encryption.borrow_mut();
+
+        encryption.account = Some(Box::new(account));
+        encryption.device_keys_uploaded.set(false);
+        encryption.one_time_key_count.set(None);
+    }
+
+    /// The identity keys of the device, signed with its Ed25519 key.
+    ///
+    /// Fails with `Error::EncryptionDisabled` if `enable_encryption` wasn't called.
+    pub fn device_keys(&self) -> Result<DeviceKeys, Error> {
+        let session = self.logged_in_session()?;
+        let encryption = self.0.encryption.borrow();
+        let account = encryption
+            .account
+            .as_ref()
+            .ok_or(Error::EncryptionDisabled)?;
+        let device_id = session.device_id();
+        let identity_keys = account.identity_keys();
+
+        let mut keys = json!({
+            "user_id": session.user_id().to_string(),
+            "device_id": device_id,
+            "algorithms": [OLM_ALGORITHM, MEGOLM_ALGORITHM],
+            "keys": {
+                format!("curve25519:{}", device_id): identity_keys.curve25519,
+                format!("ed25519:{}", device_id): identity_keys.ed25519,
+            },
+        });
+        if let Value::Object(ref mut object) = keys {
+            sign_json(&**account, session.user_id(), device_id, object)?;
+        }
+
+        Ok(serde_json::from_value(keys)?)
+    }
+
+    /// Publish the keys of the Olm account set with `enable_encryption`.
+    ///
+    /// Uploads the identity keys of the device the first time, and as many new one-time keys as
+    /// it takes for the homeserver to have half of the number the account can hold, so other
+    /// devices can start Olm sessions with this one. Resolves to the number of one-time keys the
+    /// homeserver has of each algorithm.
+    ///
+    /// Other devices use up one-time keys by claiming them, so this should be called whenever a
+    /// sync reports fewer keys than half of what the account can hold.
+    pub fn upload_keys(&self) -> impl Future<Item = BTreeMap<String, u64>, Error = Error> {
+        let client = self.clone();
+        let known_count = {
+            let encryption = self.0.encryption.borrow();
+
+            encryption
+                .one_time_key_count
+                .get()
+                .filter(|_| encryption.device_keys_uploaded.get())
+        };
+
+        // Without knowing how many one-time keys the homeserver has, only the identity keys are
+        // uploaded, and the response tells how many one-time keys are needed.
+        let count = match known_count {
+            Some(count) => Either::A(future::ok(count)),
+            None => Either::B(
+                self.send_keys(false)
+                    .map(|counts| counts.get(SIGNED_CURVE25519).cloned().unwrap_or(0)),
+            ),
+        };
+
+        count.and_then(move |count| {
+            let target = {
+                let encryption = client.0.encryption.borrow();
+
+                match encryption.account {
+                    Some(ref account) => account.max_one_time_keys() as u64 / 2,
+                    None => return Either::A(future::err(Error::EncryptionDisabled)),
+                }
+            };
+
+            if count >= target {
+                let mut counts = BTreeMap::new();
+                counts.insert(SIGNED_CURVE25519.to_owned(), count);
+
+                return Either::B(Either::A(future::ok(counts)));
+            }
+
+            if let Some(ref mut account) = client.0.encryption.borrow_mut().account {
+                account.generate_one_time_keys((target - count) as usize);
+            }
+
+            Either::B(Either::B(client.send_keys(true)))
+        })
+    }
+
+    /// Continue using an Olm session saved by `save_olm_account` with the device with the
+    /// Curve25519 identity key `identity_key`.
+    pub fn restore_olm_session<S>(&self, identity_key: String, session: S)
+    where
+        S: OlmSession + 'static,
+    {
+        self.0
+            .encryption
+            .borrow_mut()
+            .sessions
+            .insert(identity_key, Box::new(session));
+    }
+
+    /// Save the Olm account set with `enable_encryption` and its Olm sessions to a JSON file,
+    /// encrypted with `pickle_key`.
+    ///
+    /// The sessions should be saved whenever new ones were started or messages were encrypted
+    /// with them, as their state changes with every message.
+    ///
+    /// The file is replaced in one step, so a crash while writing keeps the previous version.
+    pub fn save_olm_account<P: AsRef<Path>>(
+        &self,
+        path: P,
+        pickle_key: &[u8],
+    ) -> Result<(), Error> {
+        let session = self.logged_in_session()?;
+        let encryption = self.0.encryption.borrow();
+        let account = encryption
+            .account
+            .as_ref()
+            .ok_or(Error::EncryptionDisabled)?;
+
+        let pickled = PickledOlmAccount {
+            user_id: session.user_id().clone(),
+            device_id: session.device_id().to_owned(),
+            pickle: account.pickle(pickle_key),
+            sessions: encryption
+                .sessions
+                .iter()
+                .map(|(identity_key, session)| (identity_key.clone(), session.pickle(pickle_key)))
+                .collect(),
+        };
+
+        let mut temporary = path.as_ref().as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer_pretty(&mut writer, &pickled)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(temporary, path)?;
+
+        Ok(())
+    }
+
+    /// Upload the identity keys of the device unless they were uploaded already, and the
+    /// unpublished one-time keys if `one_time_keys` is true.
+    fn send_keys(
+        &self,
+        one_time_keys: bool,
+    ) -> impl Future<Item = BTreeMap<String, u64>, Error = Error> {
+        let client = self.clone();
+        let data = self.0.clone();
+
+        future::result(self.keys_request(one_time_keys)).and_then(move |request| {
+            let published_one_time_keys = request.one_time_keys.is_some();
+
+            upload_keys::call(client, request).map(move |response| {
+                let count = response
+                    .one_time_key_counts
+                    .get(SIGNED_CURVE25519)
+                    .cloned()
+                    .unwrap_or(0);
+                let mut encryption = data.encryption.borrow_mut();
+
+                encryption.device_keys_uploaded.set(true);
+                encryption.one_time_key_count.set(Some(count));
+
+                if published_one_time_keys {
+                    if let Some(ref mut account) = encryption.account {
+                        account.mark_keys_as_published();
+                    }
+                }
+
+                response.one_time_key_counts
+            })
+        })
+    }
+
+    /// The request publishing the keys that weren't published yet.
+    fn keys_request(&self, one_time_keys: bool) -> Result<upload_keys::Request, Error> {
+        let device_keys = if self.0.encryption.borrow().device_keys_uploaded.get() {
+            None
+        } else {
+            Some(self.device_keys()?)
+        };
+
+        let one_time_keys = if one_time_keys {
+            let session = self.logged_in_session()?;
+            let encryption = self.0.encryption.borrow();
+            let account = encryption
+                .account
+                .as_ref()
+                .ok_or(Error::EncryptionDisabled)?;
+            let mut signed = BTreeMap::new();
+
+            for (key_id, key_value) in account.one_time_keys() {
+                let mut key = Map::new();
+                key.insert("key".to_owned(), Value::String(key_value));
+                sign_json(&**account, session.user_id(), session.device_id(), &mut key)?;
+                signed.insert(
+                    format!("{}:{}", SIGNED_CURVE25519, key_id),
+                    Value::Object(key),
+                );
+            }
+
+            Some(signed)
+        } else {
+            None
+        };
+
+        Ok(upload_keys::Request {
+            device_keys,
+            one_time_keys,
+        })
+    }
+}
+
+/// Sign a JSON object with the device's Ed25519 key, adding the signature to its `signatures`.
+///
+/// The signature covers the canonical JSON of the object without `signatures` and `unsigned`.
+pub(crate) fn sign_json(
+    account: &dyn OlmAccount,
+    user_id: &UserId,
+    device_id: &str,
+    object: &mut Map<String, Value>,
+) -> Result<(), Error> {
+    let signatures = object.remove("signatures");
+    let unsigned = object.remove("unsigned");
+
+    let signature = account.sign(&canonical_json(object));
+
+    let mut signatures = match signatures {
+        Some(Value::Object(signatures)) => signatures,
+        _ => Map::new(),
+    };
+    let user_signatures = signatures
+        .entry(user_id.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    if let Value::Object(user_signatures) = user_signatures {
+        user_signatures.insert(format!("ed25519:{}", device_id), Value::String(signature));
+    }
+
+    object.insert("signatures".to_owned(), Value::Object(signatures));
+
+    if let Some(unsigned) = unsigned {
+        object.insert("unsigned".to_owned(), unsigned);
+    }
+
+    Ok(())
+}
+
+/// Whether a JSON object is signed by the device `device_id` of `user_id`, whose Ed25519 key is
+/// `ed25519_key`, as `sign_json` signs it.
+pub(crate) fn verify_json(
+    account: &dyn OlmAccount,
+    user_id: &UserId,
+    device_id: &str,
+    ed25519_key: &str,
+    object: Value,
+) -> bool {
+    let mut object = match object {
+        Value::Object(object) => object,
+        _ => return false,
+    };
+    let signature = object
+        .remove("signatures")
+        .as_ref()
+        .and_then(|signatures| signatures.get(user_id.to_string()))
+        .and_then(|signatures| signatures.get(format!("ed25519:{}", device_id)))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    object.remove("unsigned");
+
+    match signature {
+        Some(signature) => account.verify(ed25519_key, &canonical_json(&object), &signature),
+        None => false,
+    }
+}
+
+/// Serialize a JSON object as canonical JSON, the form signatures cover.
+///
+/// Keys are sorted by code point and there is no whitespace. This doesn't rely on the order
+/// `serde_json` keeps keys in, which changes if any crate enables its `preserve_order` feature.
+fn canonical_json(object: &Map<String, Value>) -> String {
+    let mut json = String::new();
+
+    write_canonical_object(&mut json, object);
+    json
+}
+
+fn write_canonical_object(json: &mut String, object: &Map<String, Value>) {
+    let mut entries: Vec<_> = object.iter().collect();
+    // Comparing UTF-8 bytes orders strings by code point.
+    entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+    json.push('{');
+
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+
+        write_canonical_string(json, key);
+        json.push(':');
+        write_canonical_value(json, value);
+    }
+
+    json.push('}');
+}
+
+fn write_canonical_value(json: &mut String, value: &Value) {
+    match value {
+        Value::Null => json.push_str("null"),
+        Value::Bool(value) => json.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => json.push_str(&number.to_string()),
+        Value::String(string) => write_canonical_string(json, string),
+        Value::Array(values) => {
+            json.push('[');
+
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+
+                write_canonical_value(json, value);
+            }
+
+            json.push(']');
+        }
+        Value::Object(object) => write_canonical_object(json, object),
+    }
+}
+
+/// Write a JSON string, escaping only what JSON requires to be escaped.
+fn write_canonical_string(json: &mut String, string: &str) {
+    json.push('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\u{8}' => json.push_str("\\b"),
+            '\u{c}' => json.push_str("\\f"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        cell::Cell,
+        collections::{BTreeMap, HashMap},
+        convert::TryFrom,
+        rc::Rc,
+        sync::Arc,
+    };
+
+    use futures::{executor, Future};
+    use ruma_identifiers::UserId;
+    use serde_json::{json, Value};
+    use url::Url;
+
+    use super::{
+        canonical_json, sign_json, IdentityKeys, OlmAccount, OlmSession, OutboundGroupSession,
+        PickledOlmAccount, MEGOLM_ALGORITHM, OLM_ALGORITHM, SIGNED_CURVE25519,
+    };
+    use crate::{
+        api::r0::{
+            keys::DeviceKeys,
+            sync::sync_events::{Presence, Response, Rooms},
+        },
+        Client, ClientBuilder, Session,
+    };
+
+    /// An `OlmAccount` without cryptography, whose signatures are the signing key followed by the
+    /// message.
+    #[derive(Debug)]
+    pub(crate) struct FakeAccount {
+        name: String,
+        /// How many one-time keys were generated.
+        pub(crate) generated: Rc<Cell<usize>>,
+        /// How many outbound Megolm sessions were started.
+        pub(crate) group_sessions: Rc<Cell<usize>>,
+        unpublished: BTreeMap<String, String>,
+    }
+
+    impl FakeAccount {
+        pub(crate) fn new(name: &str) -> Self {
+            FakeAccount {
+                name: name.to_owned(),
+                generated: Rc::new(Cell::new(0)),
+                group_sessions: Rc::new(Cell::new(0)),
+                unpublished: BTreeMap::new(),
+            }
+        }
+    }
+
+    /// The signature `FakeAccount` makes of `message` with the Ed25519 key `key`.
+    pub(crate) fn fake_signature(key: &str, message: &str) -> String {
+        format!("{}|{}", key, message)
+    }
+
+    impl OlmAccount for FakeAccount {
+        fn identity_keys(&self) -> IdentityKeys {
+            IdentityKeys {
+                curve25519: format!("{}-curve25519", self.name),
+                ed25519: format!("{}-ed25519", self.name),
+            }
+        }
+
+        fn sign(&self, message: &str) -> String {
+            fake_signature(&self.identity_keys().ed25519, message)
+        }
+
+        fn verify(&self, key: &str, message: &str, signature: &str) -> bool {
+            signature == fake_signature(key, message)
+        }
+
+        fn max_one_time_keys(&self) -> usize {
+            100
+        }
+
+        fn generate_one_time_keys(&mut self, count: usize) {
+            for _ in 0..count {
+                let index = self.generated.get();
+
+                self.unpublished
+                    .insert(format!("AAAA{}", index), format!("key{}", index));
+                self.generated.set(index + 1);
+            }
+        }
+
+        fn one_time_keys(&self) -> BTreeMap<String, String> {
+            self.unpublished.clone()
+        }
+
+        fn mark_keys_as_published(&mut self) {
+            self.unpublished.clear();
+        }
+
+        fn pickle(&self, pickle_key: &[u8]) -> String {
+            format!("{}:{}", self.name, String::from_utf8_lossy(pickle_key))
+        }
+
+        fn create_outbound_session(
+            &self,
+            identity_key: &str,
+            one_time_key: &str,
+        ) -> Option<Box<dyn OlmSession>> {
+            Some(Box::new(FakeSession(format!(
+                "{}/{}",
+                identity_key, one_time_key
+            ))))
+        }
+
+        fn create_outbound_group_session(&self) -> Box<dyn OutboundGroupSession> {
+            let index = self.group_sessions.get();
+            self.group_sessions.set(index + 1);
+
+            Box::new(FakeSession(format!("{}-group{}", self.name, index)))
+        }
+    }
+
+    /// An Olm or Megolm session without cryptography, which "encrypts" to the plaintext.
+    #[derive(Debug)]
+    pub(crate) struct FakeSession(pub(crate) String);
+
+    impl OlmSession for FakeSession {
+        fn session_id(&self) -> String {
+            self.0.clone()
+        }
+
+        fn encrypt(&mut self, plaintext: &str) -> (u64, String) {
+            (0, plaintext.to_owned())
+        }
+
+        fn pickle(&self, _pickle_key: &[u8]) -> String {
+            self.0.clone()
+        }
+    }
+
+    impl OutboundGroupSession for FakeSession {
+        fn session_id(&self) -> String {
+            self.0.clone()
+        }
+
+        fn session_key(&self) -> String {
+            format!("{}-key", self.0)
+        }
+
+        fn encrypt(&mut self, plaintext: &str) -> String {
+            plaintext.to_owned()
+        }
+    }
+
+    /// Sign a JSON object as `device_id` of `user_id` with `account`.
+    pub(crate) fn signed(
+        account: &FakeAccount,
+        user_id: &str,
+        device_id: &str,
+        value: Value,
+    ) -> Value {
+        let mut object = match value {
+            Value::Object(object) => object,
+            _ => panic!("not an object"),
+        };
+
+        sign_json(
+            account,
+            &UserId::try_from(user_id).unwrap(),
+            device_id,
+            &mut object,
+        )
+        .unwrap();
+        Value::Object(object)
+    }
+
+    /// The keys of the device `device_id` of `user_id` with the identity keys of `account`,
+    /// signed by it.
+    pub(crate) fn device_keys(account: &FakeAccount, user_id: &str, device_id: &str) -> DeviceKeys {
+        let identity_keys = account.identity_keys();
+        let keys = json!({
+            "user_id": user_id,
+            "device_id": device_id,
+            "algorithms": [OLM_ALGORITHM, MEGOLM_ALGORITHM],
+            "keys": {
+                format!("curve25519:{}", device_id): identity_keys.curve25519,
+                format!("ed25519:{}", device_id): identity_keys.ed25519,
+            },
+        });
+
+        serde_json::from_value(signed(account, user_id, device_id, keys)).unwrap()
+    }
+
+    /// A client logged in as `@alice:example.org` on `DEVICE`, whose homeserver refuses
+    /// connections.
+    pub(crate) fn client() -> Client<hyper::client::HttpConnector> {
+        let session = Session::new(
+            "token".to_owned(),
+            UserId::try_from("@alice:example.org").unwrap(),
+            "DEVICE".to_owned(),
+        );
+
+        ClientBuilder::new(Url::parse("http://127.0.0.1:1").unwrap())
+            .session(session)
+            .build()
+    }
+
+    /// A `Notify` for polling futures once in tests.
+    pub(crate) fn noop_notify() -> executor::NotifyHandle {
+        struct Noop;
+
+        impl executor::Notify for Noop {
+            fn notify(&self, _id: usize) {}
+        }
+
+        executor::NotifyHandle::from(Arc::new(Noop))
+    }
+
+    /// An empty sync response.
+    pub(crate) fn sync_response() -> Response {
+        Response {
+            next_batch: "next".to_owned(),
+            rooms: Rooms {
+                leave: HashMap::new(),
+                join: HashMap::new(),
+                invite: HashMap::new(),
+                knock: HashMap::new(),
+            },
+            presence: Presence { events: Vec::new() },
+            account_data: None,
+            device_lists: Default::default(),
+            to_device: Default::default(),
+            device_one_time_keys_count: None,
+        }
+    }
+
+    fn canonical(value: Value) -> String {
+        match value {
+            Value::Object(object) => canonical_json(&object),
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn sorts_keys_by_code_point() {
+        assert_eq!(
+            canonical(json!({ "b": "2", "a": "1", "日": 1, "B": [true, null] })),
+            r#"{"B":[true,null],"a":"1","b":"2","日":1}"#
+        );
+        assert_eq!(
+            canonical(json!({ "one": 1, "two": { "z": {}, "a": [] } })),
+            r#"{"one":1,"two":{"a":[],"z":{}}}"#
+        );
+    }
+
+    #[test]
+    fn escapes_only_what_json_requires() {
+        assert_eq!(
+            canonical(json!({ "a": "\u{1}\"\\\n\t/é\u{7f}" })),
+            r#"{"a":"\u0001\"\\\n\t/é"#.to_owned() + "\u{7f}\"}"
+        );
+    }
+
+    #[test]
+    fn fewer_one_time_keys_in_a_sync_trigger_an_upload() {
+        let client = client();
+        let account = FakeAccount::new("alice");
+        let generated = account.generated.clone();
+        client.enable_encryption(account);
+        {
+            let encryption = client.0.encryption.borrow();
+            encryption.device_keys_uploaded.set(true);
+            encryption.one_time_key_count.set(Some(50));
+        }
+
+        let counts = client.upload_keys().wait().unwrap();
+        assert_eq!(counts[SIGNED_CURVE25519], 50);
+        assert_eq!(generated.get(), 0);
+
+        let mut response = sync_response();
+        let mut counts = BTreeMap::new();
+        counts.insert(SIGNED_CURVE25519.to_owned(), 10);
+        response.device_one_time_keys_count = Some(counts);
+        client.0.apply_sync(&mut response, None);
+
+        // The new keys are generated before the upload request is sent, which can't succeed
+        // without a homeserver.
+        let _ = executor::spawn(client.upload_keys()).poll_future_notify(&noop_notify(), 0);
+        assert_eq!(generated.get(), 40);
+    }
+
+    #[test]
+    fn saved_accounts_replace_the_previous_file() {
+        let path = std::env::temp_dir().join(format!(
+            "ruma-client-olm-account-{}.json",
+            std::process::id()
+        ));
+        let client = client();
+        client.enable_encryption(FakeAccount::new("alice"));
+
+        client.save_olm_account(&path, b"first").unwrap();
+        client.save_olm_account(&path, b"second").unwrap();
+
+        let saved = PickledOlmAccount::read_from_file(&path).unwrap();
+        assert_eq!(saved.pickle, "alice:second");
+        assert_eq!(saved.device_id, "DEVICE");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! The sources of time and randomness of a client.
+
+use std::{
+    fmt::Debug,
+    time::{Instant, SystemTime},
+};
+
+use futures::Future;
+use rand::RngCore;
+use tokio_timer::Delay;
+
+use crate::Error;
+
+/// A future resolving once a `Clock` reached a deadline, see `Clock::delay_until`.
+pub type ClockDelay = Box<dyn Future<Item = (), Error = Error>>;
+
+/// A source of the current time.
+///
+/// The default is `SystemClock`. Tests can set their own clock with `ClientBuilder::clock` to
+/// control rate limiting, timeouts, retries and generated transaction IDs. All waiting of the
+/// client goes through `delay_until`, so a clock and its delays always agree on the time.
+pub trait Clock: Debug {
+    /// The current wall-clock time.
+    fn now(&self) -> SystemTime;
+
+    /// The current monotonic time, used for scheduling requests.
+    fn instant(&self) -> Instant;
+
+    /// A future resolving once `instant` reaches `deadline`.
+    ///
+    /// The default waits on the Tokio timer, whose time `SystemClock::instant` follows. Clocks
+    /// with their own time have to override this.
+    fn delay_until(&self, deadline: Instant) -> ClockDelay {
+        Box::new(Delay::new(deadline).map_err(Error::from))
+    }
+}
+
+/// The clock of the operating system.
+///
+/// Monotonic time is taken from `tokio_timer::clock`, so it follows a mocked clock of the tokio
+/// runtime the client is running on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        tokio_timer::clock::now()
+    }
+}
+
+/// A source of randomness for a client, e.g. for transaction IDs.
+///
+/// This is implemented for every `rand::RngCore` that implements `Debug`, so tests can set a
+/// seeded RNG like `rand::rngs::StdRng` with `ClientBuilder::rng`. The default is a `StdRng`
+/// seeded from the operating system.
+pub trait Rng: RngCore + Debug {}
+
+impl<R> Rng for R where R: RngCore + Debug {}
+
+/// A clock for tests that only moves when it is told to.
+#[cfg(test)]
+#[derive(Clone, Debug)]
+pub(crate) struct TestClock(std::rc::Rc<std::cell::Cell<Instant>>);
+
+#[cfg(test)]
+impl TestClock {
+    pub(crate) fn new() -> Self {
+        TestClock(std::rc::Rc::new(std::cell::Cell::new(Instant::now())))
+    }
+
+    pub(crate) fn advance(&self, duration: std::time::Duration) {
+        self.0.set(self.0.get() + duration);
+    }
+}
+
+#[cfg(test)]
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH
+    }
+
+    fn instant(&self) -> Instant {
+        self.0.get()
+    }
+
+    /// Resolves when polled after the clock was advanced past `deadline`. Tests poll by hand.
+    fn delay_until(&self, deadline: Instant) -> ClockDelay {
+        let clock = self.clone();
+
+        Box::new(futures::future::poll_fn(move || {
+            if clock.instant() >= deadline {
+                Ok(futures::Async::Ready(()))
+            } else {
+                Ok(futures::Async::NotReady)
+            }
+        }))
+    }
+}
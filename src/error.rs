@@ -1,15 +1,27 @@
-use http::uri::InvalidUri;
+use std::io::Error as IoError;
+
+use http::{uri::InvalidUri, Method, StatusCode};
 use hyper::error::Error as HyperError;
 use ruma_api::Error as RumaApiError;
+#[cfg(feature = "encryption")]
+use ruma_identifiers::UserId;
+use ruma_identifiers::{EventId, RoomId};
 use serde_json::Error as SerdeJsonError;
 use serde_urlencoded::ser::Error as SerdeUrlEncodedSerializeError;
+use tokio_timer::Error as TimerError;
 use url::ParseError;
 
+use crate::{session::InvalidationReason, uiaa::UiaaInfo};
+
 /// An error that occurs during client operations.
 #[derive(Debug)]
 pub enum Error {
     /// Queried endpoint requires authentication but was called on an anonymous client
     AuthenticationRequired,
+    /// The homeserver no longer accepts the access token of the session, for the given reason.
+    SessionInvalidated(InvalidationReason),
+    /// An error when reading or writing a file.
+    Io(IoError),
     /// An error at the HTTP layer.
     Hyper(HyperError),
     /// An error when parsing a string as a URI.
@@ -22,6 +34,122 @@ pub enum Error {
     SerdeJson(SerdeJsonError),
     /// An error when serializing a query string value.
     SerdeUrlEncodedSerialize(SerdeUrlEncodedSerializeError),
+    /// An error of the timer used to wait between requests.
+    Timer(TimerError),
+    /// An event handler failed and the `ErrorPolicy` was `Stop`, with a description of the
+    /// failure.
+    EventHandler(String),
+    /// A content scanner rejected downloaded media, for the given reason.
+    ContentBlocked(String),
+    /// A file is larger than the homeserver accepts uploads to be.
+    UploadTooLarge {
+        /// The size of the file in bytes.
+        size: u64,
+        /// The maximum size of an upload in bytes.
+        max_size: u64,
+    },
+    /// The given string isn't a valid `mxc://` URI, see `MxcUri`.
+    InvalidMxcUri(String),
+    /// A migration of account data failed.
+    AccountDataMigration {
+        /// The version the migration started from.
+        from_version: u64,
+        /// Why the migration failed.
+        reason: String,
+    },
+    /// The account data of the given type kept changing while it was migrated.
+    AccountDataChanged(String),
+    /// The room's version doesn't support a feature.
+    UnsupportedByRoomVersion {
+        /// The version of the room.
+        room_version: String,
+        /// The first room version that supports the feature.
+        required_version: String,
+    },
+    /// The homeserver doesn't support any room version that supports a feature.
+    UnsupportedByServer {
+        /// The first room version that supports the feature.
+        required_version: String,
+    },
+    /// A file that should be an image isn't a PNG, GIF or JPEG image.
+    UnknownImageFormat,
+    /// Syncing was attempted with a client built in lightweight mode.
+    SyncDisabled,
+    /// A response was larger than `ClientBuilder::max_response_size` allows.
+    ResponseTooLarge {
+        /// The maximum size of a response in bytes.
+        max_size: u64,
+    },
+    /// The homeserver requires user-interactive authentication with more than the password.
+    InteractiveAuthRequired(UiaaInfo),
+    /// A deadline set with `Client::with_deadline` or the `Deadline` extension passed before the
+    /// operation finished.
+    DeadlineExceeded,
+    /// A client created with `Client::restricted` refused to make a request outside of its
+    /// scopes.
+    NotPermitted {
+        /// The method of the request.
+        method: Method,
+        /// The path of the request.
+        path: String,
+    },
+    /// The logged-in user already reacted to the event with the same key.
+    DuplicateAnnotation {
+        /// The event that was reacted to.
+        event_id: EventId,
+        /// The key of the reaction, usually an emoji.
+        key: String,
+    },
+    /// A configuration file couldn't be loaded, for the given reason.
+    #[cfg(feature = "config")]
+    Config(String),
+    /// End-to-end encryption is needed but `Client::enable_encryption` wasn't called.
+    #[cfg(feature = "encryption")]
+    EncryptionDisabled,
+    /// The given room uses end-to-end encryption, which needs the `encryption` feature.
+    EncryptionUnsupported(RoomId),
+    /// The homeserver has no history of the given room for the logged-in user, who has never been
+    /// in it.
+    NotInRoom(RoomId),
+    /// There is no Olm session with the given device, see `Client::start_olm_sessions`.
+    #[cfg(feature = "encryption")]
+    OlmSessionMissing {
+        /// The user the device belongs to.
+        user_id: UserId,
+        /// The ID of the device.
+        device_id: String,
+    },
+    /// A `WebhookServer` was created with an empty secret, which would accept every request.
+    #[cfg(feature = "webhook-server")]
+    EmptyWebhookSecret,
+    /// The homeserver reported that the room history purge with the given ID failed.
+    #[cfg(feature = "synapse-admin")]
+    PurgeHistoryFailed(String),
+}
+
+impl Error {
+    /// Whether the homeserver responded with 404 Not Found.
+    pub(crate) fn is_not_found(&self) -> bool {
+        match self {
+            Error::RumaApi(RumaApiError::StatusCode(status)) => *status == StatusCode::NOT_FOUND,
+            _ => false,
+        }
+    }
+}
+
+/// Turn a 404 Not Found response into `None`, for requests of things that may not exist.
+pub(crate) fn not_found_as_none<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ref error) if error.is_not_found() => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(error: IoError) -> Error {
+        Error::Io(error)
+    }
 }
 
 impl From<HyperError> for Error {
@@ -59,3 +187,29 @@ impl From<SerdeUrlEncodedSerializeError> for Error {
         Error::SerdeUrlEncodedSerialize(error)
     }
 }
+
+impl From<TimerError> for Error {
+    fn from(error: TimerError) -> Error {
+        Error::Timer(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_not_found_becomes_none() {
+        let not_found = Err(Error::RumaApi(RumaApiError::StatusCode(
+            StatusCode::NOT_FOUND,
+        )));
+        let forbidden = Err(Error::RumaApi(RumaApiError::StatusCode(
+            StatusCode::FORBIDDEN,
+        )));
+
+        assert_eq!(not_found_as_none::<()>(not_found).ok(), Some(None));
+        assert!(not_found_as_none::<()>(forbidden).is_err());
+        assert_eq!(not_found_as_none(Ok(1)).ok(), Some(Some(1)));
+        assert!(!Error::AuthenticationRequired.is_not_found());
+    }
+}
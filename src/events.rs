@@ -0,0 +1,161 @@
+//! Access to the fields shared by all variants of the event collections of `ruma_events`.
+
+use ruma_events::{
+    collections::{all, only},
+    EventType,
+};
+use ruma_identifiers::{EventId, UserId};
+
+/// The fields every room event has.
+pub(crate) trait RoomEventFields {
+    /// The type of the event.
+    fn event_type(&self) -> &EventType;
+
+    /// The globally unique ID of the event.
+    fn event_id(&self) -> &EventId;
+
+    /// The user who sent the event.
+    fn sender(&self) -> &UserId;
+}
+
+/// The fields every state event has.
+pub(crate) trait StateEventFields: RoomEventFields {
+    /// A key that determines which piece of room state the event represents.
+    fn state_key(&self) -> &str;
+}
+
+macro_rules! impl_room_event_fields {
+    ($module:ident::$name:ident { $($variant:ident),+ }) => {
+        impl RoomEventFields for $module::$name {
+            fn event_type(&self) -> &EventType {
+                match self {
+                    $($module::$name::$variant(event) => &event.event_type,)+
+                }
+            }
+
+            fn event_id(&self) -> &EventId {
+                match self {
+                    $($module::$name::$variant(event) => &event.event_id,)+
+                }
+            }
+
+            fn sender(&self) -> &UserId {
+                match self {
+                    $($module::$name::$variant(event) => &event.sender,)+
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_state_event_fields {
+    ($module:ident::$name:ident { $($variant:ident),+ }) => {
+        impl_room_event_fields!($module::$name { $($variant),+ });
+
+        impl StateEventFields for $module::$name {
+            fn state_key(&self) -> &str {
+                match self {
+                    $($module::$name::$variant(event) => &event.state_key,)+
+                }
+            }
+        }
+    };
+}
+
+impl_room_event_fields!(all::RoomEvent {
+    CallAnswer,
+    CallCandidates,
+    CallHangup,
+    CallInvite,
+    RoomAliases,
+    RoomAvatar,
+    RoomCanonicalAlias,
+    RoomCreate,
+    RoomGuestAccess,
+    RoomHistoryVisibility,
+    RoomJoinRules,
+    RoomMember,
+    RoomMessage,
+    RoomName,
+    RoomPinnedEvents,
+    RoomPowerLevels,
+    RoomRedaction,
+    RoomThirdPartyInvite,
+    RoomTopic,
+    CustomRoom,
+    CustomState
+});
+
+impl_room_event_fields!(only::RoomEvent {
+    CallAnswer,
+    CallCandidates,
+    CallHangup,
+    CallInvite,
+    RoomMessage,
+    RoomRedaction,
+    CustomRoom
+});
+
+impl_state_event_fields!(all::StateEvent {
+    RoomAliases,
+    RoomAvatar,
+    RoomCanonicalAlias,
+    RoomCreate,
+    RoomGuestAccess,
+    RoomHistoryVisibility,
+    RoomJoinRules,
+    RoomMember,
+    RoomName,
+    RoomPinnedEvents,
+    RoomPowerLevels,
+    RoomThirdPartyInvite,
+    RoomTopic,
+    CustomState
+});
+
+/// A timeline event, split into message-like and state events.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum TimelineEvent {
+    /// A non-state event.
+    Message(only::RoomEvent),
+    /// A state event.
+    State(all::StateEvent),
+}
+
+impl From<all::RoomEvent> for TimelineEvent {
+    fn from(event: all::RoomEvent) -> Self {
+        use ruma_events::collections::all::RoomEvent::*;
+
+        match event {
+            CallAnswer(event) => TimelineEvent::Message(only::RoomEvent::CallAnswer(event)),
+            CallCandidates(event) => TimelineEvent::Message(only::RoomEvent::CallCandidates(event)),
+            CallHangup(event) => TimelineEvent::Message(only::RoomEvent::CallHangup(event)),
+            CallInvite(event) => TimelineEvent::Message(only::RoomEvent::CallInvite(event)),
+            RoomMessage(event) => TimelineEvent::Message(only::RoomEvent::RoomMessage(event)),
+            RoomRedaction(event) => TimelineEvent::Message(only::RoomEvent::RoomRedaction(event)),
+            CustomRoom(event) => TimelineEvent::Message(only::RoomEvent::CustomRoom(event)),
+            RoomAliases(event) => TimelineEvent::State(all::StateEvent::RoomAliases(event)),
+            RoomAvatar(event) => TimelineEvent::State(all::StateEvent::RoomAvatar(event)),
+            RoomCanonicalAlias(event) => {
+                TimelineEvent::State(all::StateEvent::RoomCanonicalAlias(event))
+            }
+            RoomCreate(event) => TimelineEvent::State(all::StateEvent::RoomCreate(event)),
+            RoomGuestAccess(event) => TimelineEvent::State(all::StateEvent::RoomGuestAccess(event)),
+            RoomHistoryVisibility(event) => {
+                TimelineEvent::State(all::StateEvent::RoomHistoryVisibility(event))
+            }
+            RoomJoinRules(event) => TimelineEvent::State(all::StateEvent::RoomJoinRules(event)),
+            RoomMember(event) => TimelineEvent::State(all::StateEvent::RoomMember(event)),
+            RoomName(event) => TimelineEvent::State(all::StateEvent::RoomName(event)),
+            RoomPinnedEvents(event) => {
+                TimelineEvent::State(all::StateEvent::RoomPinnedEvents(event))
+            }
+            RoomPowerLevels(event) => TimelineEvent::State(all::StateEvent::RoomPowerLevels(event)),
+            RoomThirdPartyInvite(event) => {
+                TimelineEvent::State(all::StateEvent::RoomThirdPartyInvite(event))
+            }
+            RoomTopic(event) => TimelineEvent::State(all::StateEvent::RoomTopic(event)),
+            CustomState(event) => TimelineEvent::State(all::StateEvent::CustomState(event)),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! Typed data attached to single requests.
+//!
+//! Extensions are passed to the `call_with_extensions` function of an endpoint, e.g.
+//! `api::r0::sync::sync_events::call_with_extensions`. They are available to the client while it
+//! makes the request and are attached to the `http::Request` handed to the `hyper::Client`.
+
+use std::time::Instant;
+
+pub use http::Extensions;
+use url::Url;
+
+/// An extension that makes a request use the given access token instead of the one of the
+/// client's session.
+///
+/// This is useful for e.g. application services acting on behalf of several users with one
+/// client.
+#[derive(Clone, Debug)]
+pub struct AccessTokenOverride(pub String);
+
+/// An extension that bounds a request, including the retries of rate-limited attempts, to finish
+/// before the given instant of the client's clock.
+///
+/// The request fails with `Error::DeadlineExceeded` if no response arrived in time. To bound
+/// several requests together, e.g. everything `Room::join` does, use `Client::with_deadline`.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline(pub Instant);
+
+/// An extension that sends a request to the homeserver at the given base URL instead of the
+/// client's homeserver.
+///
+/// Such requests never get an access token, so endpoints requiring authentication fail with
+/// `Error::AuthenticationRequired`.
+#[derive(Clone, Debug)]
+pub(crate) struct BaseUrlOverride(pub Url);
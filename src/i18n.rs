@@ -0,0 +1,458 @@
+//! User-facing strings, with a hook for translating them.
+
+use std::fmt::Debug;
+
+use http::StatusCode;
+use hyper::client::connect::Connect;
+use ruma_api::Error as RumaApiError;
+use ruma_events::room::member::{MemberEvent, MembershipState};
+
+use crate::{session::InvalidationReason, Client, Error};
+
+/// A message the library shows to users, with its parameters.
+///
+/// `Message::english` renders the built-in English text. Applications can supply translations
+/// with an `I18n` implementation installed by `Client::set_i18n`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Message {
+    /// An action requires being logged in.
+    NotLoggedIn,
+    /// The homeserver couldn't be reached.
+    ConnectionFailed,
+    /// The homeserver rejected the access token.
+    SessionExpired,
+    /// The homeserver revoked the session, e.g. because the account was logged in elsewhere.
+    SessionReplaced,
+    /// The user isn't allowed to do something.
+    Forbidden,
+    /// Something doesn't exist on the homeserver.
+    NotFound,
+    /// The homeserver rejected a request because too many were sent.
+    RateLimited,
+    /// The homeserver failed to handle a request.
+    ServerError,
+    /// The homeserver answered a request with an unexpected status code.
+    RequestFailed {
+        /// The HTTP status code.
+        status: u16,
+    },
+    /// The homeserver's answer couldn't be understood.
+    InvalidResponse,
+    /// Downloaded media was blocked by the content scanner.
+    ContentBlocked {
+        /// Why the media was blocked.
+        reason: String,
+    },
+    /// The room or the homeserver doesn't support a feature.
+    Unsupported,
+    /// A file is too large to be uploaded.
+    FileTooLarge {
+        /// The maximum size of a file in bytes.
+        max_size: u64,
+    },
+    /// The user already reacted to a message with the same key.
+    AlreadyReacted {
+        /// The key of the reaction, usually an emoji.
+        key: String,
+    },
+    /// An action took too long.
+    TimedOut,
+    /// Any other error.
+    Unexpected,
+    /// A user joined a room.
+    Joined {
+        /// The name of the user.
+        user: String,
+    },
+    /// A user left a room.
+    Left {
+        /// The name of the user.
+        user: String,
+    },
+    /// A user was invited to a room.
+    Invited {
+        /// The user who sent the invite.
+        sender: String,
+        /// The name of the invited user.
+        user: String,
+    },
+    /// A user rejected an invite.
+    InviteRejected {
+        /// The name of the user.
+        user: String,
+    },
+    /// An invite was withdrawn.
+    InviteWithdrawn {
+        /// The user who withdrew the invite.
+        sender: String,
+        /// The name of the user who was invited.
+        user: String,
+    },
+    /// A user was kicked from a room.
+    Kicked {
+        /// The user who kicked.
+        sender: String,
+        /// The name of the kicked user.
+        user: String,
+    },
+    /// A user was banned from a room.
+    Banned {
+        /// The user who banned.
+        sender: String,
+        /// The name of the banned user.
+        user: String,
+    },
+    /// A user was unbanned from a room.
+    Unbanned {
+        /// The user who unbanned.
+        sender: String,
+        /// The name of the unbanned user.
+        user: String,
+    },
+    /// A user asked to be invited to a room.
+    Knocked {
+        /// The name of the user.
+        user: String,
+    },
+    /// A user's request to be invited to a room was rejected.
+    KnockRejected {
+        /// The user who rejected the request.
+        sender: String,
+        /// The name of the user who asked to be invited.
+        user: String,
+    },
+    /// Another user ended a user's invite or membership, but the event doesn't say which of the
+    /// two it was.
+    MembershipRevoked {
+        /// The user who ended the invite or membership.
+        sender: String,
+        /// The name of the user.
+        user: String,
+    },
+    /// A user set or changed their display name.
+    DisplayNameChanged {
+        /// The previous name of the user, i.e. the display name or the user ID.
+        old: String,
+        /// The new display name.
+        new: String,
+    },
+    /// A user removed their display name.
+    DisplayNameRemoved {
+        /// The removed display name.
+        old: String,
+    },
+    /// A user set, changed or removed their avatar.
+    AvatarChanged {
+        /// The name of the user.
+        user: String,
+    },
+}
+
+/// A hook that translates the messages of the library.
+///
+/// Installed with `Client::set_i18n`. Messages the hook doesn't translate are shown in English.
+pub trait I18n: Debug {
+    /// Translate `message`, or return `None` to use the English text.
+    fn translate(&self, message: &Message) -> Option<String>;
+}
+
+impl Message {
+    /// The English text of the message.
+    pub fn english(&self) -> String {
+        match self {
+            Message::NotLoggedIn => "You need to log in first.".to_owned(),
+            Message::ConnectionFailed => "Couldn't connect to the homeserver.".to_owned(),
+            Message::SessionExpired => "Your session has expired. Please log in again.".to_owned(),
+            Message::SessionReplaced => {
+                "You were logged out, possibly because your account was used to log in \
+                 elsewhere. Please log in again."
+                    .to_owned()
+            }
+            Message::Forbidden => "You aren't allowed to do that.".to_owned(),
+            Message::NotFound => "That doesn't exist.".to_owned(),
+            Message::RateLimited => "Too many requests. Please try again later.".to_owned(),
+            Message::ServerError => "The homeserver ran into a problem.".to_owned(),
+            Message::RequestFailed { status } => {
+                format!("The request failed with status {}.", status)
+            }
+            Message::InvalidResponse => "The homeserver sent an invalid response.".to_owned(),
+            Message::ContentBlocked { reason } => format!("This file was blocked: {}", reason),
+            Message::Unsupported => "This isn't supported here.".to_owned(),
+            Message::FileTooLarge { max_size } => format!(
+                "This file is too large. Files can be at most {}.",
+                format_size(*max_size)
+            ),
+            Message::AlreadyReacted { key } => format!("You already reacted with {}.", key),
+            Message::TimedOut => "That took too long. Please try again.".to_owned(),
+            Message::Unexpected => "Something went wrong.".to_owned(),
+            Message::Joined { user } => format!("{} joined the room", user),
+            Message::Left { user } => format!("{} left the room", user),
+            Message::Invited { sender, user } => format!("{} invited {}", sender, user),
+            Message::InviteRejected { user } => format!("{} rejected the invite", user),
+            Message::InviteWithdrawn { sender, user } => {
+                format!("{} withdrew the invite for {}", sender, user)
+            }
+            Message::Kicked { sender, user } => format!("{} removed {}", sender, user),
+            Message::Banned { sender, user } => format!("{} banned {}", sender, user),
+            Message::Unbanned { sender, user } => format!("{} unbanned {}", sender, user),
+            Message::Knocked { user } => format!("{} asked to join", user),
+            Message::KnockRejected { sender, user } => {
+                format!("{} rejected the request of {} to join", sender, user)
+            }
+            Message::MembershipRevoked { sender, user } => {
+                format!("{} revoked the membership of {}", sender, user)
+            }
+            Message::DisplayNameChanged { old, new } => {
+                format!("{} changed their display name to {}", old, new)
+            }
+            Message::DisplayNameRemoved { old } => format!("{} removed their display name", old),
+            Message::AvatarChanged { user } => format!("{} changed their avatar", user),
+        }
+    }
+
+    /// The message describing a change of a user's membership, or `None` if the event doesn't
+    /// change anything shown to users.
+    pub fn membership_change(event: &MemberEvent) -> Option<Message> {
+        let previous = event.prev_content.as_ref();
+        let previous_membership = previous.map(|content| content.membership);
+        let previous_name = previous.and_then(|content| content.displayname.clone());
+        let user = event
+            .content
+            .displayname
+            .clone()
+            .or_else(|| previous_name.clone())
+            .unwrap_or_else(|| event.state_key.clone());
+        let sender = event.sender.to_string();
+        let by_self = sender == event.state_key;
+
+        let message = match (previous_membership, event.content.membership) {
+            (Some(MembershipState::Join), MembershipState::Join) => {
+                let previous = previous?;
+
+                if previous.displayname != event.content.displayname {
+                    let old = previous_name.unwrap_or_else(|| event.state_key.clone());
+
+                    match event.content.displayname.clone() {
+                        Some(new) => Message::DisplayNameChanged { old, new },
+                        None => Message::DisplayNameRemoved { old },
+                    }
+                } else if previous.avatar_url != event.content.avatar_url {
+                    Message::AvatarChanged { user }
+                } else {
+                    return None;
+                }
+            }
+            (_, MembershipState::Join) => Message::Joined { user },
+            (_, MembershipState::Invite) => Message::Invited { sender, user },
+            (_, MembershipState::Knock) => Message::Knocked { user },
+            (_, MembershipState::Ban) => Message::Banned { sender, user },
+            (Some(MembershipState::Invite), MembershipState::Leave) if by_self => {
+                Message::InviteRejected { user }
+            }
+            (Some(MembershipState::Invite), MembershipState::Leave) => {
+                Message::InviteWithdrawn { sender, user }
+            }
+            (Some(MembershipState::Ban), MembershipState::Leave) => {
+                Message::Unbanned { sender, user }
+            }
+            (Some(MembershipState::Leave), MembershipState::Leave) => return None,
+            (_, MembershipState::Leave) if by_self => Message::Left { user },
+            (Some(MembershipState::Join), MembershipState::Leave) => {
+                Message::Kicked { sender, user }
+            }
+            (Some(MembershipState::Knock), MembershipState::Leave) => {
+                Message::KnockRejected { sender, user }
+            }
+            // Without the previous membership, a kick can't be told apart from a withdrawn
+            // invite.
+            (None, MembershipState::Leave) => Message::MembershipRevoked { sender, user },
+        };
+
+        Some(message)
+    }
+}
+
+/// A size in bytes in the largest unit it has at least one of.
+fn format_size(size: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    const GB: u64 = 1024 * MB;
+
+    match size {
+        size if size >= GB => format!("{} GB", size / GB),
+        size if size >= MB => format!("{} MB", size / MB),
+        size if size >= KB => format!("{} KB", size / KB),
+        size => format!("{} bytes", size),
+    }
+}
+
+impl Error {
+    /// The message to show to users for this error.
+    pub fn message(&self) -> Message {
+        match self {
+            Error::AuthenticationRequired => Message::NotLoggedIn,
+            Error::SessionInvalidated(InvalidationReason::Expired) => Message::SessionExpired,
+            Error::SessionInvalidated(InvalidationReason::Replaced) => Message::SessionReplaced,
+            Error::Hyper(_) => Message::ConnectionFailed,
+            Error::RumaApi(RumaApiError::StatusCode(status)) => match *status {
+                StatusCode::UNAUTHORIZED => Message::SessionExpired,
+                StatusCode::FORBIDDEN => Message::Forbidden,
+                StatusCode::NOT_FOUND => Message::NotFound,
+                StatusCode::TOO_MANY_REQUESTS => Message::RateLimited,
+                status if status.is_server_error() => Message::ServerError,
+                status => Message::RequestFailed {
+                    status: status.as_u16(),
+                },
+            },
+            Error::RumaApi(RumaApiError::Hyper(_)) | Error::RumaApi(RumaApiError::Io(_)) => {
+                Message::ConnectionFailed
+            }
+            Error::RumaApi(_) | Error::SerdeJson(_) | Error::ResponseTooLarge { .. } => {
+                Message::InvalidResponse
+            }
+            Error::ContentBlocked(reason) => Message::ContentBlocked {
+                reason: reason.clone(),
+            },
+            Error::UnsupportedByRoomVersion { .. }
+            | Error::UnsupportedByServer { .. }
+            | Error::UnknownImageFormat
+            | Error::SyncDisabled => Message::Unsupported,
+            Error::DuplicateAnnotation { key, .. } => Message::AlreadyReacted { key: key.clone() },
+            Error::UploadTooLarge { max_size, .. } => Message::FileTooLarge {
+                max_size: *max_size,
+            },
+            Error::DeadlineExceeded => Message::TimedOut,
+            Error::NotPermitted { .. } => Message::Forbidden,
+            Error::EncryptionUnsupported(_) => Message::Unsupported,
+            Error::Io(_)
+            | Error::Uri(_)
+            | Error::Url(_)
+            | Error::SerdeUrlEncodedSerialize(_)
+            | Error::Timer(_)
+            | Error::EventHandler(_)
+            | Error::InvalidMxcUri(_)
+            | Error::AccountDataMigration { .. }
+            | Error::AccountDataChanged(_)
+            | Error::InteractiveAuthRequired(_)
+            | Error::NotInRoom(_) => Message::Unexpected,
+            #[cfg(feature = "config")]
+            Error::Config(_) => Message::Unexpected,
+            #[cfg(feature = "encryption")]
+            Error::EncryptionDisabled | Error::OlmSessionMissing { .. } => Message::Unexpected,
+            #[cfg(feature = "webhook-server")]
+            Error::EmptyWebhookSecret => Message::Unexpected,
+            #[cfg(feature = "synapse-admin")]
+            Error::PurgeHistoryFailed(_) => Message::Unexpected,
+        }
+    }
+
+    /// The English text to show to users for this error.
+    ///
+    /// Use `Client::user_message` for the text translated by the client's `I18n` hook.
+    pub fn user_message(&self) -> String {
+        self.message().english()
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Install a hook that translates the messages returned by `user_message` and
+    /// `membership_change_message`.
+    pub fn set_i18n<T>(&self, i18n: T)
+    where
+        T: I18n + 'static,
+    {
+        *self.0.i18n.borrow_mut() = Some(Box::new(i18n));
+    }
+
+    /// The text of a message, translated by the `I18n` hook if it has a translation.
+    pub fn localize(&self, message: &Message) -> String {
+        self.0
+            .i18n
+            .borrow()
+            .as_ref()
+            .and_then(|i18n| i18n.translate(message))
+            .unwrap_or_else(|| message.english())
+    }
+
+    /// The text to show to users for an error, in their language if the `I18n` hook translates
+    /// it.
+    pub fn user_message(&self, error: &Error) -> String {
+        self.localize(&error.message())
+    }
+
+    /// The text describing a change of a user's membership, in the users' language if the
+    /// `I18n` hook translates it.
+    ///
+    /// Returns `None` if the event doesn't change anything shown to users.
+    pub fn membership_change_message(&self, event: &MemberEvent) -> Option<String> {
+        Message::membership_change(event).map(|message| self.localize(&message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn member_event(sender: &str, previous: Option<&str>, membership: &str) -> MemberEvent {
+        serde_json::from_value(json!({
+            "type": "m.room.member",
+            "event_id": "$event:example.org",
+            "room_id": "!room:example.org",
+            "sender": sender,
+            "state_key": "@alice:example.org",
+            "origin_server_ts": 0,
+            "content": { "membership": membership },
+            "prev_content": previous.map(|membership| json!({ "membership": membership })),
+        }))
+        .unwrap()
+    }
+
+    fn change(sender: &str, previous: Option<&str>, membership: &str) -> Option<Message> {
+        Message::membership_change(&member_event(sender, previous, membership))
+    }
+
+    #[test]
+    fn leaves_by_other_users_are_told_apart() {
+        let sender = "@bob:example.org".to_owned();
+        let user = "@alice:example.org".to_owned();
+
+        assert_eq!(
+            change("@bob:example.org", Some("join"), "leave"),
+            Some(Message::Kicked {
+                sender: sender.clone(),
+                user: user.clone()
+            })
+        );
+        assert_eq!(
+            change("@bob:example.org", Some("invite"), "leave"),
+            Some(Message::InviteWithdrawn {
+                sender: sender.clone(),
+                user: user.clone()
+            })
+        );
+        assert_eq!(
+            change("@bob:example.org", None, "leave"),
+            Some(Message::MembershipRevoked { sender, user })
+        );
+        assert_eq!(change("@bob:example.org", Some("leave"), "leave"), None);
+    }
+
+    #[test]
+    fn leaves_by_the_user_themselves() {
+        let user = "@alice:example.org".to_owned();
+
+        assert_eq!(
+            change("@alice:example.org", Some("join"), "leave"),
+            Some(Message::Left { user: user.clone() })
+        );
+        assert_eq!(
+            change("@alice:example.org", Some("invite"), "leave"),
+            Some(Message::InviteRejected { user })
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Packs of custom emoticons and stickers, as proposed in
+//! [MSC2545](https://github.com/matrix-org/matrix-doc/pull/2545).
+
+use std::collections::BTreeMap;
+
+use futures::future::{Future, IntoFuture};
+use hyper::client::connect::Connect;
+use ruma_events::{room::ImageInfo, EventType};
+use ruma_identifiers::EventId;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Client, Error, Room};
+
+/// The type of the state events holding the image packs of a room.
+pub const ROOM_IMAGE_PACK_EVENT_TYPE: &str = "im.ponies.room_emotes";
+
+/// The type of the account data holding the personal image pack of a user.
+pub const USER_IMAGE_PACK_EVENT_TYPE: &str = "im.ponies.user_emotes";
+
+/// A pack of images that can be used as custom emoticons or stickers.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImagePack {
+    /// The images of the pack, keyed by shortcode.
+    #[serde(default)]
+    pub images: BTreeMap<String, PackImage>,
+    /// Information about the pack itself.
+    #[serde(default)]
+    pub pack: PackInfo,
+}
+
+/// An image of an `ImagePack`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PackImage {
+    /// The `mxc://` URL of the image.
+    pub url: String,
+    /// A textual description of the image, used as the body of stickers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Metadata about the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<ImageInfo>,
+    /// What the image can be used for. If empty, the usage of the pack applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<PackUsage>,
+}
+
+/// Information about an `ImagePack`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PackInfo {
+    /// The name of the pack.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// The `mxc://` URL of the avatar of the pack.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    /// What the images of the pack can be used for. If empty, they can be used for everything.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<PackUsage>,
+    /// The attribution of the images of the pack.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+}
+
+/// What the images of an `ImagePack` can be used for.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum PackUsage {
+    /// Inline in messages, like emoji.
+    #[serde(rename = "emoticon")]
+    Emoticon,
+    /// As `m.sticker` events.
+    #[serde(rename = "sticker")]
+    Sticker,
+}
+
+impl PackImage {
+    /// Creates an image with the given `mxc://` URL and no other information.
+    pub fn new(url: String) -> Self {
+        PackImage {
+            url,
+            body: None,
+            info: None,
+            usage: Vec::new(),
+        }
+    }
+}
+
+impl ImagePack {
+    /// Add an image to the pack, replacing the image with the same shortcode, if any.
+    pub fn add_image(&mut self, shortcode: String, image: PackImage) {
+        self.images.insert(shortcode, image);
+    }
+
+    /// Remove the image with the given shortcode from the pack, returning it.
+    pub fn remove_image(&mut self, shortcode: &str) -> Option<PackImage> {
+        self.images.remove(shortcode)
+    }
+
+    /// Change the shortcode of an image. Returns `false` if there is no image with the shortcode
+    /// `from`.
+    pub fn rename_image(&mut self, from: &str, to: String) -> bool {
+        match self.images.remove(from) {
+            Some(image) => {
+                self.images.insert(to, image);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set what an image can be used for. Returns `false` if there is no image with the given
+    /// shortcode.
+    pub fn set_image_usage(&mut self, shortcode: &str, usage: Vec<PackUsage>) -> bool {
+        match self.images.get_mut(shortcode) {
+            Some(image) => {
+                image.usage = usage;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the image with the given shortcode can be used in the given way.
+    pub fn image_has_usage(&self, shortcode: &str, usage: PackUsage) -> bool {
+        let image = match self.images.get(shortcode) {
+            Some(image) => image,
+            None => return false,
+        };
+        let usages = if image.usage.is_empty() {
+            &self.pack.usage
+        } else {
+            &image.usage
+        };
+
+        usages.is_empty() || usages.contains(&usage)
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get an image pack of this room.
+    ///
+    /// Rooms can have several packs, told apart by `state_key`. Resolves to `None` if there is no
+    /// pack with the given state key.
+    pub fn image_pack(
+        &self,
+        state_key: &str,
+    ) -> impl Future<Item = Option<ImagePack>, Error = Error> {
+        self.state_event(EventType::from(ROOM_IMAGE_PACK_EVENT_TYPE), state_key)
+    }
+
+    /// Replace an image pack of this room.
+    pub fn set_image_pack(
+        &self,
+        state_key: &str,
+        pack: &ImagePack,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        self.send_state_event(EventType::from(ROOM_IMAGE_PACK_EVENT_TYPE), state_key, pack)
+    }
+
+    /// Upload an image and add it to an image pack of this room, creating the pack if it doesn't
+    /// exist yet.
+    pub fn add_pack_image(
+        &self,
+        state_key: &str,
+        shortcode: String,
+        content_type: String,
+        file: Vec<u8>,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        let room = self.clone();
+        let state_key = state_key.to_owned();
+
+        self.client()
+            .upload(content_type, None, file)
+            .join(self.image_pack(&state_key))
+            .and_then(move |(url, pack)| {
+                let mut pack = pack.unwrap_or_default();
+                pack.add_image(shortcode, PackImage::new(url.to_string()));
+
+                room.set_image_pack(&state_key, &pack)
+            })
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the personal image pack of the logged-in user.
+    ///
+    /// Resolves to `None` if the user has no personal pack.
+    pub fn user_image_pack(&self) -> impl Future<Item = Option<ImagePack>, Error = Error> {
+        self.global_account_data(USER_IMAGE_PACK_EVENT_TYPE)
+            .and_then(|content| match content {
+                Some(content) => Ok(Some(serde_json::from_value(content)?)),
+                None => Ok(None),
+            })
+    }
+
+    /// Replace the personal image pack of the logged-in user.
+    pub fn set_user_image_pack(&self, pack: &ImagePack) -> impl Future<Item = (), Error = Error> {
+        let client = self.clone();
+
+        serde_json::to_value(pack)
+            .map_err(Error::from)
+            .into_future()
+            .and_then(move |data| client.set_global_account_data(USER_IMAGE_PACK_EVENT_TYPE, data))
+    }
+}
@@ -0,0 +1,193 @@
+//! Importing sessions exported by other clients.
+
+use futures::Future;
+use hyper::client::connect::Connect;
+use ruma_identifiers::UserId;
+use serde_derive::Deserialize;
+use url::Url;
+
+use crate::{Client, ClientBuilder, Error, Session};
+
+/// A session of another client, to be continued with this crate.
+#[derive(Clone, Debug)]
+pub struct ImportedSession {
+    /// The URL of the homeserver the session belongs to.
+    pub homeserver_url: Url,
+    /// The URL of the identity server the other client used, if any.
+    pub identity_server_url: Option<Url>,
+    /// The session.
+    pub session: Session,
+    /// The `next_batch` token of the latest sync of the other client, if it was exported.
+    ///
+    /// `restore_store` fills the client's `Store` from this position, after which `Client::sync`
+    /// continues where the other client stopped.
+    pub next_batch: Option<String>,
+}
+
+/// The session keys of Element's local storage.
+#[derive(Deserialize)]
+struct ElementSession {
+    mx_hs_url: String,
+    #[serde(default)]
+    mx_is_url: Option<String>,
+    mx_user_id: UserId,
+    mx_access_token: String,
+    mx_device_id: String,
+}
+
+/// The credentials file written by matrix-nio bots, as in the examples of matrix-nio.
+#[derive(Deserialize)]
+struct NioCredentials {
+    homeserver: String,
+    user_id: UserId,
+    device_id: String,
+    access_token: String,
+    #[serde(default)]
+    next_batch: Option<String>,
+}
+
+impl ImportedSession {
+    /// Import the session of Element (formerly Riot) from the `mx_*` keys of its local storage,
+    /// as a JSON object.
+    ///
+    /// `mx_hs_url`, `mx_user_id`, `mx_access_token` and `mx_device_id` are required, other keys
+    /// are ignored. Element keeps its sync state and encryption keys in IndexedDB, so they aren't
+    /// imported.
+    pub fn from_element(json: &str) -> Result<Self, Error> {
+        let session: ElementSession = serde_json::from_str(json)?;
+
+        Ok(ImportedSession {
+            homeserver_url: Url::parse(&session.mx_hs_url)?,
+            identity_server_url: match session.mx_is_url {
+                Some(ref url) if !url.is_empty() => Some(Url::parse(url)?),
+                _ => None,
+            },
+            session: Session::new(
+                session.mx_access_token,
+                session.mx_user_id,
+                session.mx_device_id,
+            ),
+            next_batch: None,
+        })
+    }
+
+    /// Import the session of a matrix-nio client from a JSON object with the keys
+    /// `homeserver`, `user_id`, `device_id` and `access_token`, and optionally `next_batch`.
+    ///
+    /// This is the credentials file matrix-nio bots commonly save after logging in. The
+    /// encryption store of matrix-nio is not imported.
+    pub fn from_nio(json: &str) -> Result<Self, Error> {
+        let credentials: NioCredentials = serde_json::from_str(json)?;
+
+        Ok(ImportedSession {
+            homeserver_url: Url::parse(&credentials.homeserver)?,
+            identity_server_url: None,
+            session: Session::new(
+                credentials.access_token,
+                credentials.user_id,
+                credentials.device_id,
+            ),
+            next_batch: credentials.next_batch,
+        })
+    }
+
+    /// A builder for a client continuing the session.
+    ///
+    /// The device ID of the session is kept for later logins, see `ClientBuilder::device_id`.
+    pub fn client_builder(&self) -> ClientBuilder {
+        ClientBuilder::new(self.homeserver_url.clone())
+            .session(self.session.clone())
+            .device_id(self.session.device_id().to_owned())
+    }
+
+    /// Fill the `Store` of `client`, which continues the session, with the rooms of the session.
+    ///
+    /// With a `next_batch` token, the homeserver sends the full state of every room of the user
+    /// along with the events since the other client's latest sync, so the history the other client
+    /// already received isn't downloaded again. Without one, this is an initial sync. Resolves to
+    /// the `next_batch` token to continue syncing from with `Client::sync`.
+    pub fn restore_store<C>(&self, client: &Client<C>) -> impl Future<Item = String, Error = Error>
+    where
+        C: Connect + 'static,
+    {
+        use crate::api::r0::sync::sync_events;
+
+        let data = client.0.clone();
+        let since = self.next_batch.clone();
+
+        sync_events::call(
+            client.clone(),
+            sync_events::Request {
+                filter: None,
+                since: since.clone(),
+                full_state: since.as_ref().map(|_| true),
+                set_presence: None,
+                timeout: None,
+            },
+        )
+        .map(move |mut response| {
+            data.apply_sync(&mut response, since.as_deref());
+
+            response.next_batch
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImportedSession;
+
+    #[test]
+    fn element_session_ignores_empty_identity_server() {
+        let imported = ImportedSession::from_element(
+            r#"{
+                "mx_hs_url": "https://matrix.example.org",
+                "mx_is_url": "",
+                "mx_user_id": "@alice:example.org",
+                "mx_access_token": "token",
+                "mx_device_id": "DEVICE",
+                "mx_last_room_id": "!room:example.org"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            imported.homeserver_url.as_str(),
+            "https://matrix.example.org/"
+        );
+        assert!(imported.identity_server_url.is_none());
+        assert_eq!(imported.session.user_id().to_string(), "@alice:example.org");
+        assert_eq!(imported.session.device_id(), "DEVICE");
+        assert!(imported.next_batch.is_none());
+    }
+
+    #[test]
+    fn element_session_requires_the_access_token() {
+        assert!(ImportedSession::from_element(
+            r#"{
+                "mx_hs_url": "https://matrix.example.org",
+                "mx_user_id": "@alice:example.org",
+                "mx_device_id": "DEVICE"
+            }"#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn nio_credentials_keep_the_sync_position() {
+        let imported = ImportedSession::from_nio(
+            r#"{
+                "homeserver": "https://matrix.example.org",
+                "user_id": "@bot:example.org",
+                "device_id": "NIO",
+                "access_token": "token",
+                "next_batch": "s72594_4483_1934"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(imported.session.access_token(), "token");
+        assert_eq!(imported.next_batch, Some("s72594_4483_1934".to_owned()));
+        assert_eq!(imported.client_builder().device_id, Some("NIO".to_owned()));
+    }
+}
@@ -4,33 +4,193 @@
 #![deny(missing_docs)]
 #![feature(try_from)]
 
-use std::{cell::RefCell, convert::TryInto, rc::Rc, str::FromStr};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    convert::TryInto,
+    rc::Rc,
+    str::FromStr,
+    time::{Instant, UNIX_EPOCH},
+};
 
 use futures::{
-    future::{Future, FutureFrom, IntoFuture},
+    future::{self, Either, Future, FutureFrom, IntoFuture},
     stream::{self, Stream},
 };
 use hyper::{
     client::{connect::Connect, HttpConnector},
-    Client as HyperClient, Uri,
+    header::{HeaderValue, CONTENT_LENGTH},
+    Body, Client as HyperClient, Uri,
 };
 #[cfg(feature = "hyper-tls")]
 use hyper_tls::HttpsConnector;
 #[cfg(feature = "hyper-tls")]
 use native_tls::Error as NativeTlsError;
 use ruma_api::Endpoint;
+use ruma_identifiers::{RoomId, UserId};
+use serde_json::Value;
 use url::Url;
 
-pub use crate::{error::Error, session::Session};
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionState;
+use crate::{
+    extensions::BaseUrlOverride, lightweight::limit_response, processor::RegisteredProcessor,
+    scope::Restriction, session::InvalidationHooks, sync_timeout::SyncTimeout, typing::TypingState,
+};
 
+pub use crate::{
+    account_data::{
+        AccountDataMigrations, MigrationOutcome, DIRECT_EVENT_TYPE, IGNORED_USER_LIST_EVENT_TYPE,
+    },
+    alias::{AliasAudit, AliasCleanup, AliasSource, AliasStatus, AuditedAlias},
+    announcement::{AnnouncementProgress, RoomSelector},
+    bot::{BotConfigLocation, BOT_OPTIONS_EVENT_TYPE},
+    broadcast::{Broadcast, BroadcastProgress},
+    builder::ClientBuilder,
+    capabilities::RoomFeature,
+    create_room::CreateRoomBuilder,
+    dispatch::{ErrorPolicy, EventHandler, HandlerFailure, SyncEvent},
+    edit::{Edit, EditHistory},
+    environment::{Clock, ClockDelay, Rng, SystemClock},
+    error::Error,
+    extensions::{AccessTokenOverride, Deadline, Extensions},
+    i18n::{I18n, Message},
+    image_pack::{
+        ImagePack, PackImage, PackInfo, PackUsage, ROOM_IMAGE_PACK_EVENT_TYPE,
+        USER_IMAGE_PACK_EVENT_TYPE,
+    },
+    import::ImportedSession,
+    lightweight::LIGHTWEIGHT_MAX_RESPONSE_SIZE,
+    media::{
+        sniff_content_type, ContentScanner, MediaStream, RemoteContentScanner, ScanVerdict,
+        Thumbnail, ThumbnailSize, UrlPreview,
+    },
+    membership::EmailInvite,
+    message::{
+        escape_html, html_to_plain, message_body, replacement_content, strip_html_reply_fallback,
+        strip_reply_fallback, text_to_html, thread_content, FormattedMessageEventContent,
+        InReplyTo, ReactionEventContent, RelatesTo, RelationType, Reply, HTML_FORMAT,
+        REACTION_EVENT_TYPE,
+    },
+    metadata::{MetadataChange, MetadataChangeKind},
+    migration::{ExportedMember, ImportSummary, MemberExport},
+    mxc::MxcUri,
+    permissions::Action,
+    processor::{SyncProcessor, SyncStage},
+    rate_limit::RateLimiter,
+    receipt::{ReadReceipt, FULLY_READ_EVENT_TYPE},
+    room::{RedactionProgress, Room},
+    scheduler::ScheduledSend,
+    scope::{RestrictedClient, Scope},
+    search::{SearchHit, SearchResults, SearchSource},
+    session::{InvalidationReason, Session, SessionInvalidated},
+    store::{StateDiff, StateValue, Store, StoreSnapshot, UnreadCounts},
+    supervisor::{Supervisor, TaskFailure, TaskSpawner},
+    tag::{FAVOURITE_TAG, LOW_PRIORITY_TAG},
+    thread::Thread,
+    typing::TypingGuard,
+    uiaa::{
+        AuthFlow, ThreePidCredentials, UiaaInfo, EMAIL_IDENTITY_AUTH_TYPE, MSISDN_AUTH_TYPE,
+        RECAPTCHA_AUTH_TYPE,
+    },
+    upgrade::{TombstoneEventContent, TOMBSTONE_EVENT_TYPE},
+};
+
+#[cfg(feature = "config")]
+pub use crate::config::{Config, Credentials};
+#[cfg(feature = "encryption")]
+pub use crate::encryption::{
+    IdentityKeys, OlmAccount, OlmSession, OutboundGroupSession, PickledOlmAccount,
+    MEGOLM_ALGORITHM, OLM_ALGORITHM,
+};
+#[cfg(feature = "local-search")]
+pub use crate::search::LocalSearchResult;
+#[cfg(feature = "webhook-server")]
+pub use crate::webhook::{WebhookRoute, WebhookServer};
+
+mod account;
+mod account_data;
+mod alias;
+mod announcement;
 /// Matrix client-server API endpoints.
 pub mod api;
+mod bot;
+mod broadcast;
+mod builder;
+mod capabilities;
+mod compat;
+#[cfg(feature = "config")]
+mod config;
+mod contact;
+mod create_room;
+mod deadline;
+mod device;
+#[cfg(feature = "encryption")]
+mod device_tracking;
+mod directory;
+mod dispatch;
+mod edit;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod environment;
 mod error;
+mod events;
+mod extensions;
+mod i18n;
+mod image_pack;
+mod import;
+mod lightweight;
+mod media;
+#[cfg(feature = "encryption")]
+mod megolm;
+mod membership;
+mod message;
+mod metadata;
+mod migration;
+mod mxc;
+#[cfg(feature = "encryption")]
+mod olm;
+mod permissions;
+mod presence;
+mod processor;
+mod profile;
+mod rate_limit;
+mod receipt;
+mod room;
+mod scheduler;
+mod scope;
+mod search;
 mod session;
+mod store;
+mod supervisor;
+mod sync_timeout;
+mod tag;
+mod thread;
+mod typing;
+mod uiaa;
+mod upgrade;
+#[cfg(feature = "webhook-server")]
+mod webhook;
+
+/// Crates used in the code generated by `endpoint_calls!` and `ruma_api!`.
+///
+/// Endpoints for `endpoint_calls!` have to be defined with the `ruma_api!` of `ruma_api_macros`.
+pub mod reexports {
+    pub use futures;
+    pub use http;
+    pub use hyper;
+    pub use ruma_api;
+    pub use ruma_api_macros;
+    pub use serde;
+    pub use serde_derive;
+    pub use serde_json;
+    pub use serde_urlencoded;
+    pub use url;
+}
 
 /// A client for the Matrix client-server API.
 #[derive(Debug)]
-pub struct Client<C: Connect>(Rc<ClientData<C>>);
+pub struct Client<C: Connect>(Rc<ClientData<C>>, Option<Rc<Restriction>>);
 
 /// Data contained in Client's Rc
 #[derive(Debug)]
@@ -39,18 +199,151 @@ where
     C: Connect,
 {
     homeserver_url: Url,
+    /// Where requests to the media repository are sent instead of `homeserver_url`, if anywhere.
+    media_url: Option<Url>,
     hyper: HyperClient<C>,
     session: RefCell<Option<Session>>,
+    /// The device ID reused by logins that don't specify one, if device IDs are persistent.
+    device_id: RefCell<Option<String>>,
+    /// Whether logins that don't specify a device ID reuse `device_id`.
+    persistent_device_id: bool,
+    /// Whether the client was built in lightweight mode, which disables syncing.
+    lightweight: bool,
+    /// The size in bytes of the largest response that is read into memory, if limited.
+    max_response_size: Option<u64>,
+    /// The homeserver's upload size limit, once `Client::max_upload_size` fetched it.
+    max_upload_size: Cell<Option<Option<u64>>>,
+    content_scanner: RefCell<Option<Box<dyn ContentScanner>>>,
+    i18n: RefCell<Option<Box<dyn I18n>>>,
+    /// The processors of sync responses, in the order they were added.
+    sync_processors: RefCell<Vec<RegisteredProcessor>>,
+    /// The active typing notification of each room, see `Room::typing_notice`.
+    typing: RefCell<HashMap<RoomId, Rc<TypingState>>>,
+    /// Whether `Room::react` refuses to send duplicate reactions.
+    duplicate_reaction_guard: Cell<bool>,
+    /// Whether `Client::profile` may ask the homeserver of a user directly.
+    remote_profile_fallback: Cell<bool>,
+    /// The base URLs of the homeservers of other server names `Client::profile` asked, by server
+    /// name.
+    remote_homeservers: RefCell<HashMap<String, Url>>,
+    /// The Olm account and the state of its published keys.
+    #[cfg(feature = "encryption")]
+    encryption: RefCell<EncryptionState>,
+    /// The hooks called when the homeserver stops accepting the session.
+    invalidation: RefCell<InvalidationHooks>,
+    /// The account data migrations that still have to run before syncing, see
+    /// `Client::migrate_account_data_on_startup`.
+    startup_migrations: RefCell<Vec<Rc<AccountDataMigrations>>>,
+    store: Store,
+    rate_limiter: RateLimiter,
+    clock: Rc<dyn Clock>,
+    /// The timeout of sync requests, tuned to the homeserver and the network.
+    sync_timeout: RefCell<SyncTimeout>,
+    /// The versions of the specification the homeserver supports, see
+    /// `Client::negotiate_spec_versions`.
+    spec_versions: RefCell<Vec<String>>,
+    /// The deadline of the `Client::with_deadline` future that is being polled, if any.
+    deadline: Cell<Option<Instant>>,
+    /// Prefix of the transaction IDs generated by this client, unique per client instance.
+    txn_id_prefix: String,
+    /// Number of transaction IDs generated by this client so far.
+    txn_id_counter: Cell<u64>,
+}
+
+impl<C> ClientData<C>
+where
+    C: Connect,
+{
+    fn new(builder: ClientBuilder, hyper: HyperClient<C>) -> Self {
+        let ClientBuilder {
+            homeserver_url,
+            media_url,
+            session,
+            device_id,
+            persistent_device_id,
+            lightweight,
+            max_response_size,
+            clock,
+            mut rng,
+        } = builder;
+
+        // Transaction IDs only have to be unique per access token, so the creation time of the
+        // client is enough to tell apart the IDs of different instances using the same session.
+        // The random part covers instances created at the same time.
+        let created = clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or_default();
+        let txn_id_prefix = format!("{}{:04x}", created, rng.next_u32() & 0xffff);
+
+        let device_id = if persistent_device_id {
+            device_id
+                .or_else(|| session.as_ref().map(|s| s.device_id().to_owned()))
+                .or_else(|| Some(crate::session::generate_device_id(&mut *rng)))
+        } else {
+            None
+        };
+
+        ClientData {
+            homeserver_url,
+            media_url,
+            hyper,
+            session: RefCell::new(session),
+            device_id: RefCell::new(device_id),
+            persistent_device_id,
+            lightweight,
+            max_response_size: max_response_size.or(if lightweight {
+                Some(LIGHTWEIGHT_MAX_RESPONSE_SIZE)
+            } else {
+                None
+            }),
+            max_upload_size: Cell::new(None),
+            content_scanner: RefCell::new(None),
+            i18n: RefCell::new(None),
+            sync_processors: RefCell::new(Vec::new()),
+            typing: RefCell::new(HashMap::new()),
+            duplicate_reaction_guard: Cell::new(true),
+            remote_profile_fallback: Cell::new(false),
+            remote_homeservers: RefCell::new(HashMap::new()),
+            #[cfg(feature = "encryption")]
+            encryption: RefCell::new(EncryptionState::default()),
+            invalidation: RefCell::new(InvalidationHooks::default()),
+            startup_migrations: RefCell::new(Vec::new()),
+            store: Store::default(),
+            rate_limiter: RateLimiter::with_clock(clock.clone()),
+            clock,
+            sync_timeout: RefCell::new(SyncTimeout::default()),
+            spec_versions: RefCell::new(Vec::new()),
+            deadline: Cell::new(None),
+            txn_id_prefix,
+            txn_id_counter: Cell::new(0),
+        }
+    }
+
+    /// Update the store and the tracked devices with a sync response of a request with the given
+    /// `since` token, passing it to the sync processors before and after.
+    pub(crate) fn apply_sync(
+        &self,
+        response: &mut api::r0::sync::sync_events::Response,
+        since: Option<&str>,
+    ) {
+        self.run_sync_processors(SyncStage::BeforeStore, response);
+        self.store.apply_sync(response, since, self.clock.now());
+        #[cfg(feature = "encryption")]
+        self.encryption
+            .borrow_mut()
+            .apply_sync(response, &self.store.snapshot());
+        self.run_sync_processors(SyncStage::AfterStore, response);
+    }
 }
 
 impl Client<HttpConnector> {
     /// Creates a new client for making HTTP requests to the given homeserver.
     pub fn new(homeserver_url: Url, session: Option<Session>) -> Self {
-        Client(Rc::new(ClientData {
-            homeserver_url,
-            hyper: HyperClient::builder().keep_alive(true).build_http(),
-            session: RefCell::new(session),
-        }))
+        ClientBuilder::new(homeserver_url)
+            .optional_session(session)
+            .build()
     }
 }
 
@@ -58,13 +351,9 @@ impl Client<HttpConnector> {
 impl Client<HttpsConnector<HttpConnector>> {
     /// Creates a new client for making HTTPS requests to the given homeserver.
     pub fn https(homeserver_url: Url, session: Option<Session>) -> Result<Self, NativeTlsError> {
-        let connector = HttpsConnector::new(4)?;
-
-        Ok(Client(Rc::new(ClientData {
-            homeserver_url,
-            hyper: { HyperClient::builder().keep_alive(true).build(connector) },
-            session: RefCell::new(session),
-        })))
+        ClientBuilder::new(homeserver_url)
+            .optional_session(session)
+            .build_https()
     }
 }
 
@@ -80,11 +369,63 @@ where
         homeserver_url: Url,
         session: Option<Session>,
     ) -> Self {
-        Client(Rc::new(ClientData {
-            homeserver_url,
-            hyper: hyper_client,
-            session: RefCell::new(session),
-        }))
+        ClientBuilder::new(homeserver_url)
+            .optional_session(session)
+            .build_custom(hyper_client)
+    }
+
+    /// Get a handle for making requests that concern the room with the given ID.
+    pub fn room(&self, room_id: RoomId) -> Room<C> {
+        Room::new(self.clone(), room_id)
+    }
+
+    /// Whether `Room::react` refuses to send reactions the `Store` or the homeserver knows to be
+    /// duplicates.
+    ///
+    /// The guard is on by default.
+    pub fn set_duplicate_reaction_guard(&self, enabled: bool) {
+        self.0.duplicate_reaction_guard.set(enabled);
+    }
+
+    /// Get the session of the logged-in user, if any.
+    pub fn session(&self) -> Option<Session> {
+        self.0.session.borrow().clone()
+    }
+
+    /// The session of the logged-in user, or `Error::AuthenticationRequired` without one.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn logged_in_session(&self) -> Result<Session, Error> {
+        self.session().ok_or(Error::AuthenticationRequired)
+    }
+
+    /// The ID of the logged-in user, or `Error::AuthenticationRequired` without a session.
+    pub(crate) fn logged_in_user_id(&self) -> Result<UserId, Error> {
+        self.0
+            .session
+            .borrow()
+            .as_ref()
+            .map(|session| session.user_id().clone())
+            .ok_or(Error::AuthenticationRequired)
+    }
+
+    /// Ask the homeserver which user the client's access token belongs to.
+    ///
+    /// This is a cheap way to check that a restored session is still valid without syncing, and
+    /// lets application services find out which user they act as.
+    pub fn whoami(&self) -> impl Future<Item = UserId, Error = Error> {
+        use crate::api::r0::account::whoami;
+
+        whoami::call(self.clone(), whoami::Request {}).map(|response| response.user_id)
+    }
+
+    /// Get the device ID reused by logins that don't specify one.
+    ///
+    /// This is `None` unless the client was built with `ClientBuilder::persistent_device_id`,
+    /// `ClientBuilder::device_id` or `ClientBuilder::session`. A serialized `Session` contains its
+    /// device ID, so restoring it with `ClientBuilder::session` keeps using the same device after
+    /// a restart. Without a session to save, hand this to `ClientBuilder::device_id` instead.
+    pub fn device_id(&self) -> Option<String> {
+        self.0.device_id.borrow().clone()
     }
 
     /// Log in with a username and password.
@@ -92,15 +433,22 @@ where
     /// In contrast to api::r0::session::login::call(), this method stores the
     /// session data returned by the endpoint in this client, instead of
     /// returning it.
+    ///
+    /// If `device_id` is `None` and the client was built with
+    /// `ClientBuilder::persistent_device_id`, the client's device ID is used.
+    /// `initial_device_display_name` names the device in the user's device
+    /// list if the login creates a new device.
     pub fn log_in(
         &self,
         user: String,
         password: String,
         device_id: Option<String>,
+        initial_device_display_name: Option<String>,
     ) -> impl Future<Item = Session, Error = Error> {
         use crate::api::r0::session::login;
 
         let data = self.0.clone();
+        let device_id = device_id.or_else(|| self.device_id());
 
         login::call(
             self.clone(),
@@ -109,11 +457,16 @@ where
                 login_type: login::LoginType::Password,
                 medium: None,
                 device_id,
+                initial_device_display_name,
                 password,
                 user,
             },
         )
         .map(move |response| {
+            if data.persistent_device_id {
+                *data.device_id.borrow_mut() = Some(response.device_id.clone());
+            }
+
             let session = Session::new(response.access_token, response.user_id, response.device_id);
             *data.session.borrow_mut() = Some(session.clone());
 
@@ -157,27 +510,53 @@ where
     ///
     /// The username is the local part of the returned user_id. If it is
     /// omitted from this request, the server will generate one.
+    ///
+    /// If the client was built with `ClientBuilder::persistent_device_id`, the new session uses
+    /// the client's device ID. `initial_device_display_name` names the new
+    /// device in the user's device list.
+    ///
+    /// Fails with `Error::InteractiveAuthRequired` if the homeserver requires authentication
+    /// for registering, use `register_user_with_auth` to provide it.
     pub fn register_user(
         &self,
         username: Option<String>,
         password: String,
+        initial_device_display_name: Option<String>,
+    ) -> impl Future<Item = Session, Error = Error> {
+        self.register_user_with_auth(username, password, initial_device_display_name, None)
+    }
+
+    /// Register as a new user on the homeserver, completing a stage of the user-interactive
+    /// authentication the homeserver requires for it.
+    ///
+    /// Like `register_user`, but `auth` is sent as the `auth` data of the request. Registering
+    /// without it first fails with `Error::InteractiveAuthRequired`, which describes the stages
+    /// and the ID of the authentication session. For a homeserver requiring a validated email
+    /// address, request a token with `api::r0::account::request_register_token` and pass
+    /// `ThreePidCredentials::email_auth` once the user followed the link in the email. For a
+    /// homeserver requiring a reCAPTCHA, show it with `UiaaInfo::recaptcha_public_key` and pass
+    /// `UiaaInfo::recaptcha_auth` with the token of the solved reCAPTCHA. Each
+    /// completed stage that isn't the last one fails with `Error::InteractiveAuthRequired` again.
+    pub fn register_user_with_auth(
+        &self,
+        username: Option<String>,
+        password: String,
+        initial_device_display_name: Option<String>,
+        auth: Option<Value>,
     ) -> impl Future<Item = Session, Error = Error> {
         use crate::api::r0::account::register;
 
         let data = self.0.clone();
 
-        register::call(
-            self.clone(),
-            register::Request {
-                auth: None,
-                bind_email: None,
-                device_id: None,
-                initial_device_display_name: None,
-                kind: Some(register::RegistrationKind::User),
-                password: Some(password),
-                username,
-            },
-        )
+        self.request_with_uiaa::<register::Endpoint>(register::Request {
+            auth,
+            bind_email: None,
+            device_id: self.device_id(),
+            initial_device_display_name,
+            kind: Some(register::RegistrationKind::User),
+            password: Some(password),
+            username,
+        })
         .map(move |response| {
             let session = Session::new(response.access_token, response.user_id, response.device_id);
             *data.session.borrow_mut() = Some(session.clone());
@@ -188,6 +567,12 @@ where
 
     /// Convenience method that represents repeated calls to the sync_events endpoint as a stream.
     ///
+    /// Each response updates the client's `Store` before it is yielded, and is passed to the
+    /// processors added with `add_sync_processor`. The account data migrations added with
+    /// `migrate_account_data_on_startup` run before the first request.
+    ///
+    /// Fails with `Error::SyncDisabled` if the client was built in lightweight mode.
+    ///
     /// If the since parameter is None, the first Item might take a significant time to arrive and
     /// be deserialized, because it contains all events that have occured in the whole lifetime of
     /// the logged-in users account and are visible to them.
@@ -199,6 +584,10 @@ where
     ) -> impl Stream<Item = api::r0::sync::sync_events::Response, Error = Error> {
         use crate::api::r0::sync::sync_events;
 
+        if self.0.lightweight {
+            return Either::A(stream::once(Err(Error::SyncDisabled)));
+        }
+
         let client = self.clone();
         let set_presence = if set_presence {
             None
@@ -206,7 +595,15 @@ where
             Some(sync_events::SetPresence::Offline)
         };
 
-        stream::unfold(since, move |since| {
+        let unfold_client = client.clone();
+        let responses = stream::unfold(since, move |since| {
+            let client = unfold_client.clone();
+            let data = client.0.clone();
+            let tuning_data = client.0.clone();
+            let requested_since = since.clone();
+            let timeout = data.sync_timeout.borrow().get();
+            let started = data.clock.instant();
+
             Some(
                 sync_events::call(
                     client.clone(),
@@ -215,44 +612,221 @@ where
                         since,
                         full_state: None,
                         set_presence: set_presence.clone(),
-                        timeout: None,
+                        timeout: Some(timeout.as_millis() as u64),
                     },
                 )
-                .map(|res| {
+                .then(move |result| {
+                    let elapsed = tuning_data
+                        .clock
+                        .instant()
+                        .saturating_duration_since(started);
+                    let mut sync_timeout = tuning_data.sync_timeout.borrow_mut();
+
+                    match result {
+                        Ok(_) => sync_timeout.record_response(timeout, elapsed),
+                        Err(ref error) => sync_timeout.record_error(error, elapsed),
+                    }
+
+                    result
+                })
+                .map(move |mut res| {
+                    data.apply_sync(&mut res, requested_since.as_deref());
+
                     let next_batch_clone = res.next_batch.clone();
                     (res, Some(next_batch_clone))
                 }),
             )
-        })
+        });
+
+        Either::B(
+            client
+                .run_startup_migrations()
+                .map(move |()| responses)
+                .flatten_stream(),
+        )
+    }
+
+    /// Convenience method that repeatedly calls the sync_events endpoint and passes every event of
+    /// the responses to `handler`, sorted into `SyncEvent`s.
+    ///
+    /// If the handler returns an error or panics, `policy` decides how to go on. The returned
+    /// future only resolves if a sync request fails or the policy stops the sync loop.
+    pub fn sync_with_handler<H>(
+        &self,
+        filter: Option<api::r0::sync::sync_events::Filter>,
+        since: Option<String>,
+        set_presence: bool,
+        mut handler: H,
+        mut policy: ErrorPolicy<H::Error>,
+    ) -> impl Future<Item = (), Error = Error>
+    where
+        H: EventHandler,
+    {
+        self.sync(filter, since, set_presence)
+            .for_each(move |response| {
+                for event in SyncEvent::from_response(response) {
+                    dispatch::dispatch(&mut handler, &mut policy, event)?;
+                }
+
+                Ok(())
+            })
+    }
+
+    /// Get the clock of this client, set with `ClientBuilder::clock`.
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.0.clock
+    }
+
+    /// Generates a new transaction ID, unique for the lifetime of this client.
+    pub(crate) fn next_txn_id(&self) -> String {
+        let counter = self.0.txn_id_counter.get();
+        self.0.txn_id_counter.set(counter + 1);
+
+        format!("{}.{}", self.0.txn_id_prefix, counter)
     }
 
     /// Makes a request to a Matrix API endpoint.
-    pub(crate) fn request<E>(
+    ///
+    /// This is what the `call` functions of endpoints use, including those generated by
+    /// `endpoint_calls!`.
+    pub fn request<E>(
+        self,
+        request: <E as Endpoint>::Request,
+    ) -> impl Future<Item = E::Response, Error = Error>
+    where
+        E: Endpoint,
+    {
+        self.request_with_extensions::<E>(request, Extensions::new())
+    }
+
+    /// Makes a request to a Matrix API endpoint, with extension data attached.
+    pub fn request_with_extensions<E>(
         self,
         request: <E as Endpoint>::Request,
+        extensions: Extensions,
     ) -> impl Future<Item = E::Response, Error = Error>
+    where
+        E: Endpoint,
+    {
+        let max_response_size = self.0.max_response_size;
+
+        self.send_request::<E>(request, extensions)
+            .and_then(move |hyper_response| match max_response_size {
+                Some(max_size) => Either::A(limit_response(hyper_response, max_size)),
+                None => Either::B(future::ok(hyper_response)),
+            })
+            .and_then(|hyper_response| {
+                E::Response::future_from(hyper_response).map_err(Error::from)
+            })
+    }
+
+    /// Sends a request to a Matrix API endpoint, without converting the response.
+    ///
+    /// This gives access to the body of error responses, which the conversion discards.
+    pub(crate) fn send_request<E>(
+        self,
+        request: <E as Endpoint>::Request,
+        extensions: Extensions,
+    ) -> impl Future<Item = http::Response<Body>, Error = Error>
+    where
+        E: Endpoint,
+    {
+        self.send_request_with_body::<E>(request, None, extensions)
+    }
+
+    /// Sends a request to a Matrix API endpoint with its body replaced by `body` and the length of
+    /// the new body, if given, without converting the response.
+    ///
+    /// A replaced body is streamed to the homeserver as it is, so a rate-limited request isn't
+    /// retried before a deadline like other requests are.
+    pub(crate) fn send_request_with_body<E>(
+        self,
+        request: <E as Endpoint>::Request,
+        body: Option<(Body, Option<u64>)>,
+        extensions: Extensions,
+    ) -> impl Future<Item = http::Response<Body>, Error = Error>
     where
         E: Endpoint,
     {
         let data1 = self.0.clone();
         let data2 = self.0.clone();
+        let data3 = self.0.clone();
+        let data4 = self.0.clone();
+        let data5 = self.0.clone();
+        let data6 = self.0.clone();
+        let restriction = self.1.clone();
+        // Bodies that replace the request's body are streamed as they are.
+        let replaced_body = body.is_some();
         let mut url = self.0.homeserver_url.clone();
+        // The token of the client's session the request is made with, if any.
+        let mut session_token = None;
 
         request
             .try_into()
             .map_err(Error::from)
             .into_future()
-            .and_then(move |hyper_request| {
+            .and_then(move |mut hyper_request: http::Request<_>| {
+                *hyper_request.extensions_mut() = extensions;
+
                 {
                     let uri = hyper_request.uri();
 
+                    // Setting the path resolves dot segments, so the restriction is checked
+                    // against the path the homeserver gets.
                     url.set_path(uri.path());
+                    let path = url.path().to_owned();
+
+                    if let Some(restriction) = restriction {
+                        if !restriction.permits(hyper_request.method(), &path) {
+                            return Err(Error::NotPermitted {
+                                method: hyper_request.method().clone(),
+                                path,
+                            });
+                        }
+                    }
+
+                    // Requests to another homeserver never get the access token, and only
+                    // downloads go to the media URL, which doesn't get it either.
+                    let base_override = hyper_request
+                        .extensions()
+                        .get::<BaseUrlOverride>()
+                        .map(|base_override| base_override.0.clone());
+                    let other_server = base_override.is_some();
+                    match (base_override, &data1.media_url) {
+                        (Some(base_url), _) => {
+                            url = base_url;
+                            url.set_path(&format!("{}{}", url.path().trim_end_matches('/'), path));
+                        }
+                        (None, Some(media_url))
+                            if !E::METADATA.requires_authentication
+                                && mxc::is_download_path(hyper_request.method(), &path) =>
+                        {
+                            url = media_url.clone();
+                            url.set_path(&format!(
+                                "{}{}",
+                                media_url.path().trim_end_matches('/'),
+                                path
+                            ));
+                        }
+                        _ => {}
+                    }
                     url.set_query(uri.query());
 
                     if E::METADATA.requires_authentication {
-                        if let Some(ref session) = *data1.session.borrow() {
+                        if other_server {
+                            return Err(Error::AuthenticationRequired);
+                        }
+
+                        let token_override =
+                            hyper_request.extensions().get::<AccessTokenOverride>();
+
+                        if let Some(AccessTokenOverride(access_token)) = token_override {
+                            url.query_pairs_mut()
+                                .append_pair("access_token", access_token);
+                        } else if let Some(ref session) = *data1.session.borrow() {
                             url.query_pairs_mut()
                                 .append_pair("access_token", session.access_token());
+                            session_token = Some(session.access_token().to_owned());
                         } else {
                             return Err(Error::AuthenticationRequired);
                         }
@@ -260,22 +834,76 @@ where
                 }
 
                 Uri::from_str(url.as_ref())
-                    .map(move |uri| (uri, hyper_request))
+                    .map(move |uri| (uri, hyper_request, session_token))
                     .map_err(Error::from)
             })
-            .and_then(move |(uri, mut hyper_request)| {
+            .and_then(move |(uri, mut hyper_request, session_token)| {
                 *hyper_request.uri_mut() = uri;
 
-                data2.hyper.request(hyper_request).map_err(Error::from)
+                if replaced_body {
+                    return Either::A(future::ok((hyper_request, session_token)));
+                }
+
+                let method = E::METADATA.method.clone();
+
+                Either::B(
+                    compat::spec_versions_for(Client(data4, None), &method, E::METADATA.path)
+                        .and_then(move |versions| {
+                            compat::adapt_request(
+                                &versions,
+                                &method,
+                                E::METADATA.path,
+                                hyper_request,
+                            )
+                        })
+                        .map(move |hyper_request| (hyper_request, session_token)),
+                )
             })
-            .and_then(|hyper_response| {
-                E::Response::future_from(hyper_response).map_err(Error::from)
+            .and_then(move |(mut hyper_request, session_token)| {
+                let deadline = hyper_request
+                    .extensions()
+                    .get::<Deadline>()
+                    .map(|Deadline(deadline)| *deadline);
+
+                let deadline = deadline::earliest(deadline, data2.deadline.get());
+
+                match (body, deadline) {
+                    (Some((body, length)), deadline) => {
+                        *hyper_request.body_mut() = body;
+
+                        if let Some(length) = length {
+                            hyper_request
+                                .headers_mut()
+                                .insert(CONTENT_LENGTH, HeaderValue::from(length));
+                        }
+
+                        Either::A(deadline::send_once(data2, hyper_request, deadline))
+                    }
+                    (None, Some(deadline)) => Either::B(Either::A(deadline::send_until(
+                        data2,
+                        hyper_request,
+                        deadline,
+                    ))),
+                    (None, None) => Either::B(Either::B(
+                        data2.hyper.request(hyper_request).map_err(Error::from),
+                    )),
+                }
+                .and_then(move |response| session::check_token(data3, session_token, response))
+            })
+            .and_then(move |response| rate_limit::observe(data6, response))
+            .and_then(move |response| {
+                compat::adapt_response(
+                    &data5.spec_versions.borrow(),
+                    &E::METADATA.method,
+                    E::METADATA.path,
+                    response,
+                )
             })
     }
 }
 
 impl<C: Connect> Clone for Client<C> {
     fn clone(&self) -> Client<C> {
-        Client(self.0.clone())
+        Client(self.0.clone(), self.1.clone())
     }
 }
@@ -0,0 +1,116 @@
+//! Helpers for clients in lightweight mode, which send messages without syncing.
+
+use futures::{
+    future::{self, Either, Future, IntoFuture},
+    Stream,
+};
+use http::Response;
+use hyper::{client::connect::Connect, header::CONTENT_LENGTH, Body};
+use ruma_events::room::message::MessageEventContent;
+use ruma_identifiers::{EventId, RoomIdOrAliasId};
+
+use crate::{Client, Error};
+
+/// The default `ClientBuilder::max_response_size` of lightweight clients, 1 MiB.
+pub const LIGHTWEIGHT_MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Whether the client was built with `ClientBuilder::lightweight`.
+    pub fn is_lightweight(&self) -> bool {
+        self.0.lightweight
+    }
+
+    /// Send a message to a room given by its ID or one of its aliases.
+    ///
+    /// Aliases are resolved with a request every time, so nothing is cached in the client. The
+    /// logged-in user has to be a member of the room already.
+    pub fn send_to(
+        &self,
+        room: RoomIdOrAliasId,
+        content: MessageEventContent,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        let client = self.clone();
+
+        let room_id = match room {
+            RoomIdOrAliasId::RoomId(room_id) => Either::A(Ok(room_id).into_future()),
+            RoomIdOrAliasId::RoomAliasId(room_alias) => Either::B(
+                self.resolve_alias(room_alias)
+                    .map(|(room_id, _servers)| room_id),
+            ),
+        };
+
+        room_id.and_then(move |room_id| client.room(room_id).send_message(content))
+    }
+}
+
+/// Read the body of `response` into memory, failing with `Error::ResponseTooLarge` as soon as it
+/// turns out to be larger than `max_size` bytes.
+pub(crate) fn limit_response(
+    response: Response<Body>,
+    max_size: u64,
+) -> impl Future<Item = Response<Body>, Error = Error> {
+    let (parts, body) = response.into_parts();
+    let announced_size = parts
+        .headers
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok())
+        .unwrap_or_default();
+
+    if announced_size > max_size {
+        return Either::A(future::err(Error::ResponseTooLarge { max_size }));
+    }
+
+    Either::B(
+        body.map_err(Error::from)
+            .fold(Vec::new(), move |mut buffer, chunk| {
+                if (buffer.len() + chunk.len()) as u64 > max_size {
+                    return Err(Error::ResponseTooLarge { max_size });
+                }
+
+                buffer.extend_from_slice(&chunk);
+                Ok(buffer)
+            })
+            .map(move |buffer| Response::from_parts(parts, Body::from(buffer))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use http::Response;
+    use hyper::{header::CONTENT_LENGTH, Body};
+
+    use super::limit_response;
+    use crate::Error;
+
+    fn too_large(result: Result<Response<Body>, Error>) -> bool {
+        matches!(result, Err(Error::ResponseTooLarge { max_size: 4 }))
+    }
+
+    #[test]
+    fn small_responses_are_kept() {
+        let response = limit_response(Response::new(Body::from("1234")), 4)
+            .wait()
+            .unwrap();
+        let body = response.into_body().concat2().wait().unwrap();
+
+        assert_eq!(&body[..], b"1234");
+    }
+
+    #[test]
+    fn large_responses_are_rejected() {
+        let chunks: Vec<Result<_, hyper::Error>> = vec![Ok("123"), Ok("45")];
+        let streamed = Response::new(Body::wrap_stream(futures::stream::iter_result(chunks)));
+        let announced = Response::builder()
+            .header(CONTENT_LENGTH, "5")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(too_large(limit_response(streamed, 4).wait()));
+        assert!(too_large(limit_response(announced, 4).wait()));
+    }
+}
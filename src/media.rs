@@ -0,0 +1,959 @@
+//! Media repository conveniences.
+
+use std::{cmp, error::Error as StdError, fmt::Debug, io};
+
+use futures::{
+    future::{self, Either, Future, FutureFrom},
+    try_ready, Async, Poll, Stream,
+};
+use http::{
+    header::{HeaderMap, HeaderName, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE},
+    StatusCode,
+};
+use hyper::{client::connect::Connect, Body, Chunk, Client as HyperClient};
+use ruma_api::Error as RumaApiError;
+use ruma_events::room::{
+    message::{
+        AudioInfo, AudioMessageEventContent, FileInfo, FileMessageEventContent,
+        ImageMessageEventContent, MessageEventContent, MessageType, VideoInfo,
+        VideoMessageEventContent,
+    },
+    ImageInfo,
+};
+use ruma_identifiers::EventId;
+use serde_json::{Map, Value};
+use tokio_io::AsyncRead;
+use url::Url;
+
+use crate::{
+    api::{
+        local::content_scanner::scan,
+        r0::media::{
+            create_content, get_content,
+            get_content_thumbnail::{self, Method as ThumbnailMethod},
+            get_media_config, get_url_preview,
+        },
+    },
+    error::not_found_as_none,
+    extensions::Extensions,
+    mxc::MxcUri,
+    Client, ClientData, Error, Room,
+};
+
+/// How many bytes `Client::upload_reader` reads at a time.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The result of scanning downloaded media.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScanVerdict {
+    /// The media may be handed to the caller.
+    Clean,
+    /// The media must not be handed to the caller, for the given reason.
+    Blocked(String),
+}
+
+/// Media being downloaded with `Client::download_stream`.
+#[derive(Debug)]
+pub struct MediaStream {
+    /// The content type of the media, if the homeserver sent one.
+    pub content_type: Option<String>,
+    /// The `Content-Disposition` header of the response, which may contain the filename of the
+    /// media.
+    pub content_disposition: Option<String>,
+    /// The size of the media in bytes, if the homeserver sent it.
+    pub content_length: Option<u64>,
+    /// The content of the media, as it arrives.
+    pub body: Body,
+}
+
+/// The requested size of a thumbnail, see `Client::get_thumbnail`.
+#[derive(Clone, Copy, Debug)]
+pub struct ThumbnailSize {
+    /// The desired width in pixels.
+    pub width: u64,
+    /// The desired height in pixels.
+    pub height: u64,
+    /// How the image is resized to the desired size.
+    pub method: ThumbnailMethod,
+}
+
+impl ThumbnailSize {
+    /// A thumbnail that fits into the given size, keeping the aspect ratio of the image.
+    pub fn scale(width: u64, height: u64) -> Self {
+        ThumbnailSize {
+            width,
+            height,
+            method: ThumbnailMethod::Scale,
+        }
+    }
+
+    /// A thumbnail of the given size, cropped from the image.
+    pub fn crop(width: u64, height: u64) -> Self {
+        ThumbnailSize {
+            width,
+            height,
+            method: ThumbnailMethod::Crop,
+        }
+    }
+}
+
+/// A thumbnail downloaded with `Client::get_thumbnail`.
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    /// The content type of the thumbnail, if the homeserver sent one.
+    pub content_type: Option<String>,
+    /// The thumbnail image.
+    pub file: Vec<u8>,
+}
+
+/// The preview of a URL, see `Client::url_preview`.
+#[derive(Clone, Debug)]
+pub struct UrlPreview {
+    /// The title of the page, `og:title`.
+    pub title: Option<String>,
+    /// The description of the page, `og:description`.
+    pub description: Option<String>,
+    /// The name of the site, `og:site_name`.
+    pub site_name: Option<String>,
+    /// An image for the page, `og:image`, uploaded to the media repository by the homeserver.
+    pub image: Option<MxcUri>,
+    /// The width of the image in pixels, `og:image:width`.
+    pub image_width: Option<u64>,
+    /// The height of the image in pixels, `og:image:height`.
+    pub image_height: Option<u64>,
+    /// The size of the image in bytes, `matrix:image:size`.
+    pub image_size: Option<u64>,
+    /// All OpenGraph data the homeserver returned.
+    pub data: Map<String, Value>,
+}
+
+/// A hook that inspects downloaded media before it is returned by `Client::download`.
+///
+/// Use `Client::set_content_scanner` to install one, e.g. `RemoteContentScanner` to delegate
+/// to a matrix-content-scanner service.
+pub trait ContentScanner: Debug {
+    /// Scan the content of the media `mxc://{server_name}/{media_id}`.
+    fn scan(
+        &self,
+        server_name: &str,
+        media_id: &str,
+        content: &[u8],
+    ) -> Box<dyn Future<Item = ScanVerdict, Error = Error>>;
+}
+
+/// A `ContentScanner` asking a
+/// [matrix-content-scanner](https://github.com/matrix-org/matrix-content-scanner) service
+/// whether media is clean.
+///
+/// The service fetches and scans the media itself, so the downloaded content is not sent to it.
+#[derive(Debug)]
+pub struct RemoteContentScanner<C: Connect>(Client<C>);
+
+impl<C> RemoteContentScanner<C>
+where
+    C: Connect + 'static,
+{
+    /// Creates a scanner for the service at the given URL, using the given `hyper::Client`.
+    pub fn new(hyper_client: HyperClient<C>, scanner_url: Url) -> Self {
+        RemoteContentScanner(Client::custom(hyper_client, scanner_url, None))
+    }
+}
+
+impl<C> ContentScanner for RemoteContentScanner<C>
+where
+    C: Connect + Debug + 'static,
+{
+    fn scan(
+        &self,
+        server_name: &str,
+        media_id: &str,
+        _content: &[u8],
+    ) -> Box<dyn Future<Item = ScanVerdict, Error = Error>> {
+        let request = scan::Request {
+            server_name: server_name.to_owned(),
+            media_id: media_id.to_owned(),
+        };
+
+        Box::new(
+            self.0
+                .clone()
+                .request::<scan::Endpoint>(request)
+                .map(|response| {
+                    if response.clean {
+                        ScanVerdict::Clean
+                    } else {
+                        ScanVerdict::Blocked(response.info)
+                    }
+                })
+                .or_else(|error| match error {
+                    // The service answers with 403 Forbidden if the media is not clean.
+                    Error::RumaApi(RumaApiError::StatusCode(StatusCode::FORBIDDEN)) => Ok(
+                        ScanVerdict::Blocked("rejected by the content scanner".to_owned()),
+                    ),
+                    error => Err(error),
+                }),
+        )
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Install a hook that has to approve all media downloaded with `Client::download`.
+    pub fn set_content_scanner<S>(&self, scanner: S)
+    where
+        S: ContentScanner + 'static,
+    {
+        *self.0.content_scanner.borrow_mut() = Some(Box::new(scanner));
+    }
+
+    /// Get a preview of `url`, generated by the homeserver from the page's OpenGraph data.
+    ///
+    /// With `ts`, in milliseconds since the Unix epoch, the homeserver returns the preview it
+    /// had cached at that time if it still has it, e.g. to show the preview as it was when a
+    /// message was sent.
+    pub fn url_preview(
+        &self,
+        url: String,
+        ts: Option<u64>,
+    ) -> impl Future<Item = UrlPreview, Error = Error> {
+        get_url_preview::call(self.clone(), get_url_preview::Request { url, ts }).map(|response| {
+            let data = response.data;
+            let string = |key: &str| data.get(key).and_then(Value::as_str).map(ToOwned::to_owned);
+            // Some homeservers send numbers as strings, as they appear in the page.
+            let number = |key: &str| match data.get(key) {
+                Some(Value::String(number)) => number.parse().ok(),
+                Some(number) => number.as_u64(),
+                None => None,
+            };
+
+            UrlPreview {
+                title: string("og:title"),
+                description: string("og:description"),
+                site_name: string("og:site_name"),
+                image: string("og:image").and_then(|image| image.parse().ok()),
+                image_width: number("og:image:width"),
+                image_height: number("og:image:height"),
+                image_size: number("matrix:image:size"),
+                data,
+            }
+        })
+    }
+
+    /// Get the maximum size of an upload in bytes, if the homeserver has a limit.
+    ///
+    /// The limit is only fetched once per client. Homeservers without the media config endpoint
+    /// are assumed to have no limit.
+    pub fn max_upload_size(&self) -> impl Future<Item = Option<u64>, Error = Error> {
+        if let Some(max_size) = self.0.max_upload_size.get() {
+            return Either::A(future::ok(max_size));
+        }
+
+        let data = self.0.clone();
+
+        Either::B(
+            get_media_config::call(self.clone(), get_media_config::Request {})
+                .then(not_found_as_none)
+                .map(move |response| {
+                    let max_size = response.and_then(|response| response.upload_size);
+                    data.max_upload_size.set(Some(max_size));
+                    max_size
+                }),
+        )
+    }
+
+    /// Check that the homeserver accepts uploads of `size` bytes, before uploading a file.
+    ///
+    /// Fails with `Error::UploadTooLarge` if the file is larger than the homeserver's limit, so
+    /// the upload can be refused without sending the file first.
+    pub fn ensure_upload_size(&self, size: u64) -> impl Future<Item = (), Error = Error> {
+        self.max_upload_size()
+            .and_then(move |max_size| match max_size {
+                Some(max_size) if size > max_size => Err(Error::UploadTooLarge { size, max_size }),
+                _ => Ok(()),
+            })
+    }
+
+    /// Upload a file to the media repository, returning its `mxc://` URI.
+    ///
+    /// Fails with `Error::UploadTooLarge` without sending the file if it is larger than the
+    /// homeserver's limit.
+    pub fn upload(
+        &self,
+        content_type: String,
+        filename: Option<String>,
+        file: Vec<u8>,
+    ) -> impl Future<Item = MxcUri, Error = Error> {
+        let client = self.clone();
+
+        self.ensure_upload_size(file.len() as u64)
+            .and_then(move |()| {
+                create_content::call(
+                    client,
+                    create_content::Request {
+                        content_type,
+                        filename,
+                        file,
+                    },
+                )
+            })
+            .and_then(|response| response.content_uri.parse())
+    }
+
+    /// Upload a file produced by a stream of bytes to the media repository, returning its
+    /// `mxc://` URI.
+    ///
+    /// The file is sent while the stream produces it, so it never has to be in memory as a
+    /// whole. Some homeservers, including Synapse, reject uploads of unknown length, so pass the
+    /// `length` of the file in bytes if it's known, which also allows refusing files larger than
+    /// the homeserver's limit with `Error::UploadTooLarge` before sending them. Rate-limited
+    /// uploads aren't retried, since the stream can't be replayed.
+    pub fn upload_stream<S>(
+        &self,
+        content_type: String,
+        filename: Option<String>,
+        length: Option<u64>,
+        stream: S,
+    ) -> impl Future<Item = MxcUri, Error = Error>
+    where
+        S: Stream + Send + 'static,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Chunk: From<S::Item>,
+    {
+        let client = self.clone();
+        let size_check = match length {
+            Some(length) => Either::A(self.ensure_upload_size(length)),
+            None => Either::B(future::ok(())),
+        };
+
+        size_check
+            .and_then(move |()| {
+                client.send_request_with_body::<create_content::Endpoint>(
+                    create_content::Request {
+                        content_type,
+                        filename,
+                        file: Vec::new(),
+                    },
+                    Some((Body::wrap_stream(stream), length)),
+                    Extensions::new(),
+                )
+            })
+            .and_then(|response| {
+                create_content::Response::future_from(response).map_err(Error::from)
+            })
+            .and_then(|response| response.content_uri.parse())
+    }
+
+    /// Upload a file read from `reader` to the media repository, returning its `mxc://` URI.
+    ///
+    /// Like `upload_stream`, for e.g. a `tokio::fs::File`.
+    pub fn upload_reader<R>(
+        &self,
+        content_type: String,
+        filename: Option<String>,
+        length: Option<u64>,
+        reader: R,
+    ) -> impl Future<Item = MxcUri, Error = Error>
+    where
+        R: AsyncRead + Send + 'static,
+    {
+        let stream = ReadStream {
+            reader,
+            buffer: vec![0; READ_CHUNK_SIZE],
+        };
+
+        self.upload_stream(content_type, filename, length, stream)
+    }
+
+    /// Download the media `mxc_uri`.
+    ///
+    /// In contrast to api::r0::media::get_content::call(), this method passes the media to the
+    /// content scanner set with `Client::set_content_scanner`, if any, and fails with
+    /// `Error::ContentBlocked` if the scanner rejects it.
+    pub fn download(
+        &self,
+        mxc_uri: &MxcUri,
+    ) -> impl Future<Item = get_content::Response, Error = Error> {
+        let server_name = mxc_uri.server_name().to_owned();
+        let media_id = mxc_uri.media_id().to_owned();
+        let data = self.0.clone();
+
+        get_content::call(
+            self.clone(),
+            get_content::Request {
+                media_id: media_id.clone(),
+                server_name: server_name.clone(),
+            },
+        )
+        .and_then(move |response| {
+            approve(&data, &server_name, &media_id, &response.file).map(|()| response)
+        })
+    }
+
+    /// Download the media `mxc_uri` as a stream of bytes.
+    ///
+    /// This allows writing large files to disk while they arrive instead of keeping them in
+    /// memory. If a content scanner is set with `Client::set_content_scanner`, the media has to
+    /// be approved before any of it is handed out, so it is downloaded completely first and then
+    /// streamed from memory.
+    pub fn download_stream(
+        &self,
+        mxc_uri: &MxcUri,
+    ) -> impl Future<Item = MediaStream, Error = Error> {
+        let server_name = mxc_uri.server_name().to_owned();
+        let media_id = mxc_uri.media_id().to_owned();
+        let data = self.0.clone();
+
+        self.clone()
+            .send_request::<get_content::Endpoint>(
+                get_content::Request {
+                    media_id: media_id.clone(),
+                    server_name: server_name.clone(),
+                },
+                Extensions::new(),
+            )
+            .and_then(|response| {
+                let status = response.status();
+
+                if status.is_success() {
+                    Ok(response)
+                } else {
+                    Err(Error::RumaApi(RumaApiError::StatusCode(status)))
+                }
+            })
+            .and_then(move |response| {
+                let (parts, body) = response.into_parts();
+                let content_type = header(&parts.headers, &CONTENT_TYPE);
+                let content_disposition = header(&parts.headers, &CONTENT_DISPOSITION);
+                let content_length =
+                    header(&parts.headers, &CONTENT_LENGTH).and_then(|length| length.parse().ok());
+                let stream = move |body| MediaStream {
+                    content_type,
+                    content_disposition,
+                    content_length,
+                    body,
+                };
+
+                if data.content_scanner.borrow().is_none() {
+                    return future::Either::A(future::ok(stream(body)));
+                }
+
+                future::Either::B(
+                    body.concat2()
+                        .map_err(Error::from)
+                        .and_then(move |content| {
+                            approve(&data, &server_name, &media_id, &content)
+                                .map(move |()| stream(Body::from(content)))
+                        }),
+                )
+            })
+    }
+
+    /// Download a thumbnail of the image `mxc_uri` with about the given size.
+    ///
+    /// The homeserver picks the closest thumbnail size it generates, so the thumbnail may be
+    /// larger or smaller than requested. Thumbnails are passed to the content scanner set with
+    /// `Client::set_content_scanner` like other media.
+    pub fn get_thumbnail(
+        &self,
+        mxc_uri: &MxcUri,
+        size: ThumbnailSize,
+    ) -> impl Future<Item = Thumbnail, Error = Error> {
+        let server_name = mxc_uri.server_name().to_owned();
+        let media_id = mxc_uri.media_id().to_owned();
+        let data = self.0.clone();
+
+        self.clone()
+            .send_request::<get_content_thumbnail::Endpoint>(
+                get_content_thumbnail::Request {
+                    media_id: media_id.clone(),
+                    server_name: server_name.clone(),
+                    height: Some(size.height),
+                    method: Some(size.method),
+                    width: Some(size.width),
+                },
+                Extensions::new(),
+            )
+            .and_then(|response| {
+                let status = response.status();
+
+                if !status.is_success() {
+                    return future::Either::A(future::err(Error::RumaApi(
+                        RumaApiError::StatusCode(status),
+                    )));
+                }
+
+                let content_type = header(response.headers(), &CONTENT_TYPE);
+
+                future::Either::B(response.into_body().concat2().map_err(Error::from).map(
+                    move |file| Thumbnail {
+                        content_type,
+                        file: file.to_vec(),
+                    },
+                ))
+            })
+            .and_then(move |thumbnail| {
+                approve(&data, &server_name, &media_id, &thumbnail.file).map(|()| thumbnail)
+            })
+    }
+}
+
+/// Pass downloaded media to the client's content scanner, if any, failing with
+/// `Error::ContentBlocked` if the scanner rejects it.
+fn approve<C>(
+    data: &ClientData<C>,
+    server_name: &str,
+    media_id: &str,
+    content: &[u8],
+) -> impl Future<Item = (), Error = Error>
+where
+    C: Connect,
+{
+    let verdict = match *data.content_scanner.borrow() {
+        Some(ref scanner) => scanner.scan(server_name, media_id, content),
+        None => Box::new(future::ok(ScanVerdict::Clean)),
+    };
+
+    verdict.and_then(|verdict| match verdict {
+        ScanVerdict::Clean => Ok(()),
+        ScanVerdict::Blocked(reason) => Err(Error::ContentBlocked(reason)),
+    })
+}
+
+/// The value of a header of a response, if it's there and valid UTF-8.
+fn header(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned)
+}
+
+/// A stream of the chunks of bytes read from an `AsyncRead`.
+struct ReadStream<R> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R> Stream for ReadStream<R>
+where
+    R: AsyncRead,
+{
+    type Item = Chunk;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Chunk>, io::Error> {
+        let read = try_ready!(self.reader.poll_read(&mut self.buffer));
+
+        if read == 0 {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::Ready(Some(Chunk::from(
+                self.buffer[..read].to_vec(),
+            ))))
+        }
+    }
+}
+
+/// The content type of uploads whose type is neither given nor recognized.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Upload a file and send it to this room as an image, video, audio or file message.
+    ///
+    /// Without a `content_type`, the type is guessed with `sniff_content_type`, falling back to
+    /// `application/octet-stream`. The message type is chosen according to the content type, and
+    /// the size of the file is sent along, as well as the dimensions of PNG, GIF and JPEG images.
+    /// The returned future resolves to the ID of the message event.
+    pub fn send_attachment(
+        &self,
+        filename: String,
+        content_type: Option<String>,
+        file: Vec<u8>,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        let content_type = content_type
+            .or_else(|| sniff_content_type(&file).map(ToOwned::to_owned))
+            .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_owned());
+        let size = file.len() as u64;
+        let image = if content_type.starts_with("image/") {
+            image_metadata(&file)
+        } else {
+            None
+        };
+        let room = self.clone();
+
+        self.client()
+            .upload(content_type.clone(), Some(filename.clone()), file)
+            .and_then(move |url| {
+                let content =
+                    attachment_content(filename, content_type, size, image, url.to_string());
+
+                room.send_message(content)
+            })
+    }
+}
+
+/// The content of a message for an uploaded file.
+fn attachment_content(
+    filename: String,
+    mimetype: String,
+    size: u64,
+    image: Option<ImageMetadata>,
+    url: String,
+) -> MessageEventContent {
+    let kind = mimetype.split('/').next().unwrap_or_default().to_owned();
+
+    match (kind.as_str(), image) {
+        ("image", image) => MessageEventContent::Image(ImageMessageEventContent {
+            body: filename,
+            info: image.map(|image| ImageInfo {
+                height: image.height,
+                mimetype,
+                size,
+                thumbnail_info: None,
+                thumbnail_url: None,
+                width: image.width,
+            }),
+            msgtype: MessageType::Image,
+            url,
+        }),
+        ("video", _) => MessageEventContent::Video(VideoMessageEventContent {
+            body: filename,
+            info: Some(VideoInfo {
+                duration: None,
+                height: None,
+                mimetype: Some(mimetype),
+                size: Some(size),
+                thumbnail_info: None,
+                thumbnail_url: None,
+                width: None,
+            }),
+            msgtype: MessageType::Video,
+            url,
+        }),
+        ("audio", _) => MessageEventContent::Audio(AudioMessageEventContent {
+            body: filename,
+            info: Some(AudioInfo {
+                duration: None,
+                mimetype: Some(mimetype),
+                size: Some(size),
+            }),
+            msgtype: MessageType::Audio,
+            url,
+        }),
+        _ => MessageEventContent::File(FileMessageEventContent {
+            body: filename.clone(),
+            filename,
+            info: Some(FileInfo {
+                mimetype,
+                size,
+                thumbnail_info: None,
+                thumbnail_url: None,
+            }),
+            msgtype: MessageType::File,
+            url,
+        }),
+    }
+}
+
+/// Guess the MIME type of a file from the magic bytes at its start.
+///
+/// Recognizes common image, audio, video and archive formats, and PDF documents.
+pub fn sniff_content_type(file: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+    ];
+
+    if let Some((_, content_type)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| file.starts_with(signature))
+    {
+        return Some(content_type);
+    }
+
+    // RIFF containers name their format at bytes 8 to 12.
+    if file.starts_with(b"RIFF") {
+        return match file.get(8..12) {
+            Some(b"WEBP") => Some("image/webp"),
+            Some(b"WAVE") => Some("audio/wav"),
+            Some(b"AVI ") => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+
+    // ISO base media files start with an `ftyp` box naming their major brand. The same
+    // container holds images, audio and video.
+    if file.get(4..8) == Some(b"ftyp") {
+        return match file.get(8..12)? {
+            b"avif" | b"avis" => Some("image/avif"),
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => Some("image/heic"),
+            b"mif1" | b"msf1" => Some("image/heif"),
+            b"M4A " | b"M4B " => Some("audio/mp4"),
+            b"qt  " => Some("video/quicktime"),
+            b"3gp4" | b"3gp5" | b"3gp6" => Some("video/3gpp"),
+            b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"avc1"
+            | b"M4V " | b"dash" => Some("video/mp4"),
+            _ => None,
+        };
+    }
+
+    // Matroska and WebM files start with an EBML header naming their document type.
+    if file.starts_with(b"\x1a\x45\xdf\xa3") {
+        return match ebml_doc_type(file)? {
+            b"webm" => Some("video/webm"),
+            b"matroska" => Some("video/x-matroska"),
+            _ => None,
+        };
+    }
+
+    // MPEG audio frames without an ID3 tag start with a sync word.
+    match file.get(0..2) {
+        Some([0xff, second]) if second & 0xe0 == 0xe0 && second & 0x06 != 0 => Some("audio/mpeg"),
+        _ => None,
+    }
+}
+
+/// Find the `DocType` element in the EBML header at the start of `file`.
+fn ebml_doc_type(file: &[u8]) -> Option<&[u8]> {
+    // The header is short, the document type follows the version elements.
+    let header = &file[..cmp::min(file.len(), 64)];
+    let position = header.windows(2).position(|id| id == [0x42, 0x82])? + 2;
+
+    // Element sizes are variable-length integers, the length is marked by the first set bit.
+    let first = *file.get(position)?;
+    let width = first.leading_zeros() as usize + 1;
+    if width > 8 {
+        return None;
+    }
+
+    let mut size = u64::from(first) & (0xff >> width);
+    for byte in file.get(position + 1..position + width)? {
+        size = size << 8 | u64::from(*byte);
+    }
+
+    let start = position + width;
+    file.get(start..start.checked_add(size as usize)?)
+}
+
+/// The MIME type and dimensions of an image, read from its header.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct ImageMetadata {
+    pub mimetype: &'static str,
+    pub width: u64,
+    pub height: u64,
+}
+
+/// Read the MIME type and dimensions of a PNG, GIF or JPEG image.
+///
+/// Returns `None` for other formats and for truncated or malformed images.
+pub(crate) fn image_metadata(image: &[u8]) -> Option<ImageMetadata> {
+    if image.starts_with(b"\x89PNG\r\n\x1a\n") && image.get(12..16) == Some(b"IHDR") {
+        return Some(ImageMetadata {
+            mimetype: "image/png",
+            width: u64::from(read_u32_be(image.get(16..20)?)),
+            height: u64::from(read_u32_be(image.get(20..24)?)),
+        });
+    }
+
+    if image.starts_with(b"GIF87a") || image.starts_with(b"GIF89a") {
+        let dimensions = image.get(6..10)?;
+
+        return Some(ImageMetadata {
+            mimetype: "image/gif",
+            width: u64::from(dimensions[0]) | u64::from(dimensions[1]) << 8,
+            height: u64::from(dimensions[2]) | u64::from(dimensions[3]) << 8,
+        });
+    }
+
+    if image.starts_with(b"\xff\xd8") {
+        return jpeg_dimensions(image).map(|(width, height)| ImageMetadata {
+            mimetype: "image/jpeg",
+            width,
+            height,
+        });
+    }
+
+    None
+}
+
+/// Find the dimensions in the start-of-frame segment of a JPEG image.
+fn jpeg_dimensions(image: &[u8]) -> Option<(u64, u64)> {
+    let mut position = 2;
+
+    loop {
+        if *image.get(position)? != 0xff {
+            return None;
+        }
+        let marker = *image.get(position + 1)?;
+
+        match marker {
+            // Fill bytes before a marker.
+            0xff => position += 1,
+            // Markers without a segment.
+            0x01 | 0xd0..=0xd8 => position += 2,
+            // End of image.
+            0xd9 => return None,
+            // Start of frame, except for the DHT, JPG and DAC markers in the same range.
+            0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                let height = image.get(position + 5..position + 7)?;
+                let width = image.get(position + 7..position + 9)?;
+
+                return Some((
+                    u64::from(width[0]) << 8 | u64::from(width[1]),
+                    u64::from(height[0]) << 8 | u64::from(height[1]),
+                ));
+            }
+            _ => {
+                let length = image.get(position + 2..position + 4)?;
+                position += 2 + (usize::from(length[0]) << 8 | usize::from(length[1]));
+            }
+        }
+    }
+}
+
+fn read_u32_be(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) << 24
+        | u32::from(bytes[1]) << 16
+        | u32::from(bytes[2]) << 8
+        | u32::from(bytes[3])
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use url::Url;
+
+    use super::{image_metadata, sniff_content_type};
+    use crate::{ClientBuilder, Error};
+
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        let mut file = b"\0\0\0\x18ftyp".to_vec();
+        file.extend_from_slice(brand);
+        file.extend_from_slice(b"\0\0\0\0mif1miaf");
+        file
+    }
+
+    fn ebml(doc_type: &[u8]) -> Vec<u8> {
+        let mut file = b"\x1a\x45\xdf\xa3\x9f\x42\x86\x81\x01\x42\xf7\x81\x01\x42\x82".to_vec();
+        file.push(0x80 | doc_type.len() as u8);
+        file.extend_from_slice(doc_type);
+        file.extend_from_slice(b"\x42\x87\x81\x04");
+        file
+    }
+
+    #[test]
+    fn sniffs_simple_signatures() {
+        assert_eq!(
+            sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0"),
+            Some("image/png")
+        );
+        assert_eq!(sniff_content_type(b"GIF89a\x01\0"), Some("image/gif"));
+        assert_eq!(sniff_content_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_content_type(b"\xff\xfb\x90\x00"), Some("audio/mpeg"));
+        assert_eq!(sniff_content_type(b"plain text"), None);
+        assert_eq!(sniff_content_type(b""), None);
+    }
+
+    #[test]
+    fn sniffs_the_major_brand_of_iso_media() {
+        assert_eq!(sniff_content_type(&ftyp(b"avif")), Some("image/avif"));
+        assert_eq!(sniff_content_type(&ftyp(b"heic")), Some("image/heic"));
+        assert_eq!(sniff_content_type(&ftyp(b"mif1")), Some("image/heif"));
+        assert_eq!(sniff_content_type(&ftyp(b"M4A ")), Some("audio/mp4"));
+        assert_eq!(sniff_content_type(&ftyp(b"qt  ")), Some("video/quicktime"));
+        assert_eq!(sniff_content_type(&ftyp(b"isom")), Some("video/mp4"));
+        assert_eq!(sniff_content_type(&ftyp(b"mp42")), Some("video/mp4"));
+        assert_eq!(sniff_content_type(&ftyp(b"crx ")), None);
+        assert_eq!(sniff_content_type(b"\0\0\0\x18ftyp"), None);
+    }
+
+    #[test]
+    fn sniffs_the_doc_type_of_ebml() {
+        assert_eq!(sniff_content_type(&ebml(b"webm")), Some("video/webm"));
+        assert_eq!(
+            sniff_content_type(&ebml(b"matroska")),
+            Some("video/x-matroska")
+        );
+        assert_eq!(sniff_content_type(&ebml(b"other")), None);
+        assert_eq!(
+            sniff_content_type(b"\x1a\x45\xdf\xa3\x9f\x42\x82\x88web"),
+            None
+        );
+    }
+
+    #[test]
+    fn png_dimensions_come_from_the_header() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 1, 0x2c, 0, 0, 0, 0xc8]);
+        let metadata = image_metadata(&png).unwrap();
+
+        assert_eq!(metadata.mimetype, "image/png");
+        assert_eq!((metadata.width, metadata.height), (300, 200));
+        assert!(image_metadata(&png[..20]).is_none());
+    }
+
+    #[test]
+    fn gif_dimensions_are_little_endian() {
+        let gif = b"GIF89a\x2c\x01\xc8\x00";
+        let metadata = image_metadata(gif).unwrap();
+
+        assert_eq!(metadata.mimetype, "image/gif");
+        assert_eq!((metadata.width, metadata.height), (300, 200));
+    }
+
+    #[test]
+    fn jpeg_dimensions_come_from_the_frame_after_other_segments() {
+        let jpeg = [
+            0xff, 0xd8, // Start of image.
+            0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, // An APP0 segment.
+            0xff, 0xc4, 0x00, 0x02, // A DHT segment, which isn't a frame.
+            0xff, 0xff, // A fill byte.
+            0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0xc8, 0x01, 0x2c, // Baseline frame.
+        ];
+        let metadata = image_metadata(&jpeg).unwrap();
+
+        assert_eq!(metadata.mimetype, "image/jpeg");
+        assert_eq!((metadata.width, metadata.height), (300, 200));
+        assert!(image_metadata(&[0xff, 0xd8, 0xff, 0xd9]).is_none());
+    }
+
+    #[test]
+    fn other_formats_have_no_metadata() {
+        assert!(image_metadata(b"RIFF\0\0\0\0WEBPVP8 ").is_none());
+        assert!(image_metadata(b"").is_none());
+    }
+
+    #[test]
+    fn uploads_over_the_limit_are_refused_before_sending() {
+        let client = ClientBuilder::new(Url::parse("https://example.org").unwrap()).build();
+        client.0.max_upload_size.set(Some(Some(4)));
+
+        let result = client
+            .upload("text/plain".to_owned(), None, b"hello".to_vec())
+            .wait();
+
+        assert!(matches!(
+            result,
+            Err(Error::UploadTooLarge {
+                size: 5,
+                max_size: 4
+            })
+        ));
+    }
+}
@@ -0,0 +1,533 @@
+//! Encrypting room messages with outbound Megolm sessions.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+use futures::future::{self, Either, Future, Loop};
+use hyper::client::connect::Connect;
+use ruma_events::{collections::only, room::member::MembershipState, EventType};
+use ruma_identifiers::{RoomId, UserId};
+use serde_json::{json, Value};
+
+use crate::{
+    api::r0::to_device::send_event_to_device,
+    encryption::{OutboundGroupSession, MEGOLM_ALGORITHM},
+    store::ENCRYPTION_EVENT_TYPE,
+    Client, Error,
+};
+
+/// The type of encrypted events.
+const ENCRYPTED_EVENT_TYPE: &str = "m.room.encrypted";
+
+/// The type of the to-device events sharing Megolm session keys.
+const ROOM_KEY_EVENT_TYPE: &str = "m.room_key";
+
+/// How long a Megolm session is used if the `m.room.encryption` event doesn't say.
+const DEFAULT_ROTATION_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How many messages a Megolm session encrypts if the `m.room.encryption` event doesn't say.
+const DEFAULT_ROTATION_MESSAGES: u64 = 100;
+
+/// The outbound Megolm session of a room, and who it was shared with.
+#[derive(Debug)]
+pub(crate) struct OutboundGroup {
+    session: Box<dyn OutboundGroupSession>,
+    created_at: Instant,
+    /// How many messages the session encrypted.
+    messages: u64,
+    /// The devices the session key was sent to.
+    shared_with: HashSet<(UserId, String)>,
+}
+
+impl OutboundGroup {
+    /// The devices of `recipients` the session key wasn't sent to yet.
+    fn unshared(&self, recipients: Vec<(UserId, String)>) -> Vec<(UserId, String)> {
+        recipients
+            .into_iter()
+            .filter(|device| !self.shared_with.contains(device))
+            .collect()
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Encrypt a message event for an encrypted room, returning the type and content of the
+    /// `m.room.encrypted` event to send instead.
+    ///
+    /// The room's Megolm session is replaced by a new one once it is too old or encrypted too
+    /// many messages according to the room's `m.room.encryption` event, or when a device it was
+    /// shared with left the room. The session key is sent to the devices of the room's members
+    /// that don't have it yet, over Olm sessions started as needed. Devices no Olm session can be
+    /// started with don't get the key, and can't decrypt the message.
+    pub(crate) fn megolm_encrypt(
+        &self,
+        room_id: RoomId,
+        event_type: EventType,
+        content: Value,
+    ) -> impl Future<Item = (EventType, Value), Error = Error> {
+        if self.0.encryption.borrow().account.is_none() {
+            return Either::A(future::err(Error::EncryptionDisabled));
+        }
+
+        let client = self.clone();
+
+        Either::B(self.update_devices().and_then(move |()| {
+            // Another message to the room may replace the session while its key is shared, in
+            // which case this one is shared and encrypted with the new session.
+            future::loop_fn((), move |()| {
+                client
+                    .try_megolm_encrypt(room_id.clone(), event_type.clone(), content.clone())
+                    .map(|encrypted| match encrypted {
+                        Some(encrypted) => Loop::Break(encrypted),
+                        None => Loop::Continue(()),
+                    })
+            })
+        }))
+    }
+
+    /// Share the room's current Megolm session with the devices that don't have it yet and
+    /// encrypt the message with it, or resolve to `None` if the session was replaced meanwhile.
+    fn try_megolm_encrypt(
+        &self,
+        room_id: RoomId,
+        event_type: EventType,
+        content: Value,
+    ) -> impl Future<Item = Option<(EventType, Value)>, Error = Error> {
+        let share_client = self.clone();
+        let encrypt_client = self.clone();
+
+        let recipients = self.room_devices(&room_id);
+        self.rotate_group_session(&room_id, &recipients);
+
+        let (session_id, unshared): (String, Vec<_>) = {
+            let encryption = self.0.encryption.borrow();
+            let group = &encryption.group_sessions[&room_id];
+
+            (group.session.session_id(), group.unshared(recipients))
+        };
+        let share_session_id = session_id.clone();
+
+        self.start_olm_sessions(unshared.clone())
+            .and_then(move |failed| {
+                let failed: HashSet<_> = failed.into_iter().collect();
+                let recipients = unshared
+                    .into_iter()
+                    .filter(|device| !failed.contains(device))
+                    .collect();
+
+                share_client
+                    .share_group_session(&room_id, &share_session_id, recipients)
+                    .map(move |()| room_id)
+            })
+            .and_then(move |room_id| {
+                encrypt_client.group_encrypt(&room_id, &session_id, event_type, content)
+            })
+    }
+
+    /// The devices of the joined and invited members of a room, except the client's own device.
+    fn room_devices(&self, room_id: &RoomId) -> Vec<(UserId, String)> {
+        let own_device = self
+            .session()
+            .map(|session| (session.user_id().clone(), session.device_id().to_owned()));
+        let snapshot = self.store().snapshot();
+        let encryption = self.0.encryption.borrow();
+        let mut devices = Vec::new();
+
+        for event in snapshot.state(room_id) {
+            let member = match event {
+                only::StateEvent::RoomMember(member) => member,
+                _ => continue,
+            };
+            let user_id = match UserId::try_from(member.state_key.as_str()) {
+                Ok(user_id) => user_id,
+                Err(_) => continue,
+            };
+            let shares_room = matches!(
+                member.content.membership,
+                MembershipState::Join | MembershipState::Invite
+            );
+            let user_devices = encryption
+                .devices
+                .user_devices(&user_id)
+                .filter(|_| shares_room);
+
+            for device_id in user_devices.into_iter().flat_map(BTreeMap::keys) {
+                let device = (user_id.clone(), device_id.clone());
+
+                if Some(&device) != own_device.as_ref() {
+                    devices.push(device);
+                }
+            }
+        }
+
+        devices
+    }
+
+    /// Start a new Megolm session for a room unless the current one can still be used for the
+    /// given recipients.
+    fn rotate_group_session(&self, room_id: &RoomId, recipients: &[(UserId, String)]) {
+        let (period, max_messages) = self.rotation_settings(room_id);
+        let now = self.0.clock.instant();
+        let mut encryption = self.0.encryption.borrow_mut();
+        let encryption = &mut *encryption;
+
+        let expired = match encryption.group_sessions.get(room_id) {
+            Some(group) => {
+                let recipients: HashSet<_> = recipients.iter().collect();
+
+                now.saturating_duration_since(group.created_at) >= period
+                    || group.messages >= max_messages
+                    || group
+                        .shared_with
+                        .iter()
+                        .any(|device| !recipients.contains(device))
+            }
+            None => true,
+        };
+
+        if let (true, Some(account)) = (expired, encryption.account.as_ref()) {
+            encryption.group_sessions.insert(
+                room_id.clone(),
+                OutboundGroup {
+                    session: account.create_outbound_group_session(),
+                    created_at: now,
+                    messages: 0,
+                    shared_with: HashSet::new(),
+                },
+            );
+        }
+    }
+
+    /// How long and for how many messages a Megolm session is used in a room.
+    fn rotation_settings(&self, room_id: &RoomId) -> (Duration, u64) {
+        let content = self
+            .store()
+            .state_event(room_id, &EventType::from(ENCRYPTION_EVENT_TYPE), "")
+            .and_then(|event| serde_json::to_value(event).ok())
+            .and_then(|mut event| event.get_mut("content").map(Value::take))
+            .unwrap_or(Value::Null);
+
+        let period = content
+            .get("rotation_period_ms")
+            .and_then(Value::as_u64)
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_ROTATION_PERIOD);
+        let max_messages = content
+            .get("rotation_period_msgs")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_ROTATION_MESSAGES);
+
+        (period, max_messages)
+    }
+
+    /// Send the key of a room's Megolm session with the given ID to the given devices, encrypted
+    /// with Olm.
+    ///
+    /// Does nothing if the session isn't the room's current session anymore.
+    fn share_group_session(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+        recipients: Vec<(UserId, String)>,
+    ) -> impl Future<Item = (), Error = Error> {
+        let data = self.0.clone();
+        let room_id = room_id.clone();
+        let session_id = session_id.to_owned();
+        let room_key = {
+            let encryption = self.0.encryption.borrow();
+
+            match encryption.group_sessions.get(&room_id) {
+                Some(group) if group.session.session_id() == session_id => json!({
+                    "algorithm": MEGOLM_ALGORITHM,
+                    "room_id": room_id.to_string(),
+                    "session_id": session_id,
+                    "session_key": group.session.session_key(),
+                }),
+                _ => return Either::A(future::ok(())),
+            }
+        };
+
+        let mut messages: HashMap<UserId, BTreeMap<String, Value>> = HashMap::new();
+
+        for (user_id, device_id) in &recipients {
+            match self.olm_encrypt(user_id, device_id, ROOM_KEY_EVENT_TYPE, room_key.clone()) {
+                Ok(content) => {
+                    messages
+                        .entry(user_id.clone())
+                        .or_default()
+                        .insert(device_id.clone(), content);
+                }
+                Err(error) => return Either::A(future::err(error)),
+            }
+        }
+
+        if messages.is_empty() {
+            return Either::A(future::ok(()));
+        }
+
+        Either::B(
+            send_event_to_device::call(
+                self.clone(),
+                send_event_to_device::Request {
+                    event_type: ENCRYPTED_EVENT_TYPE.to_owned(),
+                    txn_id: self.next_txn_id(),
+                    messages,
+                },
+            )
+            .map(move |_| {
+                let mut encryption = data.encryption.borrow_mut();
+
+                match encryption.group_sessions.get_mut(&room_id) {
+                    Some(group) if group.session.session_id() == session_id => {
+                        group.shared_with.extend(recipients);
+                    }
+                    _ => {}
+                }
+            }),
+        )
+    }
+
+    /// Encrypt a message event with the room's Megolm session with the given ID, or return `None`
+    /// if it isn't the room's current session anymore.
+    fn group_encrypt(
+        &self,
+        room_id: &RoomId,
+        session_id: &str,
+        event_type: EventType,
+        content: Value,
+    ) -> Result<Option<(EventType, Value)>, Error> {
+        let session = self.logged_in_session()?;
+        let mut encryption = self.0.encryption.borrow_mut();
+        let encryption = &mut *encryption;
+        let sender_key = encryption
+            .account
+            .as_ref()
+            .ok_or(Error::EncryptionDisabled)?
+            .identity_keys()
+            .curve25519;
+        let group = encryption
+            .group_sessions
+            .get_mut(room_id)
+            .ok_or(Error::EncryptionDisabled)?;
+
+        if group.session.session_id() != session_id {
+            return Ok(None);
+        }
+
+        let payload = json!({
+            "type": event_type.to_string(),
+            "content": content,
+            "room_id": room_id.to_string(),
+        });
+        let ciphertext = group.session.encrypt(&serde_json::to_string(&payload)?);
+        group.messages += 1;
+
+        Ok(Some((
+            EventType::from(ENCRYPTED_EVENT_TYPE),
+            json!({
+                "algorithm": MEGOLM_ALGORITHM,
+                "sender_key": sender_key,
+                "ciphertext": ciphertext,
+                "session_id": group.session.session_id(),
+                "device_id": session.device_id(),
+            }),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, time::Duration};
+
+    use futures::Future;
+    use hyper::client::HttpConnector;
+    use ruma_events::EventType;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_json::{json, Value};
+    use url::Url;
+
+    use crate::{
+        encryption::tests::{device_keys, sync_response, FakeAccount, FakeSession},
+        environment::TestClock,
+        Client, ClientBuilder, Session,
+    };
+
+    fn room_id() -> RoomId {
+        RoomId::try_from("!room:example.org").unwrap()
+    }
+
+    fn device(user_id: &str, device_id: &str) -> (UserId, String) {
+        (UserId::try_from(user_id).unwrap(), device_id.to_owned())
+    }
+
+    fn state_event(event_type: &str, state_key: &str, content: Value) -> Value {
+        json!({
+            "type": event_type,
+            "state_key": state_key,
+            "content": content,
+            "event_id": format!("${}{}:example.org", event_type, state_key.replace(':', ".")),
+            "sender": "@alice:example.org",
+            "origin_server_ts": 0,
+        })
+    }
+
+    /// A client logged in as Alice with encryption enabled, in an encrypted room with the given
+    /// members and `m.room.encryption` content.
+    fn client(clock: &TestClock, members: &[&str], encryption: Value) -> Client<HttpConnector> {
+        let session = Session::new(
+            "token".to_owned(),
+            UserId::try_from("@alice:example.org").unwrap(),
+            "ALICE".to_owned(),
+        );
+        let client = ClientBuilder::new(Url::parse("http://127.0.0.1:1").unwrap())
+            .session(session)
+            .clock(clock.clone())
+            .build();
+        client.enable_encryption(FakeAccount::new("alice"));
+
+        let mut state = vec![state_event("m.room.encryption", "", encryption)];
+        state.extend(
+            members.iter().map(|user_id| {
+                state_event("m.room.member", user_id, json!({ "membership": "join" }))
+            }),
+        );
+        let room = serde_json::from_value(json!({
+            "unread_notifications": {},
+            "timeline": { "limited": false, "prev_batch": "prev", "events": [] },
+            "state": { "events": state },
+            "account_data": { "events": [] },
+            "ephemeral": { "events": [] },
+        }))
+        .unwrap();
+        let mut response = sync_response();
+        response.rooms.join.insert(room_id(), room);
+        client.0.apply_sync(&mut response, None);
+
+        client
+    }
+
+    /// Encrypt a message, which works without a homeserver as long as no session key has to be
+    /// sent, and return the ID of the Megolm session used.
+    fn encrypt(client: &Client<HttpConnector>) -> String {
+        let (_, content) = client
+            .try_megolm_encrypt(
+                room_id(),
+                EventType::RoomMessage,
+                json!({ "msgtype": "m.text", "body": "Hi" }),
+            )
+            .wait()
+            .unwrap()
+            .unwrap();
+
+        content["session_id"].as_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn sessions_rotate_after_the_message_limit() {
+        let clock = TestClock::new();
+        let client = client(
+            &clock,
+            &["@alice:example.org"],
+            json!({ "algorithm": "m.megolm.v1.aes-sha2", "rotation_period_msgs": 2 }),
+        );
+
+        assert_eq!(encrypt(&client), "alice-group0");
+        assert_eq!(encrypt(&client), "alice-group0");
+        assert_eq!(encrypt(&client), "alice-group1");
+    }
+
+    #[test]
+    fn sessions_rotate_after_the_rotation_period() {
+        let clock = TestClock::new();
+        let client = client(
+            &clock,
+            &["@alice:example.org"],
+            json!({ "algorithm": "m.megolm.v1.aes-sha2", "rotation_period_ms": 1000 }),
+        );
+
+        assert_eq!(encrypt(&client), "alice-group0");
+        clock.advance(Duration::from_millis(999));
+        assert_eq!(encrypt(&client), "alice-group0");
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(encrypt(&client), "alice-group1");
+    }
+
+    #[test]
+    fn sessions_are_shared_only_with_devices_that_dont_have_them() {
+        let clock = TestClock::new();
+        let client = client(
+            &clock,
+            &[
+                "@alice:example.org",
+                "@bob:example.org",
+                "@carol:example.org",
+            ],
+            json!({ "algorithm": "m.megolm.v1.aes-sha2" }),
+        );
+        let bob = device("@bob:example.org", "BOB");
+        let carol = device("@carol:example.org", "CAROL");
+        {
+            let mut encryption = client.0.encryption.borrow_mut();
+            encryption.devices.insert_device(device_keys(
+                &FakeAccount::new("bob"),
+                "@bob:example.org",
+                "BOB",
+            ));
+            encryption.sessions.insert(
+                "bob-curve25519".to_owned(),
+                Box::new(FakeSession("bob".to_owned())),
+            );
+        }
+        let recipients = client.room_devices(&room_id());
+        assert_eq!(recipients, vec![bob.clone()]);
+
+        client.rotate_group_session(&room_id(), &recipients);
+        client
+            .0
+            .encryption
+            .borrow_mut()
+            .group_sessions
+            .get_mut(&room_id())
+            .unwrap()
+            .shared_with
+            .insert(bob.clone());
+
+        // Bob has the session already, so no key is sent and no homeserver is needed.
+        assert_eq!(encrypt(&client), "alice-group0");
+
+        let recipients = vec![bob, carol.clone()];
+        let encryption = client.0.encryption.borrow();
+        assert_eq!(
+            encryption.group_sessions[&room_id()].unshared(recipients),
+            vec![carol]
+        );
+    }
+
+    #[test]
+    fn sessions_rotate_when_a_device_leaves() {
+        let clock = TestClock::new();
+        let client = client(
+            &clock,
+            &["@alice:example.org"],
+            json!({ "algorithm": "m.megolm.v1.aes-sha2" }),
+        );
+
+        assert_eq!(encrypt(&client), "alice-group0");
+        client
+            .0
+            .encryption
+            .borrow_mut()
+            .group_sessions
+            .get_mut(&room_id())
+            .unwrap()
+            .shared_with
+            .insert(device("@bob:example.org", "BOB"));
+
+        assert_eq!(encrypt(&client), "alice-group1");
+    }
+}
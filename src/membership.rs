@@ -0,0 +1,242 @@
+//! Room membership conveniences.
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_identifiers::{RoomId, RoomIdOrAliasId, UserId};
+use url::Url;
+
+use crate::{api::local::identity::sign_ed25519, Client, Error, Room, RoomFeature};
+
+/// The details of an invite to a room sent to an email address by an identity server.
+#[derive(Clone, Debug)]
+pub struct EmailInvite {
+    /// The identity server that sent the invite.
+    pub identity_server: Url,
+    /// The token of the invite, i.e. the state key of its `m.third_party_invite` event.
+    pub token: String,
+    /// The private key to sign the invite with, encoded as unpadded base64.
+    pub private_key: String,
+}
+
+impl EmailInvite {
+    /// Get the invite details from the signing URL of an invite email, i.e. the `signurl`
+    /// parameter of the link in the email.
+    ///
+    /// Returns `None` if the URL lacks the token or the private key.
+    pub fn from_sign_url(sign_url: &Url) -> Option<Self> {
+        let mut token = None;
+        let mut private_key = None;
+
+        for (key, value) in sign_url.query_pairs() {
+            match key.as_ref() {
+                "token" => token = Some(value.into_owned()),
+                "private_key" => private_key = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        let mut identity_server = sign_url.clone();
+        identity_server.set_path("");
+        identity_server.set_query(None);
+
+        Some(EmailInvite {
+            identity_server,
+            token: token?,
+            private_key: private_key?,
+        })
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Ask to be invited to a room whose join rule is `knock`.
+    ///
+    /// Fails with `Error::UnsupportedByServer` before knocking if the homeserver doesn't support
+    /// knocking. Pending knocks are listed by `Store::knocked_room_ids`. The returned future
+    /// resolves to the ID of the room.
+    pub fn knock(
+        &self,
+        room_id_or_alias: RoomIdOrAliasId,
+        reason: Option<String>,
+    ) -> impl Future<Item = RoomId, Error = Error> {
+        use crate::api::r0::membership::knock_room;
+
+        let client = self.clone();
+
+        self.ensure_server_supports(RoomFeature::Knocking)
+            .and_then(move |()| {
+                knock_room::call(
+                    client,
+                    knock_room::Request {
+                        room_id_or_alias,
+                        reason,
+                    },
+                )
+            })
+            .map(|response| response.room_id)
+    }
+
+    /// Join a room the logged-in user was invited to by email.
+    ///
+    /// The identity server that sent the invite signs it for the logged-in user, proving that
+    /// they own the email address, and the signature is then used to join the room. The returned
+    /// future resolves to the ID of the joined room.
+    pub fn join_with_email_invite(
+        &self,
+        room_id_or_alias: RoomIdOrAliasId,
+        invite: &EmailInvite,
+    ) -> impl Future<Item = RoomId, Error = Error> {
+        use crate::api::r0::membership::join_room_by_id_or_alias;
+
+        let mxid = match self.logged_in_user_id() {
+            Ok(user_id) => user_id,
+            Err(error) => return Either::A(future::err(error)),
+        };
+        let identity_server =
+            Client::custom(self.0.hyper.clone(), invite.identity_server.clone(), None)
+                .restricted_like(self);
+        let client = self.clone();
+
+        Either::B(
+            identity_server
+                .request::<sign_ed25519::Endpoint>(sign_ed25519::Request {
+                    mxid,
+                    token: invite.token.clone(),
+                    private_key: invite.private_key.clone(),
+                })
+                .and_then(move |response| {
+                    join_room_by_id_or_alias::call(
+                        client,
+                        join_room_by_id_or_alias::Request {
+                            room_id_or_alias,
+                            third_party_signed: Some(response.signed),
+                        },
+                    )
+                })
+                .map(|response| response.room_id),
+        )
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Leave this room, or reject the invite to it.
+    ///
+    /// Afterwards, the room is removed from the client's store.
+    pub fn leave(&self) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::membership::leave_room;
+
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+
+        leave_room::call(
+            client.clone(),
+            leave_room::Request {
+                room_id: room_id.clone(),
+            },
+        )
+        .map(move |_| client.store().remove_room(&room_id))
+    }
+
+    /// Forget this room, so that the homeserver no longer returns its history to the logged-in
+    /// user.
+    ///
+    /// The room has to be left first. Afterwards, the room is removed from the client's store,
+    /// in case it was left by another client.
+    pub fn forget(&self) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::membership::forget_room;
+
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+
+        forget_room::call(
+            client.clone(),
+            forget_room::Request {
+                room_id: room_id.clone(),
+            },
+        )
+        .map(move |_| client.store().remove_room(&room_id))
+    }
+
+    /// Invite a user to this room.
+    pub fn invite(
+        &self,
+        user_id: UserId,
+        reason: Option<String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::membership::invite_user;
+
+        invite_user::call(
+            self.client().clone(),
+            invite_user::Request {
+                room_id: self.room_id().clone(),
+                user_id,
+                reason,
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Kick a user from this room.
+    pub fn kick(
+        &self,
+        user_id: UserId,
+        reason: Option<String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::membership::kick_user;
+
+        kick_user::call(
+            self.client().clone(),
+            kick_user::Request {
+                room_id: self.room_id().clone(),
+                user_id,
+                reason,
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Ban a user from this room, kicking them if they are a member.
+    pub fn ban(
+        &self,
+        user_id: UserId,
+        reason: Option<String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::membership::ban_user;
+
+        ban_user::call(
+            self.client().clone(),
+            ban_user::Request {
+                room_id: self.room_id().clone(),
+                user_id,
+                reason,
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Lift the ban of a user from this room.
+    ///
+    /// The user has to be invited or join again to become a member.
+    pub fn unban(
+        &self,
+        user_id: UserId,
+        reason: Option<String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::membership::unban_user;
+
+        unban_user::call(
+            self.client().clone(),
+            unban_user::Request {
+                room_id: self.room_id().clone(),
+                user_id,
+                reason,
+            },
+        )
+        .map(|_| ())
+    }
+}
@@ -0,0 +1,544 @@
+//! Message content not covered by `ruma_events`.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use ruma_events::room::message::{MessageEvent, MessageEventContent, MessageType};
+use ruma_identifiers::{EventId, RoomId, UserId};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::Error;
+
+/// The type of reaction events.
+pub const REACTION_EVENT_TYPE: &str = "m.reaction";
+
+/// The `format` of message bodies formatted with HTML.
+pub const HTML_FORMAT: &str = "org.matrix.custom.html";
+
+/// The content of an `m.room.message` event with a formatted body in addition to the plain text
+/// one.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FormattedMessageEventContent {
+    /// The message type.
+    pub msgtype: MessageType,
+    /// The plain text body, used by clients that can't display the formatted one.
+    pub body: String,
+    /// The format of `formatted_body`, e.g. `HTML_FORMAT`.
+    pub format: String,
+    /// The formatted body.
+    pub formatted_body: String,
+    /// How this message relates to other events.
+    #[serde(
+        rename = "m.relates_to",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub relates_to: Option<RelatesTo>,
+}
+
+/// How a message relates to other events.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RelatesTo {
+    /// The type of the relation to `event_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rel_type: Option<RelationType>,
+    /// The event this message relates to with `rel_type`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<EventId>,
+    /// The key of an annotation, e.g. the emoji of a reaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Whether `in_reply_to` is only a fallback for clients without support for threads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_falling_back: Option<bool>,
+    /// The event this message is a reply to.
+    #[serde(
+        rename = "m.in_reply_to",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub in_reply_to: Option<InReplyTo>,
+}
+
+/// The type of a relation between two events.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum RelationType {
+    /// The message replaces the content of the event, i.e. it is an edit.
+    #[serde(rename = "m.replace")]
+    Replace,
+    /// The message annotates the event, e.g. with a reaction.
+    #[serde(rename = "m.annotation")]
+    Annotation,
+    /// The message is part of the thread started by the event.
+    #[serde(rename = "m.thread")]
+    Thread,
+}
+
+impl Display for RelationType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let rel_type = match self {
+            RelationType::Replace => "m.replace",
+            RelationType::Annotation => "m.annotation",
+            RelationType::Thread => "m.thread",
+        };
+
+        f.write_str(rel_type)
+    }
+}
+
+/// The content of an `m.reaction` event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReactionEventContent {
+    /// The annotation relation to the event that is reacted to.
+    #[serde(rename = "m.relates_to")]
+    pub relates_to: RelatesTo,
+}
+
+impl ReactionEventContent {
+    /// Creates the content of a reaction to the event `event_id` with `key`, usually an emoji.
+    pub fn new(event_id: EventId, key: String) -> Self {
+        ReactionEventContent {
+            relates_to: RelatesTo {
+                rel_type: Some(RelationType::Annotation),
+                event_id: Some(event_id),
+                key: Some(key),
+                ..RelatesTo::default()
+            },
+        }
+    }
+}
+
+/// A reference to the event a message is a reply to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InReplyTo {
+    /// The ID of the event.
+    pub event_id: EventId,
+}
+
+impl FormattedMessageEventContent {
+    /// Creates message content with an HTML body.
+    ///
+    /// If `plain` is `None`, the plain text body is generated from `html` with `html_to_plain`.
+    pub fn html(msgtype: MessageType, html: String, plain: Option<String>) -> Self {
+        FormattedMessageEventContent {
+            msgtype,
+            body: plain.unwrap_or_else(|| html_to_plain(&html)),
+            format: HTML_FORMAT.to_owned(),
+            formatted_body: html,
+            relates_to: None,
+        }
+    }
+
+    /// Creates the content of a reply to `original`, which was sent in the room `room_id`.
+    ///
+    /// Both bodies are prefixed with a quote of the original message, as recommended by the
+    /// specification for clients that don't support replies. If `plain` is `None`, the plain
+    /// text body is generated from `html` with `html_to_plain`.
+    pub fn reply(
+        room_id: &RoomId,
+        original: &MessageEvent,
+        html: String,
+        plain: Option<String>,
+    ) -> Self {
+        let original_body = message_body(&original.content);
+        let plain = plain.unwrap_or_else(|| html_to_plain(&html));
+
+        let mut quote = format!("> <{}>", original.sender);
+        for (i, line) in original_body.lines().enumerate() {
+            if i > 0 {
+                quote.push_str("\n>");
+            }
+            quote.push(' ');
+            quote.push_str(line);
+        }
+
+        let html_quote = format!(
+            "<mx-reply><blockquote>\
+             <a href=\"https://matrix.to/#/{room_id}/{event_id}\">In reply to</a> \
+             <a href=\"https://matrix.to/#/{sender}\">{sender}</a><br />{body}\
+             </blockquote></mx-reply>",
+            room_id = room_id,
+            event_id = original.event_id,
+            sender = original.sender,
+            body = text_to_html(original_body),
+        );
+
+        FormattedMessageEventContent {
+            msgtype: MessageType::Text,
+            body: format!("{}\n\n{}", quote, plain),
+            format: HTML_FORMAT.to_owned(),
+            formatted_body: html_quote + &html,
+            relates_to: Some(RelatesTo {
+                in_reply_to: Some(InReplyTo {
+                    event_id: original.event_id.clone(),
+                }),
+                ..RelatesTo::default()
+            }),
+        }
+    }
+}
+
+/// The content of an edit, as sent by another client.
+#[derive(Deserialize)]
+struct ReplacementEvent {
+    sender: UserId,
+    content: ReplacementEventContent,
+}
+
+/// The content of an edit event.
+#[derive(Deserialize)]
+pub(crate) struct ReplacementEventContent {
+    #[serde(rename = "m.new_content")]
+    pub(crate) new_content: MessageEventContent,
+    #[serde(rename = "m.relates_to")]
+    pub(crate) relates_to: RelatesTo,
+}
+
+/// Creates the content of an edit of the event `original_event_id`, replacing its content with
+/// `new_content`.
+///
+/// `new_content` must serialize to the content of an `m.room.message` event. The fallback for
+/// clients that don't support edits is the new content with its bodies prefixed by `* `.
+pub fn replacement_content<T>(original_event_id: &EventId, new_content: &T) -> Result<Value, Error>
+where
+    T: serde::Serialize,
+{
+    let new_content = serde_json::to_value(new_content)?;
+    let relates_to = serde_json::to_value(RelatesTo {
+        rel_type: Some(RelationType::Replace),
+        event_id: Some(original_event_id.clone()),
+        ..RelatesTo::default()
+    })?;
+
+    let mut content = new_content.clone();
+    if let Value::Object(ref mut fields) = content {
+        for field in &["body", "formatted_body"] {
+            if let Some(Value::String(text)) = fields.get_mut(*field) {
+                text.insert_str(0, "* ");
+            }
+        }
+
+        fields.insert("m.new_content".to_owned(), new_content);
+        fields.insert("m.relates_to".to_owned(), relates_to);
+    }
+
+    Ok(content)
+}
+
+/// Creates the content of a message in the thread started by `root_event_id`.
+///
+/// `new_content` must serialize to the content of an `m.room.message` event. Clients without
+/// support for threads show the message as a reply to `latest_event_id`, the latest event of the
+/// thread.
+pub fn thread_content<T>(
+    root_event_id: &EventId,
+    latest_event_id: &EventId,
+    content: &T,
+) -> Result<Value, Error>
+where
+    T: serde::Serialize,
+{
+    let mut content = serde_json::to_value(content)?;
+    let relates_to = serde_json::to_value(RelatesTo {
+        rel_type: Some(RelationType::Thread),
+        event_id: Some(root_event_id.clone()),
+        in_reply_to: Some(InReplyTo {
+            event_id: latest_event_id.clone(),
+        }),
+        is_falling_back: Some(true),
+        ..RelatesTo::default()
+    })?;
+
+    if let Value::Object(ref mut fields) = content {
+        fields.insert("m.relates_to".to_owned(), relates_to);
+    }
+
+    Ok(content)
+}
+
+/// The content of `original` after applying the edit `replacement`, an event as JSON.
+///
+/// Edits by other users than the sender of `original` and events that aren't edits of it are
+/// ignored.
+pub(crate) fn apply_replacement(
+    original: &MessageEvent,
+    replacement: Value,
+) -> MessageEventContent {
+    match serde_json::from_value::<ReplacementEvent>(replacement) {
+        Ok(ref replacement)
+            if replacement.sender == original.sender
+                && replacement.content.relates_to.rel_type == Some(RelationType::Replace)
+                && replacement.content.relates_to.event_id.as_ref() == Some(&original.event_id) =>
+        {
+            replacement.content.new_content.clone()
+        }
+        _ => original.content.clone(),
+    }
+}
+
+/// The content of a reply, without the quote of the original message.
+#[derive(Clone, Debug)]
+pub struct Reply {
+    /// The ID of the event that is replied to.
+    pub in_reply_to: EventId,
+    /// The plain text body, without the fallback.
+    pub body: String,
+    /// The formatted body, without the fallback, if the reply has one.
+    pub formatted_body: Option<String>,
+}
+
+impl Reply {
+    /// Get the reply from the content of an `m.room.message` event as JSON, e.g. from the
+    /// response of `get_room_event`.
+    ///
+    /// Returns `None` if the message isn't a reply. Messages in threads whose reply relation is
+    /// only a fallback for clients without support for threads don't count as replies.
+    pub fn from_content(content: &Value) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct ReplyContent {
+            body: String,
+            formatted_body: Option<String>,
+            #[serde(rename = "m.relates_to")]
+            relates_to: RelatesTo,
+        }
+
+        let content: ReplyContent = serde_json::from_value(content.clone()).ok()?;
+
+        if content.relates_to.is_falling_back == Some(true) {
+            return None;
+        }
+
+        Some(Reply {
+            in_reply_to: content.relates_to.in_reply_to?.event_id,
+            body: strip_reply_fallback(&content.body).to_owned(),
+            formatted_body: content
+                .formatted_body
+                .map(|html| strip_html_reply_fallback(&html)),
+        })
+    }
+}
+
+/// Remove the quote of the original message from the plain text body of a reply.
+///
+/// The quote consists of the lines at the start of the body beginning with `> `, followed by an
+/// empty line.
+pub fn strip_reply_fallback(body: &str) -> &str {
+    let mut rest = body;
+    let mut stripped = false;
+
+    while rest.starts_with("> ") || rest == ">" || rest.starts_with(">\n") {
+        stripped = true;
+        rest = match rest.find('\n') {
+            Some(end) => &rest[end + 1..],
+            None => "",
+        };
+    }
+
+    if stripped && rest.starts_with('\n') {
+        rest = &rest[1..];
+    }
+
+    rest
+}
+
+/// Remove the quote of the original message, i.e. the `mx-reply` element, from the HTML body of
+/// a reply.
+pub fn strip_html_reply_fallback(html: &str) -> String {
+    const END_TAG: &str = "</mx-reply>";
+
+    match (html.find("<mx-reply>"), html.rfind(END_TAG)) {
+        (Some(start), Some(end)) if start < end => {
+            let mut stripped = html[..start].to_owned();
+            stripped.push_str(&html[end + END_TAG.len()..]);
+            stripped
+        }
+        _ => html.to_owned(),
+    }
+}
+
+/// The plain text body of a message.
+pub fn message_body(content: &MessageEventContent) -> &str {
+    match content {
+        MessageEventContent::Audio(content) => &content.body,
+        MessageEventContent::Emote(content) => &content.body,
+        MessageEventContent::File(content) => &content.body,
+        MessageEventContent::Image(content) => &content.body,
+        MessageEventContent::Location(content) => &content.body,
+        MessageEventContent::Notice(content) => &content.body,
+        MessageEventContent::Text(content) => &content.body,
+        MessageEventContent::Video(content) => &content.body,
+    }
+}
+
+/// Escapes the characters with a special meaning in HTML.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Converts a plain text message body into HTML, escaping it and turning newlines into line
+/// breaks.
+pub fn text_to_html(text: &str) -> String {
+    escape_html(text).replace('\n', "<br />")
+}
+
+/// Converts an HTML message body into a plain text fallback.
+///
+/// Tags are removed, line breaks and the ends of block elements become newlines, list items are
+/// prefixed with a dash and character references are decoded.
+pub fn html_to_plain(html: &str) -> String {
+    let mut plain = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(&['<', '&'][..]) {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('<') {
+            let end = match rest.find('>') {
+                Some(end) => end,
+                // Not a tag, keep the rest of the text as it is.
+                None => break,
+            };
+            let tag = rest[1..end].trim().to_ascii_lowercase();
+            let name = tag
+                .trim_end_matches('/')
+                .split_whitespace()
+                .next()
+                .unwrap_or("");
+
+            match name {
+                "br" | "/p" | "/div" | "/li" | "/pre" | "/blockquote" | "/h1" | "/h2" | "/h3"
+                | "/h4" | "/h5" | "/h6" | "/tr" => plain.push('\n'),
+                "li" => plain.push_str("- "),
+                _ => {}
+            }
+
+            rest = &rest[end + 1..];
+        } else {
+            match rest
+                .find(';')
+                .and_then(|end| Some((decode_entity(&rest[1..end])?, end)))
+            {
+                Some((decoded, end)) => {
+                    plain.push(decoded);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    plain.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+    }
+
+    plain.push_str(rest);
+    plain.trim_end_matches('\n').to_owned()
+}
+
+/// Decodes the character reference `&{entity};`.
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+            u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32)
+        }
+        _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(std::char::from_u32),
+        _ => None,
+    }
+}
+
+/// Replace the `{{path}}` placeholders of a template with the values at these paths of a JSON
+/// object, e.g. `{{alert.name}}`.
+///
+/// Strings are inserted as they are and other values as JSON. Placeholders for missing values are
+/// removed.
+pub(crate) fn render_template(template: &str, payload: &Value) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        rendered.push_str(&rest[..start]);
+
+        let pointer: String = rest[start + 2..end]
+            .trim()
+            .split('.')
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        match payload.pointer(&pointer) {
+            Some(Value::String(value)) => rendered.push_str(value),
+            Some(Value::Null) | None => {}
+            Some(value) => rendered.push_str(&value.to_string()),
+        }
+
+        rest = &rest[end + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::render_template;
+
+    #[test]
+    fn render_template_inserts_values() {
+        let payload = json!({
+            "alert": { "name": "disk full", "value": 97.5, "tags": ["a"] },
+            "a/b": "slash",
+        });
+
+        assert_eq!(
+            render_template("{{alert.name}} at {{ alert.value }}%", &payload),
+            "disk full at 97.5%"
+        );
+        assert_eq!(render_template("{{alert.tags}}", &payload), r#"["a"]"#);
+        assert_eq!(render_template("{{a/b}}", &payload), "slash");
+    }
+
+    #[test]
+    fn render_template_removes_missing_values() {
+        let payload = json!({ "alert": { "name": null } });
+
+        assert_eq!(
+            render_template("[{{alert.name}}{{missing}}]", &payload),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn render_template_keeps_unclosed_placeholders() {
+        assert_eq!(render_template("a {{b", &json!({})), "a {{b");
+        assert_eq!(
+            render_template("no placeholders", &json!({})),
+            "no placeholders"
+        );
+    }
+}
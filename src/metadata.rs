@@ -0,0 +1,225 @@
+//! Changes of the name, topic, avatar and aliases of rooms, with who made them.
+
+use futures::stream::{self, Stream};
+use hyper::client::connect::Connect;
+use ruma_events::collections::all;
+use ruma_identifiers::{EventId, RoomAliasId, RoomId, UserId};
+
+use crate::{
+    api::r0::sync::sync_events::{Filter, Response},
+    events::TimelineEvent,
+    Client, Error,
+};
+
+/// A change of a room's metadata, taken from a state event in its timeline.
+#[derive(Clone, Debug)]
+pub struct MetadataChange {
+    /// The room that changed.
+    pub room_id: RoomId,
+    /// The user who made the change.
+    pub sender: UserId,
+    /// The state event that made the change.
+    pub event_id: EventId,
+    /// When the change was made, in milliseconds since the Unix epoch.
+    pub origin_server_ts: u64,
+    /// What changed.
+    pub kind: MetadataChangeKind,
+}
+
+/// What changed about a room, with the value before and after the change.
+///
+/// Previous values are taken from the `prev_content` of the state event, so they are `None` both
+/// if the room had no such value before and if the homeserver didn't include it. Empty names,
+/// topics and avatar URLs are treated as removed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MetadataChangeKind {
+    /// The name of the room changed.
+    Name {
+        /// The name before the change.
+        old: Option<String>,
+        /// The name after the change.
+        new: Option<String>,
+    },
+    /// The topic of the room changed.
+    Topic {
+        /// The topic before the change.
+        old: Option<String>,
+        /// The topic after the change.
+        new: Option<String>,
+    },
+    /// The avatar of the room changed.
+    Avatar {
+        /// The `mxc://` URL of the avatar before the change.
+        old: Option<String>,
+        /// The `mxc://` URL of the avatar after the change.
+        new: Option<String>,
+    },
+    /// The canonical alias of the room changed.
+    CanonicalAlias {
+        /// The canonical alias before the change.
+        old: Option<RoomAliasId>,
+        /// The canonical alias after the change.
+        new: RoomAliasId,
+    },
+    /// The aliases a homeserver publishes for the room changed.
+    Aliases {
+        /// The homeserver the aliases belong to.
+        server_name: String,
+        /// The aliases that were added.
+        added: Vec<RoomAliasId>,
+        /// The aliases that were removed.
+        removed: Vec<RoomAliasId>,
+    },
+}
+
+impl MetadataChange {
+    /// All metadata changes in the timelines of a sync response, in timeline order for each room.
+    ///
+    /// State events in the `state` sections are left out, since they describe the state at the
+    /// start of the timeline rather than changes.
+    pub fn from_response(response: &Response) -> Vec<MetadataChange> {
+        let timelines = response
+            .rooms
+            .join
+            .iter()
+            .map(|(room_id, room)| (room_id, &room.timeline.events))
+            .chain(
+                response
+                    .rooms
+                    .leave
+                    .iter()
+                    .map(|(room_id, room)| (room_id, &room.timeline.events)),
+            );
+
+        timelines
+            .flat_map(|(room_id, events)| {
+                events
+                    .iter()
+                    .filter_map(move |event| MetadataChange::from_event(room_id, event))
+            })
+            .collect()
+    }
+
+    /// The metadata change made by a timeline event, if it is a name, topic, avatar or alias
+    /// event that changed anything.
+    pub fn from_event(room_id: &RoomId, event: &all::RoomEvent) -> Option<MetadataChange> {
+        let (sender, event_id, origin_server_ts, kind) = match TimelineEvent::from(event.clone()) {
+            TimelineEvent::State(all::StateEvent::RoomName(event)) => (
+                event.sender,
+                event.event_id,
+                event.origin_server_ts,
+                MetadataChangeKind::Name {
+                    old: event
+                        .prev_content
+                        .and_then(|content| non_empty(content.name)),
+                    new: non_empty(event.content.name),
+                },
+            ),
+            TimelineEvent::State(all::StateEvent::RoomTopic(event)) => (
+                event.sender,
+                event.event_id,
+                event.origin_server_ts,
+                MetadataChangeKind::Topic {
+                    old: event
+                        .prev_content
+                        .and_then(|content| non_empty(content.topic)),
+                    new: non_empty(event.content.topic),
+                },
+            ),
+            TimelineEvent::State(all::StateEvent::RoomAvatar(event)) => (
+                event.sender,
+                event.event_id,
+                event.origin_server_ts,
+                MetadataChangeKind::Avatar {
+                    old: event
+                        .prev_content
+                        .and_then(|content| non_empty(content.url)),
+                    new: non_empty(event.content.url),
+                },
+            ),
+            TimelineEvent::State(all::StateEvent::RoomCanonicalAlias(event)) => (
+                event.sender,
+                event.event_id,
+                event.origin_server_ts,
+                MetadataChangeKind::CanonicalAlias {
+                    old: event.prev_content.map(|content| content.alias),
+                    new: event.content.alias,
+                },
+            ),
+            TimelineEvent::State(all::StateEvent::RoomAliases(event)) => {
+                let old = event
+                    .prev_content
+                    .map(|content| content.aliases)
+                    .unwrap_or_default();
+                let new = event.content.aliases;
+
+                (
+                    event.sender,
+                    event.event_id,
+                    event.origin_server_ts,
+                    MetadataChangeKind::Aliases {
+                        server_name: event.state_key,
+                        added: new.iter().filter(|a| !old.contains(a)).cloned().collect(),
+                        removed: old.iter().filter(|a| !new.contains(a)).cloned().collect(),
+                    },
+                )
+            }
+            _ => return None,
+        };
+
+        if !kind.is_change() {
+            return None;
+        }
+
+        Some(MetadataChange {
+            room_id: room_id.clone(),
+            sender,
+            event_id,
+            origin_server_ts,
+            kind,
+        })
+    }
+}
+
+impl MetadataChangeKind {
+    /// Whether the value after the change differs from the one before.
+    fn is_change(&self) -> bool {
+        match self {
+            MetadataChangeKind::Name { old, new }
+            | MetadataChangeKind::Topic { old, new }
+            | MetadataChangeKind::Avatar { old, new } => old != new,
+            MetadataChangeKind::CanonicalAlias { old, new } => old.as_ref() != Some(new),
+            MetadataChangeKind::Aliases { added, removed, .. } => {
+                !added.is_empty() || !removed.is_empty()
+            }
+        }
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Repeatedly call the sync_events endpoint like `sync`, and stream the metadata changes of
+    /// the responses.
+    ///
+    /// The stream ends with an error if a sync request fails.
+    pub fn metadata_changes(
+        &self,
+        filter: Option<Filter>,
+        since: Option<String>,
+        set_presence: bool,
+    ) -> impl Stream<Item = MetadataChange, Error = Error> {
+        self.sync(filter, since, set_presence)
+            .map(|response| stream::iter_ok(MetadataChange::from_response(&response)))
+            .flatten()
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
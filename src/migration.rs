@@ -0,0 +1,285 @@
+//! Moving the members of a room to another room.
+
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use futures::{
+    future::{self, Either, Future},
+    stream::{self, Stream},
+};
+use hyper::client::connect::Connect;
+use ruma_events::{
+    room::{member::MembershipState, power_levels::PowerLevelsEventContent},
+    EventType,
+};
+use ruma_identifiers::{RoomId, UserId};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{Error, Room};
+
+/// How many invites `Room::import_members` sends at the same time.
+const IMPORT_CONCURRENCY: usize = 4;
+
+/// The joined members of a room and their power levels.
+///
+/// Created by `Room::export_members` and applied to another room with `Room::import_members`,
+/// e.g. after a room upgrade or when moving a community to another homeserver.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MemberExport {
+    /// The room the members were exported from.
+    pub room_id: RoomId,
+    /// The exported members.
+    pub members: Vec<ExportedMember>,
+}
+
+/// A member of a `MemberExport`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedMember {
+    /// The ID of the user.
+    pub user_id: UserId,
+    /// The power level of the user in the exported room.
+    pub power_level: u64,
+}
+
+/// The outcome of `Room::import_members`.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// The users that were invited.
+    pub invited: Vec<UserId>,
+    /// The users that were skipped because they were already invited to, joined or banned from
+    /// the room.
+    pub skipped: Vec<UserId>,
+    /// The users that couldn't be invited, with the error of the invite.
+    pub failed: Vec<(UserId, Error)>,
+    /// Whether the room's power levels were changed.
+    pub power_levels_changed: bool,
+}
+
+impl MemberExport {
+    /// Write the export to a JSON file.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+
+        Ok(())
+    }
+
+    /// Read an export from a JSON file written by `write_to_file`.
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Export the joined members of this room with their power levels.
+    pub fn export_members(&self) -> impl Future<Item = MemberExport, Error = Error> {
+        let room_id = self.room_id().clone();
+
+        self.members()
+            .join(self.power_levels())
+            .map(move |(members, power_levels)| MemberExport {
+                room_id,
+                members: members
+                    .into_iter()
+                    .filter(|(_, membership)| *membership == MembershipState::Join)
+                    .map(|(user_id, _)| ExportedMember {
+                        power_level: power_levels
+                            .as_ref()
+                            .map(|power_levels| {
+                                power_levels
+                                    .users
+                                    .get(&user_id)
+                                    .cloned()
+                                    .unwrap_or(power_levels.users_default)
+                            })
+                            .unwrap_or_default(),
+                        user_id,
+                    })
+                    .collect(),
+            })
+    }
+
+    /// Invite the members of `export` to this room and give them their exported power levels.
+    ///
+    /// Users that are already invited to, joined or banned from this room are skipped, so an
+    /// interrupted import can be resumed by calling this method again. Up to four invites are
+    /// sent at the same time, spaced out by the client's `RateLimiter`, so the returned future
+    /// has to be run on a runtime providing a `tokio_timer` timer. Power levels are never raised
+    /// above the power level of the logged-in user.
+    pub fn import_members(
+        &self,
+        export: MemberExport,
+    ) -> impl Future<Item = ImportSummary, Error = Error> {
+        use crate::api::r0::membership::invite_user;
+
+        let room = self.clone();
+
+        self.members().and_then(move |members| {
+            let mut summary = ImportSummary::default();
+            let mut to_invite = Vec::new();
+
+            for member in &export.members {
+                let membership = members
+                    .iter()
+                    .find(|(user_id, _)| *user_id == member.user_id)
+                    .map(|(_, membership)| membership);
+
+                match membership {
+                    Some(MembershipState::Invite)
+                    | Some(MembershipState::Join)
+                    | Some(MembershipState::Ban) => summary.skipped.push(member.user_id.clone()),
+                    _ => to_invite.push(member.user_id.clone()),
+                }
+            }
+
+            let client = room.client().clone();
+            let room_id = room.room_id().clone();
+
+            stream::iter_ok(to_invite)
+                .map(move |user_id| {
+                    let client = client.clone();
+                    let request = invite_user::Request {
+                        room_id: room_id.clone(),
+                        user_id: user_id.clone(),
+                        reason: None,
+                    };
+
+                    client
+                        .clone()
+                        .rate_limited(move || invite_user::call(client.clone(), request.clone()))
+                        .then(move |result| Ok((user_id, result)))
+                })
+                .buffer_unordered(IMPORT_CONCURRENCY)
+                .fold(summary, |mut summary, (user_id, result)| {
+                    match result {
+                        Ok(_) => summary.invited.push(user_id),
+                        Err(error) => summary.failed.push((user_id, error)),
+                    }
+
+                    Ok::<_, Error>(summary)
+                })
+                .and_then(move |summary| room.apply_power_levels(&export, summary))
+        })
+    }
+
+    /// The latest membership of every user who has ever been a member of this room.
+    fn members(&self) -> impl Future<Item = Vec<(UserId, MembershipState)>, Error = Error> {
+        use crate::api::r0::sync::get_member_events;
+
+        get_member_events::call(
+            self.client().clone(),
+            get_member_events::Request {
+                room_id: self.room_id().clone(),
+                at: None,
+                membership: None,
+                not_membership: None,
+            },
+        )
+        .map(|response| {
+            response
+                .chunk
+                .into_iter()
+                .filter_map(|event| {
+                    UserId::try_from(event.state_key.as_str())
+                        .ok()
+                        .map(|user_id| (user_id, event.content.membership))
+                })
+                .collect()
+        })
+    }
+
+    /// The content of this room's `m.room.power_levels` event as JSON, so that it can be
+    /// modified without losing fields `ruma_events` doesn't know about.
+    fn power_levels_json(&self) -> impl Future<Item = Value, Error = Error> {
+        use crate::api::r0::sync::get_state_events_for_empty_key;
+
+        get_state_events_for_empty_key::call(
+            self.client().clone(),
+            get_state_events_for_empty_key::Request {
+                room_id: self.room_id().clone(),
+                event_type: EventType::RoomPowerLevels,
+            },
+        )
+        .map(|response| response.content)
+    }
+
+    /// Set the power levels of the members of `export` in this room, if they differ.
+    fn apply_power_levels(
+        &self,
+        export: &MemberExport,
+        mut summary: ImportSummary,
+    ) -> impl Future<Item = ImportSummary, Error = Error> {
+        use crate::api::r0::send::send_state_event_for_empty_key;
+
+        let own_user_id = self
+            .client()
+            .session()
+            .map(|session| session.user_id().clone());
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+        let members = export.members.clone();
+
+        self.power_levels_json().and_then(move |mut content| {
+            let current: PowerLevelsEventContent = match serde_json::from_value(content.clone()) {
+                Ok(current) => current,
+                Err(error) => return Either::A(future::err(Error::from(error))),
+            };
+            let own_level = own_user_id
+                .and_then(|user_id| current.users.get(&user_id).cloned())
+                .unwrap_or(current.users_default);
+
+            let mut changed = false;
+            if let Value::Object(fields) = &mut content {
+                let users = fields
+                    .entry("users")
+                    .or_insert_with(|| Value::Object(Map::new()));
+
+                if let Value::Object(users) = users {
+                    for member in members {
+                        let level = member.power_level.min(own_level);
+                        let current_level = current
+                            .users
+                            .get(&member.user_id)
+                            .cloned()
+                            .unwrap_or(current.users_default);
+
+                        // Users with at least our own power level can't be changed by us.
+                        if level != current_level && current_level < own_level {
+                            users.insert(member.user_id.to_string(), Value::from(level));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                return Either::A(future::ok(summary));
+            }
+
+            Either::B(
+                send_state_event_for_empty_key::call(
+                    client,
+                    send_state_event_for_empty_key::Request {
+                        room_id,
+                        event_type: EventType::RoomPowerLevels,
+                        data: content,
+                    },
+                )
+                .map(move |_| {
+                    summary.power_levels_changed = true;
+                    summary
+                }),
+            )
+        })
+    }
+}
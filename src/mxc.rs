@@ -0,0 +1,249 @@
+//! `mxc://` URIs of the media repository.
+
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+use http::Method;
+use hyper::client::connect::Connect;
+use serde::{
+    de::{Error as _, Unexpected},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use url::Url;
+
+use crate::{
+    api::r0::media::get_content_thumbnail::Method as ThumbnailMethod, media::ThumbnailSize, Client,
+    Error,
+};
+
+/// The URI of a file in the media repository, `mxc://{server_name}/{media_id}`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MxcUri {
+    server_name: String,
+    media_id: String,
+}
+
+impl MxcUri {
+    /// The server the file was uploaded to.
+    pub fn server_name(&self) -> &str {
+        &self.server_name
+    }
+
+    /// The ID of the file on its server.
+    pub fn media_id(&self) -> &str {
+        &self.media_id
+    }
+
+    /// The URL to download the file from the given homeserver.
+    ///
+    /// `Client::download_url` uses the media URL of the client instead, if it has one.
+    pub fn download_url(&self, homeserver_url: &Url) -> Url {
+        self.media_url(homeserver_url, "download")
+    }
+
+    /// The URL to download a thumbnail of the file with about the given size from the given
+    /// homeserver.
+    ///
+    /// `Client::thumbnail_url` uses the media URL of the client instead, if it has one.
+    pub fn thumbnail_url(&self, homeserver_url: &Url, size: ThumbnailSize) -> Url {
+        let method = match size.method {
+            ThumbnailMethod::Crop => "crop",
+            ThumbnailMethod::Scale => "scale",
+        };
+        let mut url = self.media_url(homeserver_url, "thumbnail");
+
+        url.query_pairs_mut()
+            .append_pair("width", &size.width.to_string())
+            .append_pair("height", &size.height.to_string())
+            .append_pair("method", method);
+
+        url
+    }
+
+    /// The URL of the given media repository endpoint for the file.
+    fn media_url(&self, homeserver_url: &Url, endpoint: &str) -> Url {
+        let mut url = homeserver_url.clone();
+        url.set_query(None);
+
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().extend(&[
+                "_matrix",
+                "media",
+                "r0",
+                endpoint,
+                &self.server_name,
+                &self.media_id,
+            ]);
+        }
+
+        url
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// The URL to download a file from, on the media URL of this client if it has one.
+    pub fn download_url(&self, mxc_uri: &MxcUri) -> Url {
+        mxc_uri.download_url(self.media_base_url())
+    }
+
+    /// The URL to download a thumbnail of a file with about the given size from, on the media
+    /// URL of this client if it has one.
+    pub fn thumbnail_url(&self, mxc_uri: &MxcUri, size: ThumbnailSize) -> Url {
+        mxc_uri.thumbnail_url(self.media_base_url(), size)
+    }
+
+    fn media_base_url(&self) -> &Url {
+        self.0.media_url.as_ref().unwrap_or(&self.0.homeserver_url)
+    }
+}
+
+/// Whether a request with the given method and path downloads a file or a thumbnail from the
+/// media repository.
+pub(crate) fn is_download_path(method: &Method, path: &str) -> bool {
+    let segments: Vec<_> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    *method == Method::GET
+        && matches!(
+            segments[..],
+            ["_matrix", "media", _, "download", ..] | ["_matrix", "media", _, "thumbnail", ..]
+        )
+}
+
+impl FromStr for MxcUri {
+    type Err = Error;
+
+    /// Parse an `mxc://` URI, failing with `Error::InvalidMxcUri` unless the server name is a
+    /// host with an optional port and the media ID consists of the characters allowed by the
+    /// specification.
+    fn from_str(mxc_uri: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidMxcUri(mxc_uri.to_owned());
+
+        if !mxc_uri.starts_with("mxc://") {
+            return Err(invalid());
+        }
+
+        let mut parts = mxc_uri["mxc://".len()..].splitn(2, '/');
+        let server_name = parts.next().unwrap_or_default();
+        let media_id = parts.next().ok_or_else(invalid)?;
+
+        let valid_server_name = !server_name.is_empty()
+            && server_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-.:[]".contains(c));
+        let valid_media_id = !media_id.is_empty()
+            && media_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid_server_name || !valid_media_id {
+            return Err(invalid());
+        }
+
+        Ok(MxcUri {
+            server_name: server_name.to_owned(),
+            media_id: media_id.to_owned(),
+        })
+    }
+}
+
+impl Display for MxcUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "mxc://{}/{}", self.server_name, self.media_id)
+    }
+}
+
+impl Serialize for MxcUri {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MxcUri {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mxc_uri = String::deserialize(deserializer)?;
+
+        mxc_uri
+            .parse()
+            .map_err(|_| D::Error::invalid_value(Unexpected::Str(&mxc_uri), &"an mxc:// URI"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Method;
+    use serde_json::json;
+
+    use super::{is_download_path, MxcUri};
+
+    #[test]
+    fn valid_uris_are_parsed() {
+        let mxc_uri: MxcUri = "mxc://example.org:8448/abc-DEF_123".parse().unwrap();
+
+        assert_eq!(mxc_uri.server_name(), "example.org:8448");
+        assert_eq!(mxc_uri.media_id(), "abc-DEF_123");
+        assert_eq!(mxc_uri.to_string(), "mxc://example.org:8448/abc-DEF_123");
+        assert!("mxc://[::1]/abc".parse::<MxcUri>().is_ok());
+    }
+
+    #[test]
+    fn invalid_uris_are_rejected() {
+        for mxc_uri in &[
+            "https://example.org/abc",
+            "mxc://example.org",
+            "mxc:///abc",
+            "mxc://example.org/",
+            "mxc://example.org/a/b",
+            "mxc://exa mple.org/abc",
+            "mxc://example.org/abc?",
+        ] {
+            assert!(mxc_uri.parse::<MxcUri>().is_err(), "{}", mxc_uri);
+        }
+    }
+
+    #[test]
+    fn uris_are_serialized_as_strings() {
+        let mxc_uri: MxcUri = "mxc://example.org/abc".parse().unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&mxc_uri).unwrap(),
+            json!("mxc://example.org/abc")
+        );
+        assert_eq!(
+            serde_json::from_value::<MxcUri>(json!("mxc://example.org/abc")).unwrap(),
+            mxc_uri
+        );
+        assert!(serde_json::from_value::<MxcUri>(json!("example.org/abc")).is_err());
+    }
+
+    #[test]
+    fn only_downloads_are_download_paths() {
+        assert!(is_download_path(
+            &Method::GET,
+            "/_matrix/media/r0/download/example.org/abc"
+        ));
+        assert!(is_download_path(
+            &Method::GET,
+            "/_matrix/media/r0/thumbnail/example.org/abc"
+        ));
+        assert!(!is_download_path(&Method::POST, "/_matrix/media/r0/upload"));
+        assert!(!is_download_path(&Method::GET, "/_matrix/media/r0/config"));
+        assert!(!is_download_path(
+            &Method::GET,
+            "/_matrix/media/r0/preview_url"
+        ));
+    }
+}
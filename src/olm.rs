@@ -0,0 +1,368 @@
+//! Olm sessions with other devices, started with claimed one-time keys.
+
+use std::collections::{BTreeMap, HashMap};
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_identifiers::UserId;
+use serde_json::{json, Value};
+
+use crate::{
+    api::r0::keys::{claim_keys, DeviceKeys},
+    device_tracking::{curve25519_key, ed25519_key},
+    encryption::{verify_json, EncryptionState, OlmAccount, OlmSession, OLM_ALGORITHM},
+    Client, Error,
+};
+
+/// How long `/keys/claim` waits for remote homeservers, in milliseconds.
+const CLAIM_TIMEOUT: u64 = 10_000;
+
+/// The algorithm of the one-time keys to claim.
+const SIGNED_CURVE25519: &str = "signed_curve25519";
+
+/// The algorithm of the one-time keys to claim, by user and device ID.
+type ClaimRequest = HashMap<UserId, BTreeMap<String, String>>;
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Start Olm sessions with the given devices, unless the client has one with them already.
+    ///
+    /// A one-time key is claimed for each device, and only used if it is signed by the device.
+    /// The devices have to be known from `update_devices`. Resolves to the devices no session
+    /// could be started with, e.g. because they ran out of one-time keys.
+    pub fn start_olm_sessions(
+        &self,
+        devices: Vec<(UserId, String)>,
+    ) -> impl Future<Item = Vec<(UserId, String)>, Error = Error> {
+        let data = self.0.clone();
+
+        let (one_time_keys, failed) = {
+            let encryption = self.0.encryption.borrow();
+
+            if encryption.account.is_none() {
+                return Either::A(future::err(Error::EncryptionDisabled));
+            }
+
+            one_time_keys_to_claim(&encryption, devices)
+        };
+
+        if one_time_keys.is_empty() {
+            return Either::A(future::ok(failed));
+        }
+
+        let requested = one_time_keys.clone();
+
+        Either::B(
+            claim_keys::call(
+                self.clone(),
+                claim_keys::Request {
+                    timeout: Some(CLAIM_TIMEOUT),
+                    one_time_keys,
+                },
+            )
+            .map(move |response| {
+                let mut encryption = data.encryption.borrow_mut();
+
+                start_sessions(&mut encryption, requested, response.one_time_keys, failed)
+            }),
+        )
+    }
+
+    /// Encrypt an event for a device with the Olm session started by `start_olm_sessions`.
+    ///
+    /// Returns the content of the `m.room.encrypted` to-device event to send to the device.
+    pub fn olm_encrypt(
+        &self,
+        user_id: &UserId,
+        device_id: &str,
+        event_type: &str,
+        content: Value,
+    ) -> Result<Value, Error> {
+        let session = self.logged_in_session()?;
+        let mut encryption = self.0.encryption.borrow_mut();
+        let encryption = &mut *encryption;
+        let account = encryption
+            .account
+            .as_ref()
+            .ok_or(Error::EncryptionDisabled)?;
+        let no_session = || Error::OlmSessionMissing {
+            user_id: user_id.clone(),
+            device_id: device_id.to_owned(),
+        };
+
+        let keys = encryption
+            .devices
+            .device(user_id, device_id)
+            .ok_or_else(no_session)?;
+        let (identity_key, recipient_ed25519) = match (curve25519_key(keys), ed25519_key(keys)) {
+            (Some(identity_key), Some(ed25519)) => (identity_key.clone(), ed25519.clone()),
+            _ => return Err(no_session()),
+        };
+        let own_keys = account.identity_keys();
+
+        let payload = json!({
+            "sender": session.user_id().to_string(),
+            "sender_device": session.device_id(),
+            "keys": { "ed25519": own_keys.ed25519 },
+            "recipient": user_id.to_string(),
+            "recipient_keys": { "ed25519": recipient_ed25519 },
+            "type": event_type,
+            "content": content,
+        });
+
+        let olm_session = encryption
+            .sessions
+            .get_mut(&identity_key)
+            .ok_or_else(no_session)?;
+        let (message_type, body) = olm_session.encrypt(&serde_json::to_string(&payload)?);
+
+        Ok(json!({
+            "algorithm": OLM_ALGORITHM,
+            "sender_key": own_keys.curve25519,
+            "ciphertext": {
+                identity_key: { "type": message_type, "body": body },
+            },
+        }))
+    }
+}
+
+/// The one-time keys to claim to start Olm sessions with `devices`, by user and device ID, and
+/// the devices no session can be started with because their keys are unknown.
+///
+/// Devices the client has a session with already are skipped.
+fn one_time_keys_to_claim(
+    encryption: &EncryptionState,
+    devices: Vec<(UserId, String)>,
+) -> (ClaimRequest, Vec<(UserId, String)>) {
+    let mut one_time_keys: ClaimRequest = HashMap::new();
+    let mut failed = Vec::new();
+
+    for (user_id, device_id) in devices {
+        let identity_key = encryption
+            .devices
+            .device(&user_id, &device_id)
+            .and_then(curve25519_key);
+
+        match identity_key {
+            Some(key) if encryption.sessions.contains_key(key) => {}
+            Some(_) => {
+                one_time_keys
+                    .entry(user_id)
+                    .or_default()
+                    .insert(device_id, SIGNED_CURVE25519.to_owned());
+            }
+            None => failed.push((user_id, device_id)),
+        }
+    }
+
+    (one_time_keys, failed)
+}
+
+/// Start Olm sessions with the `requested` devices using the one-time keys `claimed` from them,
+/// adding the devices no session could be started with to `failed`.
+fn start_sessions(
+    encryption: &mut EncryptionState,
+    requested: ClaimRequest,
+    mut claimed: HashMap<UserId, BTreeMap<String, BTreeMap<String, Value>>>,
+    mut failed: Vec<(UserId, String)>,
+) -> Vec<(UserId, String)> {
+    let account = match encryption.account {
+        Some(ref account) => account,
+        None => return requested.into_iter().fold(failed, add_devices),
+    };
+
+    for (user_id, device_ids) in requested {
+        for device_id in device_ids.into_keys() {
+            let keys = encryption.devices.device(&user_id, &device_id);
+            let one_time_key = claimed
+                .get_mut(&user_id)
+                .and_then(|devices| devices.remove(&device_id))
+                .and_then(|keys| keys.into_iter().next())
+                .map(|(_, key)| key);
+
+            let session = match (keys, one_time_key) {
+                (Some(keys), Some(one_time_key)) => start_session(&**account, keys, one_time_key),
+                _ => None,
+            };
+
+            match session {
+                Some((identity_key, session)) => {
+                    encryption.sessions.insert(identity_key, session);
+                }
+                None => failed.push((user_id.clone(), device_id)),
+            }
+        }
+    }
+
+    failed
+}
+
+/// Start an Olm session with a device using a one-time key claimed from it, if the key is signed
+/// by the device.
+fn start_session(
+    account: &dyn OlmAccount,
+    keys: &DeviceKeys,
+    claimed: Value,
+) -> Option<(String, Box<dyn OlmSession>)> {
+    let identity_key = curve25519_key(keys)?;
+    let one_time_key = claimed.get("key")?.as_str()?.to_owned();
+    let ed25519_key = ed25519_key(keys)?;
+
+    if !verify_json(
+        account,
+        &keys.user_id,
+        &keys.device_id,
+        ed25519_key,
+        claimed,
+    ) {
+        log::warn!(
+            "ignoring one-time key of device {} of {} with a bad signature",
+            keys.device_id,
+            keys.user_id
+        );
+        return None;
+    }
+
+    account
+        .create_outbound_session(identity_key, &one_time_key)
+        .map(|session| (identity_key.clone(), session))
+}
+
+/// Add the requested devices of a user to `devices`.
+fn add_devices(
+    mut devices: Vec<(UserId, String)>,
+    (user_id, device_ids): (UserId, BTreeMap<String, String>),
+) -> Vec<(UserId, String)> {
+    devices.extend(
+        device_ids
+            .into_keys()
+            .map(|device_id| (user_id.clone(), device_id)),
+    );
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        convert::TryFrom,
+    };
+
+    use ruma_identifiers::UserId;
+    use serde_json::{json, Value};
+
+    use super::{one_time_keys_to_claim, start_sessions, SIGNED_CURVE25519};
+    use crate::encryption::{
+        tests::{device_keys, signed, FakeAccount, FakeSession},
+        EncryptionState,
+    };
+
+    fn device(user_id: &str, device_id: &str) -> (UserId, String) {
+        (UserId::try_from(user_id).unwrap(), device_id.to_owned())
+    }
+
+    /// Encryption state with Alice's account, tracking Bob's and Carol's devices.
+    fn encryption() -> EncryptionState {
+        let mut encryption = EncryptionState::default();
+        encryption.account = Some(Box::new(FakeAccount::new("alice")));
+        encryption.devices.insert_device(device_keys(
+            &FakeAccount::new("bob"),
+            "@bob:example.org",
+            "BOB",
+        ));
+        encryption.devices.insert_device(device_keys(
+            &FakeAccount::new("carol"),
+            "@carol:example.org",
+            "CAROL",
+        ));
+        encryption
+    }
+
+    /// A one-time key claimed from a device, signed by `account`.
+    fn claimed_key(
+        account: &FakeAccount,
+        user_id: &str,
+        device_id: &str,
+    ) -> BTreeMap<String, Value> {
+        let key = signed(account, user_id, device_id, json!({ "key": "one-time" }));
+        let mut keys = BTreeMap::new();
+        keys.insert(format!("{}:AAAA", SIGNED_CURVE25519), key);
+        keys
+    }
+
+    #[test]
+    fn devices_with_a_session_are_skipped() {
+        let mut encryption = encryption();
+        encryption.sessions.insert(
+            "bob-curve25519".to_owned(),
+            Box::new(FakeSession("bob".to_owned())),
+        );
+
+        let (one_time_keys, failed) = one_time_keys_to_claim(
+            &encryption,
+            vec![
+                device("@bob:example.org", "BOB"),
+                device("@carol:example.org", "CAROL"),
+                device("@dave:example.org", "DAVE"),
+            ],
+        );
+
+        assert_eq!(one_time_keys.len(), 1);
+        assert_eq!(
+            one_time_keys[&UserId::try_from("@carol:example.org").unwrap()]["CAROL"],
+            SIGNED_CURVE25519
+        );
+        assert_eq!(failed, vec![device("@dave:example.org", "DAVE")]);
+    }
+
+    #[test]
+    fn one_time_keys_with_a_bad_signature_are_rejected() {
+        let mut encryption = encryption();
+        let (one_time_keys, _) = one_time_keys_to_claim(
+            &encryption,
+            vec![
+                device("@bob:example.org", "BOB"),
+                device("@carol:example.org", "CAROL"),
+            ],
+        );
+        let mut claimed = HashMap::new();
+        claimed.insert(
+            UserId::try_from("@bob:example.org").unwrap(),
+            vec![(
+                "BOB".to_owned(),
+                claimed_key(&FakeAccount::new("bob"), "@bob:example.org", "BOB"),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        claimed.insert(
+            UserId::try_from("@carol:example.org").unwrap(),
+            vec![(
+                "CAROL".to_owned(),
+                claimed_key(&FakeAccount::new("mallory"), "@carol:example.org", "CAROL"),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let failed = start_sessions(&mut encryption, one_time_keys, claimed, Vec::new());
+
+        assert_eq!(failed, vec![device("@carol:example.org", "CAROL")]);
+        assert!(encryption.sessions.contains_key("bob-curve25519"));
+        assert!(!encryption.sessions.contains_key("carol-curve25519"));
+    }
+
+    #[test]
+    fn devices_without_a_claimed_key_fail() {
+        let mut encryption = encryption();
+        let (one_time_keys, _) =
+            one_time_keys_to_claim(&encryption, vec![device("@bob:example.org", "BOB")]);
+
+        let failed = start_sessions(&mut encryption, one_time_keys, HashMap::new(), Vec::new());
+
+        assert_eq!(failed, vec![device("@bob:example.org", "BOB")]);
+        assert!(encryption.sessions.is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+//! Checking whether a user is allowed to do something in a room, before trying.
+
+use std::convert::TryFrom;
+
+use hyper::client::connect::Connect;
+use ruma_events::{
+    collections::only, room::member::MembershipState, room::power_levels::PowerLevelsEventContent,
+    EventType,
+};
+use ruma_identifiers::{RoomId, UserId};
+use serde_json::Value;
+
+use crate::{capabilities::RoomFeature, Room, StoreSnapshot};
+
+/// The level required to invite users, if the room has no `m.room.power_levels` event.
+const DEFAULT_INVITE_LEVEL: u64 = 0;
+
+/// The level required to kick, ban and redact, if the room has no `m.room.power_levels` event.
+const DEFAULT_MODERATION_LEVEL: u64 = 50;
+
+/// Something a user can try to do in a room.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Send a message event of the type `m.room.message`.
+    SendMessage,
+    /// Invite the given user.
+    Invite(UserId),
+    /// Kick the given user.
+    Kick(UserId),
+    /// Ban the given user.
+    Ban(UserId),
+    /// Redact an event sent by the given user.
+    Redact(UserId),
+    /// Send a state event of the given type.
+    ChangeState(EventType),
+    /// Join the room.
+    Join,
+    /// Ask to be invited to the room.
+    Knock,
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Whether `user_id` is allowed to do `action` in this room, according to the room's state in
+    /// the store.
+    ///
+    /// This evaluates the user's membership and power level, the power levels required for the
+    /// action, the join rule and, if the store knows it, the room version the way the homeserver
+    /// would, so requests that are bound to fail can be skipped. The answer is only as current as
+    /// the store, see `StoreSnapshot::state_value`.
+    pub fn can(&self, action: &Action, user_id: &UserId) -> bool {
+        let snapshot = self.client().store().snapshot();
+
+        Permissions {
+            snapshot: &snapshot,
+            room_id: self.room_id(),
+        }
+        .can(action, user_id)
+    }
+}
+
+/// The state of a room that decides what users are allowed to do.
+struct Permissions<'a> {
+    snapshot: &'a StoreSnapshot,
+    room_id: &'a RoomId,
+}
+
+impl Permissions<'_> {
+    fn can(&self, action: &Action, user_id: &UserId) -> bool {
+        let membership = self.membership(user_id);
+        let joined = membership == Some(MembershipState::Join);
+        let level = self.snapshot.power_level(self.room_id, user_id);
+        let power_levels = self.power_levels();
+        let required = |select: fn(&PowerLevelsEventContent) -> u64, default| {
+            power_levels.as_ref().map(select).unwrap_or(default)
+        };
+
+        match action {
+            Action::SendMessage => joined && level >= self.event_level(&EventType::RoomMessage),
+            Action::Invite(target) => {
+                let target_membership = self.membership(target);
+
+                joined
+                    && level >= required(|levels| levels.invite, DEFAULT_INVITE_LEVEL)
+                    && target_membership != Some(MembershipState::Join)
+                    && target_membership != Some(MembershipState::Ban)
+            }
+            Action::Kick(target) if target == user_id => {
+                // Leaving the room takes no power.
+                joined || membership == Some(MembershipState::Invite)
+            }
+            Action::Kick(target) => {
+                let target_membership = self.membership(target);
+
+                joined
+                    && level >= required(|levels| levels.kick, DEFAULT_MODERATION_LEVEL)
+                    && level > self.snapshot.power_level(self.room_id, target)
+                    && (target_membership == Some(MembershipState::Join)
+                        || target_membership == Some(MembershipState::Invite))
+            }
+            Action::Ban(target) => {
+                joined
+                    && level >= required(|levels| levels.ban, DEFAULT_MODERATION_LEVEL)
+                    && level > self.snapshot.power_level(self.room_id, target)
+            }
+            Action::Redact(sender) => {
+                joined
+                    && level >= self.event_level(&EventType::RoomRedaction)
+                    && (sender == user_id
+                        || level >= required(|levels| levels.redact, DEFAULT_MODERATION_LEVEL))
+            }
+            Action::ChangeState(event_type) => joined && level >= self.state_level(event_type),
+            Action::Join => match membership {
+                Some(MembershipState::Join) | Some(MembershipState::Invite) => true,
+                Some(MembershipState::Ban) => false,
+                _ => match self.join_rule().as_deref() {
+                    Some("public") => true,
+                    Some("restricted") => {
+                        self.supports(RoomFeature::RestrictedJoinRule)
+                            && self.is_member_of_allowed_room(user_id)
+                    }
+                    _ => false,
+                },
+            },
+            Action::Knock => {
+                membership != Some(MembershipState::Join)
+                    && membership != Some(MembershipState::Invite)
+                    && membership != Some(MembershipState::Ban)
+                    && self.join_rule().as_deref() == Some("knock")
+                    && self.supports(RoomFeature::Knocking)
+            }
+        }
+    }
+
+    /// The membership of a user in the room, if the user ever was a member.
+    fn membership(&self, user_id: &UserId) -> Option<MembershipState> {
+        match self.state_event(EventType::RoomMember, &user_id.to_string()) {
+            Some(only::StateEvent::RoomMember(event)) => Some(event.content.membership),
+            _ => None,
+        }
+    }
+
+    fn power_levels(&self) -> Option<PowerLevelsEventContent> {
+        match self.state_event(EventType::RoomPowerLevels, "") {
+            Some(only::StateEvent::RoomPowerLevels(event)) => Some(event.content.clone()),
+            _ => None,
+        }
+    }
+
+    /// The level required to send message events of the given type.
+    fn event_level(&self, event_type: &EventType) -> u64 {
+        self.power_levels()
+            .map(|levels| {
+                levels
+                    .events
+                    .get(event_type)
+                    .cloned()
+                    .unwrap_or(levels.events_default)
+            })
+            .unwrap_or(0)
+    }
+
+    /// The level required to send state events of the given type.
+    ///
+    /// Without an `m.room.power_levels` event, anybody can send state events.
+    fn state_level(&self, event_type: &EventType) -> u64 {
+        self.power_levels()
+            .map(|levels| {
+                levels
+                    .events
+                    .get(event_type)
+                    .cloned()
+                    .unwrap_or(levels.state_default)
+            })
+            .unwrap_or(0)
+    }
+
+    /// The join rule of the room, from the JSON content because `ruma_events` doesn't know all of
+    /// them.
+    fn join_rule(&self) -> Option<String> {
+        self.state_content(EventType::RoomJoinRules)?
+            .get("join_rule")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+    }
+
+    /// Whether the version of the room supports `feature`.
+    ///
+    /// `ruma_events` drops the `room_version` of `m.room.create` events, so the store usually
+    /// doesn't know the version. The join rule alone decides then.
+    fn supports(&self, feature: RoomFeature) -> bool {
+        let content = match self.state_content(EventType::RoomCreate) {
+            Some(content) => content,
+            None => return true,
+        };
+
+        match content.get("room_version").and_then(Value::as_str) {
+            Some(room_version) => feature.is_supported_by(room_version),
+            None => true,
+        }
+    }
+
+    /// Whether the user is joined to one of the rooms the `restricted` join rule allows.
+    fn is_member_of_allowed_room(&self, user_id: &UserId) -> bool {
+        let join_rules = match self.state_content(EventType::RoomJoinRules) {
+            Some(content) => content,
+            None => return false,
+        };
+        let allowed_rooms = join_rules
+            .get("allow")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|allow| allow.get("room_id").and_then(Value::as_str))
+            .filter_map(|room_id| RoomId::try_from(room_id).ok());
+
+        for room_id in allowed_rooms {
+            let permissions = Permissions {
+                snapshot: self.snapshot,
+                room_id: &room_id,
+            };
+
+            if permissions.membership(user_id) == Some(MembershipState::Join) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn state_event(&self, event_type: EventType, state_key: &str) -> Option<&only::StateEvent> {
+        self.snapshot
+            .state_event(self.room_id, &event_type, state_key)
+    }
+
+    /// The content of the state event with the given type and an empty state key as JSON.
+    fn state_content(&self, event_type: EventType) -> Option<Value> {
+        self.snapshot.state_content(self.room_id, event_type, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, convert::TryFrom, time::SystemTime};
+
+    use ruma_events::EventType;
+    use ruma_identifiers::{RoomId, UserId};
+    use serde_json::{json, Value};
+
+    use super::{Action, Permissions};
+    use crate::{
+        api::r0::sync::sync_events::{Presence, Response, Rooms},
+        Store,
+    };
+
+    fn state_event(event_type: &str, state_key: &str, content: Value) -> Value {
+        json!({
+            "type": event_type,
+            "state_key": state_key,
+            "content": content,
+            "event_id": format!("${}{}:example.org", event_type, state_key.replace(':', ".")),
+            "sender": "@alice:example.org",
+            "origin_server_ts": 0,
+        })
+    }
+
+    fn member(user_id: &str, membership: &str) -> Value {
+        state_event(
+            "m.room.member",
+            user_id,
+            json!({ "membership": membership }),
+        )
+    }
+
+    /// A store of the joined rooms with the given state.
+    fn store(rooms: Vec<(&RoomId, Vec<Value>)>) -> Store {
+        let join = rooms
+            .into_iter()
+            .map(|(room_id, state)| {
+                let room = serde_json::from_value(json!({
+                    "unread_notifications": {},
+                    "timeline": { "limited": false, "prev_batch": "prev", "events": [] },
+                    "state": { "events": state },
+                    "account_data": { "events": [] },
+                    "ephemeral": { "events": [] },
+                }))
+                .unwrap();
+
+                (room_id.clone(), room)
+            })
+            .collect();
+        let response = Response {
+            next_batch: "next".to_owned(),
+            rooms: Rooms {
+                leave: HashMap::new(),
+                join,
+                invite: HashMap::new(),
+                knock: HashMap::new(),
+            },
+            presence: Presence { events: Vec::new() },
+            account_data: None,
+            device_lists: Default::default(),
+            to_device: Default::default(),
+            device_one_time_keys_count: None,
+        };
+
+        let store = Store::default();
+        store.apply_sync(&response, None, SystemTime::now());
+        store
+    }
+
+    fn user(user_id: &str) -> UserId {
+        UserId::try_from(user_id).unwrap()
+    }
+
+    #[test]
+    fn moderation_follows_the_power_levels() {
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+        let store = store(vec![(
+            &room_id,
+            vec![
+                member("@alice:example.org", "join"),
+                member("@bob:example.org", "join"),
+                state_event(
+                    "m.room.power_levels",
+                    "",
+                    json!({
+                        "events": { "m.room.name": 100 },
+                        "users": { "@alice:example.org": 50 },
+                    }),
+                ),
+            ],
+        )]);
+        let snapshot = store.snapshot();
+        let permissions = Permissions {
+            snapshot: &snapshot,
+            room_id: &room_id,
+        };
+        let (alice, bob) = (user("@alice:example.org"), user("@bob:example.org"));
+
+        assert!(permissions.can(&Action::SendMessage, &bob));
+        assert!(!permissions.can(&Action::SendMessage, &user("@carol:example.org")));
+        assert!(permissions.can(&Action::Kick(bob.clone()), &alice));
+        assert!(!permissions.can(&Action::Kick(alice.clone()), &bob));
+        assert!(permissions.can(&Action::Kick(bob.clone()), &bob));
+        assert!(permissions.can(&Action::ChangeState(EventType::RoomTopic), &alice));
+        assert!(!permissions.can(&Action::ChangeState(EventType::RoomName), &alice));
+    }
+
+    #[test]
+    fn joining_and_knocking_follow_the_join_rule() {
+        let public_room_id = RoomId::try_from("!public:example.org").unwrap();
+        let knock_room_id = RoomId::try_from("!knock:example.org").unwrap();
+        let room = |join_rule: &str| {
+            vec![
+                state_event(
+                    "m.room.create",
+                    "",
+                    json!({ "creator": "@alice:example.org" }),
+                ),
+                state_event("m.room.join_rules", "", json!({ "join_rule": join_rule })),
+                member("@mallory:example.org", "ban"),
+            ]
+        };
+        let store = store(vec![
+            (&public_room_id, room("public")),
+            (&knock_room_id, room("knock")),
+        ]);
+        let snapshot = store.snapshot();
+        let permissions = |room_id| Permissions {
+            snapshot: &snapshot,
+            room_id,
+        };
+        let (carol, mallory) = (user("@carol:example.org"), user("@mallory:example.org"));
+
+        assert!(permissions(&public_room_id).can(&Action::Join, &carol));
+        assert!(!permissions(&public_room_id).can(&Action::Join, &mallory));
+        assert!(!permissions(&public_room_id).can(&Action::Knock, &carol));
+        assert!(!permissions(&knock_room_id).can(&Action::Join, &carol));
+        assert!(permissions(&knock_room_id).can(&Action::Knock, &carol));
+        assert!(!permissions(&knock_room_id).can(&Action::Knock, &mallory));
+    }
+}
@@ -0,0 +1,49 @@
+//! Presence of users.
+
+use futures::future::{self, Future};
+use hyper::client::connect::Connect;
+use ruma_events::presence::PresenceState;
+use ruma_identifiers::UserId;
+
+use crate::{api::r0::presence::get_presence, Client, Error};
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Set the logged-in user's presence, e.g. to `PresenceState::Unavailable`, with an optional
+    /// status message.
+    ///
+    /// Syncing with `set_presence` also sets the user online, which overrides this.
+    pub fn set_presence(
+        &self,
+        presence: PresenceState,
+        status_msg: Option<String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::presence::set_presence;
+
+        let request = self
+            .logged_in_user_id()
+            .map(|user_id| set_presence::Request {
+                presence,
+                status_msg,
+                user_id,
+            });
+        let client = self.clone();
+
+        future::result(request)
+            .and_then(move |request| set_presence::call(client, request))
+            .map(|_| ())
+    }
+
+    /// Get a user's presence and status message from the homeserver.
+    ///
+    /// The presence updates received with sync responses are available from `Store::presence`
+    /// without a request, but they lack the status message.
+    pub fn presence(
+        &self,
+        user_id: UserId,
+    ) -> impl Future<Item = get_presence::Response, Error = Error> {
+        get_presence::call(self.clone(), get_presence::Request { user_id })
+    }
+}
@@ -0,0 +1,86 @@
+//! Custom processing of sync responses.
+
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+
+use hyper::client::connect::Connect;
+
+use crate::{api::r0::sync::sync_events::Response, Client, ClientData};
+
+/// When a `SyncProcessor` runs, relative to the update of the client's `Store`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SyncStage {
+    /// Before the store is updated with the response.
+    BeforeStore,
+    /// After the store was updated with the response, before it is yielded by `Client::sync`.
+    AfterStore,
+}
+
+/// A hook that gets to see, and change, every sync response of a client.
+///
+/// Processors can implement features that are missing from this crate without their own sync
+/// loop. They can also consume sections of a response by removing them, e.g. the rooms or the
+/// account data they take care of, which hides them from the processors that run after them, from
+/// the store if they run `BeforeStore`, and from the caller of `Client::sync`.
+pub trait SyncProcessor {
+    /// Process a sync response.
+    fn process(&mut self, response: &mut Response);
+}
+
+impl<F> SyncProcessor for F
+where
+    F: FnMut(&mut Response),
+{
+    fn process(&mut self, response: &mut Response) {
+        self(response)
+    }
+}
+
+/// A `SyncProcessor` registered with `Client::add_sync_processor`.
+pub(crate) struct RegisteredProcessor {
+    stage: SyncStage,
+    processor: Box<dyn SyncProcessor>,
+}
+
+impl Debug for RegisteredProcessor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("RegisteredProcessor")
+            .field("stage", &self.stage)
+            .finish()
+    }
+}
+
+impl<C> ClientData<C>
+where
+    C: Connect,
+{
+    /// Pass a sync response to the processors of a stage, in the order they were added.
+    pub(crate) fn run_sync_processors(&self, stage: SyncStage, response: &mut Response) {
+        for registered in self.sync_processors.borrow_mut().iter_mut() {
+            if registered.stage == stage {
+                registered.processor.process(response);
+            }
+        }
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Add a processor for the responses of `Client::sync` and the methods built on it.
+    ///
+    /// Processors of the same stage run in the order they were added. They must not add further
+    /// processors while processing a response.
+    pub fn add_sync_processor<P>(&self, stage: SyncStage, processor: P)
+    where
+        P: SyncProcessor + 'static,
+    {
+        self.0
+            .sync_processors
+            .borrow_mut()
+            .push(RegisteredProcessor {
+                stage,
+                processor: Box::new(processor),
+            });
+    }
+}
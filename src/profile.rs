@@ -0,0 +1,239 @@
+//! Looking up and changing the profiles of users.
+
+use futures::future::{self, Either, Future};
+use http::StatusCode;
+use hyper::client::connect::Connect;
+use ruma_api::Error as RumaApiError;
+use ruma_identifiers::UserId;
+use url::Url;
+
+use crate::{
+    api::{r0::profile::get_profile, unversioned::discover_homeserver},
+    extensions::BaseUrlOverride,
+    media::image_metadata,
+    Client, Error, Extensions, MxcUri,
+};
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Allow or forbid `Client::profile` to ask the homeserver of a user directly for their
+    /// profile if the client's homeserver can't provide it.
+    ///
+    /// This is off by default, because it reveals the IP address of the client and the interest
+    /// in the user to their homeserver.
+    pub fn set_remote_profile_fallback(&self, enabled: bool) {
+        self.0.remote_profile_fallback.set(enabled);
+    }
+
+    /// Get the display name and avatar URL of a user.
+    ///
+    /// The profile is requested from the client's homeserver. If it doesn't know the profile or
+    /// fails with a server error, and the fallback was enabled with `set_remote_profile_fallback`,
+    /// the profile is requested from the user's homeserver. That homeserver is discovered through
+    /// `/.well-known/matrix/client` on the server name of the user ID, or taken to be the server
+    /// name itself, once per server name. If both fail, the error of the client's homeserver is
+    /// returned.
+    pub fn profile(
+        &self,
+        user_id: UserId,
+    ) -> impl Future<Item = get_profile::Response, Error = Error> {
+        let client = self.clone();
+
+        get_profile::call(
+            self.clone(),
+            get_profile::Request {
+                user_id: user_id.clone(),
+            },
+        )
+        .or_else(move |error| {
+            if !client.0.remote_profile_fallback.get() || !is_worth_asking_remote(&error) {
+                return Either::A(future::err(error));
+            }
+
+            let request = get_profile::Request {
+                user_id: user_id.clone(),
+            };
+
+            Either::B(
+                client
+                    .remote_homeserver(&user_id)
+                    .and_then(move |base_url| {
+                        let mut extensions = Extensions::new();
+                        extensions.insert(BaseUrlOverride(base_url));
+
+                        get_profile::call_with_extensions(client, request, extensions)
+                    })
+                    .or_else(move |_| Err(error)),
+            )
+        })
+    }
+
+    /// Resolves to the base URL of the homeserver of the server name of `user_id`.
+    fn remote_homeserver(&self, user_id: &UserId) -> impl Future<Item = Url, Error = Error> {
+        let server_name = server_name(user_id);
+
+        if let Some(base_url) = self.0.remote_homeservers.borrow().get(&server_name) {
+            return Either::A(future::ok(base_url.clone()));
+        }
+
+        let server_url = match Url::parse(&format!("https://{}", server_name)) {
+            Ok(url) => url,
+            Err(error) => return Either::A(future::err(Error::from(error))),
+        };
+        let mut extensions = Extensions::new();
+        extensions.insert(BaseUrlOverride(server_url.clone()));
+        let data = self.0.clone();
+
+        Either::B(
+            discover_homeserver::call_with_extensions(
+                self.clone(),
+                discover_homeserver::Request {},
+                extensions,
+            )
+            .then(move |result| {
+                let base_url = result
+                    .ok()
+                    .and_then(|response| well_known_base_url(&response))
+                    .unwrap_or(server_url);
+
+                data.remote_homeservers
+                    .borrow_mut()
+                    .insert(server_name, base_url.clone());
+
+                Ok(base_url)
+            }),
+        )
+    }
+
+    /// Set the logged-in user's display name, or remove it with `None`.
+    pub fn set_display_name(
+        &self,
+        displayname: Option<String>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::profile::set_display_name;
+
+        let request = self
+            .logged_in_user_id()
+            .map(|user_id| set_display_name::Request {
+                displayname,
+                user_id,
+            });
+        let client = self.clone();
+
+        future::result(request)
+            .and_then(move |request| set_display_name::call(client, request))
+            .map(|_| ())
+    }
+
+    /// Set the logged-in user's avatar to an `mxc://` URL of an image that was already uploaded.
+    pub fn set_avatar_url(&self, avatar_url: String) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::profile::set_avatar_url;
+
+        let request = self
+            .logged_in_user_id()
+            .map(|user_id| set_avatar_url::Request {
+                avatar_url,
+                user_id,
+            });
+        let client = self.clone();
+
+        future::result(request)
+            .and_then(move |request| set_avatar_url::call(client, request))
+            .map(|_| ())
+    }
+
+    /// Upload a PNG, GIF or JPEG image and make it the logged-in user's avatar.
+    ///
+    /// Resolves to the `mxc://` URI of the uploaded image. Fails with `Error::UnknownImageFormat`
+    /// if the image is in another format.
+    pub fn set_avatar(&self, image: Vec<u8>) -> impl Future<Item = MxcUri, Error = Error> {
+        let mimetype = match image_metadata(&image) {
+            Some(metadata) => metadata.mimetype,
+            None => return Either::A(future::err(Error::UnknownImageFormat)),
+        };
+        let client = self.clone();
+
+        Either::B(
+            self.upload(mimetype.to_owned(), None, image)
+                .and_then(move |url| client.set_avatar_url(url.to_string()).map(move |()| url)),
+        )
+    }
+}
+
+/// Whether the user's homeserver may know a profile the client's homeserver failed to return with
+/// `error`.
+///
+/// The client's homeserver refusing to share the profile, e.g. with 403 Forbidden, is respected.
+fn is_worth_asking_remote(error: &Error) -> bool {
+    match error {
+        Error::RumaApi(RumaApiError::StatusCode(status)) => {
+            *status == StatusCode::NOT_FOUND || status.is_server_error()
+        }
+        _ => false,
+    }
+}
+
+/// The server name of `user_id`, with the port only if it isn't the default one.
+fn server_name(user_id: &UserId) -> String {
+    match user_id.port() {
+        443 => user_id.hostname().to_string(),
+        port => format!("{}:{}", user_id.hostname(), port),
+    }
+}
+
+/// The homeserver base URL of a `/.well-known/matrix/client` response, if it is valid.
+fn well_known_base_url(response: &discover_homeserver::Response) -> Option<Url> {
+    Url::parse(&response.homeserver.base_url)
+        .ok()
+        .filter(|url| url.scheme() == "https" || url.scheme() == "http")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::api::unversioned::discover_homeserver::HomeserverInfo;
+
+    fn status(status: StatusCode) -> Error {
+        Error::RumaApi(RumaApiError::StatusCode(status))
+    }
+
+    fn well_known(base_url: &str) -> discover_homeserver::Response {
+        discover_homeserver::Response {
+            homeserver: HomeserverInfo {
+                base_url: base_url.to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn only_missing_profiles_and_server_errors_ask_remote() {
+        assert!(is_worth_asking_remote(&status(StatusCode::NOT_FOUND)));
+        assert!(is_worth_asking_remote(&status(StatusCode::BAD_GATEWAY)));
+        assert!(!is_worth_asking_remote(&status(StatusCode::FORBIDDEN)));
+        assert!(!is_worth_asking_remote(&Error::AuthenticationRequired));
+    }
+
+    #[test]
+    fn server_name_omits_default_port() {
+        let default_port = UserId::try_from("@alice:example.com").unwrap();
+        let other_port = UserId::try_from("@alice:example.com:8448").unwrap();
+
+        assert_eq!(server_name(&default_port), "example.com");
+        assert_eq!(server_name(&other_port), "example.com:8448");
+    }
+
+    #[test]
+    fn well_known_base_url_must_be_http() {
+        assert_eq!(
+            well_known_base_url(&well_known("https://matrix.example.com/"))
+                .map(|url| url.to_string()),
+            Some("https://matrix.example.com/".to_owned())
+        );
+        assert!(well_known_base_url(&well_known("matrix.example.com")).is_none());
+        assert!(well_known_base_url(&well_known("ftp://example.com")).is_none());
+    }
+}
@@ -0,0 +1,253 @@
+//! Client-side rate limiting of bulk operations.
+
+use std::{
+    cell::Cell,
+    cmp,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{self, Either, Loop},
+    Future, IntoFuture,
+};
+use http::{Response, StatusCode};
+use hyper::{client::connect::Connect, Body};
+use ruma_api::Error as RumaApiError;
+
+use crate::{deadline, environment::ClockDelay, Client, ClientData, Clock, Error, SystemClock};
+
+/// The default minimum time between two requests of a bulk operation.
+///
+/// This is faster than the sustained rate of 0.2 events per second Synapse allows by default,
+/// relying on its bursts of 10 events for short operations. Longer ones run into
+/// `M_LIMIT_EXCEEDED` errors, which push the next slot back by the `retry_after_ms` the
+/// homeserver asks for and are retried.
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often a request of a bulk operation is retried after being rate-limited.
+const MAX_RETRIES: u32 = 5;
+
+/// Spaces out the requests of bulk operations like `Room::redact_matching`, so that they don't
+/// run into the rate limits of the homeserver.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Cell<Duration>,
+    next_slot: Cell<Instant>,
+    clock: Rc<dyn Clock>,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter that allows one request per `interval`.
+    pub fn new(interval: Duration) -> Self {
+        RateLimiter::with_interval_and_clock(interval, Rc::new(SystemClock))
+    }
+
+    /// Creates a rate limiter with the default interval that takes the time from `clock`.
+    pub(crate) fn with_clock(clock: Rc<dyn Clock>) -> Self {
+        RateLimiter::with_interval_and_clock(DEFAULT_INTERVAL, clock)
+    }
+
+    fn with_interval_and_clock(interval: Duration, clock: Rc<dyn Clock>) -> Self {
+        RateLimiter {
+            interval: Cell::new(interval),
+            next_slot: Cell::new(clock.instant()),
+            clock,
+        }
+    }
+
+    /// The minimum time between two requests.
+    pub fn interval(&self) -> Duration {
+        self.interval.get()
+    }
+
+    /// Change the minimum time between two requests.
+    ///
+    /// Requests that are already waiting for their turn are not affected.
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval.set(interval);
+    }
+
+    /// Reserve the next free slot for a request, returning a future that completes when it
+    /// starts.
+    pub(crate) fn wait(&self) -> ClockDelay {
+        let slot = cmp::max(self.next_slot.get(), self.clock.instant());
+        self.next_slot.set(slot + self.interval.get());
+
+        self.clock.delay_until(slot)
+    }
+
+    /// Don't start another request before `delay` has passed, e.g. because the homeserver asked
+    /// for it.
+    pub(crate) fn back_off(&self, delay: Duration) {
+        let slot = self.clock.instant() + delay;
+
+        if slot > self.next_slot.get() {
+            self.next_slot.set(slot);
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(DEFAULT_INTERVAL)
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the rate limiter used for bulk operations of this client.
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.0.rate_limiter
+    }
+
+    /// Run a request of a bulk operation in its turn, retrying it if the homeserver rate-limits
+    /// it anyway.
+    ///
+    /// `request` is called for each attempt, the future it returns is only polled in the
+    /// attempt's turn. The retries wait for the `retry_after_ms` from the
+    /// rate-limited response, which `observe` passed on to the rate limiter.
+    pub(crate) fn rate_limited<F, R>(
+        &self,
+        mut request: F,
+    ) -> impl Future<Item = R::Item, Error = Error>
+    where
+        F: FnMut() -> R,
+        R: IntoFuture<Error = Error>,
+    {
+        let client = self.clone();
+
+        future::loop_fn(0, move |retries| {
+            let attempt = request().into_future();
+
+            client
+                .rate_limiter()
+                .wait()
+                .and_then(move |()| attempt)
+                .then(move |result| match result {
+                    Err(ref error) if is_rate_limited(error) && retries < MAX_RETRIES => {
+                        Ok(Loop::Continue(retries + 1))
+                    }
+                    result => result.map(Loop::Break),
+                })
+        })
+    }
+}
+
+/// Whether `error` is the homeserver refusing a request because of its rate limits.
+fn is_rate_limited(error: &Error) -> bool {
+    match error {
+        Error::RumaApi(RumaApiError::StatusCode(status)) => {
+            *status == StatusCode::TOO_MANY_REQUESTS
+        }
+        _ => false,
+    }
+}
+
+/// Pass the `retry_after_ms` of a rate-limited `response` on to the client's rate limiter.
+pub(crate) fn observe<C>(
+    data: Rc<ClientData<C>>,
+    response: Response<Body>,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    C: Connect,
+{
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return Either::A(future::ok(response));
+    }
+
+    Either::B(
+        deadline::retry_delay(response).map(move |(response, delay)| {
+            data.rate_limiter.back_off(delay);
+            response
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{rc::Rc, time::Duration};
+
+    use futures::{Async, Future};
+    use http::StatusCode;
+    use ruma_api::Error as RumaApiError;
+
+    use super::{is_rate_limited, RateLimiter};
+    use crate::{environment::TestClock, Error};
+
+    #[test]
+    fn spaces_out_requests() {
+        let clock = TestClock::new();
+        let limiter =
+            RateLimiter::with_interval_and_clock(Duration::from_secs(1), Rc::new(clock.clone()));
+
+        let mut first = limiter.wait();
+        let mut second = limiter.wait();
+        let mut third = limiter.wait();
+
+        assert_eq!(first.poll().unwrap(), Async::Ready(()));
+        assert_eq!(second.poll().unwrap(), Async::NotReady);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(second.poll().unwrap(), Async::Ready(()));
+        assert_eq!(third.poll().unwrap(), Async::NotReady);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(third.poll().unwrap(), Async::Ready(()));
+    }
+
+    #[test]
+    fn idle_time_isnt_saved_up() {
+        let clock = TestClock::new();
+        let limiter =
+            RateLimiter::with_interval_and_clock(Duration::from_secs(1), Rc::new(clock.clone()));
+
+        clock.advance(Duration::from_secs(10));
+
+        assert_eq!(limiter.wait().poll().unwrap(), Async::Ready(()));
+        assert_eq!(limiter.wait().poll().unwrap(), Async::NotReady);
+    }
+
+    #[test]
+    fn back_off_delays_the_next_slot() {
+        let clock = TestClock::new();
+        let limiter =
+            RateLimiter::with_interval_and_clock(Duration::from_secs(1), Rc::new(clock.clone()));
+
+        assert_eq!(limiter.wait().poll().unwrap(), Async::Ready(()));
+        limiter.back_off(Duration::from_secs(5));
+
+        let mut next = limiter.wait();
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(next.poll().unwrap(), Async::NotReady);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(next.poll().unwrap(), Async::Ready(()));
+    }
+
+    #[test]
+    fn back_off_never_brings_the_next_slot_forward() {
+        let clock = TestClock::new();
+        let limiter =
+            RateLimiter::with_interval_and_clock(Duration::from_secs(10), Rc::new(clock.clone()));
+
+        assert_eq!(limiter.wait().poll().unwrap(), Async::Ready(()));
+        limiter.back_off(Duration::from_secs(1));
+
+        let mut next = limiter.wait();
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(next.poll().unwrap(), Async::NotReady);
+    }
+
+    #[test]
+    fn only_too_many_requests_is_rate_limited() {
+        let too_many = Error::RumaApi(RumaApiError::StatusCode(StatusCode::TOO_MANY_REQUESTS));
+        let forbidden = Error::RumaApi(RumaApiError::StatusCode(StatusCode::FORBIDDEN));
+
+        assert!(is_rate_limited(&too_many));
+        assert!(!is_rate_limited(&forbidden));
+        assert!(!is_rate_limited(&Error::DeadlineExceeded));
+    }
+}
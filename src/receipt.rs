@@ -0,0 +1,109 @@
+//! Read receipts and the fully-read marker.
+
+use std::collections::HashMap;
+
+use futures::Future;
+use hyper::client::connect::Connect;
+use ruma_events::receipt::ReceiptEventContent;
+use ruma_identifiers::{EventId, UserId};
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::{Error, Room};
+
+/// The type of the room account data event holding the fully-read marker.
+pub const FULLY_READ_EVENT_TYPE: &str = "m.fully_read";
+
+/// The content of an `m.fully_read` account data event.
+#[derive(Deserialize)]
+struct FullyReadEventContent {
+    event_id: EventId,
+}
+
+/// Parse the event ID from the content of an `m.fully_read` account data event.
+pub(crate) fn fully_read_from_content(content: &Value) -> Option<EventId> {
+    serde_json::from_value::<FullyReadEventContent>(content.clone())
+        .map(|content| content.event_id)
+        .ok()
+}
+
+/// A user's receipt for having read a room up to an event.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ReadReceipt {
+    /// The ID of the latest event the user has read.
+    pub event_id: EventId,
+    /// When the receipt was sent, in milliseconds since the Unix epoch.
+    pub ts: u64,
+}
+
+/// Record the `m.read` receipts of an `m.receipt` event, replacing older receipts of the same
+/// users.
+pub(crate) fn apply_receipts(
+    receipts: &mut HashMap<UserId, ReadReceipt>,
+    content: &ReceiptEventContent,
+) {
+    for (event_id, event_receipts) in content {
+        for (user_id, receipt) in &event_receipts.m_read {
+            let newer = match receipts.get(user_id) {
+                Some(known) => known.ts <= receipt.ts,
+                None => true,
+            };
+
+            if newer {
+                receipts.insert(
+                    user_id.clone(),
+                    ReadReceipt {
+                        event_id: event_id.clone(),
+                        ts: receipt.ts,
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Mark the messages of this room up to and including the given event as read.
+    ///
+    /// The receipts of all users, including the logged-in one, are available from
+    /// `Store::read_receipts` once they come back with a sync response.
+    pub fn send_read_receipt(&self, event_id: EventId) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::receipt::create_receipt::{self, ReceiptType};
+
+        create_receipt::call(
+            self.client().clone(),
+            create_receipt::Request {
+                event_id,
+                receipt_type: ReceiptType::Read,
+                room_id: self.room_id().clone(),
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Move the logged-in user's fully-read marker to the given event, and optionally their read
+    /// receipt to `read_receipt`, in one request.
+    ///
+    /// The current marker is available from `Store::fully_read` once it comes back with a sync
+    /// response.
+    pub fn set_read_marker(
+        &self,
+        fully_read: EventId,
+        read_receipt: Option<EventId>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::read_marker::set_read_marker;
+
+        set_read_marker::call(
+            self.client().clone(),
+            set_read_marker::Request {
+                room_id: self.room_id().clone(),
+                fully_read,
+                read_receipt,
+            },
+        )
+        .map(|_| ())
+    }
+}
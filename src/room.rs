@@ -0,0 +1,1224 @@
+#[cfg(feature = "synapse-admin")]
+use std::time::Duration;
+use std::{cell::Cell, collections::HashMap, convert::TryFrom, path::Path, rc::Rc};
+
+#[cfg(feature = "synapse-admin")]
+use futures::future::Loop;
+use futures::{
+    future::{self, Either},
+    stream, Future, IntoFuture, Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_events::{
+    collections::{all, only},
+    room::{
+        avatar::AvatarEventContent,
+        join_rules::{JoinRule, JoinRulesEventContent},
+        message::{
+            EmoteMessageEventContent, MessageEvent, MessageEventContent, MessageType,
+            NoticeMessageEventContent, TextMessageEventContent,
+        },
+        name::NameEventContent,
+        power_levels::PowerLevelsEventContent,
+        topic::TopicEventContent,
+        ImageInfo,
+    },
+    EventType,
+};
+use ruma_identifiers::{EventId, RoomId, UserId};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    api::r0::{
+        directory::{
+            get_room_visibility::{self, Visibility},
+            set_room_visibility,
+        },
+        membership::joined_members::RoomMember,
+        sync::get_message_events::Direction,
+    },
+    error::not_found_as_none,
+    events::{RoomEventFields, TimelineEvent},
+    media::image_metadata,
+    message::{
+        apply_replacement, replacement_content, text_to_html, FormattedMessageEventContent,
+        ReactionEventContent, RelationType, REACTION_EVENT_TYPE,
+    },
+    store::ENCRYPTION_EVENT_TYPE,
+    Client, Error,
+};
+
+/// How long to wait between two queries of the status of a history purge.
+#[cfg(feature = "synapse-admin")]
+const PURGE_STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long `Room::peek_events` waits for new events with each request, in milliseconds.
+const PEEK_TIMEOUT: u64 = 30_000;
+
+/// How many events `Room::messages_stream` requests per page.
+const MESSAGES_PAGE_SIZE: u64 = 100;
+
+/// How many events `Room::relations` requests per page.
+const RELATIONS_PAGE_SIZE: u64 = 50;
+
+/// A handle for making requests that concern a single room.
+#[derive(Debug)]
+pub struct Room<C: Connect> {
+    client: Client<C>,
+    room_id: RoomId,
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    pub(crate) fn new(client: Client<C>, room_id: RoomId) -> Self {
+        Room { client, room_id }
+    }
+
+    /// Get the ID of this room.
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+
+    pub(crate) fn client(&self) -> &Client<C> {
+        &self.client
+    }
+
+    /// Send a message event to this room.
+    ///
+    /// In contrast to api::r0::send::send_message_event::call(), this method generates the
+    /// transaction ID itself, and encrypts the message if the room is encrypted, see
+    /// `send_custom_message`. The returned future resolves to the ID of the new event.
+    pub fn send_message(
+        &self,
+        content: MessageEventContent,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        self.send_custom_message(EventType::RoomMessage, &content)
+    }
+
+    /// Send a plain text message to this room.
+    pub fn send_text(&self, body: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_message(MessageEventContent::Text(TextMessageEventContent {
+            body,
+            msgtype: MessageType::Text,
+        }))
+    }
+
+    /// Send a notice to this room.
+    ///
+    /// Notices are meant for automated messages, e.g. from bots, and clients won't answer them
+    /// automatically.
+    pub fn send_notice(&self, body: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_message(MessageEventContent::Notice(NoticeMessageEventContent {
+            body,
+            msgtype: MessageType::Notice,
+        }))
+    }
+
+    /// Send an emote, i.e. a message describing an action of the user, to this room.
+    pub fn send_emote(&self, body: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_message(MessageEventContent::Emote(EmoteMessageEventContent {
+            body,
+            msgtype: MessageType::Emote,
+        }))
+    }
+
+    /// Send a message with an HTML body to this room.
+    ///
+    /// If `plain` is `None`, the plain text fallback is generated from `html`.
+    pub fn send_html(
+        &self,
+        html: String,
+        plain: Option<String>,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        self.send_custom_message(
+            EventType::RoomMessage,
+            &FormattedMessageEventContent::html(MessageType::Text, html, plain),
+        )
+    }
+
+    /// Send a text message to this room as a reply to `original`.
+    ///
+    /// The message quotes the original one so that clients without support for replies can
+    /// still show the context.
+    pub fn send_reply(
+        &self,
+        original: &MessageEvent,
+        body: String,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        self.send_custom_message(
+            EventType::RoomMessage,
+            &FormattedMessageEventContent::reply(
+                &self.room_id,
+                original,
+                text_to_html(&body),
+                Some(body),
+            ),
+        )
+    }
+
+    /// React to the event `event_id` with `key`, usually an emoji.
+    ///
+    /// Unless disabled with `Client::set_duplicate_reaction_guard`, this fails with
+    /// `Error::DuplicateAnnotation` if the logged-in user already reacted to the event with the
+    /// same key, since homeservers reject such duplicates. The `Store` is checked first, then the
+    /// annotations the homeserver aggregated for the event.
+    pub fn react(
+        &self,
+        event_id: EventId,
+        key: String,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        let room = self.clone();
+        let content = ReactionEventContent::new(event_id.clone(), key.clone());
+
+        self.check_duplicate_reaction(event_id, key)
+            .and_then(move |()| {
+                room.send_custom_message(
+                    EventType::Custom(REACTION_EVENT_TYPE.to_owned()),
+                    &content,
+                )
+            })
+    }
+
+    /// Fail with `Error::DuplicateAnnotation` if the logged-in user already reacted to `event_id`
+    /// with `key` and the duplicate reaction guard is on.
+    fn check_duplicate_reaction(
+        &self,
+        event_id: EventId,
+        key: String,
+    ) -> impl Future<Item = (), Error = Error> {
+        let user_id = match self.client.session() {
+            Some(ref session) if self.client.0.duplicate_reaction_guard.get() => {
+                session.user_id().clone()
+            }
+            _ => return Either::A(future::ok(())),
+        };
+
+        if self
+            .client
+            .store()
+            .has_reacted(&self.room_id, &event_id, &user_id, &key)
+        {
+            return Either::A(future::err(Error::DuplicateAnnotation { event_id, key }));
+        }
+
+        let reactions = self.relations_json(
+            event_id.clone(),
+            Some(RelationType::Annotation),
+            Some(EventType::Custom(REACTION_EVENT_TYPE.to_owned())),
+        );
+        let duplicate_key = key.clone();
+
+        Either::B(
+            reactions
+                .filter(move |event| is_reaction_by(event, &user_id, &duplicate_key))
+                .into_future()
+                .map_err(|(error, _)| error)
+                .then(move |result| match result {
+                    Ok((Some(_), _)) => Err(Error::DuplicateAnnotation { event_id, key }),
+                    Ok((None, _)) => Ok(()),
+                    // Homeservers that don't support relations can't be asked for duplicates.
+                    Err(ref error) if error.is_not_found() => Ok(()),
+                    Err(error) => Err(error),
+                }),
+        )
+    }
+
+    /// Edit the message `original_event_id`, replacing its content with `new_content`.
+    ///
+    /// `new_content` can be e.g. a `MessageEventContent` or a `FormattedMessageEventContent`.
+    pub fn edit_message<T>(
+        &self,
+        original_event_id: EventId,
+        new_content: &T,
+    ) -> impl Future<Item = EventId, Error = Error>
+    where
+        T: Serialize,
+    {
+        let room = self.clone();
+
+        replacement_content(&original_event_id, new_content)
+            .into_future()
+            .and_then(move |content| room.send_custom_message(EventType::RoomMessage, &content))
+    }
+
+    /// Get the content of the latest edit of the message `original`, or its own content if it
+    /// hasn't been edited.
+    ///
+    /// This relies on the homeserver aggregating edits in the `unsigned` data of events. If it
+    /// only references the latest edit, that event is fetched.
+    pub fn latest_content(
+        &self,
+        original: &MessageEvent,
+    ) -> impl Future<Item = MessageEventContent, Error = Error> {
+        use crate::api::r0::room::get_room_event;
+
+        let replacement = original
+            .unsigned
+            .as_ref()
+            .and_then(|unsigned| unsigned.pointer("/m.relations/m.replace"))
+            .cloned();
+        let replacement_id = replacement
+            .as_ref()
+            .filter(|replacement| replacement.get("content").is_none())
+            .and_then(|replacement| replacement.get("event_id"))
+            .and_then(Value::as_str)
+            .and_then(|event_id| EventId::try_from(event_id).ok());
+        let original = original.clone();
+
+        match (replacement, replacement_id) {
+            (Some(_), Some(event_id)) => Either::A(
+                get_room_event::call(
+                    self.client.clone(),
+                    get_room_event::Request {
+                        room_id: self.room_id.clone(),
+                        event_id,
+                    },
+                )
+                .map(move |response| apply_replacement(&original, response.event)),
+            ),
+            (Some(replacement), None) => {
+                Either::B(future::ok(apply_replacement(&original, replacement)))
+            }
+            (None, _) => Either::B(future::ok(original.content)),
+        }
+    }
+
+    /// Send a message event of any type, with any serializable content, to this room.
+    ///
+    /// This is useful for message content that `ruma_events` can't represent yet.
+    ///
+    /// With the `encryption` feature, messages to rooms with end-to-end encryption are encrypted.
+    /// Without it, sending to such rooms fails with `Error::EncryptionUnsupported`.
+    pub fn send_custom_message<T>(
+        &self,
+        event_type: EventType,
+        content: &T,
+    ) -> impl Future<Item = EventId, Error = Error>
+    where
+        T: Serialize,
+    {
+        use crate::api::r0::send::send_raw_message_event;
+
+        let room = self.clone();
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+
+        serde_json::to_value(content)
+            .map_err(Error::from)
+            .into_future()
+            .and_then(move |content| room.outgoing_event(event_type, content))
+            .and_then(move |(event_type, data)| {
+                let txn_id = client.next_txn_id();
+
+                send_raw_message_event::call(
+                    client,
+                    send_raw_message_event::Request {
+                        room_id,
+                        event_type,
+                        txn_id,
+                        data,
+                    },
+                )
+            })
+            .map(|response| response.event_id)
+    }
+
+    /// The type and content of a message event to send, encrypted if the room is.
+    #[cfg(feature = "encryption")]
+    fn outgoing_event(
+        &self,
+        event_type: EventType,
+        content: Value,
+    ) -> impl Future<Item = (EventType, Value), Error = Error> {
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+
+        self.is_encrypted().and_then(move |encrypted| {
+            if encrypted {
+                Either::A(client.megolm_encrypt(room_id, event_type, content))
+            } else {
+                Either::B(future::ok((event_type, content)))
+            }
+        })
+    }
+
+    /// The type and content of a message event to send, which fails for encrypted rooms.
+    #[cfg(not(feature = "encryption"))]
+    fn outgoing_event(
+        &self,
+        event_type: EventType,
+        content: Value,
+    ) -> impl Future<Item = (EventType, Value), Error = Error> {
+        let room_id = self.room_id.clone();
+
+        self.is_encrypted().and_then(move |encrypted| {
+            if encrypted {
+                Err(Error::EncryptionUnsupported(room_id))
+            } else {
+                Ok((event_type, content))
+            }
+        })
+    }
+
+    /// Whether this room uses end-to-end encryption, according to the store, or to the homeserver
+    /// if the store doesn't know the state of the room.
+    fn is_encrypted(&self) -> impl Future<Item = bool, Error = Error> {
+        let snapshot = self.client.store().snapshot();
+
+        if snapshot.is_encrypted(&self.room_id) {
+            Either::A(future::ok(true))
+        } else if snapshot.state(&self.room_id).next().is_some() {
+            Either::A(future::ok(false))
+        } else {
+            Either::B(
+                self.state_event(EventType::from(ENCRYPTION_EVENT_TYPE), "")
+                    .map(|content: Option<Value>| content.is_some()),
+            )
+        }
+    }
+
+    /// Get the current state of this room, or its state when the logged-in user left it.
+    pub fn state(&self) -> impl Future<Item = Vec<only::StateEvent>, Error = Error> {
+        use crate::api::r0::sync::get_state_events;
+
+        get_state_events::call(
+            self.client.clone(),
+            get_state_events::Request {
+                room_id: self.room_id.clone(),
+            },
+        )
+        .map(|response| response.room_state)
+    }
+
+    /// The name to show for a member of this room, from the store.
+    ///
+    /// Display names used by several members are followed by the user ID, as in
+    /// `Alice (@alice:example.org)`, so that users can tell the members apart.
+    pub fn disambiguated_name(&self, user_id: &UserId) -> String {
+        self.client
+            .store()
+            .disambiguated_name(&self.room_id, user_id)
+    }
+
+    /// Get the currently joined members of this room with their display names and avatars.
+    pub fn joined_members(&self) -> impl Future<Item = HashMap<UserId, RoomMember>, Error = Error> {
+        use crate::api::r0::membership::joined_members;
+
+        joined_members::call(
+            self.client.clone(),
+            joined_members::Request {
+                room_id: self.room_id.clone(),
+            },
+        )
+        .map(|response| response.joined)
+    }
+
+    /// Get the content of the state event with the given type and state key from the homeserver.
+    ///
+    /// Resolves to `None` if there is no such state event in this room.
+    pub fn state_event<T>(
+        &self,
+        event_type: EventType,
+        state_key: &str,
+    ) -> impl Future<Item = Option<T>, Error = Error>
+    where
+        T: DeserializeOwned,
+    {
+        use crate::api::r0::sync::{get_state_events_for_empty_key, get_state_events_for_key};
+
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+
+        let content = if state_key.is_empty() {
+            Either::A(
+                get_state_events_for_empty_key::call(
+                    client,
+                    get_state_events_for_empty_key::Request {
+                        room_id,
+                        event_type,
+                    },
+                )
+                .map(|response| response.content),
+            )
+        } else {
+            Either::B(
+                get_state_events_for_key::call(
+                    client,
+                    get_state_events_for_key::Request {
+                        room_id,
+                        event_type,
+                        state_key: state_key.to_owned(),
+                    },
+                )
+                .map(|response| response.content),
+            )
+        };
+
+        content.then(|result| match not_found_as_none(result)? {
+            Some(content) => Ok(Some(serde_json::from_value(content)?)),
+            None => Ok(None),
+        })
+    }
+
+    /// Get the name of this room, if it has one.
+    pub fn name(&self) -> impl Future<Item = Option<String>, Error = Error> {
+        self.state_event(EventType::RoomName, "")
+            .map(|content: Option<NameEventContent>| content.map(|content| content.name))
+    }
+
+    /// Get the topic of this room, if it has one.
+    pub fn topic(&self) -> impl Future<Item = Option<String>, Error = Error> {
+        self.state_event(EventType::RoomTopic, "")
+            .map(|content: Option<TopicEventContent>| content.map(|content| content.topic))
+    }
+
+    /// Get the avatar of this room, if it has one.
+    pub fn avatar(&self) -> impl Future<Item = Option<AvatarEventContent>, Error = Error> {
+        self.state_event(EventType::RoomAvatar, "")
+    }
+
+    /// Get the rule for who can join this room.
+    ///
+    /// Resolves to `None` if the room has no `m.room.join_rules` event.
+    pub fn join_rule(&self) -> impl Future<Item = Option<JoinRule>, Error = Error> {
+        self.state_event(EventType::RoomJoinRules, "")
+            .map(|content: Option<JoinRulesEventContent>| content.map(|content| content.join_rule))
+    }
+
+    /// Get the power levels of this room.
+    ///
+    /// Resolves to `None` if the room has no `m.room.power_levels` event.
+    pub fn power_levels(
+        &self,
+    ) -> impl Future<Item = Option<PowerLevelsEventContent>, Error = Error> {
+        self.state_event(EventType::RoomPowerLevels, "")
+    }
+
+    /// Send a state event of any type, with any serializable content, to this room.
+    ///
+    /// An empty `state_key` sets the state that is identified by the event type alone, like the
+    /// name of the room.
+    pub fn send_state_event<T>(
+        &self,
+        event_type: EventType,
+        state_key: &str,
+        content: &T,
+    ) -> impl Future<Item = EventId, Error = Error>
+    where
+        T: Serialize,
+    {
+        use crate::api::r0::send::{send_state_event_for_empty_key, send_state_event_for_key};
+
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+        let state_key = state_key.to_owned();
+
+        serde_json::to_value(content)
+            .map_err(Error::from)
+            .into_future()
+            .and_then(move |data: Value| {
+                if state_key.is_empty() {
+                    Either::A(
+                        send_state_event_for_empty_key::call(
+                            client,
+                            send_state_event_for_empty_key::Request {
+                                room_id,
+                                event_type,
+                                data,
+                            },
+                        )
+                        .map(|response| response.event_id),
+                    )
+                } else {
+                    Either::B(
+                        send_state_event_for_key::call(
+                            client,
+                            send_state_event_for_key::Request {
+                                room_id,
+                                event_type,
+                                state_key,
+                                data,
+                            },
+                        )
+                        .map(|response| response.event_id),
+                    )
+                }
+            })
+    }
+
+    /// Change the name of this room.
+    pub fn set_name(&self, name: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_state_event(EventType::RoomName, "", &NameEventContent { name })
+    }
+
+    /// Change the topic of this room.
+    pub fn set_topic(&self, topic: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_state_event(EventType::RoomTopic, "", &TopicEventContent { topic })
+    }
+
+    /// Change the avatar of this room to the image at the `mxc://` URL `url`.
+    pub fn set_avatar(&self, url: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_state_event(
+            EventType::RoomAvatar,
+            "",
+            &AvatarEventContent {
+                info: None,
+                thumbnail_info: None,
+                thumbnail_url: None,
+                url,
+            },
+        )
+    }
+
+    /// Upload the PNG, GIF or JPEG image at `path` and make it the avatar of this room.
+    ///
+    /// The MIME type, dimensions and size of the image are read from the file and sent along with
+    /// the avatar. Fails with `Error::UnknownImageFormat` if the file is in another format. The
+    /// file is read with `tokio_fs`, so the returned future has to be run on a Tokio runtime with
+    /// a thread pool for blocking operations, e.g. with `tokio::run`.
+    pub fn set_avatar_from_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        let path = path.as_ref().to_owned();
+        let filename = path
+            .file_name()
+            .map(|filename| filename.to_string_lossy().into_owned());
+        let room = self.clone();
+
+        tokio_fs::read(path)
+            .map_err(Error::from)
+            .and_then(|image| {
+                let metadata = image_metadata(&image).ok_or(Error::UnknownImageFormat)?;
+
+                Ok((image, metadata))
+            })
+            .and_then(move |(image, metadata)| {
+                let info = ImageInfo {
+                    height: metadata.height,
+                    mimetype: metadata.mimetype.to_owned(),
+                    size: image.len() as u64,
+                    thumbnail_info: None,
+                    thumbnail_url: None,
+                    width: metadata.width,
+                };
+
+                room.client
+                    .upload(metadata.mimetype.to_owned(), filename, image)
+                    .and_then(move |url| {
+                        room.send_state_event(
+                            EventType::RoomAvatar,
+                            "",
+                            &AvatarEventContent {
+                                info: Some(info),
+                                thumbnail_info: None,
+                                thumbnail_url: None,
+                                url: url.to_string(),
+                            },
+                        )
+                    })
+            })
+    }
+
+    /// Replace the power levels of this room.
+    ///
+    /// Fields of the current power levels that `PowerLevelsEventContent` doesn't know about are
+    /// kept.
+    pub fn set_power_levels(
+        &self,
+        power_levels: &PowerLevelsEventContent,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        let room = self.clone();
+
+        serde_json::to_value(power_levels)
+            .map_err(Error::from)
+            .into_future()
+            .and_then(move |content| {
+                room.state_event::<Value>(EventType::RoomPowerLevels, "")
+                    .and_then(move |current| {
+                        room.send_state_event(
+                            EventType::RoomPowerLevels,
+                            "",
+                            &overlay_content(current, content),
+                        )
+                    })
+            })
+    }
+
+    /// Change who can join this room.
+    pub fn set_join_rule(&self, join_rule: JoinRule) -> impl Future<Item = EventId, Error = Error> {
+        self.send_state_event(
+            EventType::RoomJoinRules,
+            "",
+            &JoinRulesEventContent { join_rule },
+        )
+    }
+
+    /// Whether this room is listed in the public room directory.
+    pub fn visibility(&self) -> impl Future<Item = Visibility, Error = Error> {
+        get_room_visibility::call(
+            self.client.clone(),
+            get_room_visibility::Request {
+                room_id: self.room_id.clone(),
+            },
+        )
+        .map(|response| response.visibility)
+    }
+
+    /// Publish this room in the public room directory, or remove it from it.
+    ///
+    /// This requires a high enough power level in the room, or being a homeserver admin.
+    pub fn set_visibility(&self, visibility: Visibility) -> impl Future<Item = (), Error = Error> {
+        set_room_visibility::call(
+            self.client.clone(),
+            set_room_visibility::Request {
+                room_id: self.room_id.clone(),
+                visibility,
+            },
+        )
+        .map(|_| ())
+    }
+
+    /// Read the history of this room page by page, starting at the pagination token `from`.
+    ///
+    /// `from` can be e.g. the `prev_batch` token of a sync response's timeline, or the `start` or
+    /// `end` token of an earlier `get_message_events` response. The events of each page are
+    /// ordered in `direction`. The stream ends when there are no more events in that direction.
+    pub fn messages_stream(
+        &self,
+        direction: Direction,
+        from: String,
+    ) -> impl Stream<Item = Vec<all::RoomEvent>, Error = Error> {
+        use crate::api::r0::sync::get_message_events;
+
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+
+        // The token to continue from, or `None` after the last page.
+        stream::unfold(Some(from), move |from| {
+            let from = from?;
+
+            Some(
+                get_message_events::call(
+                    client.clone(),
+                    get_message_events::Request {
+                        room_id: room_id.clone(),
+                        from,
+                        to: None,
+                        dir: direction.clone(),
+                        limit: Some(MESSAGES_PAGE_SIZE),
+                    },
+                )
+                .map(|response| {
+                    let next = if response.chunk.is_empty() {
+                        None
+                    } else {
+                        response.end
+                    };
+
+                    (response.chunk, next)
+                }),
+            )
+        })
+    }
+
+    /// The events that relate to the event `event_id`, newest first.
+    ///
+    /// With `rel_type`, only events with that relation are returned, e.g. the edits, reactions or
+    /// thread replies of the event. `event_type` restricts them to events of that type. The
+    /// homeserver only filters by type together with `rel_type`, so without one, events of other
+    /// types are fetched and skipped. The events are fetched page by page from the homeserver as
+    /// the stream is polled.
+    pub fn relations(
+        &self,
+        event_id: EventId,
+        rel_type: Option<RelationType>,
+        event_type: Option<EventType>,
+    ) -> impl Stream<Item = all::RoomEvent, Error = Error> {
+        self.relations_json(event_id, rel_type, event_type)
+            .and_then(|event| serde_json::from_value(event).map_err(Error::from))
+    }
+
+    /// Like `relations`, with the events as JSON, which keeps their relations.
+    pub(crate) fn relations_json(
+        &self,
+        event_id: EventId,
+        rel_type: Option<RelationType>,
+        event_type: Option<EventType>,
+    ) -> impl Stream<Item = Value, Error = Error> {
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+        // The relations endpoint can only filter by event type together with a relation type.
+        let local_type_filter = match rel_type {
+            Some(_) => None,
+            None => event_type.as_ref().map(ToString::to_string),
+        };
+
+        // `None` after the last page, otherwise the `from` token of the next page, which the
+        // first page is requested without.
+        stream::unfold(Some(None), move |from: Option<Option<String>>| {
+            let from = from?;
+            let page = relations_page(
+                client.clone(),
+                room_id.clone(),
+                event_id.clone(),
+                rel_type,
+                event_type.clone(),
+                from,
+            );
+
+            Some(page.map(|(chunk, next_batch)| {
+                let next = match next_batch {
+                    Some(next_batch) if !chunk.is_empty() => Some(Some(next_batch)),
+                    _ => None,
+                };
+
+                (chunk, next)
+            }))
+        })
+        .map(stream::iter_ok)
+        .flatten()
+        .filter(move |event| match local_type_filter {
+            Some(ref event_type) => has_event_type(event, event_type),
+            None => true,
+        })
+    }
+
+    /// Stream the new events of this room without joining it, e.g. as a guest.
+    ///
+    /// This works for rooms that are readable by the logged-in user, in particular for
+    /// world-readable rooms when the client is logged in as a guest with
+    /// `Client::register_guest`. The stream starts at the token `from`, or at the latest event if
+    /// it is `None`, and never ends.
+    pub fn peek_events(
+        &self,
+        from: Option<String>,
+    ) -> impl Stream<Item = all::RoomEvent, Error = Error> {
+        use crate::api::r0::peeking::get_events;
+
+        let client = self.client.clone();
+        let room_id = self.room_id.clone();
+
+        stream::unfold(from, move |from| {
+            Some(
+                get_events::call(
+                    client.clone(),
+                    get_events::Request {
+                        from,
+                        timeout: Some(PEEK_TIMEOUT),
+                        room_id: Some(room_id.clone()),
+                    },
+                )
+                .map(|response| (response.chunk, Some(response.end))),
+            )
+        })
+        .map(stream::iter_ok)
+        .flatten()
+    }
+
+    /// Redact the event `event_id`, optionally giving a reason.
+    ///
+    /// The returned future resolves to the ID of the redaction event.
+    pub fn redact(
+        &self,
+        event_id: EventId,
+        reason: Option<String>,
+    ) -> impl Future<Item = EventId, Error = Error> {
+        use crate::api::r0::redact::redact_event;
+
+        redact_event::call(
+            self.client.clone(),
+            redact_event::Request {
+                event_id,
+                reason,
+                room_id: self.room_id.clone(),
+                txn_id: self.client.next_txn_id(),
+            },
+        )
+        .map(|response| response.event_id)
+    }
+
+    /// Redact all events in the history of this room that match `filter`, newest first.
+    ///
+    /// `filter` is called with the sender and the content of each message-like event, state
+    /// events are never redacted. The timeline in the client's `Store` is searched first, then the
+    /// history before it is fetched page by page until the creation of the room. If the store
+    /// doesn't know the room, the whole history is fetched from the homeserver. The redactions
+    /// are spaced out by the client's `RateLimiter` and retried when the homeserver rate-limits
+    /// them anyway, so the returned stream has to be run on a runtime providing a `tokio_timer`
+    /// timer. It yields the progress after each redaction.
+    pub fn redact_matching<F>(
+        &self,
+        mut filter: F,
+        reason: Option<String>,
+    ) -> impl Stream<Item = RedactionProgress, Error = Error>
+    where
+        F: FnMut(&UserId, &only::RoomEvent) -> bool,
+    {
+        let room = self.clone();
+        let scanned = Rc::new(Cell::new(0));
+        let redacted = Rc::new(Cell::new(0));
+
+        let mut store_page = self.client.store().timeline(&self.room_id);
+        store_page.reverse();
+        let older_pages = match self.client.store().backfill_position(&self.room_id) {
+            Some((_, prev_batch)) => Either::A(Either::A(
+                self.messages_stream(Direction::Backward, prev_batch),
+            )),
+            // An empty timeline means the store doesn't know the room, as every room has at least
+            // its creation event.
+            None if store_page.is_empty() => {
+                let room = self.clone();
+
+                Either::A(Either::B(
+                    self.latest_pagination_token()
+                        .map(move |from| room.messages_stream(Direction::Backward, from))
+                        .flatten_stream(),
+                ))
+            }
+            None => Either::B(stream::empty()),
+        };
+        let pages = stream::once(Ok(store_page)).chain(older_pages);
+
+        let scanned_events = scanned.clone();
+
+        pages
+            .map(stream::iter_ok)
+            .flatten()
+            .filter_map(move |event| {
+                scanned_events.set(scanned_events.get() + 1);
+
+                match TimelineEvent::from(event) {
+                    TimelineEvent::Message(event) => Some(event),
+                    TimelineEvent::State(_) => None,
+                }
+            })
+            .filter(move |event| {
+                *event.event_type() != EventType::RoomRedaction && filter(event.sender(), event)
+            })
+            .and_then(move |event| {
+                let room = room.clone();
+                let reason = reason.clone();
+                let scanned = scanned.clone();
+                let redacted = redacted.clone();
+                let event_id = event.event_id().clone();
+                let client = room.client.clone();
+
+                client
+                    .rate_limited(move || room.redact(event_id.clone(), reason.clone()))
+                    .map(move |_| {
+                        redacted.set(redacted.get() + 1);
+
+                        RedactionProgress {
+                            event_id: event.event_id().clone(),
+                            scanned: scanned.get(),
+                            redacted: redacted.get(),
+                        }
+                    })
+            })
+    }
+
+    /// Get a token to read the history of this room backwards from its newest event.
+    ///
+    /// This makes a sync that only includes this room and none of its events, for rooms the
+    /// client's store doesn't know.
+    fn latest_pagination_token(&self) -> impl Future<Item = String, Error = Error> {
+        use crate::api::r0::{
+            filter::{Filter, FilterDefinition, RoomEventFilter, RoomFilter},
+            sync::sync_events,
+        };
+
+        let nothing = || RoomEventFilter {
+            not_types: vec!["*".to_owned()],
+            not_rooms: Vec::new(),
+            limit: Some(0),
+            rooms: Vec::new(),
+            not_senders: Vec::new(),
+            senders: Vec::new(),
+            types: Vec::new(),
+        };
+        let filter = FilterDefinition {
+            event_fields: Vec::new(),
+            event_format: None,
+            account_data: Some(Filter {
+                not_types: vec!["*".to_owned()],
+                limit: Some(0),
+                senders: Vec::new(),
+                types: Vec::new(),
+                not_senders: Vec::new(),
+            }),
+            room: Some(RoomFilter {
+                include_leave: Some(true),
+                account_data: Some(nothing()),
+                timeline: Some(nothing()),
+                ephemeral: Some(nothing()),
+                state: Some(nothing()),
+                not_rooms: Vec::new(),
+                rooms: vec![self.room_id.clone()],
+            }),
+            presence: Some(Filter {
+                not_types: vec!["*".to_owned()],
+                limit: Some(0),
+                senders: Vec::new(),
+                types: Vec::new(),
+                not_senders: Vec::new(),
+            }),
+        };
+        let room_id = self.room_id.clone();
+
+        sync_events::call(
+            self.client.clone(),
+            sync_events::Request {
+                filter: Some(sync_events::Filter::FilterDefinition(filter)),
+                since: None,
+                full_state: None,
+                set_presence: Some(sync_events::SetPresence::Offline),
+                timeout: None,
+            },
+        )
+        .and_then(move |response| {
+            let rooms = response.rooms;
+
+            rooms
+                .join
+                .get(&room_id)
+                .map(|room| room.timeline.prev_batch.clone())
+                .or_else(|| {
+                    rooms
+                        .leave
+                        .get(&room_id)
+                        .map(|room| room.timeline.prev_batch.clone())
+                })
+                .ok_or(Error::NotInRoom(room_id))
+        })
+    }
+
+    /// Purge the history of this room up to and including `before_event`, using Synapse's admin
+    /// API.
+    ///
+    /// Unless `delete_local_events` is true, only events sent by users of other homeservers are
+    /// purged. Files uploaded to the media repository are never deleted, since other rooms,
+    /// avatars or image packs may still use them. The returned future polls the status of the
+    /// purge job and resolves once it is complete, so it has to be run on a runtime providing a
+    /// `tokio_timer` timer.
+    #[cfg(feature = "synapse-admin")]
+    pub fn purge_history(
+        &self,
+        before_event: EventId,
+        delete_local_events: bool,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::synapse_admin::purge_history;
+
+        let client = self.client.clone();
+
+        purge_history::call(
+            self.client.clone(),
+            purge_history::Request {
+                room_id: self.room_id.clone(),
+                purge_up_to_event_id: before_event,
+                delete_local_events,
+            },
+        )
+        .and_then(move |response| wait_for_purge(client, response.purge_id))
+    }
+}
+
+/// Poll the status of the history purge `purge_id` until it is complete.
+#[cfg(feature = "synapse-admin")]
+fn wait_for_purge<C>(client: Client<C>, purge_id: String) -> impl Future<Item = (), Error = Error>
+where
+    C: Connect + 'static,
+{
+    use crate::api::synapse_admin::purge_history_status::{self, PurgeStatus};
+
+    future::loop_fn(purge_id, move |purge_id| {
+        let client = client.clone();
+
+        let clock = client.clock();
+
+        clock
+            .delay_until(clock.instant() + PURGE_STATUS_POLL_INTERVAL)
+            .and_then(move |()| {
+                purge_history_status::call(
+                    client,
+                    purge_history_status::Request {
+                        purge_id: purge_id.clone(),
+                    },
+                )
+                .and_then(move |response| match response.status {
+                    PurgeStatus::Active => Ok(Loop::Continue(purge_id)),
+                    PurgeStatus::Complete => Ok(Loop::Break(())),
+                    PurgeStatus::Failed => Err(Error::PurgeHistoryFailed(purge_id)),
+                })
+            })
+    })
+}
+
+/// Whether `event`, as JSON, is of the type `event_type`.
+fn has_event_type(event: &Value, event_type: &str) -> bool {
+    event.get("type").and_then(Value::as_str) == Some(event_type)
+}
+
+/// Whether `event`, as JSON, is a reaction by `user_id` with `key`.
+fn is_reaction_by(event: &Value, user_id: &UserId, key: &str) -> bool {
+    event.get("sender").and_then(Value::as_str) == Some(&user_id.to_string())
+        && event
+            .pointer("/content/m.relates_to/key")
+            .and_then(Value::as_str)
+            == Some(key)
+}
+
+/// `current` state event content with the fields of `content` written over it.
+fn overlay_content(current: Option<Value>, content: Value) -> Value {
+    match (current, content) {
+        (Some(Value::Object(mut current)), Value::Object(content)) => {
+            current.extend(content);
+            Value::Object(current)
+        }
+        (_, content) => content,
+    }
+}
+
+/// Fetch a page of the events relating to `event_id`, with the token of the next page.
+fn relations_page<C>(
+    client: Client<C>,
+    room_id: RoomId,
+    event_id: EventId,
+    rel_type: Option<RelationType>,
+    event_type: Option<EventType>,
+    from: Option<String>,
+) -> Box<dyn Future<Item = (Vec<Value>, Option<String>), Error = Error>>
+where
+    C: Connect + 'static,
+{
+    use crate::api::r0::relations::{
+        get_relating_events, get_relating_events_with_rel_type,
+        get_relating_events_with_rel_type_and_event_type,
+    };
+
+    let limit = Some(RELATIONS_PAGE_SIZE);
+
+    match (rel_type, event_type) {
+        (None, _) => Box::new(
+            get_relating_events::call(
+                client,
+                get_relating_events::Request {
+                    room_id,
+                    event_id,
+                    from,
+                    to: None,
+                    limit,
+                },
+            )
+            .map(|response| (response.chunk, response.next_batch)),
+        ),
+        (Some(rel_type), None) => Box::new(
+            get_relating_events_with_rel_type::call(
+                client,
+                get_relating_events_with_rel_type::Request {
+                    room_id,
+                    event_id,
+                    rel_type,
+                    from,
+                    to: None,
+                    limit,
+                },
+            )
+            .map(|response| (response.chunk, response.next_batch)),
+        ),
+        (Some(rel_type), Some(event_type)) => Box::new(
+            get_relating_events_with_rel_type_and_event_type::call(
+                client,
+                get_relating_events_with_rel_type_and_event_type::Request {
+                    room_id,
+                    event_id,
+                    rel_type,
+                    event_type,
+                    from,
+                    to: None,
+                    limit,
+                },
+            )
+            .map(|response| (response.chunk, response.next_batch)),
+        ),
+    }
+}
+
+/// The progress of `Room::redact_matching`, reported after each redaction.
+#[derive(Clone, Debug)]
+pub struct RedactionProgress {
+    /// The ID of the event that was just redacted.
+    pub event_id: EventId,
+    /// The number of events checked against the filter so far.
+    pub scanned: u64,
+    /// The number of events redacted so far.
+    pub redacted: u64,
+}
+
+impl<C: Connect> Clone for Room<C> {
+    fn clone(&self) -> Room<C> {
+        Room {
+            client: self.client.clone(),
+            room_id: self.room_id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::convert::TryFrom;
+
+    use ruma_identifiers::UserId;
+
+    use super::{has_event_type, is_reaction_by, overlay_content};
+
+    #[test]
+    fn event_type_is_read_from_the_json() {
+        let reaction = json!({ "type": "m.reaction", "content": {} });
+
+        assert!(has_event_type(&reaction, "m.reaction"));
+        assert!(!has_event_type(&reaction, "m.room.message"));
+        assert!(!has_event_type(&json!({ "content": {} }), "m.reaction"));
+    }
+
+    #[test]
+    fn unknown_fields_of_the_current_content_are_kept() {
+        let current = json!({ "ban": 50, "org.example.custom": { "x": 1 } });
+        let content = json!({ "ban": 100, "kick": 50 });
+
+        assert_eq!(
+            overlay_content(Some(current), content.clone()),
+            json!({ "ban": 100, "kick": 50, "org.example.custom": { "x": 1 } })
+        );
+        assert_eq!(overlay_content(None, content.clone()), content);
+    }
+
+    #[test]
+    fn reactions_are_matched_by_sender_and_key() {
+        let user_id = UserId::try_from("@alice:example.org").unwrap();
+        let reaction = json!({
+            "type": "m.reaction",
+            "sender": "@alice:example.org",
+            "content": {
+                "m.relates_to": {
+                    "rel_type": "m.annotation",
+                    "event_id": "$original:example.org",
+                    "key": "👍",
+                },
+            },
+        });
+
+        assert!(is_reaction_by(&reaction, &user_id, "👍"));
+        assert!(!is_reaction_by(&reaction, &user_id, "👎"));
+        assert!(!is_reaction_by(
+            &reaction,
+            &UserId::try_from("@bob:example.org").unwrap(),
+            "👍"
+        ));
+    }
+}
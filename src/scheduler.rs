@@ -0,0 +1,187 @@
+//! Sending messages at a later time.
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use futures::{
+    future::{self, Either, Loop},
+    stream, Future, Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_events::{room::message::MessageEventContent, EventType};
+use ruma_identifiers::{EventId, RoomId};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Client, Error, Supervisor};
+
+/// How long `Client::deliver_scheduled_sends` waits at most before looking for due messages
+/// again, so that messages scheduled in the meantime aren't delayed for long.
+const SCHEDULER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A message waiting to be sent to a room at a given time.
+///
+/// Scheduled sends live in the client's `Store`. To keep them across restarts, persist them
+/// with `Client::persist_scheduled_sends`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScheduledSend {
+    /// The ID of the scheduled send, which is also used as the transaction ID of the message.
+    ///
+    /// Reusing the transaction ID means that a message which was sent right before the client
+    /// stopped isn't sent again after restoring it, as long as the same session is used.
+    pub id: String,
+    /// The room to send the message to.
+    pub room_id: RoomId,
+    /// The content of the message.
+    pub content: MessageEventContent,
+    /// The earliest time the message will be sent at.
+    pub send_at: SystemTime,
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Schedule a message to be sent to a room at the given time.
+    ///
+    /// Messages are only sent while the stream returned by `deliver_scheduled_sends` is running.
+    /// Returns the ID of the scheduled send, for use with `cancel_scheduled_send`. Fails if the
+    /// scheduled sends are persisted and can't be saved.
+    pub fn schedule_send(
+        &self,
+        room_id: RoomId,
+        content: MessageEventContent,
+        send_at: SystemTime,
+    ) -> Result<String, Error> {
+        let id = self.next_txn_id();
+
+        self.store().insert_scheduled_send(ScheduledSend {
+            id: id.clone(),
+            room_id,
+            content,
+            send_at,
+        })?;
+
+        Ok(id)
+    }
+
+    /// Cancel a scheduled send that hasn't been sent yet.
+    ///
+    /// Returns the cancelled send, or `None` if there is no pending send with the given ID.
+    pub fn cancel_scheduled_send(&self, id: &str) -> Result<Option<ScheduledSend>, Error> {
+        self.store().remove_scheduled_send(id)
+    }
+
+    /// Keep the scheduled sends in the JSON file at `path`, so that they survive restarts.
+    ///
+    /// The sends already saved in the file are added to the store, and the file is rewritten
+    /// whenever a send is scheduled, cancelled or sent from then on. Sends whose time has passed
+    /// while the client wasn't running are sent as soon as `deliver_scheduled_sends` runs.
+    pub fn persist_scheduled_sends<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.store()
+            .persist_scheduled_sends(path.as_ref().to_owned())
+    }
+
+    /// Add scheduled sends that were saved from `Store::scheduled_sends`, e.g. before a restart.
+    ///
+    /// Sends whose time has already passed are sent as soon as `deliver_scheduled_sends` runs.
+    pub fn restore_scheduled_sends(&self, sends: Vec<ScheduledSend>) -> Result<(), Error> {
+        for send in sends {
+            self.store().insert_scheduled_send(send)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the scheduled messages as they become due as a task of `supervisor`, which is
+    /// restarted after messages fail to be sent.
+    ///
+    /// This is `deliver_scheduled_sends` for applications that don't need the sent messages.
+    pub fn spawn_scheduled_sends(&self, supervisor: &Supervisor) {
+        let client = self.clone();
+
+        supervisor.spawn_restartable("scheduled sends", move || {
+            client.deliver_scheduled_sends().for_each(|_| Ok(()))
+        });
+    }
+
+    /// Send the scheduled messages as they become due.
+    ///
+    /// The returned stream yields every sent message along with the ID of its event, and never
+    /// ends on its own. A message is only removed from the store once it has been sent, so a
+    /// message that fails to be sent ends the stream with the error and is tried again by the
+    /// next call. The stream has to be run on a runtime providing a `tokio_timer` timer.
+    pub fn deliver_scheduled_sends(
+        &self,
+    ) -> impl Stream<Item = (ScheduledSend, EventId), Error = Error> {
+        let client = self.clone();
+
+        stream::unfold((), move |()| {
+            let client = client.clone();
+
+            Some(
+                future::loop_fn((), move |()| {
+                    let client = client.clone();
+                    let now = client.clock().now();
+
+                    let next = match client.store().next_scheduled_send() {
+                        Some(next) => next,
+                        None => {
+                            return Either::B(wait(&client, SCHEDULER_POLL_INTERVAL));
+                        }
+                    };
+
+                    match next.send_at.duration_since(now) {
+                        Ok(remaining) if remaining > Duration::from_secs(0) => {
+                            Either::B(wait(&client, remaining.min(SCHEDULER_POLL_INTERVAL)))
+                        }
+                        _ => Either::A(send_scheduled(client, next).map(Loop::Break)),
+                    }
+                })
+                .map(|sent| (sent, ())),
+            )
+        })
+    }
+}
+
+/// Wait for `duration` before looking for due messages again.
+fn wait<C>(
+    client: &Client<C>,
+    duration: Duration,
+) -> impl Future<Item = Loop<(ScheduledSend, EventId), ()>, Error = Error>
+where
+    C: Connect + 'static,
+{
+    let clock = client.clock();
+
+    clock
+        .delay_until(clock.instant() + duration)
+        .map(|()| Loop::Continue(()))
+}
+
+/// Send a scheduled message and remove it from the store.
+fn send_scheduled<C>(
+    client: Client<C>,
+    send: ScheduledSend,
+) -> impl Future<Item = (ScheduledSend, EventId), Error = Error>
+where
+    C: Connect + 'static,
+{
+    use crate::api::r0::send::send_message_event;
+
+    send_message_event::call(
+        client.clone(),
+        send_message_event::Request {
+            room_id: send.room_id.clone(),
+            event_type: EventType::RoomMessage,
+            txn_id: send.id.clone(),
+            data: send.content.clone(),
+        },
+    )
+    .and_then(move |response| {
+        client.store().remove_scheduled_send(&send.id)?;
+
+        Ok((send, response.event_id))
+    })
+}
@@ -0,0 +1,240 @@
+//! Client handles restricted to some kinds of requests, e.g. for sandboxing plugins.
+
+use std::rc::Rc;
+
+use futures::Future;
+use http::Method;
+use hyper::client::connect::Connect;
+use ruma_api::Endpoint;
+use ruma_identifiers::{RoomId, UserId};
+use url::percent_encoding::percent_decode;
+
+use crate::{Client, Error, Extensions};
+
+/// A kind of request a client created with `Client::restricted` may make.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Scope {
+    /// Requests that don't change anything, i.e. `GET` requests like syncing or fetching the
+    /// history of a room.
+    Read,
+    /// Sending message events to any room.
+    SendMessages,
+    /// Sending message events to the given room.
+    SendMessagesTo(RoomId),
+    /// Uploading files to the media repository.
+    Upload,
+    /// Any request to an endpoint whose path starts with the given prefix, e.g.
+    /// `/_matrix/client/r0/profile/`.
+    PathPrefix(String),
+}
+
+/// The scopes a restricted client may use, and the restriction of the client it was created
+/// from.
+#[derive(Debug)]
+pub(crate) struct Restriction {
+    scopes: Vec<Scope>,
+    parent: Option<Rc<Restriction>>,
+}
+
+impl Scope {
+    /// Whether a request with the given method and path is in this scope.
+    fn permits(&self, method: &Method, path: &str) -> bool {
+        let segments: Vec<_> = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+
+        match self {
+            Scope::Read => *method == Method::GET,
+            Scope::SendMessages => *method == Method::PUT && send_room(&segments).is_some(),
+            Scope::SendMessagesTo(room_id) => {
+                *method == Method::PUT && send_room(&segments) == Some(room_id.to_string())
+            }
+            Scope::Upload => {
+                *method == Method::POST
+                    && segments.len() == 4
+                    && segments[..2] == ["_matrix", "media"]
+                    && segments[3] == "upload"
+            }
+            Scope::PathPrefix(prefix) => path.starts_with(&prefix[..]),
+        }
+    }
+}
+
+impl Restriction {
+    /// Whether a request with the given method and path is permitted by this restriction and
+    /// all restrictions it was derived from.
+    pub(crate) fn permits(&self, method: &Method, path: &str) -> bool {
+        let own = self.scopes.iter().any(|scope| scope.permits(method, path));
+
+        match self.parent {
+            Some(ref parent) => own && parent.permits(method, path),
+            None => own,
+        }
+    }
+}
+
+/// The room of a path of the endpoint sending message events, as it was before being
+/// percent-encoded.
+fn send_room(segments: &[&str]) -> Option<String> {
+    match segments {
+        ["_matrix", "client", _, "rooms", room_id, "send", _, _] => {
+            percent_decode(room_id.as_bytes())
+                .decode_utf8()
+                .ok()
+                .map(|room_id| room_id.into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// A handle to a client that may only make requests in some scopes, created with
+/// `Client::restricted`.
+///
+/// Unlike a `Client`, it gives access neither to the session and its access token nor to the
+/// settings of the client, so it can be handed to untrusted code like the plugins of a bot.
+#[derive(Debug)]
+pub struct RestrictedClient<C: Connect>(Client<C>);
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Create a handle to this client that may only make requests in one of the given scopes.
+    ///
+    /// The handle shares the session, the store and everything else with this client, but
+    /// requests outside of the scopes fail with `Error::NotPermitted` before they are sent. This
+    /// can be used to sandbox plugins of a bot.
+    pub fn restricted(&self, scopes: Vec<Scope>) -> RestrictedClient<C> {
+        RestrictedClient(Client(
+            self.0.clone(),
+            Some(Rc::new(Restriction {
+                scopes,
+                parent: self.1.clone(),
+            })),
+        ))
+    }
+
+    /// Apply the restriction of `other` to this client, which talks to another server on its
+    /// behalf.
+    pub(crate) fn restricted_like(mut self, other: &Client<C>) -> Client<C> {
+        self.1 = other.1.clone();
+        self
+    }
+}
+
+impl<C> RestrictedClient<C>
+where
+    C: Connect + 'static,
+{
+    /// Makes a request to a Matrix API endpoint, if it is in the scopes of this handle.
+    pub fn request<E>(
+        &self,
+        request: <E as Endpoint>::Request,
+    ) -> impl Future<Item = E::Response, Error = Error>
+    where
+        E: Endpoint,
+    {
+        self.0.clone().request::<E>(request)
+    }
+
+    /// Makes a request to a Matrix API endpoint with extension data attached, if it is in the
+    /// scopes of this handle.
+    pub fn request_with_extensions<E>(
+        &self,
+        request: <E as Endpoint>::Request,
+        extensions: Extensions,
+    ) -> impl Future<Item = E::Response, Error = Error>
+    where
+        E: Endpoint,
+    {
+        self.0
+            .clone()
+            .request_with_extensions::<E>(request, extensions)
+    }
+
+    /// Create a handle that may only make requests in one of the given scopes that this handle
+    /// permits as well.
+    pub fn restricted(&self, scopes: Vec<Scope>) -> RestrictedClient<C> {
+        self.0.restricted(scopes)
+    }
+
+    /// The user ID of the client's session, if it has one.
+    pub fn user_id(&self) -> Option<UserId> {
+        self.0.session().map(|session| session.user_id().clone())
+    }
+}
+
+impl<C: Connect> Clone for RestrictedClient<C> {
+    fn clone(&self) -> RestrictedClient<C> {
+        RestrictedClient(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, rc::Rc};
+
+    use http::Method;
+    use ruma_identifiers::RoomId;
+
+    use super::{Restriction, Scope};
+
+    fn restriction(scopes: Vec<Scope>, parent: Option<Restriction>) -> Restriction {
+        Restriction {
+            scopes,
+            parent: parent.map(Rc::new),
+        }
+    }
+
+    #[test]
+    fn send_messages_to_room() {
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+        let restriction = restriction(vec![Scope::SendMessagesTo(room_id)], None);
+
+        assert!(restriction.permits(
+            &Method::PUT,
+            "/_matrix/client/r0/rooms/%21room%3Aexample.org/send/m.room.message/1",
+        ));
+        assert!(!restriction.permits(
+            &Method::PUT,
+            "/_matrix/client/r0/rooms/%21other%3Aexample.org/send/m.room.message/1",
+        ));
+        assert!(!restriction.permits(
+            &Method::GET,
+            "/_matrix/client/r0/rooms/%21room%3Aexample.org/send/m.room.message/1",
+        ));
+    }
+
+    #[test]
+    fn upload() {
+        let restriction = restriction(vec![Scope::Upload], None);
+
+        assert!(restriction.permits(&Method::POST, "/_matrix/media/r0/upload"));
+        assert!(!restriction.permits(&Method::POST, "/_matrix/media/r0/upload/extra"));
+        assert!(!restriction.permits(&Method::POST, "/_matrix/client/r0/upload"));
+    }
+
+    #[test]
+    fn nested_restriction_never_permits_more() {
+        let parent = restriction(vec![Scope::Read], None);
+        let restriction = restriction(vec![Scope::Read, Scope::Upload], Some(parent));
+
+        assert!(restriction.permits(&Method::GET, "/_matrix/client/r0/sync"));
+        assert!(!restriction.permits(&Method::POST, "/_matrix/media/r0/upload"));
+    }
+
+    #[test]
+    fn path_prefix() {
+        let restriction = restriction(
+            vec![Scope::PathPrefix("/_matrix/client/r0/profile/".to_owned())],
+            None,
+        );
+
+        assert!(restriction.permits(
+            &Method::PUT,
+            "/_matrix/client/r0/profile/%40bot%3Aexample.org/displayname",
+        ));
+        assert!(!restriction.permits(&Method::PUT, "/_matrix/client/r0/presence/x/status"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Searching messages on the homeserver and in the local index.
+
+use std::cmp::Ordering;
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_events::collections::all;
+use ruma_identifiers::{EventId, RoomId, UserId};
+
+use crate::{
+    api::r0::{
+        filter::RoomEventFilter,
+        search::search_events::{self, Categories, Criteria, OrderBy, SearchKeys},
+    },
+    message::{message_body, strip_reply_fallback},
+    Client, Error,
+};
+
+#[cfg(feature = "local-search")]
+pub use self::index::LocalSearchResult;
+#[cfg(feature = "local-search")]
+pub(crate) use self::index::SearchIndex;
+
+#[cfg(feature = "local-search")]
+mod index;
+
+/// The results of `Client::search`.
+#[derive(Clone, Debug, Default)]
+pub struct SearchResults {
+    /// The messages found, best match first.
+    pub hits: Vec<SearchHit>,
+    /// The encrypted rooms that couldn't be searched, because the `local-search` feature is
+    /// disabled.
+    pub unsearched_rooms: Vec<RoomId>,
+}
+
+/// A message found by `Client::search`.
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    /// The room the message was sent to.
+    pub room_id: RoomId,
+    /// The ID of the message event.
+    pub event_id: EventId,
+    /// The sender of the message.
+    pub sender: UserId,
+    /// The body of the message, without reply fallback.
+    pub body: String,
+    /// The time the message was sent, in milliseconds since the UNIX epoch.
+    pub origin_server_ts: u64,
+    /// How well the message matches the query, between 0 and 1. Higher is better.
+    pub rank: f64,
+    /// Where the message was found.
+    pub source: SearchSource,
+}
+
+/// Where `Client::search` found a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SearchSource {
+    /// The homeserver's search, used for unencrypted rooms.
+    Server,
+    /// The local index of `Client::search_local`, used for encrypted rooms.
+    LocalIndex,
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Search for messages containing `query`, in the given rooms or in all rooms.
+    ///
+    /// The homeserver can't search the content of encrypted rooms, so these are searched in the
+    /// local index if the `local-search` feature is enabled, and the others on the homeserver.
+    /// Only the first page of the homeserver's results is used. The ranks of both are scaled to
+    /// the same range, so that the results can be merged.
+    pub fn search(
+        &self,
+        query: &str,
+        rooms: Option<&[RoomId]>,
+    ) -> impl Future<Item = SearchResults, Error = Error> {
+        let snapshot = self.store().snapshot();
+        let (encrypted, unencrypted): (Vec<RoomId>, Vec<RoomId>) = match rooms {
+            Some(rooms) => rooms
+                .iter()
+                .cloned()
+                .partition(|room_id| snapshot.is_encrypted(room_id)),
+            None => (
+                snapshot
+                    .room_ids()
+                    .filter(|room_id| snapshot.is_encrypted(room_id))
+                    .cloned()
+                    .collect(),
+                Vec::new(),
+            ),
+        };
+
+        let mut results = SearchResults::default();
+
+        #[cfg(feature = "local-search")]
+        {
+            results.hits = normalize_ranks(
+                self.search_local(query, Some(&encrypted))
+                    .into_iter()
+                    .map(|result| SearchHit {
+                        room_id: result.room_id,
+                        event_id: result.event_id,
+                        sender: result.sender,
+                        body: result.body,
+                        origin_server_ts: result.origin_server_ts,
+                        rank: result.rank,
+                        source: SearchSource::LocalIndex,
+                    })
+                    .collect(),
+            );
+        }
+        #[cfg(not(feature = "local-search"))]
+        {
+            results.unsearched_rooms = encrypted.clone();
+        }
+
+        if rooms.is_some() && unencrypted.is_empty() {
+            return Either::A(future::ok(results));
+        }
+
+        let filter = RoomEventFilter {
+            not_types: Vec::new(),
+            not_rooms: if rooms.is_none() {
+                encrypted.iter().map(ToString::to_string).collect()
+            } else {
+                Vec::new()
+            },
+            limit: None,
+            rooms: unencrypted,
+            not_senders: Vec::new(),
+            senders: Vec::new(),
+            types: vec!["m.room.message".to_owned()],
+        };
+
+        Either::B(
+            search_events::call(
+                self.clone(),
+                search_events::Request {
+                    next_batch: None,
+                    search_categories: Categories {
+                        room_events: Some(Criteria {
+                            event_context: None,
+                            filter: Some(filter),
+                            groupings: None,
+                            include_state: None,
+                            keys: vec![SearchKeys::ContentBody],
+                            order_by: Some(OrderBy::Rank),
+                            search_term: query.to_owned(),
+                        }),
+                    },
+                },
+            )
+            .map(move |response| {
+                let server_hits = response
+                    .search_categories
+                    .room_events
+                    .into_iter()
+                    .flat_map(|room_events| room_events.results)
+                    .filter_map(|result| match result.result {
+                        all::Event::RoomMessage(event) => Some(SearchHit {
+                            room_id: event.room_id?,
+                            body: strip_reply_fallback(message_body(&event.content)).to_owned(),
+                            event_id: event.event_id,
+                            sender: event.sender,
+                            origin_server_ts: event.origin_server_ts,
+                            rank: result.rank,
+                            source: SearchSource::Server,
+                        }),
+                        _ => None,
+                    })
+                    .collect();
+
+                results.hits.extend(normalize_ranks(server_hits));
+                results.hits.sort_by(|a, b| {
+                    b.rank
+                        .partial_cmp(&a.rank)
+                        .unwrap_or(Ordering::Equal)
+                        .then(b.origin_server_ts.cmp(&a.origin_server_ts))
+                });
+
+                results
+            }),
+        )
+    }
+}
+
+/// Scale the ranks of `hits` so that the best one has rank 1.
+fn normalize_ranks(mut hits: Vec<SearchHit>) -> Vec<SearchHit> {
+    let max = hits.iter().map(|hit| hit.rank).fold(0.0, f64::max);
+
+    if max > 0.0 {
+        for hit in &mut hits {
+            hit.rank /= max;
+        }
+    }
+
+    hits
+}
@@ -0,0 +1,261 @@
+//! A local full-text index of the messages the client has seen.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use hyper::client::connect::Connect;
+use ruma_events::collections::all;
+use ruma_identifiers::{EventId, RoomId, UserId};
+
+use crate::{
+    message::{message_body, strip_reply_fallback},
+    Client,
+};
+
+/// A message found by `Client::search_local`.
+#[derive(Clone, Debug)]
+pub struct LocalSearchResult {
+    /// The room the message was sent to.
+    pub room_id: RoomId,
+    /// The ID of the message event.
+    pub event_id: EventId,
+    /// The sender of the message.
+    pub sender: UserId,
+    /// The body of the message, without reply fallback.
+    pub body: String,
+    /// The time the message was sent, in milliseconds since the UNIX epoch.
+    pub origin_server_ts: u64,
+    /// How well the message matches the query. Higher is better.
+    pub rank: f64,
+}
+
+/// An inverted index of the bodies of `m.room.message` events.
+#[derive(Debug, Default)]
+pub(crate) struct SearchIndex {
+    /// The indexed messages. Redacted messages are replaced with `None`.
+    documents: Vec<Option<Document>>,
+    /// The positions of the indexed messages in `documents`.
+    positions: HashMap<EventId, usize>,
+    /// The positions of the messages containing each term, with the number of occurrences.
+    postings: HashMap<String, HashMap<usize, usize>>,
+}
+
+#[derive(Debug)]
+struct Document {
+    room_id: RoomId,
+    event_id: EventId,
+    sender: UserId,
+    body: String,
+    origin_server_ts: u64,
+    /// The number of terms of the body.
+    length: usize,
+}
+
+impl SearchIndex {
+    /// Add the messages among `events` to the index and remove redacted messages from it.
+    pub(crate) fn add_events<'a, I>(&mut self, room_id: &RoomId, events: I)
+    where
+        I: IntoIterator<Item = &'a all::RoomEvent>,
+    {
+        for event in events {
+            match event {
+                all::RoomEvent::RoomMessage(event) => {
+                    if self.positions.contains_key(&event.event_id) {
+                        continue;
+                    }
+
+                    let body = strip_reply_fallback(message_body(&event.content)).to_owned();
+                    let position = self.documents.len();
+                    let mut length = 0;
+
+                    for term in terms(&body) {
+                        *self
+                            .postings
+                            .entry(term)
+                            .or_default()
+                            .entry(position)
+                            .or_default() += 1;
+                        length += 1;
+                    }
+
+                    self.positions.insert(event.event_id.clone(), position);
+                    self.documents.push(Some(Document {
+                        room_id: room_id.clone(),
+                        event_id: event.event_id.clone(),
+                        sender: event.sender.clone(),
+                        body,
+                        origin_server_ts: event.origin_server_ts,
+                        length,
+                    }));
+                }
+                all::RoomEvent::RoomRedaction(event) => self.remove(&event.redacts),
+                _ => {}
+            }
+        }
+    }
+
+    /// Find the messages containing all terms of `query`, best match first.
+    pub(crate) fn search(&self, query: &str, rooms: Option<&[RoomId]>) -> Vec<LocalSearchResult> {
+        let query_terms: HashSet<String> = terms(query).collect();
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+
+        for (i, term) in query_terms.iter().enumerate() {
+            let postings = match self.postings.get(term) {
+                Some(postings) => postings,
+                None => return Vec::new(),
+            };
+            // Rare terms say more about a message than common ones.
+            let weight = (self.documents.len() as f64 / postings.len() as f64).ln() + 1.0;
+
+            if i == 0 {
+                scores = postings
+                    .iter()
+                    .map(|(&position, &count)| (position, count as f64 * weight))
+                    .collect();
+            } else {
+                scores = scores
+                    .into_iter()
+                    .filter_map(|(position, score)| {
+                        postings
+                            .get(&position)
+                            .map(|&count| (position, score + count as f64 * weight))
+                    })
+                    .collect();
+            }
+        }
+
+        let mut results: Vec<LocalSearchResult> = scores
+            .into_iter()
+            .filter_map(|(position, score)| {
+                let document = self.documents[position].as_ref()?;
+
+                if let Some(rooms) = rooms {
+                    if !rooms.contains(&document.room_id) {
+                        return None;
+                    }
+                }
+
+                Some(LocalSearchResult {
+                    room_id: document.room_id.clone(),
+                    event_id: document.event_id.clone(),
+                    sender: document.sender.clone(),
+                    body: document.body.clone(),
+                    origin_server_ts: document.origin_server_ts,
+                    rank: score / (document.length as f64).sqrt(),
+                })
+            })
+            .collect();
+
+        results.sort_by(|a, b| {
+            b.rank
+                .partial_cmp(&a.rank)
+                .unwrap_or(Ordering::Equal)
+                .then(b.origin_server_ts.cmp(&a.origin_server_ts))
+        });
+
+        results
+    }
+
+    /// Remove a message from the index.
+    /// Remove all messages of a room from the index.
+    pub(crate) fn remove_room(&mut self, room_id: &RoomId) {
+        let event_ids: Vec<EventId> = self
+            .documents
+            .iter()
+            .flatten()
+            .filter(|document| document.room_id == *room_id)
+            .map(|document| document.event_id.clone())
+            .collect();
+
+        for event_id in event_ids {
+            self.remove(&event_id);
+            // Unlike redacted messages, the messages can be indexed again if the room is joined
+            // again.
+            self.positions.remove(&event_id);
+        }
+    }
+
+    fn remove(&mut self, event_id: &EventId) {
+        let position = match self.positions.get(event_id) {
+            Some(&position) => position,
+            None => return,
+        };
+
+        if let Some(document) = self.documents[position].take() {
+            for term in terms(&document.body) {
+                if let Some(postings) = self.postings.get_mut(&term) {
+                    postings.remove(&position);
+
+                    if postings.is_empty() {
+                        self.postings.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Search the messages received by `Client::sync` and `Client::backfill_recent` for
+    /// messages containing all words of `query`, best match first.
+    ///
+    /// The search is case-insensitive. If `rooms` is given, only messages of these rooms are
+    /// returned. Unlike the server-side search, this works on homeservers with search disabled,
+    /// but only finds messages the client has seen since it was created.
+    pub fn search_local(&self, query: &str, rooms: Option<&[RoomId]>) -> Vec<LocalSearchResult> {
+        self.store().search_index().search(query, rooms)
+    }
+}
+
+/// The lowercase words of a text.
+fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use ruma_events::collections::all;
+    use ruma_identifiers::RoomId;
+
+    use super::SearchIndex;
+
+    fn message(room_id: &RoomId, event_id: &str, body: &str) -> all::RoomEvent {
+        serde_json::from_value(serde_json::json!({
+            "type": "m.room.message",
+            "event_id": event_id,
+            "room_id": room_id.to_string(),
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1,
+            "content": { "msgtype": "m.text", "body": body },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn removing_a_room_removes_only_its_messages() {
+        let mut index = SearchIndex::default();
+        let room = RoomId::try_from("!room:example.org").unwrap();
+        let other = RoomId::try_from("!other:example.org").unwrap();
+
+        index.add_events(&room, &[message(&room, "$1:example.org", "hello there")]);
+        index.add_events(&other, &[message(&other, "$2:example.org", "hello again")]);
+        index.remove_room(&room);
+
+        let results = index.search("hello", None);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].room_id, other);
+
+        // The messages are indexed again when the room is joined again.
+        index.add_events(&room, &[message(&room, "$1:example.org", "hello there")]);
+        assert_eq!(index.search("hello", None).len(), 2);
+    }
+}
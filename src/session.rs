@@ -1,7 +1,26 @@
+use std::{
+    fmt::{Debug, Formatter, Result as FmtResult},
+    rc::Rc,
+};
+
+use futures::{
+    future::{self, Either},
+    Future, Stream,
+};
+use http::{Response, StatusCode};
+use hyper::{client::connect::Connect, Body};
 use ruma_identifiers::UserId;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{environment::Rng, Client, ClientData, Error};
+
+/// The number of characters of the device IDs generated by `generate_device_id`.
+const DEVICE_ID_LENGTH: usize = 10;
 
 /// A user session, containing an access token and information about the associated user account.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+///
+/// Sessions can be serialized to keep them between runs, see `Config::log_in`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct Session {
     /// The access token used for this session.
     access_token: String,
@@ -36,3 +55,157 @@ impl Session {
         &self.device_id
     }
 }
+
+/// Generate a random device ID of uppercase letters, like the ones minted by homeservers.
+pub(crate) fn generate_device_id(rng: &mut dyn Rng) -> String {
+    (0..DEVICE_ID_LENGTH)
+        .map(|_| char::from(b'A' + (rng.next_u32() % 26) as u8))
+        .collect()
+}
+
+/// Why the homeserver stopped accepting the access token of a session.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InvalidationReason {
+    /// The token expired. Logging in again, with the same device ID, continues the session
+    /// ("soft logout").
+    Expired,
+    /// The token was revoked without a soft logout.
+    ///
+    /// This happens when another login replaces the token on a homeserver that allows only one
+    /// session per user, but also when the device is logged out elsewhere or an administrator
+    /// revokes the token. The homeserver doesn't say which, so these can't be told apart.
+    Replaced,
+}
+
+/// The state of a session the homeserver stopped accepting, see
+/// `Client::on_session_invalidated`.
+#[derive(Clone, Debug)]
+pub struct SessionInvalidated {
+    /// Why the session stopped working.
+    pub reason: InvalidationReason,
+    /// The session that stopped working.
+    pub session: Session,
+}
+
+/// A hook installed with `Client::on_session_invalidated`.
+type InvalidationHook = Rc<dyn Fn(&SessionInvalidated)>;
+
+/// The hooks installed with `Client::on_session_invalidated`, and the latest invalidation.
+#[derive(Default)]
+pub(crate) struct InvalidationHooks {
+    hooks: Vec<InvalidationHook>,
+    latest: Option<SessionInvalidated>,
+}
+
+impl Debug for InvalidationHooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("InvalidationHooks")
+            .field("hooks", &self.hooks.len())
+            .field("latest", &self.latest)
+            .finish()
+    }
+}
+
+/// The body of an error response of the homeserver, as far as it's needed here.
+#[derive(Deserialize)]
+struct ErrorBody {
+    errcode: String,
+    #[serde(default)]
+    soft_logout: bool,
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Install a hook that is called when the homeserver stops accepting the access token of the
+    /// client's session.
+    ///
+    /// Requests failing this way fail with `Error::SessionInvalidated`, whose reason tells an
+    /// expired token apart from one replaced by another login, so applications can ask the user
+    /// to log in again. The hooks are called once per session.
+    pub fn on_session_invalidated<F>(&self, hook: F)
+    where
+        F: Fn(&SessionInvalidated) + 'static,
+    {
+        self.0.invalidation.borrow_mut().hooks.push(Rc::new(hook));
+    }
+
+    /// Whether, and why, the homeserver stopped accepting the client's current session.
+    pub fn session_invalidated(&self) -> Option<SessionInvalidated> {
+        let session = self.session()?;
+
+        self.0
+            .invalidation
+            .borrow()
+            .latest
+            .clone()
+            .filter(|invalidated| invalidated.session == session)
+    }
+}
+
+/// Check whether the homeserver rejected `access_token`, the token of the client's session a
+/// request was made with.
+///
+/// Other responses, including those asking for user-interactive authentication, are returned as
+/// they are. Only the body of responses with the status `401 Unauthorized` is read.
+pub(crate) fn check_token<C>(
+    data: Rc<ClientData<C>>,
+    access_token: Option<String>,
+    response: Response<Body>,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    C: Connect + 'static,
+{
+    let access_token = match access_token {
+        Some(ref access_token) if response.status() == StatusCode::UNAUTHORIZED => {
+            access_token.clone()
+        }
+        _ => return Either::A(future::ok(response)),
+    };
+    let (parts, body) = response.into_parts();
+
+    Either::B(body.concat2().map_err(Error::from).and_then(move |body| {
+        let reason = match serde_json::from_slice::<ErrorBody>(&body) {
+            Ok(ref error) if error.errcode == "M_UNKNOWN_TOKEN" && error.soft_logout => {
+                InvalidationReason::Expired
+            }
+            Ok(ref error) if error.errcode == "M_UNKNOWN_TOKEN" => InvalidationReason::Replaced,
+            _ => return Ok(Response::from_parts(parts, Body::from(body))),
+        };
+
+        let session = data.session.borrow().clone();
+
+        if let Some(session) = session.filter(|session| session.access_token == access_token) {
+            invalidate(&data, reason, session);
+        }
+
+        Err(Error::SessionInvalidated(reason))
+    }))
+}
+
+/// Record that the homeserver stopped accepting `session` and call the hooks, unless that was
+/// already recorded.
+fn invalidate<C>(data: &ClientData<C>, reason: InvalidationReason, session: Session)
+where
+    C: Connect,
+{
+    let invalidated = SessionInvalidated { reason, session };
+    let hooks = {
+        let mut invalidation = data.invalidation.borrow_mut();
+
+        if let Some(ref latest) = invalidation.latest {
+            if latest.session == invalidated.session {
+                return;
+            }
+        }
+
+        invalidation.latest = Some(invalidated.clone());
+        invalidation.hooks.clone()
+    };
+
+    // The hooks are called without holding the borrow, so they may use the client.
+    for hook in hooks {
+        hook(&invalidated);
+    }
+}
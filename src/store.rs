@@ -0,0 +1,1526 @@
+//! An in-memory cache of the rooms the client knows about.
+
+#[cfg(feature = "local-search")]
+use std::cell::Ref;
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use futures::{
+    future::{self, Either, Future, Loop},
+    stream::{self, Stream},
+};
+use hyper::client::connect::Connect;
+use ruma_events::{
+    collections::{all, only},
+    presence::PresenceEventContent,
+    room::member::MembershipState,
+    EventType,
+};
+use ruma_identifiers::{EventId, RoomId, UserId};
+use serde::Serialize;
+use serde_json::Value;
+use tokio_sync::watch;
+
+#[cfg(feature = "local-search")]
+use crate::search::SearchIndex;
+use crate::{
+    account_data::{ignored_users_from_content, DIRECT_EVENT_TYPE, IGNORED_USER_LIST_EVENT_TYPE},
+    api::r0::{
+        sync::sync_events::{Response, Timeline, UnreadNotificationsCount},
+        tag::TagInfo,
+    },
+    events::{RoomEventFields, StateEventFields, TimelineEvent},
+    message::{ReactionEventContent, RelationType, REACTION_EVENT_TYPE},
+    receipt::{apply_receipts, fully_read_from_content, ReadReceipt, FULLY_READ_EVENT_TYPE},
+    scheduler::ScheduledSend,
+    tag::{tags_from_content, TAG_EVENT_TYPE},
+    upgrade::{TombstoneEventContent, TOMBSTONE_EVENT_TYPE},
+    Client, Error,
+};
+
+/// How many rooms `Client::backfill_recent` fetches history for at the same time.
+const BACKFILL_CONCURRENCY: usize = 4;
+
+/// The type of the state event that enables end-to-end encryption in a room.
+pub(crate) const ENCRYPTION_EVENT_TYPE: &str = "m.room.encryption";
+
+/// The type of the state events listing the rooms of a space.
+const SPACE_CHILD_EVENT_TYPE: &str = "m.space.child";
+
+/// How many sync positions the store remembers for `Store::state_diff`.
+const SYNC_POSITION_HISTORY: usize = 100;
+
+/// The timeline and state of the rooms the client knows about.
+///
+/// The store is kept up to date with the responses of `Client::sync` and extended with older
+/// events by `Client::backfill_recent`.
+#[derive(Debug, Default)]
+pub struct Store {
+    data: RefCell<Arc<StoreData>>,
+    watchers: RefCell<Vec<Watcher>>,
+    scheduled_sends: RefCell<HashMap<String, ScheduledSend>>,
+    /// The file the scheduled sends are kept in, see `Client::persist_scheduled_sends`.
+    scheduled_sends_file: RefCell<Option<PathBuf>>,
+    #[cfg(feature = "local-search")]
+    search_index: RefCell<SearchIndex>,
+}
+
+/// An immutable, internally consistent view of the `Store` at one point in time.
+///
+/// Taking a snapshot is cheap: the data is only copied once the store is updated while the
+/// snapshot is still alive, and then only for the rooms that changed. Snapshots can be sent to
+/// other threads, e.g. to render a user interface while the client keeps syncing.
+#[derive(Clone, Debug)]
+pub struct StoreSnapshot(Arc<StoreData>);
+
+#[derive(Clone, Debug, Default)]
+struct StoreData {
+    rooms: HashMap<RoomId, Arc<RoomData>>,
+    /// The `next_batch` tokens of the latest sync responses, oldest first, with the sequence
+    /// number of their position.
+    sync_positions: VecDeque<(u64, String)>,
+    /// The sequence number of the next sync position.
+    next_sync_position: u64,
+    /// The position of the latest sync response that didn't continue from the previous one.
+    ///
+    /// State received before it may have missed changes.
+    gap_position: Option<u64>,
+    /// The rooms the user has knocked on and that haven't answered yet.
+    knocks: HashSet<RoomId>,
+    /// The rooms removed with `remove_room` that the user hasn't joined again since.
+    ///
+    /// Sync responses report them as left once more, which mustn't add them back.
+    removed_rooms: HashSet<RoomId>,
+    /// The content of the user's global account data, keyed by event type.
+    account_data: HashMap<String, Value>,
+    /// The latest presence of other users.
+    presence: HashMap<UserId, PresenceEventContent>,
+}
+
+/// What the store knows about a single room.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RoomData {
+    /// The known part of the timeline, oldest event first.
+    timeline: Vec<all::RoomEvent>,
+    /// The token to paginate backwards from the oldest known timeline event, if there are older
+    /// events.
+    prev_batch: Option<String>,
+    /// The current state, keyed by event type and state key.
+    state: HashMap<(String, String), all::StateEvent>,
+    /// The number of unread notifications.
+    unread_counts: UnreadCounts,
+    /// The reactions to events of the room, keyed by the ID of the event reacted to.
+    reactions: HashMap<EventId, Vec<Reaction>>,
+    /// The content of the user's account data for the room, keyed by event type.
+    account_data: HashMap<String, Value>,
+    /// The latest read receipt of each user.
+    read_receipts: HashMap<UserId, ReadReceipt>,
+    /// The users currently typing.
+    typing_users: Vec<UserId>,
+    /// How many joined or invited members use each display name.
+    display_name_counts: HashMap<String, usize>,
+    /// The changes of the state since the oldest remembered sync position, oldest first.
+    state_changes: Vec<StateChange>,
+    /// When each piece of state was last received, keyed like `state`.
+    state_updates: HashMap<(String, String), StateUpdate>,
+    /// Whether the user left the room, so that its state isn't updated anymore.
+    left: bool,
+}
+
+/// When a state event was received.
+#[derive(Clone, Copy, Debug)]
+struct StateUpdate {
+    /// The sequence number of the sync position of the response with the event.
+    position: u64,
+    /// When the client received the response.
+    received_at: SystemTime,
+}
+
+/// A value derived from the state of a room, with how up to date it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateValue<T> {
+    /// The value.
+    pub value: T,
+    /// When the client received the state event the value comes from, or `None` if the value is
+    /// a default because the room has no such state.
+    pub last_updated: Option<SystemTime>,
+    /// Whether changes of the value may have been missed, so that it should be fetched from the
+    /// homeserver before acting on it.
+    ///
+    /// This is the case if the user left the room, or if the value was received before a sync
+    /// response that didn't continue from the previous one, e.g. because syncing was restarted
+    /// with an older or unrelated token.
+    pub possibly_stale: bool,
+}
+
+/// A change of a room's state by a sync response.
+#[derive(Clone, Debug)]
+struct StateChange {
+    /// The sequence number of the sync position the response leads to.
+    position: u64,
+    previous: Option<all::StateEvent>,
+    current: all::StateEvent,
+}
+
+/// The changes of a room's state between two sync positions, created by `Store::state_diff`.
+#[derive(Clone, Debug, Default)]
+pub struct StateDiff {
+    /// State events for type and state key combinations that had no state before.
+    pub added: Vec<only::StateEvent>,
+    /// Replaced state events, as pairs of the previous and the new event.
+    pub changed: Vec<(only::StateEvent, only::StateEvent)>,
+    /// State events that were replaced by events with empty content, e.g. a removed topic.
+    pub removed: Vec<only::StateEvent>,
+}
+
+/// A reaction to an event.
+#[derive(Clone, Debug)]
+struct Reaction {
+    /// The ID of the `m.reaction` event.
+    event_id: EventId,
+    key: String,
+    sender: UserId,
+}
+
+/// The number of unread notifications in a room.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct UnreadCounts {
+    /// The number of unread notifications with the highlight flag set.
+    pub highlight_count: u64,
+    /// The total number of unread notifications.
+    pub notification_count: u64,
+}
+
+/// Pushes a value derived from a room's data to a watch channel whenever it changes.
+struct Watcher {
+    room_id: RoomId,
+    /// Returns false once all receivers of the channel are gone.
+    notify: Box<dyn FnMut(&RoomData) -> bool>,
+}
+
+impl Store {
+    /// Get a view of the current contents of the store that isn't affected by later updates.
+    pub fn snapshot(&self) -> StoreSnapshot {
+        StoreSnapshot(self.data.borrow().clone())
+    }
+
+    /// The IDs of all rooms in the store.
+    pub fn room_ids(&self) -> Vec<RoomId> {
+        self.snapshot().room_ids().cloned().collect()
+    }
+
+    /// The IDs of the rooms the user has knocked on, and which have neither let the user in nor
+    /// rejected the knock yet.
+    pub fn knocked_room_ids(&self) -> Vec<RoomId> {
+        self.snapshot().knocked_room_ids().cloned().collect()
+    }
+
+    /// The messages scheduled with `Client::schedule_send` that haven't been sent yet, earliest
+    /// first.
+    pub fn scheduled_sends(&self) -> Vec<ScheduledSend> {
+        let mut sends: Vec<_> = self.scheduled_sends.borrow().values().cloned().collect();
+        sends.sort_by_key(|send| send.send_at);
+        sends
+    }
+
+    /// The known part of the timeline of a room, oldest event first.
+    pub fn timeline(&self, room_id: &RoomId) -> Vec<all::RoomEvent> {
+        self.snapshot().timeline(room_id).to_vec()
+    }
+
+    /// The current state event of a room with the given type and state key.
+    pub fn state_event(
+        &self,
+        room_id: &RoomId,
+        event_type: &EventType,
+        state_key: &str,
+    ) -> Option<only::StateEvent> {
+        self.snapshot()
+            .state_event(room_id, event_type, state_key)
+            .cloned()
+    }
+
+    /// All current state events of a room.
+    pub fn state(&self, room_id: &RoomId) -> Vec<only::StateEvent> {
+        self.snapshot().state(room_id).cloned().collect()
+    }
+
+    /// The changes of the state of a room between two sync positions.
+    ///
+    /// `from` and `to` are `next_batch` tokens of sync responses the store was updated with.
+    /// Only the latest 100 positions are remembered, so this returns `None` if either token is
+    /// unknown, or if `from` is after `to`.
+    pub fn state_diff(&self, room_id: &RoomId, from: &str, to: &str) -> Option<StateDiff> {
+        self.snapshot().state_diff(room_id, from, to)
+    }
+
+    /// The rooms a space lists as its children.
+    pub fn space_children(&self, space_id: &RoomId) -> Vec<RoomId> {
+        self.snapshot().space_children(space_id)
+    }
+
+    /// The content of the `m.room.tombstone` event of a room, if the room has been replaced by
+    /// another one.
+    pub fn tombstone(&self, room_id: &RoomId) -> Option<TombstoneEventContent> {
+        self.snapshot().tombstone(room_id)
+    }
+
+    /// The room that replaces a room after all known upgrades, or the room itself if it hasn't
+    /// been replaced.
+    pub fn latest_room(&self, room_id: &RoomId) -> RoomId {
+        self.snapshot().latest_room(room_id)
+    }
+
+    /// The number of unread notifications of a room.
+    pub fn unread_counts(&self, room_id: &RoomId) -> UnreadCounts {
+        self.snapshot().unread_counts(room_id)
+    }
+
+    /// The content of the logged-in user's global account data of the given type.
+    pub fn account_data(&self, event_type: &str) -> Option<Value> {
+        self.snapshot().account_data(event_type).cloned()
+    }
+
+    /// The rooms the logged-in user considers direct chats, keyed by the other user, from the
+    /// `m.direct` account data.
+    pub fn direct_rooms(&self) -> HashMap<UserId, Vec<RoomId>> {
+        self.snapshot().direct_rooms()
+    }
+
+    /// The users the logged-in user ignores, from the `m.ignored_user_list` account data.
+    pub fn ignored_users(&self) -> Vec<UserId> {
+        self.snapshot().ignored_users()
+    }
+
+    /// The latest presence of a user, as of the latest sync response.
+    ///
+    /// The status message isn't included, use `Client::presence` to get it.
+    pub fn presence(&self, user_id: &UserId) -> Option<PresenceEventContent> {
+        self.snapshot().presence(user_id).cloned()
+    }
+
+    /// The content of the logged-in user's account data of the given type for a room.
+    pub fn room_account_data(&self, room_id: &RoomId, event_type: &str) -> Option<Value> {
+        self.snapshot()
+            .room_account_data(room_id, event_type)
+            .cloned()
+    }
+
+    /// The logged-in user's tags for a room, from the room's `m.tag` account data.
+    pub fn tags(&self, room_id: &RoomId) -> HashMap<String, TagInfo> {
+        self.snapshot().tags(room_id)
+    }
+
+    /// The event the logged-in user has fully read a room up to, from the room's `m.fully_read`
+    /// account data.
+    pub fn fully_read(&self, room_id: &RoomId) -> Option<EventId> {
+        self.snapshot().fully_read(room_id)
+    }
+
+    /// The users currently typing in a room, as of the latest sync response.
+    pub fn typing_users(&self, room_id: &RoomId) -> Vec<UserId> {
+        self.snapshot().typing_users(room_id).to_vec()
+    }
+
+    /// The latest read receipt of each user in a room.
+    pub fn read_receipts(&self, room_id: &RoomId) -> HashMap<UserId, ReadReceipt> {
+        self.snapshot().read_receipts(room_id)
+    }
+
+    /// The latest read receipt of a user in a room.
+    pub fn read_receipt(&self, room_id: &RoomId, user_id: &UserId) -> Option<ReadReceipt> {
+        self.snapshot().read_receipt(room_id, user_id).cloned()
+    }
+
+    /// The number of users who reacted to an event with each key.
+    pub fn reaction_counts(&self, room_id: &RoomId, event_id: &EventId) -> HashMap<String, u64> {
+        self.snapshot().reaction_counts(room_id, event_id)
+    }
+
+    /// Whether a user has reacted to an event with the given key.
+    pub fn has_reacted(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        user_id: &UserId,
+        key: &str,
+    ) -> bool {
+        self.snapshot().has_reacted(room_id, event_id, user_id, key)
+    }
+
+    /// The power level of a user in a room.
+    pub fn power_level(&self, room_id: &RoomId, user_id: &UserId) -> u64 {
+        self.snapshot().power_level(room_id, user_id)
+    }
+
+    /// The current state event of a room with the given type and state key, with how up to date
+    /// it is.
+    pub fn state_value(
+        &self,
+        room_id: &RoomId,
+        event_type: &EventType,
+        state_key: &str,
+    ) -> Option<StateValue<only::StateEvent>> {
+        self.snapshot().state_value(room_id, event_type, state_key)
+    }
+
+    /// The power level of a user in a room, with how up to date it is.
+    pub fn power_level_value(&self, room_id: &RoomId, user_id: &UserId) -> StateValue<u64> {
+        self.snapshot().power_level_value(room_id, user_id)
+    }
+
+    /// The name to show for a member of a room.
+    ///
+    /// See `StoreSnapshot::disambiguated_name`.
+    pub fn disambiguated_name(&self, room_id: &RoomId, user_id: &UserId) -> String {
+        self.snapshot().disambiguated_name(room_id, user_id)
+    }
+
+    /// Watch the name of a room.
+    ///
+    /// The receiver yields the current name first, then every new one.
+    pub fn watch_room_name(&self, room_id: &RoomId) -> watch::Receiver<Option<String>> {
+        self.watch(room_id, RoomData::name)
+    }
+
+    /// Watch the power level of a user in a room.
+    ///
+    /// The receiver yields the current power level first, then every new one.
+    pub fn watch_power_level(&self, room_id: &RoomId, user_id: &UserId) -> watch::Receiver<u64> {
+        let user_id = user_id.clone();
+        self.watch(room_id, move |room| room.power_level(&user_id))
+    }
+
+    /// Watch the logged-in user's tags for a room.
+    ///
+    /// The receiver yields the current tags first, then every change.
+    pub fn watch_tags(&self, room_id: &RoomId) -> watch::Receiver<HashMap<String, TagInfo>> {
+        self.watch(room_id, RoomData::tags)
+    }
+
+    /// Watch the number of unread notifications of a room.
+    ///
+    /// The receiver yields the current counts first, then every new value.
+    pub fn watch_unread_counts(&self, room_id: &RoomId) -> watch::Receiver<UnreadCounts> {
+        self.watch(room_id, |room| room.unread_counts)
+    }
+
+    /// Create a watch channel for a value derived from a room's data.
+    pub(crate) fn watch<T, F>(&self, room_id: &RoomId, select: F) -> watch::Receiver<T>
+    where
+        T: Clone + PartialEq + 'static,
+        F: Fn(&RoomData) -> T + 'static,
+    {
+        let current = match self.data.borrow().rooms.get(room_id) {
+            Some(room) => select(room),
+            None => select(&RoomData::default()),
+        };
+        let (mut sender, receiver) = watch::channel(current.clone());
+        let mut last = current;
+
+        self.watchers.borrow_mut().push(Watcher {
+            room_id: room_id.clone(),
+            notify: Box::new(move |room| {
+                let value = select(room);
+
+                if value == last {
+                    return true;
+                }
+
+                last = value.clone();
+                sender.broadcast(value).is_ok()
+            }),
+        });
+
+        receiver
+    }
+
+    /// Update the store with a sync response received at `received_at`, which was requested with
+    /// the token `since`.
+    pub(crate) fn apply_sync(
+        &self,
+        response: &Response,
+        since: Option<&str>,
+        received_at: SystemTime,
+    ) {
+        self.update_knocks(response);
+        self.update_account_data(response);
+        self.update_presence(response);
+
+        let continues = match (since, self.snapshot().0.sync_positions.back()) {
+            (_, None) => true,
+            (Some(since), Some((_, latest))) => since == latest,
+            (None, Some(_)) => false,
+        };
+        let position = self.push_sync_position(&response.next_batch);
+        let oldest_position = self.snapshot().0.sync_positions[0].0;
+        let update = StateUpdate {
+            position,
+            received_at,
+        };
+
+        if !continues {
+            let mut data = self.data.borrow_mut();
+            Arc::make_mut(&mut data).gap_position = Some(position);
+        }
+
+        if !response.rooms.join.is_empty() {
+            let mut data = self.data.borrow_mut();
+            let removed_rooms = &mut Arc::make_mut(&mut data).removed_rooms;
+
+            for room_id in response.rooms.join.keys() {
+                removed_rooms.remove(room_id);
+            }
+        }
+
+        for (room_id, room) in &response.rooms.join {
+            self.update_room(room_id, |data| {
+                data.unread_counts = UnreadCounts::from(&room.unread_notifications);
+
+                for event in &room.account_data.events {
+                    if let Some((event_type, content)) = type_and_content(event) {
+                        data.account_data.insert(event_type, content);
+                    }
+                }
+
+                for event in &room.ephemeral.events {
+                    match event {
+                        only::Event::Receipt(event) => {
+                            apply_receipts(&mut data.read_receipts, &event.content);
+                        }
+                        only::Event::Typing(event) => {
+                            data.typing_users = event.content.user_ids.clone();
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+
+        let joined = response
+            .rooms
+            .join
+            .iter()
+            .map(|(room_id, room)| (room_id, false, &room.state.events, &room.timeline));
+        // Rooms removed by `Room::leave` or `Room::forget` shouldn't come back.
+        let snapshot = self.snapshot();
+        let left = response
+            .rooms
+            .leave
+            .iter()
+            .filter(|(room_id, _)| !snapshot.0.removed_rooms.contains(*room_id))
+            .map(|(room_id, room)| (room_id, true, &room.state.events, &room.timeline));
+
+        for (room_id, left, state, timeline) in joined.chain(left) {
+            #[cfg(feature = "local-search")]
+            self.search_index
+                .borrow_mut()
+                .add_events(room_id, &timeline.events);
+
+            self.update_room(room_id, |room| {
+                room.forget_state_changes_before(oldest_position);
+                room.left = left;
+
+                for event in state {
+                    room.set_state(event.clone(), update);
+                }
+
+                room.extend_timeline(timeline, update);
+            });
+        }
+    }
+
+    /// The full-text index of the messages in the store.
+    #[cfg(feature = "local-search")]
+    pub(crate) fn search_index(&self) -> Ref<'_, SearchIndex> {
+        self.search_index.borrow()
+    }
+
+    /// Remember the position a sync response leads to, returning its sequence number.
+    fn push_sync_position(&self, next_batch: &str) -> u64 {
+        let mut data = self.data.borrow_mut();
+        let data = Arc::make_mut(&mut data);
+        let position = data.next_sync_position;
+
+        data.next_sync_position += 1;
+        data.sync_positions
+            .push_back((position, next_batch.to_owned()));
+        if data.sync_positions.len() > SYNC_POSITION_HISTORY {
+            data.sync_positions.pop_front();
+        }
+
+        position
+    }
+
+    /// Replace the global account data of the types in a sync response.
+    fn update_account_data(&self, response: &Response) {
+        let events = match response.account_data {
+            Some(ref account_data) if !account_data.events.is_empty() => &account_data.events,
+            _ => return,
+        };
+        let mut data = self.data.borrow_mut();
+        let account_data = &mut Arc::make_mut(&mut data).account_data;
+
+        for event in events {
+            if let Some((event_type, content)) = type_and_content(event) {
+                account_data.insert(event_type, content);
+            }
+        }
+    }
+
+    /// Replace the presence of the users in a sync response.
+    fn update_presence(&self, response: &Response) {
+        if response.presence.events.is_empty() {
+            return;
+        }
+
+        let mut data = self.data.borrow_mut();
+        let presence = &mut Arc::make_mut(&mut data).presence;
+
+        for event in &response.presence.events {
+            if let only::Event::Presence(event) = event {
+                presence.insert(event.sender.clone(), event.content.clone());
+            }
+        }
+    }
+
+    /// Track the rooms the user knocked on, until they are invited, joined or left.
+    fn update_knocks(&self, response: &Response) {
+        let rooms = &response.rooms;
+        let mut data = self.data.borrow_mut();
+        let knocks = &mut Arc::make_mut(&mut data).knocks;
+
+        knocks.extend(rooms.knock.keys().cloned());
+        knocks.retain(|room_id| {
+            !rooms.join.contains_key(room_id)
+                && !rooms.leave.contains_key(room_id)
+                && !rooms.invite.contains_key(room_id)
+        });
+    }
+
+    /// Add a scheduled send, and save the scheduled sends if they are persisted.
+    pub(crate) fn insert_scheduled_send(&self, send: ScheduledSend) -> Result<(), Error> {
+        self.scheduled_sends
+            .borrow_mut()
+            .insert(send.id.clone(), send);
+
+        self.save_scheduled_sends()
+    }
+
+    /// Remove a scheduled send, and save the scheduled sends if they are persisted.
+    pub(crate) fn remove_scheduled_send(&self, id: &str) -> Result<Option<ScheduledSend>, Error> {
+        let removed = self.scheduled_sends.borrow_mut().remove(id);
+
+        if removed.is_some() {
+            self.save_scheduled_sends()?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Keep the scheduled sends in the JSON file at `path`, adding the ones already saved there.
+    pub(crate) fn persist_scheduled_sends(&self, path: PathBuf) -> Result<(), Error> {
+        let saved: Vec<ScheduledSend> = match File::open(&path) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        self.scheduled_sends
+            .borrow_mut()
+            .extend(saved.into_iter().map(|send| (send.id.clone(), send)));
+        *self.scheduled_sends_file.borrow_mut() = Some(path);
+
+        self.save_scheduled_sends()
+    }
+
+    /// Write the scheduled sends to their file, if they are persisted.
+    ///
+    /// The file is replaced in one step, so a crash while writing keeps the previous version.
+    fn save_scheduled_sends(&self) -> Result<(), Error> {
+        let path = match *self.scheduled_sends_file.borrow() {
+            Some(ref path) => path.clone(),
+            None => return Ok(()),
+        };
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer_pretty(&mut writer, &self.scheduled_sends())?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(temporary, path)?;
+
+        Ok(())
+    }
+
+    /// The scheduled send that is due first.
+    pub(crate) fn next_scheduled_send(&self) -> Option<ScheduledSend> {
+        self.scheduled_sends
+            .borrow()
+            .values()
+            .min_by_key(|send| send.send_at)
+            .cloned()
+    }
+
+    /// Remove everything the store knows about a room.
+    pub(crate) fn remove_room(&self, room_id: &RoomId) {
+        #[cfg(feature = "local-search")]
+        self.search_index.borrow_mut().remove_room(room_id);
+
+        let mut data = self.data.borrow_mut();
+        let data = Arc::make_mut(&mut data);
+        data.rooms.remove(room_id);
+        data.removed_rooms.insert(room_id.clone());
+    }
+
+    /// The number of known timeline events of a room and the token to fetch older ones.
+    pub(crate) fn backfill_position(&self, room_id: &RoomId) -> Option<(usize, String)> {
+        self.snapshot().0.rooms.get(room_id).and_then(|room| {
+            room.prev_batch
+                .clone()
+                .map(|prev_batch| (room.timeline.len(), prev_batch))
+        })
+    }
+
+    /// Add older events, newest first, to the start of a room's timeline.
+    fn prepend_timeline(&self, room_id: &RoomId, events: Vec<all::RoomEvent>, end: Option<String>) {
+        #[cfg(feature = "local-search")]
+        self.search_index.borrow_mut().add_events(room_id, &events);
+
+        self.update_room(room_id, |room| {
+            for event in events.iter().rev() {
+                room.aggregate(event);
+            }
+
+            room.prev_batch = if events.is_empty() { None } else { end };
+            room.timeline.splice(0..0, events.into_iter().rev());
+        });
+    }
+
+    /// Modify the data of a room, copying it first if a snapshot still refers to it.
+    ///
+    /// Afterwards, the watchers of the room are notified of changes.
+    fn update_room<F>(&self, room_id: &RoomId, update: F)
+    where
+        F: FnOnce(&mut RoomData),
+    {
+        let mut data = self.data.borrow_mut();
+        let rooms = &mut Arc::make_mut(&mut data).rooms;
+        let room = Arc::make_mut(rooms.entry(room_id.clone()).or_default());
+
+        update(room);
+
+        let mut watchers = self.watchers.borrow_mut();
+        let active = watchers
+            .drain(..)
+            .filter_map(|mut watcher| {
+                if watcher.room_id != *room_id || (watcher.notify)(room) {
+                    Some(watcher)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        *watchers = active;
+    }
+}
+
+impl StoreSnapshot {
+    /// The IDs of all rooms in the snapshot.
+    pub fn room_ids(&self) -> impl Iterator<Item = &RoomId> {
+        self.0.rooms.keys()
+    }
+
+    /// The IDs of the rooms the user has knocked on, and which have neither let the user in nor
+    /// rejected the knock yet.
+    pub fn knocked_room_ids(&self) -> impl Iterator<Item = &RoomId> {
+        self.0.knocks.iter()
+    }
+
+    /// The known part of the timeline of a room, oldest event first.
+    pub fn timeline(&self, room_id: &RoomId) -> &[all::RoomEvent] {
+        self.0
+            .rooms
+            .get(room_id)
+            .map(|room| &room.timeline[..])
+            .unwrap_or(&[])
+    }
+
+    /// The state event of a room with the given type and state key.
+    pub fn state_event(
+        &self,
+        room_id: &RoomId,
+        event_type: &EventType,
+        state_key: &str,
+    ) -> Option<&only::StateEvent> {
+        self.0.rooms.get(room_id).and_then(|room| {
+            room.state
+                .get(&(event_type.to_string(), state_key.to_owned()))
+        })
+    }
+
+    /// The content of the state event of a room with the given type and state key as JSON.
+    pub(crate) fn state_content(
+        &self,
+        room_id: &RoomId,
+        event_type: EventType,
+        state_key: &str,
+    ) -> Option<Value> {
+        self.0
+            .rooms
+            .get(room_id)?
+            .state_content(event_type, state_key)
+    }
+
+    /// All state events of a room.
+    pub fn state(&self, room_id: &RoomId) -> impl Iterator<Item = &only::StateEvent> {
+        self.0
+            .rooms
+            .get(room_id)
+            .into_iter()
+            .flat_map(|room| room.state.values())
+    }
+
+    /// The changes of the state of a room between two sync positions.
+    ///
+    /// See `Store::state_diff`.
+    pub fn state_diff(&self, room_id: &RoomId, from: &str, to: &str) -> Option<StateDiff> {
+        let position = |token: &str| {
+            self.0
+                .sync_positions
+                .iter()
+                .find(|(_, next_batch)| next_batch == token)
+                .map(|(position, _)| *position)
+        };
+        let (from, to) = (position(from)?, position(to)?);
+
+        if from > to {
+            return None;
+        }
+
+        // The first and the last change of each piece of state between the two positions.
+        let mut changes: HashMap<(String, &str), (&StateChange, &StateChange)> = HashMap::new();
+        let room_changes = self
+            .0
+            .rooms
+            .get(room_id)
+            .map(|room| &room.state_changes[..])
+            .unwrap_or(&[]);
+
+        for change in room_changes {
+            if change.position <= from || change.position > to {
+                continue;
+            }
+
+            let key = (
+                change.current.event_type().to_string(),
+                change.current.state_key(),
+            );
+            changes
+                .entry(key)
+                .and_modify(|(_, last)| *last = change)
+                .or_insert((change, change));
+        }
+
+        let mut diff = StateDiff::default();
+
+        for (first, last) in changes.values() {
+            let previous = first
+                .previous
+                .as_ref()
+                .filter(|event| !has_empty_content(event));
+            let current = Some(&last.current).filter(|event| !has_empty_content(event));
+
+            match (previous, current) {
+                (None, Some(current)) => diff.added.push(current.clone()),
+                (Some(previous), Some(current)) if previous.event_id() != current.event_id() => {
+                    diff.changed.push((previous.clone(), current.clone()))
+                }
+                (Some(previous), None) => diff.removed.push(previous.clone()),
+                _ => {}
+            }
+        }
+
+        Some(diff)
+    }
+
+    /// The power level of a user in a room, or 0 if the room isn't in the snapshot.
+    pub fn power_level(&self, room_id: &RoomId, user_id: &UserId) -> u64 {
+        self.0
+            .rooms
+            .get(room_id)
+            .map(|room| room.power_level(user_id))
+            .unwrap_or(0)
+    }
+
+    /// The state event of a room with the given type and state key, with how up to date it is.
+    pub fn state_value(
+        &self,
+        room_id: &RoomId,
+        event_type: &EventType,
+        state_key: &str,
+    ) -> Option<StateValue<only::StateEvent>> {
+        let room = self.0.rooms.get(room_id)?;
+        let event = room.state_event(event_type.clone(), state_key)?;
+
+        Some(room.state_value(event_type, state_key, event.clone(), self.0.gap_position))
+    }
+
+    /// The power level of a user in a room, with how up to date it is.
+    ///
+    /// The power level of users in rooms that aren't in the snapshot is 0, and possibly stale.
+    pub fn power_level_value(&self, room_id: &RoomId, user_id: &UserId) -> StateValue<u64> {
+        let room = match self.0.rooms.get(room_id) {
+            Some(room) => room,
+            None => {
+                return StateValue {
+                    value: 0,
+                    last_updated: None,
+                    possibly_stale: true,
+                }
+            }
+        };
+        let event_type = if room.state_event(EventType::RoomPowerLevels, "").is_some() {
+            EventType::RoomPowerLevels
+        } else {
+            EventType::RoomCreate
+        };
+
+        room.state_value(
+            &event_type,
+            "",
+            room.power_level(user_id),
+            self.0.gap_position,
+        )
+    }
+
+    /// The name to show for a member of a room.
+    ///
+    /// This is the member's display name, followed by the user ID in parentheses if another
+    /// joined or invited member uses the same display name or if the name looks like a user ID.
+    /// Members without a display name are shown with their user ID.
+    pub fn disambiguated_name(&self, room_id: &RoomId, user_id: &UserId) -> String {
+        match self.0.rooms.get(room_id) {
+            Some(room) => room.disambiguated_name(user_id),
+            None => user_id.to_string(),
+        }
+    }
+
+    /// Whether end-to-end encryption is enabled in a room.
+    pub fn is_encrypted(&self, room_id: &RoomId) -> bool {
+        self.state_event(room_id, &EventType::from(ENCRYPTION_EVENT_TYPE), "")
+            .is_some()
+    }
+
+    /// The rooms a space lists as its children, from its `m.space.child` state events.
+    pub fn space_children(&self, space_id: &RoomId) -> Vec<RoomId> {
+        self.0
+            .rooms
+            .get(space_id)
+            .into_iter()
+            .flat_map(|space| space.state.iter())
+            .filter(|((event_type, _), event)| {
+                event_type == SPACE_CHILD_EVENT_TYPE && !has_empty_content(event)
+            })
+            .filter_map(|((_, state_key), _)| RoomId::try_from(&state_key[..]).ok())
+            .collect()
+    }
+
+    /// The content of the `m.room.tombstone` event of a room, if the room has been replaced by
+    /// another one.
+    pub fn tombstone(&self, room_id: &RoomId) -> Option<TombstoneEventContent> {
+        self.0
+            .rooms
+            .get(room_id)?
+            .state_content(EventType::from(TOMBSTONE_EVENT_TYPE), "")
+            .and_then(|content| serde_json::from_value(content).ok())
+    }
+
+    /// The room that replaces a room after all known upgrades, or the room itself if it hasn't
+    /// been replaced.
+    pub fn latest_room(&self, room_id: &RoomId) -> RoomId {
+        let mut latest = room_id.clone();
+        let mut seen = HashSet::new();
+
+        // Tombstones pointing back to an earlier room would otherwise make this loop forever.
+        while seen.insert(latest.clone()) {
+            match self.tombstone(&latest) {
+                Some(tombstone) => latest = tombstone.replacement_room,
+                None => break,
+            }
+        }
+
+        latest
+    }
+
+    /// The number of unread notifications of a room.
+    pub fn unread_counts(&self, room_id: &RoomId) -> UnreadCounts {
+        self.0
+            .rooms
+            .get(room_id)
+            .map(|room| room.unread_counts)
+            .unwrap_or_default()
+    }
+
+    /// The content of the logged-in user's global account data of the given type.
+    pub fn account_data(&self, event_type: &str) -> Option<&Value> {
+        self.0.account_data.get(event_type)
+    }
+
+    /// The rooms the logged-in user considers direct chats, keyed by the other user, from the
+    /// `m.direct` account data.
+    pub fn direct_rooms(&self) -> HashMap<UserId, Vec<RoomId>> {
+        self.account_data(DIRECT_EVENT_TYPE)
+            .and_then(|content| serde_json::from_value(content.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// The users the logged-in user ignores, from the `m.ignored_user_list` account data.
+    pub fn ignored_users(&self) -> Vec<UserId> {
+        self.account_data(IGNORED_USER_LIST_EVENT_TYPE)
+            .map(ignored_users_from_content)
+            .unwrap_or_default()
+    }
+
+    /// The latest presence of a user, as of the latest sync response.
+    ///
+    /// The status message isn't included, use `Client::presence` to get it.
+    pub fn presence(&self, user_id: &UserId) -> Option<&PresenceEventContent> {
+        self.0.presence.get(user_id)
+    }
+
+    /// The content of the logged-in user's account data of the given type for a room.
+    pub fn room_account_data(&self, room_id: &RoomId, event_type: &str) -> Option<&Value> {
+        self.0.rooms.get(room_id)?.account_data(event_type)
+    }
+
+    /// The logged-in user's tags for a room, from the room's `m.tag` account data.
+    pub fn tags(&self, room_id: &RoomId) -> HashMap<String, TagInfo> {
+        self.0
+            .rooms
+            .get(room_id)
+            .map(|room| room.tags())
+            .unwrap_or_default()
+    }
+
+    /// The event the logged-in user has fully read a room up to, from the room's `m.fully_read`
+    /// account data.
+    pub fn fully_read(&self, room_id: &RoomId) -> Option<EventId> {
+        self.room_account_data(room_id, FULLY_READ_EVENT_TYPE)
+            .and_then(fully_read_from_content)
+    }
+
+    /// The users currently typing in a room, as of the latest sync response.
+    pub fn typing_users(&self, room_id: &RoomId) -> &[UserId] {
+        self.0
+            .rooms
+            .get(room_id)
+            .map(|room| &room.typing_users[..])
+            .unwrap_or(&[])
+    }
+
+    /// The latest read receipt of each user in a room.
+    pub fn read_receipts(&self, room_id: &RoomId) -> HashMap<UserId, ReadReceipt> {
+        self.0
+            .rooms
+            .get(room_id)
+            .map(|room| room.read_receipts.clone())
+            .unwrap_or_default()
+    }
+
+    /// The latest read receipt of a user in a room.
+    pub fn read_receipt(&self, room_id: &RoomId, user_id: &UserId) -> Option<&ReadReceipt> {
+        self.0.rooms.get(room_id)?.read_receipts.get(user_id)
+    }
+
+    /// The number of users who reacted to an event with each key.
+    pub fn reaction_counts(&self, room_id: &RoomId, event_id: &EventId) -> HashMap<String, u64> {
+        let mut senders: HashMap<&str, HashSet<&UserId>> = HashMap::new();
+
+        let reactions = self
+            .0
+            .rooms
+            .get(room_id)
+            .and_then(|room| room.reactions.get(event_id))
+            .into_iter()
+            .flatten();
+        for reaction in reactions {
+            senders
+                .entry(&reaction.key)
+                .or_default()
+                .insert(&reaction.sender);
+        }
+
+        senders
+            .into_iter()
+            .map(|(key, senders)| (key.to_owned(), senders.len() as u64))
+            .collect()
+    }
+
+    /// Whether a user has reacted to an event with the given key.
+    pub fn has_reacted(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        user_id: &UserId,
+        key: &str,
+    ) -> bool {
+        self.0
+            .rooms
+            .get(room_id)
+            .and_then(|room| room.reactions.get(event_id))
+            .into_iter()
+            .flatten()
+            .any(|reaction| reaction.sender == *user_id && reaction.key == key)
+    }
+}
+
+impl RoomData {
+    fn state_event(&self, event_type: EventType, state_key: &str) -> Option<&all::StateEvent> {
+        self.state
+            .get(&(event_type.to_string(), state_key.to_owned()))
+    }
+
+    /// The content of a state event as JSON.
+    pub(crate) fn state_content(&self, event_type: EventType, state_key: &str) -> Option<Value> {
+        self.state_event(event_type, state_key)
+            .and_then(type_and_content)
+            .map(|(_, content)| content)
+    }
+
+    fn tags(&self) -> HashMap<String, TagInfo> {
+        self.account_data(TAG_EVENT_TYPE)
+            .map(tags_from_content)
+            .unwrap_or_default()
+    }
+
+    /// The content of the user's account data for the room with the given event type.
+    pub(crate) fn account_data(&self, event_type: &str) -> Option<&Value> {
+        self.account_data.get(event_type)
+    }
+
+    /// The name of the room from its `m.room.name` event.
+    fn name(&self) -> Option<String> {
+        match self.state_event(EventType::RoomName, "") {
+            Some(all::StateEvent::RoomName(event)) => Some(event.content.name.clone()),
+            _ => None,
+        }
+    }
+
+    /// The power level of a user according to the room's `m.room.power_levels` event.
+    ///
+    /// Without such an event, the creator of the room has power level 100 and everybody else 0.
+    fn power_level(&self, user_id: &UserId) -> u64 {
+        match self.state_event(EventType::RoomPowerLevels, "") {
+            Some(all::StateEvent::RoomPowerLevels(event)) => event
+                .content
+                .users
+                .get(user_id)
+                .cloned()
+                .unwrap_or(event.content.users_default),
+            _ => match self.state_event(EventType::RoomCreate, "") {
+                Some(all::StateEvent::RoomCreate(event)) if event.content.creator == *user_id => {
+                    100
+                }
+                _ => 0,
+            },
+        }
+    }
+
+    /// Wrap a value derived from the state event with the given type and state key with how up
+    /// to date the event is.
+    fn state_value<T>(
+        &self,
+        event_type: &EventType,
+        state_key: &str,
+        value: T,
+        gap_position: Option<u64>,
+    ) -> StateValue<T> {
+        let update = self
+            .state_updates
+            .get(&(event_type.to_string(), state_key.to_owned()));
+        let before_gap = match (update, gap_position) {
+            (Some(update), Some(gap_position)) => update.position < gap_position,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+
+        StateValue {
+            value,
+            last_updated: update.map(|update| update.received_at),
+            possibly_stale: self.left || before_gap,
+        }
+    }
+
+    /// The name to show for a member, see `StoreSnapshot::disambiguated_name`.
+    fn disambiguated_name(&self, user_id: &UserId) -> String {
+        let event = match self.state_event(EventType::RoomMember, &user_id.to_string()) {
+            Some(event) => event,
+            None => return user_id.to_string(),
+        };
+        let name = match display_name(event) {
+            Some(name) => name,
+            None => return user_id.to_string(),
+        };
+        let mut others = self.display_name_counts.get(name).cloned().unwrap_or(0);
+
+        if counted_display_name(event).is_some() {
+            others -= 1;
+        }
+
+        if others > 0 || (name.starts_with('@') && name.contains(':')) {
+            format!("{} ({})", name, user_id)
+        } else {
+            name.to_owned()
+        }
+    }
+
+    /// Replace a state event, recording the change as part of the sync position of `update`.
+    fn set_state(&mut self, event: all::StateEvent, update: StateUpdate) {
+        let key = (event.event_type().to_string(), event.state_key().to_owned());
+        self.state_updates.insert(key.clone(), update);
+        let previous = self.state.insert(key, event.clone());
+
+        if let Some(name) = previous.as_ref().and_then(counted_display_name) {
+            if let Entry::Occupied(mut entry) = self.display_name_counts.entry(name.to_owned()) {
+                *entry.get_mut() -= 1;
+
+                if *entry.get() == 0 {
+                    entry.remove();
+                }
+            }
+        }
+        if let Some(name) = counted_display_name(&event) {
+            *self.display_name_counts.entry(name.to_owned()).or_insert(0) += 1;
+        }
+
+        // The same event can be part of both the state and the timeline of a sync response.
+        if previous.as_ref().map(|previous| previous.event_id()) != Some(event.event_id()) {
+            self.state_changes.push(StateChange {
+                position: update.position,
+                previous,
+                current: event,
+            });
+        }
+    }
+
+    /// Drop the state changes that no remembered sync position can be compared with anymore.
+    fn forget_state_changes_before(&mut self, oldest_position: u64) {
+        self.state_changes
+            .retain(|change| change.position > oldest_position);
+    }
+
+    fn extend_timeline(&mut self, timeline: &Timeline, update: StateUpdate) {
+        // After a gap, the events known so far are no longer adjacent to the new ones.
+        if timeline.limited {
+            self.timeline.clear();
+        }
+
+        if self.timeline.is_empty() {
+            self.prev_batch = Some(timeline.prev_batch.clone());
+        }
+
+        for event in &timeline.events {
+            if let TimelineEvent::State(state_event) = TimelineEvent::from(event.clone()) {
+                self.set_state(state_event, update);
+            }
+
+            self.aggregate(event);
+            self.timeline.push(event.clone());
+        }
+    }
+
+    /// Update the aggregated relations of the room's events with a timeline event.
+    fn aggregate(&mut self, event: &all::RoomEvent) {
+        match event {
+            all::RoomEvent::CustomRoom(event)
+                if event.event_type == EventType::Custom(REACTION_EVENT_TYPE.to_owned()) =>
+            {
+                // Redacted reactions have no content, so they are skipped here.
+                let relates_to =
+                    match serde_json::from_value::<ReactionEventContent>(event.content.clone()) {
+                        Ok(content) => content.relates_to,
+                        Err(_) => return,
+                    };
+
+                if let (Some(RelationType::Annotation), Some(target), Some(key)) =
+                    (relates_to.rel_type, relates_to.event_id, relates_to.key)
+                {
+                    let reactions = self.reactions.entry(target).or_default();
+
+                    if reactions
+                        .iter()
+                        .all(|reaction| reaction.event_id != event.event_id)
+                    {
+                        reactions.push(Reaction {
+                            event_id: event.event_id.clone(),
+                            key,
+                            sender: event.sender.clone(),
+                        });
+                    }
+                }
+            }
+            all::RoomEvent::RoomRedaction(event) => {
+                for reactions in self.reactions.values_mut() {
+                    reactions.retain(|reaction| reaction.event_id != event.redacts);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether the content of a state event is an empty object, which is how state is removed.
+fn has_empty_content(event: &all::StateEvent) -> bool {
+    type_and_content(event)
+        .and_then(|(_, content)| content.as_object().map(|content| content.is_empty()))
+        .unwrap_or(false)
+}
+
+/// The display name of a member event, if it sets a non-empty one.
+fn display_name(event: &all::StateEvent) -> Option<&str> {
+    match event {
+        all::StateEvent::RoomMember(event) => event
+            .content
+            .displayname
+            .as_deref()
+            .filter(|name| !name.is_empty()),
+        _ => None,
+    }
+}
+
+/// The display name of a member event, if the member is joined or invited and so takes part in
+/// disambiguation.
+fn counted_display_name(event: &all::StateEvent) -> Option<&str> {
+    match event {
+        all::StateEvent::RoomMember(member) => match member.content.membership {
+            MembershipState::Join | MembershipState::Invite => display_name(event),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The type and the content of an event as JSON.
+fn type_and_content<T: Serialize>(event: &T) -> Option<(String, Value)> {
+    match serde_json::to_value(event) {
+        Ok(Value::Object(mut fields)) => match (fields.remove("type"), fields.remove("content")) {
+            (Some(Value::String(event_type)), Some(content)) => Some((event_type, content)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl<'a> From<&'a UnreadNotificationsCount> for UnreadCounts {
+    fn from(counts: &'a UnreadNotificationsCount) -> Self {
+        UnreadCounts {
+            highlight_count: counts.highlight_count.unwrap_or(0),
+            notification_count: counts.notification_count.unwrap_or(0),
+        }
+    }
+}
+
+impl Debug for Watcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Watcher")
+            .field("room_id", &self.room_id)
+            .finish()
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the store holding the rooms this client knows about.
+    pub fn store(&self) -> &Store {
+        &self.0.store
+    }
+
+    /// Fetch older events for the given rooms, so that each has at least `n_events` timeline
+    /// events in the store.
+    ///
+    /// This is useful after a sync with a small timeline limit. The history of up to four rooms is
+    /// fetched at the same time. Rooms the store doesn't know about, or whose timeline already
+    /// starts at the creation of the room, are skipped.
+    pub fn backfill_recent(
+        &self,
+        rooms: Vec<RoomId>,
+        n_events: usize,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::sync::get_message_events::{self, Direction};
+
+        let client = self.clone();
+
+        stream::iter_ok(rooms)
+            .map(move |room_id| {
+                let client = client.clone();
+
+                // Homeservers may return fewer events than asked for, so pages are fetched until
+                // there are enough.
+                future::loop_fn(room_id, move |room_id| {
+                    let (known, prev_batch) = match client.store().backfill_position(&room_id) {
+                        Some((known, prev_batch)) if known < n_events => (known, prev_batch),
+                        _ => return Either::A(future::ok(Loop::Break(()))),
+                    };
+                    let data = client.0.clone();
+
+                    Either::B(
+                        get_message_events::call(
+                            client.clone(),
+                            get_message_events::Request {
+                                room_id: room_id.clone(),
+                                from: prev_batch,
+                                to: None,
+                                dir: Direction::Backward,
+                                limit: Some((n_events - known) as u64),
+                            },
+                        )
+                        .map(move |response| {
+                            data.store
+                                .prepend_timeline(&room_id, response.chunk, response.end);
+
+                            Loop::Continue(room_id)
+                        }),
+                    )
+                })
+            })
+            .buffer_unordered(BACKFILL_CONCURRENCY)
+            .for_each(|()| Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, convert::TryFrom, time::SystemTime};
+
+    use ruma_identifiers::RoomId;
+    use serde_json::json;
+
+    use super::Store;
+    use crate::{
+        api::r0::sync::sync_events::{
+            JoinedRoom, LeftRoom, Presence, Response, Rooms, State, Timeline,
+        },
+        scheduler::ScheduledSend,
+    };
+
+    fn timeline() -> Timeline {
+        Timeline {
+            limited: false,
+            prev_batch: "prev".to_owned(),
+            events: Vec::new(),
+        }
+    }
+
+    fn sync(join: HashMap<RoomId, JoinedRoom>, leave: HashMap<RoomId, LeftRoom>) -> Response {
+        Response {
+            next_batch: "next".to_owned(),
+            rooms: Rooms {
+                leave,
+                join,
+                invite: HashMap::new(),
+                knock: HashMap::new(),
+            },
+            presence: Presence { events: Vec::new() },
+            account_data: None,
+            device_lists: Default::default(),
+            to_device: Default::default(),
+            device_one_time_keys_count: None,
+        }
+    }
+
+    fn left(room_id: &RoomId) -> Response {
+        let room = LeftRoom {
+            timeline: timeline(),
+            state: State { events: Vec::new() },
+        };
+
+        sync(
+            HashMap::new(),
+            vec![(room_id.clone(), room)].into_iter().collect(),
+        )
+    }
+
+    fn joined(room_id: &RoomId) -> Response {
+        let room = serde_json::from_value(serde_json::json!({
+            "unread_notifications": {},
+            "timeline": { "limited": false, "prev_batch": "prev", "events": [] },
+            "state": { "events": [] },
+            "account_data": { "events": [] },
+            "ephemeral": { "events": [] },
+        }))
+        .unwrap();
+
+        sync(
+            vec![(room_id.clone(), room)].into_iter().collect(),
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn keeps_left_rooms_it_didnt_know() {
+        let store = Store::default();
+        let room_id = RoomId::try_from("!left:example.org").unwrap();
+
+        store.apply_sync(&left(&room_id), None, SystemTime::now());
+
+        assert_eq!(store.room_ids(), vec![room_id]);
+    }
+
+    #[test]
+    fn removed_rooms_stay_removed_until_joined_again() {
+        let store = Store::default();
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+
+        store.apply_sync(&joined(&room_id), None, SystemTime::now());
+        store.remove_room(&room_id);
+        store.apply_sync(&left(&room_id), Some("next"), SystemTime::now());
+        assert!(store.room_ids().is_empty());
+
+        store.apply_sync(&joined(&room_id), Some("next"), SystemTime::now());
+        assert_eq!(store.room_ids(), vec![room_id]);
+    }
+
+    #[test]
+    fn persisted_scheduled_sends_survive_a_new_store() {
+        let path = std::env::temp_dir().join(format!(
+            "ruma-client-scheduled-sends-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let send = ScheduledSend {
+            id: "send".to_owned(),
+            room_id: RoomId::try_from("!room:example.org").unwrap(),
+            content: serde_json::from_value(json!({ "msgtype": "m.text", "body": "Hi" })).unwrap(),
+            send_at: SystemTime::UNIX_EPOCH,
+        };
+
+        let store = Store::default();
+        store.persist_scheduled_sends(path.clone()).unwrap();
+        store.insert_scheduled_send(send).unwrap();
+
+        let restarted = Store::default();
+        restarted.persist_scheduled_sends(path.clone()).unwrap();
+        assert_eq!(restarted.scheduled_sends().len(), 1);
+
+        assert!(restarted.remove_scheduled_send("send").unwrap().is_some());
+        let again = Store::default();
+        again.persist_scheduled_sends(path.clone()).unwrap();
+        assert!(again.scheduled_sends().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
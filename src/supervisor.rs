@@ -0,0 +1,598 @@
+//! Running background tasks of a client together, restarting the ones that fail.
+
+use std::{
+    cell::RefCell,
+    cmp,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    rc::{Rc, Weak},
+    time::{Duration, Instant},
+};
+
+use futures::{task, Async, Future, IntoFuture, Poll};
+use hyper::client::connect::Connect;
+
+use crate::{
+    dispatch::{panic_message, HandlerFailure},
+    environment::ClockDelay,
+    Client, Clock, Error,
+};
+
+/// How long a restartable task waits before its first restart.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest a restartable task waits before a restart.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// How long a restarted task has to run before it fails for its backoff to start over.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+type TaskFuture = Box<dyn Future<Item = (), Error = Error>>;
+type TaskFactory = Box<dyn FnMut() -> TaskFuture>;
+
+/// A failure of a background task, see `Supervisor::take_failures`.
+#[derive(Debug)]
+pub struct TaskFailure {
+    /// The name the task was spawned with.
+    pub task: String,
+    /// The error the task failed with, or the message it panicked with.
+    pub failure: HandlerFailure<Error>,
+    /// How long until the task is restarted, or `None` if it isn't restartable.
+    pub restart_in: Option<Duration>,
+}
+
+/// The background tasks of a client, created with `Client::supervisor`.
+///
+/// Tasks run once the future returned by `run` is polled. Dropping the supervisor, or that
+/// future, drops all tasks in the order they were spawned. The supervisor isn't part of the
+/// client, so tasks holding clones of the client don't keep each other alive.
+pub struct Supervisor(Rc<Shared>);
+
+/// A handle for spawning tasks on a `Supervisor`, e.g. from within its tasks, that doesn't keep
+/// the supervisor alive.
+#[derive(Clone)]
+pub struct TaskSpawner(Weak<Shared>);
+
+struct Shared {
+    state: RefCell<State>,
+    clock: Rc<dyn Clock>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The tasks that aren't being polled right now, in the order they were spawned.
+    tasks: Vec<Task>,
+    /// The failures that weren't taken yet.
+    failures: Vec<TaskFailure>,
+    /// Whether `Supervisor::shutdown` was called while the future of `Supervisor::run` was
+    /// running.
+    shutdown: bool,
+    /// Whether the supervisor was dropped, after which no tasks can be spawned.
+    closed: bool,
+    /// The future of `Supervisor::run`, if it is running.
+    driver: Option<task::Task>,
+}
+
+struct Task {
+    name: String,
+    state: TaskState,
+    /// Creates the future of the task again, if it is restartable.
+    factory: Option<TaskFactory>,
+    /// How long to wait before the next restart.
+    backoff: Duration,
+    started_at: Instant,
+}
+
+enum TaskState {
+    Running(TaskFuture),
+    /// Waiting to be restarted.
+    Waiting(ClockDelay),
+}
+
+impl Debug for Supervisor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let state = self.0.state.borrow();
+
+        f.debug_struct("Supervisor")
+            .field(
+                "tasks",
+                &state
+                    .tasks
+                    .iter()
+                    .map(|task| &task.name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("failures", &state.failures)
+            .finish()
+    }
+}
+
+impl Debug for TaskSpawner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TaskSpawner").finish()
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Create a supervisor for background tasks of this client, taking the time from the
+    /// client's clock.
+    pub fn supervisor(&self) -> Supervisor {
+        Supervisor(Rc::new(Shared {
+            state: RefCell::new(State::default()),
+            clock: self.0.clock.clone(),
+        }))
+    }
+}
+
+impl Supervisor {
+    /// Run a future as a background task.
+    ///
+    /// If it fails or panics, the failure is recorded, see `take_failures`.
+    pub fn spawn<F>(&self, name: &str, future: F)
+    where
+        F: IntoFuture<Item = (), Error = Error>,
+        F::Future: 'static,
+    {
+        self.0.add(name, Box::new(future.into_future()), None);
+    }
+
+    /// Run the future created by `factory` as a background task, e.g. a sync loop.
+    ///
+    /// Whenever the future fails or panics, the failure is recorded and a new future is created
+    /// after a delay. The delay starts at one second and doubles with each failure up to five
+    /// minutes, and starts over once the task ran for a minute.
+    pub fn spawn_restartable<F, T>(&self, name: &str, factory: F)
+    where
+        F: FnMut() -> T + 'static,
+        T: IntoFuture<Item = (), Error = Error>,
+        T::Future: 'static,
+    {
+        add_restartable(&self.0, name, factory);
+    }
+
+    /// A handle for spawning tasks on this supervisor that doesn't keep it alive.
+    pub fn spawner(&self) -> TaskSpawner {
+        TaskSpawner(Rc::downgrade(&self.0))
+    }
+
+    /// Run the tasks.
+    ///
+    /// The returned future resolves once all tasks finished, or once `shutdown` is called or the
+    /// supervisor is dropped. Dropping it shuts the tasks down as well. Only one such future
+    /// should run at a time.
+    pub fn run(&self) -> impl Future<Item = (), Error = Error> {
+        Run(self.0.clone())
+    }
+
+    /// Stop all tasks, dropping them in the order they were spawned, and resolve the future
+    /// returned by `run`.
+    ///
+    /// A task that calls this is dropped right after its poll returns.
+    pub fn shutdown(&self) {
+        self.0.shutdown();
+    }
+
+    /// The failures of tasks since the last call, oldest first.
+    pub fn take_failures(&self) -> Vec<TaskFailure> {
+        mem::take(&mut self.0.state.borrow_mut().failures)
+    }
+
+    /// The names of the tasks that aren't being polled right now.
+    #[cfg(test)]
+    pub(crate) fn task_names(&self) -> Vec<String> {
+        let state = self.0.state.borrow();
+
+        state.tasks.iter().map(|task| task.name.clone()).collect()
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.0.state.borrow_mut().closed = true;
+        self.0.shutdown();
+    }
+}
+
+impl TaskSpawner {
+    /// Run a future as a background task, see `Supervisor::spawn`.
+    ///
+    /// Returns `false` and drops the future if the supervisor was dropped.
+    pub fn spawn<F>(&self, name: &str, future: F) -> bool
+    where
+        F: IntoFuture<Item = (), Error = Error>,
+        F::Future: 'static,
+    {
+        match self.0.upgrade() {
+            Some(shared) => shared.add(name, Box::new(future.into_future()), None),
+            None => false,
+        }
+    }
+
+    /// Run the future created by `factory` as a restartable background task, see
+    /// `Supervisor::spawn_restartable`.
+    ///
+    /// Returns `false` if the supervisor was dropped.
+    pub fn spawn_restartable<F, T>(&self, name: &str, factory: F) -> bool
+    where
+        F: FnMut() -> T + 'static,
+        T: IntoFuture<Item = (), Error = Error>,
+        T::Future: 'static,
+    {
+        match self.0.upgrade() {
+            Some(shared) => add_restartable(&shared, name, factory),
+            None => false,
+        }
+    }
+}
+
+fn add_restartable<F, T>(shared: &Shared, name: &str, mut factory: F) -> bool
+where
+    F: FnMut() -> T + 'static,
+    T: IntoFuture<Item = (), Error = Error>,
+    T::Future: 'static,
+{
+    let future: TaskFuture = Box::new(factory().into_future());
+    let factory: TaskFactory = Box::new(move || Box::new(factory().into_future()));
+
+    shared.add(name, future, Some(factory))
+}
+
+impl Shared {
+    fn add(&self, name: &str, future: TaskFuture, factory: Option<TaskFactory>) -> bool {
+        let mut state = self.state.borrow_mut();
+
+        if state.closed {
+            return false;
+        }
+
+        state.tasks.push(Task {
+            name: name.to_owned(),
+            state: TaskState::Running(future),
+            factory,
+            backoff: INITIAL_BACKOFF,
+            started_at: self.clock.instant(),
+        });
+
+        if let Some(ref driver) = state.driver {
+            driver.notify();
+        }
+
+        true
+    }
+
+    fn shutdown(&self) {
+        let tasks = {
+            let mut state = self.state.borrow_mut();
+
+            if let Some(driver) = state.driver.take() {
+                state.shutdown = true;
+                driver.notify();
+            }
+
+            mem::take(&mut state.tasks)
+        };
+
+        // Dropped outside the borrow, because dropping a task may spawn or shut down tasks.
+        drop(tasks);
+    }
+}
+
+impl Task {
+    /// Poll the task, restarting it if it is due. Returns whether the task still runs, and its
+    /// failure, if it failed.
+    fn poll(&mut self, clock: &dyn Clock) -> (bool, Option<TaskFailure>) {
+        loop {
+            let failure = match self.state {
+                TaskState::Waiting(ref mut delay) => {
+                    if let Ok(Async::NotReady) = delay.poll() {
+                        return (true, None);
+                    }
+
+                    match self.factory {
+                        Some(ref mut factory) => {
+                            self.state = TaskState::Running(factory());
+                            self.started_at = clock.instant();
+                            continue;
+                        }
+                        None => return (false, None),
+                    }
+                }
+                TaskState::Running(ref mut future) => {
+                    match panic::catch_unwind(AssertUnwindSafe(|| future.poll())) {
+                        Ok(Ok(Async::NotReady)) => return (true, None),
+                        Ok(Ok(Async::Ready(()))) => return (false, None),
+                        Ok(Err(error)) => HandlerFailure::Error(error),
+                        Err(payload) => HandlerFailure::Panic(panic_message(payload)),
+                    }
+                }
+            };
+
+            if self.factory.is_none() {
+                let failure = TaskFailure {
+                    task: self.name.clone(),
+                    failure,
+                    restart_in: None,
+                };
+
+                return (false, Some(failure));
+            }
+
+            let now = clock.instant();
+
+            if now.saturating_duration_since(self.started_at) >= STABLE_AFTER {
+                self.backoff = INITIAL_BACKOFF;
+            }
+
+            let restart_in = self.backoff;
+            self.backoff = cmp::min(self.backoff * 2, MAX_BACKOFF);
+            self.state = TaskState::Waiting(clock.delay_until(now + restart_in));
+
+            // Polling the delay registers the task to be woken up for the restart.
+            let (running, _) = self.poll(clock);
+            let failure = TaskFailure {
+                task: self.name.clone(),
+                failure,
+                restart_in: Some(restart_in),
+            };
+
+            return (running, Some(failure));
+        }
+    }
+}
+
+/// The future of `Supervisor::run`.
+struct Run(Rc<Shared>);
+
+impl Future for Run {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        let shared = &self.0;
+
+        let tasks = {
+            let mut state = shared.state.borrow_mut();
+
+            if state.shutdown || state.closed {
+                return Ok(Async::Ready(()));
+            }
+
+            state.driver = Some(task::current());
+            mem::take(&mut state.tasks)
+        };
+
+        // The tasks are polled without borrowing the state, so they can spawn new tasks.
+        let mut running = Vec::with_capacity(tasks.len());
+        let mut failures = Vec::new();
+
+        for mut task in tasks {
+            let (still_running, failure) = task.poll(&*shared.clock);
+
+            if let Some(failure) = failure {
+                log::error!("background task {} failed: {:?}", failure.task, failure);
+                failures.push(failure);
+            }
+            if still_running {
+                running.push(task);
+            }
+        }
+
+        let mut state = shared.state.borrow_mut();
+        state.failures.append(&mut failures);
+
+        if state.shutdown {
+            drop(state);
+            drop(running);
+
+            return Ok(Async::Ready(()));
+        }
+
+        // Tasks spawned while polling haven't been polled yet.
+        if !state.tasks.is_empty() {
+            task::current().notify();
+        }
+
+        running.append(&mut state.tasks);
+        state.tasks = running;
+
+        if state.tasks.is_empty() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let tasks = {
+            let mut state = self.0.state.borrow_mut();
+
+            state.driver = None;
+            state.shutdown = false;
+            mem::take(&mut state.tasks)
+        };
+
+        // Dropped outside the borrow, because dropping a task may spawn or shut down tasks.
+        drop(tasks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        sync::Arc,
+        time::Duration,
+    };
+
+    use futures::{
+        executor::{self, Notify, Spawn},
+        future, Async, Future, Poll,
+    };
+
+    use super::{Shared, State, Supervisor};
+    use crate::{environment::TestClock, Error, HandlerFailure};
+
+    struct NoNotify;
+
+    impl Notify for NoNotify {
+        fn notify(&self, _: usize) {}
+    }
+
+    fn supervisor(clock: &TestClock) -> Supervisor {
+        Supervisor(Rc::new(Shared {
+            state: RefCell::new(State::default()),
+            clock: Rc::new(clock.clone()),
+        }))
+    }
+
+    fn poll<F: Future>(run: &mut Spawn<F>) -> Poll<F::Item, F::Error> {
+        run.poll_future_notify(&Arc::new(NoNotify), 0)
+    }
+
+    /// Records when it is dropped.
+    struct DropRecorder(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    impl Drop for DropRecorder {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn run_resolves_once_tasks_finished() {
+        let clock = TestClock::new();
+        let supervisor = supervisor(&clock);
+        let ran = Rc::new(Cell::new(0));
+
+        for _ in 0..2 {
+            let ran = ran.clone();
+            supervisor.spawn(
+                "task",
+                future::lazy(move || {
+                    ran.set(ran.get() + 1);
+                    Ok(())
+                }),
+            );
+        }
+
+        let mut run = executor::spawn(supervisor.run());
+        assert_eq!(poll(&mut run).unwrap(), Async::Ready(()));
+        assert_eq!(ran.get(), 2);
+        assert!(supervisor.take_failures().is_empty());
+    }
+
+    #[test]
+    fn restartable_tasks_back_off() {
+        let clock = TestClock::new();
+        let supervisor = supervisor(&clock);
+        let starts = Rc::new(Cell::new(0));
+        let task_starts = starts.clone();
+
+        supervisor.spawn_restartable("flaky", move || {
+            task_starts.set(task_starts.get() + 1);
+            Err::<(), _>(Error::SyncDisabled)
+        });
+
+        let mut run = executor::spawn(supervisor.run());
+        assert_eq!(poll(&mut run).unwrap(), Async::NotReady);
+        assert_eq!(starts.get(), 1);
+
+        let failures = supervisor.take_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].task, "flaky");
+        assert_eq!(failures[0].restart_in, Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(poll(&mut run).unwrap(), Async::NotReady);
+        assert_eq!(starts.get(), 2);
+        assert_eq!(
+            supervisor.take_failures()[0].restart_in,
+            Some(Duration::from_secs(2))
+        );
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(poll(&mut run).unwrap(), Async::NotReady);
+        assert_eq!(starts.get(), 2);
+    }
+
+    #[test]
+    fn panics_are_recorded() {
+        let clock = TestClock::new();
+        let supervisor = supervisor(&clock);
+
+        supervisor.spawn(
+            "panicking",
+            future::lazy(|| -> Result<(), Error> { panic!("oops") }),
+        );
+
+        let mut run = executor::spawn(supervisor.run());
+        assert_eq!(poll(&mut run).unwrap(), Async::Ready(()));
+
+        let failures = supervisor.take_failures();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].restart_in.is_none());
+        match failures[0].failure {
+            HandlerFailure::Panic(ref message) => assert_eq!(message, "oops"),
+            ref failure => panic!("unexpected failure {:?}", failure),
+        }
+    }
+
+    #[test]
+    fn dropping_the_supervisor_drops_tasks_in_spawn_order() {
+        let clock = TestClock::new();
+        let supervisor = supervisor(&clock);
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+
+        for name in &["first", "second", "third"] {
+            let recorder = DropRecorder(name, dropped.clone());
+            supervisor.spawn(
+                name,
+                future::empty::<(), Error>().map(move |()| drop(recorder)),
+            );
+        }
+
+        let spawner = supervisor.spawner();
+        let mut run = executor::spawn(supervisor.run());
+        assert_eq!(poll(&mut run).unwrap(), Async::NotReady);
+
+        drop(supervisor);
+        assert_eq!(*dropped.borrow(), vec!["first", "second", "third"]);
+        assert_eq!(poll(&mut run).unwrap(), Async::Ready(()));
+        assert!(!spawner.spawn("late", Ok(())));
+    }
+
+    #[test]
+    fn tasks_can_spawn_tasks() {
+        let clock = TestClock::new();
+        let supervisor = supervisor(&clock);
+        let spawner = supervisor.spawner();
+        let ran = Rc::new(Cell::new(false));
+        let child_ran = ran.clone();
+
+        supervisor.spawn(
+            "parent",
+            future::lazy(move || {
+                spawner.spawn(
+                    "child",
+                    future::lazy(move || {
+                        child_ran.set(true);
+                        Ok(())
+                    }),
+                );
+                Ok(())
+            }),
+        );
+
+        let mut run = executor::spawn(supervisor.run());
+        assert_eq!(poll(&mut run).unwrap(), Async::NotReady);
+        assert_eq!(poll(&mut run).unwrap(), Async::Ready(()));
+        assert!(ran.get());
+    }
+}
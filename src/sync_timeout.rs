@@ -0,0 +1,270 @@
+//! Choosing how long sync requests wait for new events.
+
+use std::{cmp, time::Duration};
+
+use http::StatusCode;
+use hyper::client::connect::Connect;
+use ruma_api::Error as RumaApiError;
+
+use crate::{Client, Error};
+
+/// The timeout of the first sync requests.
+const INITIAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The shortest timeout the tuning picks.
+const MIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The longest timeout the tuning picks.
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How much the timeout grows after `GROW_AFTER` requests in a row waited for the whole timeout.
+const GROW_STEP: Duration = Duration::from_secs(10);
+
+/// How many requests in a row have to wait for the whole timeout before it grows.
+const GROW_AFTER: u32 = 10;
+
+/// How long before an intermediary was seen cutting off requests they have to be answered.
+const SAFETY_MARGIN: Duration = Duration::from_secs(5);
+
+/// How many requests have to be cut off before an intermediary is assumed to cut them off.
+const CONFIRM_AFTER: u32 = 2;
+
+/// How many requests in a row have to wait for the whole timeout below a learned cutoff before
+/// it's forgotten, so that the timeout grows again and finds out whether it still applies.
+const REPROBE_AFTER: u32 = 60;
+
+/// The timeout of sync requests, adjusted to how the homeserver and the network in between
+/// behave.
+///
+/// The timeout grows slowly while requests keep waiting for all of it, since longer requests
+/// mean fewer of them. When a request fails with a connection error or a gateway error after a
+/// while, more than once, an intermediary like a reverse proxy is assumed to cut off requests
+/// taking that long, and the timeout shrinks below that, minus the time requests take on top of
+/// their timeout. A request that outlives the cutoff disproves it, and the cutoff is forgotten
+/// after a while, in case the intermediary went away.
+#[derive(Debug)]
+pub(crate) struct SyncTimeout {
+    current: Duration,
+    pinned: Option<Duration>,
+    /// The average time requests waiting for the whole timeout took longer than it.
+    overhead: Duration,
+    /// The time after which requests are assumed to be cut off, if they are.
+    cutoff: Option<Duration>,
+    /// The shortest time after which a request was cut off since the last cutoff was learned,
+    /// and how many were.
+    suspected_cutoff: Option<(Duration, u32)>,
+    /// How many requests in a row waited for the whole timeout.
+    full_polls: u32,
+    /// How many requests in a row waited for the whole timeout since the cutoff was learned.
+    polls_below_cutoff: u32,
+}
+
+impl Default for SyncTimeout {
+    fn default() -> Self {
+        SyncTimeout {
+            current: INITIAL_TIMEOUT,
+            pinned: None,
+            overhead: Duration::from_secs(0),
+            cutoff: None,
+            suspected_cutoff: None,
+            full_polls: 0,
+            polls_below_cutoff: 0,
+        }
+    }
+}
+
+impl SyncTimeout {
+    /// The timeout for the next sync request.
+    pub(crate) fn get(&self) -> Duration {
+        self.pinned.unwrap_or(self.current)
+    }
+
+    /// Learn from a successful sync request with the given timeout that took `elapsed`.
+    pub(crate) fn record_response(&mut self, timeout: Duration, elapsed: Duration) {
+        // A request that returned early because of new events says nothing about the timeout.
+        if elapsed < timeout {
+            return;
+        }
+
+        self.overhead = (self.overhead * 3 + (elapsed - timeout)) / 4;
+
+        // Nothing cuts off requests that take as long as this one did.
+        if let Some((suspected, _)) = self.suspected_cutoff {
+            if elapsed >= suspected {
+                self.suspected_cutoff = None;
+            }
+        }
+
+        if let Some(cutoff) = self.cutoff {
+            self.polls_below_cutoff += 1;
+
+            if elapsed >= cutoff || self.polls_below_cutoff >= REPROBE_AFTER {
+                self.cutoff = None;
+                self.polls_below_cutoff = 0;
+            }
+        }
+
+        self.full_polls += 1;
+
+        if self.full_polls >= GROW_AFTER {
+            self.full_polls = 0;
+            self.current = cmp::min(self.current + GROW_STEP, self.limit());
+        }
+    }
+
+    /// Learn from a sync request that failed with `error` after `elapsed`.
+    pub(crate) fn record_error(&mut self, error: &Error, elapsed: Duration) {
+        // Errors right away are failures to connect rather than cut-off requests.
+        if elapsed < MIN_TIMEOUT || !is_cutoff(error) {
+            return;
+        }
+
+        self.full_polls = 0;
+
+        let (suspected, observations) = match self.suspected_cutoff {
+            Some((suspected, observations)) => (cmp::min(suspected, elapsed), observations + 1),
+            None => (elapsed, 1),
+        };
+
+        // A single failure may just be the network, so the timeout only shrinks on repetition.
+        if observations < CONFIRM_AFTER {
+            self.suspected_cutoff = Some((suspected, observations));
+            return;
+        }
+
+        self.suspected_cutoff = None;
+        self.cutoff = Some(match self.cutoff {
+            Some(cutoff) => cmp::min(cutoff, suspected),
+            None => suspected,
+        });
+        self.polls_below_cutoff = 0;
+        self.current = cmp::min(self.current, self.limit());
+    }
+
+    /// The longest timeout that is safe from being cut off.
+    fn limit(&self) -> Duration {
+        let limit = match self.cutoff {
+            Some(cutoff) => cutoff
+                .checked_sub(SAFETY_MARGIN + self.overhead)
+                .unwrap_or(MIN_TIMEOUT),
+            None => MAX_TIMEOUT,
+        };
+
+        cmp::max(cmp::min(limit, MAX_TIMEOUT), MIN_TIMEOUT)
+    }
+}
+
+/// Whether an error is what an intermediary cutting off a request looks like.
+fn is_cutoff(error: &Error) -> bool {
+    match error {
+        Error::Hyper(_) | Error::RumaApi(RumaApiError::Hyper(_)) => true,
+        Error::RumaApi(RumaApiError::StatusCode(status)) => {
+            *status == StatusCode::BAD_GATEWAY || *status == StatusCode::GATEWAY_TIMEOUT
+        }
+        _ => false,
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// The time the next sync request will wait for new events.
+    ///
+    /// Unless it's pinned with `pin_sync_timeout`, the timeout is tuned to the homeserver and
+    /// the network: it starts at 30 seconds and grows while requests keep waiting for all of it,
+    /// and shrinks when something between the client and the homeserver cuts off requests that
+    /// take too long.
+    pub fn sync_timeout(&self) -> Duration {
+        self.0.sync_timeout.borrow().get()
+    }
+
+    /// Use the given timeout for all sync requests instead of tuning it, or tune it again with
+    /// `None`.
+    pub fn pin_sync_timeout(&self, timeout: Option<Duration>) {
+        self.0.sync_timeout.borrow_mut().pinned = timeout;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::StatusCode;
+    use ruma_api::Error as RumaApiError;
+
+    use super::{SyncTimeout, GROW_AFTER, INITIAL_TIMEOUT, REPROBE_AFTER};
+    use crate::Error;
+
+    fn gateway_timeout() -> Error {
+        Error::RumaApi(RumaApiError::StatusCode(StatusCode::GATEWAY_TIMEOUT))
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn grows_while_requests_wait_for_the_whole_timeout() {
+        let mut timeout = SyncTimeout::default();
+
+        for _ in 0..GROW_AFTER {
+            timeout.record_response(timeout.get(), timeout.get());
+        }
+
+        assert_eq!(timeout.get(), INITIAL_TIMEOUT + secs(10));
+    }
+
+    #[test]
+    fn a_single_cutoff_is_not_enough_to_shrink() {
+        let mut timeout = SyncTimeout::default();
+
+        timeout.record_error(&gateway_timeout(), secs(20));
+        assert_eq!(timeout.get(), INITIAL_TIMEOUT);
+
+        timeout.record_error(&gateway_timeout(), secs(22));
+        assert_eq!(timeout.get(), secs(15));
+    }
+
+    #[test]
+    fn ignores_quick_and_unrelated_failures() {
+        let mut timeout = SyncTimeout::default();
+
+        for _ in 0..5 {
+            timeout.record_error(&gateway_timeout(), secs(1));
+            timeout.record_error(&Error::AuthenticationRequired, secs(20));
+        }
+
+        assert_eq!(timeout.get(), INITIAL_TIMEOUT);
+    }
+
+    #[test]
+    fn a_longer_request_disproves_a_suspected_cutoff() {
+        let mut timeout = SyncTimeout::default();
+
+        timeout.record_error(&gateway_timeout(), secs(20));
+        timeout.record_response(secs(30), secs(30));
+        timeout.record_error(&gateway_timeout(), secs(20));
+
+        assert_eq!(timeout.get(), INITIAL_TIMEOUT);
+    }
+
+    #[test]
+    fn forgets_the_cutoff_after_a_while() {
+        let mut timeout = SyncTimeout::default();
+
+        timeout.record_error(&gateway_timeout(), secs(20));
+        timeout.record_error(&gateway_timeout(), secs(20));
+        assert_eq!(timeout.get(), secs(15));
+
+        for _ in 0..REPROBE_AFTER {
+            timeout.record_response(timeout.get(), timeout.get());
+        }
+        assert!(timeout.cutoff.is_none());
+
+        for _ in 0..GROW_AFTER {
+            timeout.record_response(timeout.get(), timeout.get());
+        }
+        assert!(timeout.get() > secs(15));
+    }
+}
@@ -0,0 +1,136 @@
+//! Tagging rooms, e.g. as favourites.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use futures::future::{self, Future};
+use hyper::client::connect::Connect;
+use ruma_identifiers::RoomId;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::{api::r0::tag::TagInfo, Client, Error, Room};
+
+/// The tag of rooms the user marked as favourites.
+pub const FAVOURITE_TAG: &str = "m.favourite";
+
+/// The tag of rooms the user wants to see after all other rooms.
+pub const LOW_PRIORITY_TAG: &str = "m.lowpriority";
+
+/// The type of the room account data event holding the tags of a room.
+pub(crate) const TAG_EVENT_TYPE: &str = "m.tag";
+
+/// The content of an `m.tag` account data event.
+#[derive(Deserialize)]
+struct TagEventContent {
+    #[serde(default)]
+    tags: HashMap<String, TagInfo>,
+}
+
+/// Parse the tags from the content of an `m.tag` account data event.
+pub(crate) fn tags_from_content(content: &Value) -> HashMap<String, TagInfo> {
+    serde_json::from_value::<TagEventContent>(content.clone())
+        .map(|content| content.tags)
+        .unwrap_or_default()
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the logged-in user's tags for this room from the homeserver.
+    ///
+    /// The tags received with sync responses are available from `Store::tags` without a request.
+    pub fn tags(&self) -> impl Future<Item = HashMap<String, TagInfo>, Error = Error> {
+        use crate::api::r0::tag::get_tags;
+
+        let request = self
+            .client()
+            .logged_in_user_id()
+            .map(|user_id| get_tags::Request {
+                user_id,
+                room_id: self.room_id().clone(),
+            });
+        let client = self.client().clone();
+
+        future::result(request)
+            .and_then(move |request| get_tags::call(client, request))
+            .map(|response| response.tags)
+    }
+
+    /// Add a tag to this room, or change its order.
+    ///
+    /// `order` is the position of the room among the rooms with the same tag, between 0 and 1.
+    /// Tags starting with `m.` are reserved by the specification, e.g. `FAVOURITE_TAG`; custom
+    /// tags should use a namespace like `u.`.
+    pub fn set_tag(
+        &self,
+        tag: String,
+        order: Option<f64>,
+    ) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::tag::create_tag;
+
+        let request = self
+            .client()
+            .logged_in_user_id()
+            .map(|user_id| create_tag::Request {
+                user_id,
+                room_id: self.room_id().clone(),
+                tag,
+                tag_info: TagInfo { order },
+            });
+        let client = self.client().clone();
+
+        future::result(request)
+            .and_then(move |request| create_tag::call(client, request))
+            .map(|_| ())
+    }
+
+    /// Remove a tag from this room.
+    pub fn remove_tag(&self, tag: String) -> impl Future<Item = (), Error = Error> {
+        use crate::api::r0::tag::delete_tag;
+
+        let request = self
+            .client()
+            .logged_in_user_id()
+            .map(|user_id| delete_tag::Request {
+                user_id,
+                room_id: self.room_id().clone(),
+                tag,
+            });
+        let client = self.client().clone();
+
+        future::result(request)
+            .and_then(move |request| delete_tag::call(client, request))
+            .map(|_| ())
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// The IDs of the rooms in the `Store` with the given tag, ordered by the order of the tag.
+    ///
+    /// Rooms without an order come last.
+    pub fn tagged_rooms(&self, tag: &str) -> Vec<RoomId> {
+        let snapshot = self.store().snapshot();
+        let mut rooms: Vec<_> = snapshot
+            .room_ids()
+            .filter_map(|room_id| {
+                snapshot
+                    .tags(room_id)
+                    .remove(tag)
+                    .map(|info| (room_id.clone(), info.order))
+            })
+            .collect();
+
+        rooms.sort_by(|(_, a), (_, b)| match (a, b) {
+            (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+
+        rooms.into_iter().map(|(room_id, _)| room_id).collect()
+    }
+}
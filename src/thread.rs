@@ -0,0 +1,120 @@
+//! Threads of messages.
+
+use std::{cell::RefCell, rc::Rc};
+
+use futures::{
+    future::{Future, IntoFuture},
+    stream::Stream,
+};
+use hyper::client::connect::Connect;
+use ruma_events::{
+    collections::all,
+    room::message::{MessageEventContent, MessageType, TextMessageEventContent},
+    EventType,
+};
+use ruma_identifiers::EventId;
+use serde::Serialize;
+
+use crate::{
+    events::RoomEventFields,
+    message::{thread_content, RelationType},
+    Error, Room,
+};
+
+/// A view of the thread started by an event, created with `Room::thread`.
+#[derive(Debug)]
+pub struct Thread<C: Connect> {
+    room: Room<C>,
+    root_event_id: EventId,
+    /// The latest event of the thread this view knows about.
+    latest_event_id: Rc<RefCell<Option<EventId>>>,
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Get a view of the thread started by the event `root_event_id`.
+    pub fn thread(&self, root_event_id: EventId) -> Thread<C> {
+        Thread {
+            room: self.clone(),
+            root_event_id,
+            latest_event_id: Rc::new(RefCell::new(None)),
+        }
+    }
+}
+
+impl<C> Thread<C>
+where
+    C: Connect + 'static,
+{
+    /// Get the ID of the event that started the thread.
+    pub fn root_event_id(&self) -> &EventId {
+        &self.root_event_id
+    }
+
+    /// Send a plain text message to the thread.
+    pub fn send_text(&self, body: String) -> impl Future<Item = EventId, Error = Error> {
+        self.send_message(&MessageEventContent::Text(TextMessageEventContent {
+            body,
+            msgtype: MessageType::Text,
+        }))
+    }
+
+    /// Send a message to the thread.
+    ///
+    /// `content` must serialize to the content of an `m.room.message` event, e.g. it can be a
+    /// `MessageEventContent` or a `FormattedMessageEventContent`. Clients without support for
+    /// threads show the message as a reply to the latest event of the thread known to this
+    /// view, or to the root event.
+    pub fn send_message<T>(&self, content: &T) -> impl Future<Item = EventId, Error = Error>
+    where
+        T: Serialize,
+    {
+        let room = self.room.clone();
+        let latest_event_id = self.latest_event_id.clone();
+        let in_reply_to = latest_event_id
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| self.root_event_id.clone());
+
+        thread_content(&self.root_event_id, &in_reply_to, content)
+            .into_future()
+            .and_then(move |content| room.send_custom_message(EventType::RoomMessage, &content))
+            .map(move |event_id| {
+                *latest_event_id.borrow_mut() = Some(event_id.clone());
+                event_id
+            })
+    }
+
+    /// The events of the thread, newest first, not including the root event.
+    ///
+    /// The events are fetched page by page from the homeserver as the stream is polled.
+    pub fn events(&self) -> impl Stream<Item = all::RoomEvent, Error = Error> {
+        let latest_event_id = self.latest_event_id.clone();
+        let mut first = true;
+
+        self.room
+            .relations(self.root_event_id.clone(), Some(RelationType::Thread), None)
+            .inspect(move |event| {
+                if first {
+                    first = false;
+
+                    let mut latest = latest_event_id.borrow_mut();
+                    if latest.is_none() {
+                        *latest = Some(event.event_id().clone());
+                    }
+                }
+            })
+    }
+}
+
+impl<C: Connect> Clone for Thread<C> {
+    fn clone(&self) -> Thread<C> {
+        Thread {
+            room: self.room.clone(),
+            root_event_id: self.root_event_id.clone(),
+            latest_event_id: self.latest_event_id.clone(),
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! Typing notifications.
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{Debug, Formatter, Result as FmtResult},
+    rc::Rc,
+    time::Duration,
+};
+
+use futures::{
+    future::{self, Either, Future, Loop},
+    task::{self, Task},
+    Async, Poll,
+};
+use hyper::client::connect::Connect;
+use ruma_identifiers::{RoomId, UserId};
+
+use crate::{Client, Error, Room, TaskSpawner};
+
+/// How long the homeserver shows the logged-in user as typing after each notification.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often an active typing notification is sent again, so that it doesn't expire in between.
+const TYPING_REFRESH_INTERVAL: Duration = Duration::from_secs(25);
+
+/// Shows the logged-in user as typing in a room while it's alive, see `Room::typing_guard`.
+pub struct TypingGuard {
+    state: Rc<TypingState>,
+    /// Sends the notification that the user stopped typing.
+    stop: Option<Box<dyn FnOnce()>>,
+}
+
+/// The state of an active typing notification, shared with the future refreshing it.
+#[derive(Debug, Default)]
+pub(crate) struct TypingState {
+    /// Whether the notification was stopped or replaced by a later one.
+    ended: Cell<bool>,
+    /// The task to wake up once the notification ended.
+    task: RefCell<Option<Task>>,
+    /// Whether a refresh of the notification is being sent.
+    sending: Cell<bool>,
+    /// The tasks to wake up once the refresh being sent finished.
+    waiting: RefCell<Vec<Task>>,
+}
+
+/// A future resolving once a typing notification ended.
+struct Ended(Rc<TypingState>);
+
+/// A future resolving once no refresh of a typing notification is being sent, so that the
+/// notification that the user stopped typing can't reach the homeserver before it.
+struct Idle(Rc<TypingState>);
+
+/// Marks a refresh of a typing notification as being sent while it's alive.
+struct Sending(Rc<TypingState>);
+
+impl TypingState {
+    /// Mark the notification as ended and wake up the future refreshing it.
+    fn end(&self) {
+        self.ended.set(true);
+
+        if let Some(task) = self.task.borrow_mut().take() {
+            task.notify();
+        }
+    }
+}
+
+impl Sending {
+    fn new(state: Rc<TypingState>) -> Self {
+        state.sending.set(true);
+
+        Sending(state)
+    }
+}
+
+impl Drop for Sending {
+    fn drop(&mut self) {
+        self.0.sending.set(false);
+
+        for task in self.0.waiting.borrow_mut().drain(..) {
+            task.notify();
+        }
+    }
+}
+
+impl Debug for TypingGuard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TypingGuard")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl Drop for TypingGuard {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
+        }
+
+        self.state.end();
+    }
+}
+
+impl Future for Ended {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        if self.0.ended.get() {
+            return Ok(Async::Ready(()));
+        }
+
+        *self.0.task.borrow_mut() = Some(task::current());
+
+        Ok(Async::NotReady)
+    }
+}
+
+impl Future for Idle {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<(), Error> {
+        if !self.0.sending.get() {
+            return Ok(Async::Ready(()));
+        }
+
+        self.0.waiting.borrow_mut().push(task::current());
+
+        Ok(Async::NotReady)
+    }
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Tell the other members of this room whether the logged-in user is typing.
+    ///
+    /// With `true`, the notification is sent again before it expires until this method is called
+    /// with `false` for the same room, at which point the returned future resolves right away.
+    /// The refreshes wait on the client's `Clock`. With `false`, the future resolves once the
+    /// notification was sent, which happens only after a refresh still being sent finished.
+    ///
+    /// The users currently typing in a room are available from `Store::typing_users`.
+    pub fn typing_notice(&self, typing: bool) -> impl Future<Item = (), Error = Error> {
+        let user_id = match self.client().logged_in_user_id() {
+            Ok(user_id) => user_id,
+            Err(error) => return Either::A(future::err(error)),
+        };
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+
+        if !typing {
+            let stopped = stop_typing(&client, &room_id);
+
+            return Either::B(Either::A(send_stop(client, room_id, user_id, stopped)));
+        }
+
+        let state = start_typing(&client, &room_id);
+
+        Either::B(Either::B(refresh_typing(client, room_id, user_id, state)))
+    }
+
+    /// Show the logged-in user as typing in this room for as long as the returned guard is
+    /// alive.
+    ///
+    /// The notification is sent, and sent again before it expires, by a task spawned with
+    /// `spawner`, e.g. the `TaskSpawner` of the application's `Supervisor`. Dropping the guard
+    /// ends that task and spawns another one sending the notification that the user stopped
+    /// typing. If the supervisor is gone by then, the homeserver stops showing the user as typing
+    /// once the last notification expires.
+    ///
+    /// A later `typing_notice` or `typing_guard` for the same room takes over from the guard.
+    pub fn typing_guard(&self, spawner: &TaskSpawner) -> Result<TypingGuard, Error> {
+        let user_id = self.client().logged_in_user_id()?;
+        let client = self.client().clone();
+        let room_id = self.room_id().clone();
+        let state = start_typing(&client, &room_id);
+
+        spawner.spawn(
+            "typing notifications",
+            refresh_typing(
+                client.clone(),
+                room_id.clone(),
+                user_id.clone(),
+                state.clone(),
+            ),
+        );
+
+        let spawner = spawner.clone();
+        let guarded = state.clone();
+        let stop = move || {
+            // Nothing to stop if a later notification took over.
+            if guarded.ended.get() {
+                return;
+            }
+
+            stop_typing(&client, &room_id);
+            spawner.spawn(
+                "typing notifications",
+                send_stop(client, room_id, user_id, Some(guarded)),
+            );
+        };
+
+        Ok(TypingGuard {
+            state,
+            stop: Some(Box::new(stop)),
+        })
+    }
+}
+
+/// Make a new typing notification the active one of a room, ending the previous one.
+fn start_typing<C>(client: &Client<C>, room_id: &RoomId) -> Rc<TypingState>
+where
+    C: Connect,
+{
+    let state = Rc::new(TypingState::default());
+    let previous = client
+        .0
+        .typing
+        .borrow_mut()
+        .insert(room_id.clone(), state.clone());
+
+    if let Some(previous) = previous {
+        previous.end();
+    }
+
+    state
+}
+
+/// End the active typing notification of a room, if any, and return it.
+fn stop_typing<C>(client: &Client<C>, room_id: &RoomId) -> Option<Rc<TypingState>>
+where
+    C: Connect,
+{
+    let active = client.0.typing.borrow_mut().remove(room_id);
+
+    if let Some(active) = &active {
+        active.end();
+    }
+
+    active
+}
+
+/// Send a typing notification again before it expires, until it ended.
+fn refresh_typing<C>(
+    client: Client<C>,
+    room_id: RoomId,
+    user_id: UserId,
+    state: Rc<TypingState>,
+) -> impl Future<Item = (), Error = Error>
+where
+    C: Connect + 'static,
+{
+    future::loop_fn((), move |()| {
+        let client = client.clone();
+
+        if state.ended.get() {
+            return Either::A(future::ok(Loop::Break(())));
+        }
+
+        let refresh_at = client.clock().instant() + TYPING_REFRESH_INTERVAL;
+        let ended = Ended(state.clone());
+        let sending = Sending::new(state.clone());
+
+        Either::B(
+            send_typing(&client, room_id.clone(), user_id.clone(), true)
+                .then(move |result| {
+                    drop(sending);
+                    result
+                })
+                .and_then(move |()| {
+                    client
+                        .clock()
+                        .delay_until(refresh_at)
+                        .select2(ended)
+                        .map(|_| Loop::Continue(()))
+                        .map_err(|error| error.split().0)
+                }),
+        )
+    })
+}
+
+/// Send the notification that the user stopped typing once the refresh of the stopped
+/// notification being sent, if any, finished.
+fn send_stop<C>(
+    client: Client<C>,
+    room_id: RoomId,
+    user_id: UserId,
+    stopped: Option<Rc<TypingState>>,
+) -> impl Future<Item = (), Error = Error>
+where
+    C: Connect + 'static,
+{
+    let idle = match stopped {
+        Some(state) => Either::A(Idle(state)),
+        None => Either::B(future::ok(())),
+    };
+
+    idle.and_then(move |()| send_typing(&client, room_id, user_id, false))
+}
+
+/// Send a single typing notification.
+fn send_typing<C>(
+    client: &Client<C>,
+    room_id: RoomId,
+    user_id: UserId,
+    typing: bool,
+) -> impl Future<Item = (), Error = Error>
+where
+    C: Connect + 'static,
+{
+    use crate::api::r0::typing::create_typing_event;
+
+    let timeout = if typing {
+        Some(TYPING_TIMEOUT.as_millis() as u64)
+    } else {
+        None
+    };
+
+    create_typing_event::call(
+        client.clone(),
+        create_typing_event::Request {
+            room_id,
+            timeout,
+            typing,
+            user_id,
+        },
+    )
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use futures::Future;
+    use hyper::client::HttpConnector;
+    use ruma_identifiers::{RoomId, UserId};
+    use url::Url;
+
+    use futures::{future, Async};
+
+    use super::{start_typing, stop_typing, Ended, Idle, Sending};
+    use crate::{Client, Session};
+
+    fn client() -> Client<HttpConnector> {
+        let session = Session::new(
+            "token".to_owned(),
+            UserId::try_from("@alice:example.org").unwrap(),
+            "DEVICE".to_owned(),
+        );
+
+        Client::new(Url::parse("https://example.org").unwrap(), Some(session))
+    }
+
+    #[test]
+    fn dropping_the_guard_spawns_the_stop_notification() {
+        let client = client();
+        let room = client.room(RoomId::try_from("!room:example.org").unwrap());
+        let supervisor = client.supervisor();
+
+        let guard = room.typing_guard(&supervisor.spawner()).unwrap();
+        assert!(client.0.typing.borrow().contains_key(room.room_id()));
+        assert_eq!(supervisor.task_names().len(), 1);
+
+        drop(guard);
+        assert!(!client.0.typing.borrow().contains_key(room.room_id()));
+        assert_eq!(
+            supervisor.task_names(),
+            vec!["typing notifications", "typing notifications"]
+        );
+    }
+
+    #[test]
+    fn a_replaced_guard_stops_nothing() {
+        let client = client();
+        let room = client.room(RoomId::try_from("!room:example.org").unwrap());
+        let supervisor = client.supervisor();
+
+        let first = room.typing_guard(&supervisor.spawner()).unwrap();
+        let second = room.typing_guard(&supervisor.spawner()).unwrap();
+        drop(first);
+        assert!(client.0.typing.borrow().contains_key(room.room_id()));
+        assert_eq!(supervisor.task_names().len(), 2);
+
+        drop(second);
+        assert!(!client.0.typing.borrow().contains_key(room.room_id()));
+        assert_eq!(supervisor.task_names().len(), 3);
+    }
+
+    #[test]
+    fn stopping_ends_the_refreshes_right_away() {
+        let client = client();
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+
+        let state = start_typing(&client, &room_id);
+        assert!(!state.ended.get());
+
+        stop_typing(&client, &room_id);
+        assert!(!client.0.typing.borrow().contains_key(&room_id));
+        assert!(Ended(state).wait().is_ok());
+    }
+
+    #[test]
+    fn a_new_notification_ends_the_previous_one() {
+        let client = client();
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+
+        let first = start_typing(&client, &room_id);
+        let second = start_typing(&client, &room_id);
+        assert!(first.ended.get());
+        assert!(!second.ended.get());
+    }
+
+    #[test]
+    fn stopping_waits_for_the_refresh_being_sent() {
+        let client = client();
+        let room_id = RoomId::try_from("!room:example.org").unwrap();
+
+        let state = start_typing(&client, &room_id);
+        let sending = Sending::new(state.clone());
+        let stopped = stop_typing(&client, &room_id).unwrap();
+        let mut idle = Idle(stopped);
+        assert_eq!(
+            future::lazy(|| idle.poll()).wait().unwrap(),
+            Async::NotReady
+        );
+
+        drop(sending);
+        assert_eq!(
+            future::lazy(|| idle.poll()).wait().unwrap(),
+            Async::Ready(())
+        );
+    }
+}
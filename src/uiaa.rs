@@ -0,0 +1,227 @@
+//! The user-interactive authentication API, which protects sensitive endpoints.
+
+use futures::{
+    future::{self, Either, FutureFrom},
+    Future, Stream,
+};
+use http::StatusCode;
+use hyper::{client::connect::Connect, Body};
+use ruma_api::{Endpoint, Error as RumaApiError};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{extensions::Extensions, Client, Error};
+
+/// The authentication type of a stage that asks for the user's password.
+const PASSWORD_AUTH_TYPE: &str = "m.login.password";
+
+/// The authentication type of a stage that asks for a validated email address.
+pub const EMAIL_IDENTITY_AUTH_TYPE: &str = "m.login.email.identity";
+
+/// The authentication type of a stage that asks for a validated phone number.
+pub const MSISDN_AUTH_TYPE: &str = "m.login.msisdn";
+
+/// The authentication type of a stage that asks for a solved Google reCAPTCHA.
+pub const RECAPTCHA_AUTH_TYPE: &str = "m.login.recaptcha";
+
+/// What a homeserver requires to authenticate a request with the user-interactive
+/// authentication API.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UiaaInfo {
+    /// The ways to complete the authentication, any of which is enough.
+    pub flows: Vec<AuthFlow>,
+    /// The stages the client has completed already.
+    #[serde(default)]
+    pub completed: Vec<String>,
+    /// Parameters of the stages, keyed by their authentication type.
+    #[serde(default)]
+    pub params: Value,
+    /// The ID of the authentication session, which has to be passed along with each stage.
+    pub session: Option<String>,
+}
+
+/// One way to complete user-interactive authentication.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuthFlow {
+    /// The authentication types of the stages to complete, in order.
+    pub stages: Vec<String>,
+}
+
+/// Proof that the user validated an email address or phone number, for the
+/// `m.login.email.identity` and `m.login.msisdn` stages.
+///
+/// The `sid` is returned by the endpoint that sent the validation token, e.g.
+/// `api::r0::account::request_register_token`, and `client_secret` is the secret passed to it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ThreePidCredentials {
+    /// The ID of the validation session.
+    pub sid: String,
+    /// The client-generated secret of the validation session.
+    pub client_secret: String,
+    /// The identity server that sent the token, if the homeserver didn't send it itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_server: Option<String>,
+    /// An access token registered with the identity server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_access_token: Option<String>,
+}
+
+impl ThreePidCredentials {
+    /// The `auth` data completing an `m.login.email.identity` stage of the authentication
+    /// session `session`, e.g. for `Client::register_user_with_auth`.
+    pub fn email_auth(&self, session: Option<String>) -> Value {
+        self.auth(EMAIL_IDENTITY_AUTH_TYPE, session)
+    }
+
+    /// The `auth` data completing an `m.login.msisdn` stage of the authentication session
+    /// `session`.
+    pub fn msisdn_auth(&self, session: Option<String>) -> Value {
+        self.auth(MSISDN_AUTH_TYPE, session)
+    }
+
+    fn auth(&self, auth_type: &str, session: Option<String>) -> Value {
+        json!({
+            "type": auth_type,
+            "threepid_creds": self,
+            "session": session,
+        })
+    }
+}
+
+impl UiaaInfo {
+    /// Whether one of the flows only asks for the user's password.
+    pub fn allows_password(&self) -> bool {
+        self.flows.iter().any(|flow| {
+            !flow.stages.is_empty() && flow.stages.iter().all(|stage| stage == PASSWORD_AUTH_TYPE)
+        })
+    }
+
+    /// The public key, also called site key, to show the reCAPTCHA widget with, if one of the
+    /// flows has an `m.login.recaptcha` stage that isn't completed yet.
+    pub fn recaptcha_public_key(&self) -> Option<&str> {
+        let pending = self
+            .flows
+            .iter()
+            .any(|flow| flow.stages.iter().any(|stage| stage == RECAPTCHA_AUTH_TYPE))
+            && !self
+                .completed
+                .iter()
+                .any(|stage| stage == RECAPTCHA_AUTH_TYPE);
+
+        if !pending {
+            return None;
+        }
+
+        self.params
+            .pointer("/m.login.recaptcha/public_key")
+            .and_then(Value::as_str)
+    }
+
+    /// The `auth` data completing the `m.login.recaptcha` stage of this authentication session
+    /// with the response token of the solved reCAPTCHA.
+    pub fn recaptcha_auth(&self, response: String) -> Value {
+        json!({
+            "type": RECAPTCHA_AUTH_TYPE,
+            "response": response,
+            "session": self.session,
+        })
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Make a request to an endpoint protected by user-interactive authentication, answering the
+    /// authentication with the logged-in user's password.
+    ///
+    /// `make_request` builds the request from the `auth` data to send along. The request is sent
+    /// without authentication first, since homeservers may not require it. If the homeserver
+    /// asks for more than the password, this fails with `Error::InteractiveAuthRequired`.
+    pub(crate) fn request_with_password<E, F>(
+        &self,
+        password: String,
+        make_request: F,
+    ) -> impl Future<Item = E::Response, Error = Error>
+    where
+        E: Endpoint,
+        F: Fn(Option<Value>) -> E::Request + 'static,
+    {
+        let user_id = match self.logged_in_user_id() {
+            Ok(user_id) => user_id,
+            Err(error) => return Either::A(future::err(error)),
+        };
+        let client = self.clone();
+
+        Either::B(
+            self.clone()
+                .send_request::<E>(make_request(None), Extensions::new())
+                .and_then(move |response| {
+                    if response.status() != StatusCode::UNAUTHORIZED {
+                        return Either::A(E::Response::future_from(response).map_err(Error::from));
+                    }
+
+                    let retry = uiaa_info(response).and_then(move |info| {
+                        if !info.allows_password() {
+                            return Either::A(future::err(Error::InteractiveAuthRequired(info)));
+                        }
+
+                        let auth = json!({
+                            "type": PASSWORD_AUTH_TYPE,
+                            "identifier": {
+                                "type": "m.id.user",
+                                "user": user_id.to_string(),
+                            },
+                            "user": user_id.to_string(),
+                            "password": password,
+                            "session": info.session,
+                        });
+
+                        Either::B(client.request::<E>(make_request(Some(auth))))
+                    });
+
+                    Either::B(retry)
+                }),
+        )
+    }
+
+    /// Make a request to an endpoint protected by user-interactive authentication, failing with
+    /// `Error::InteractiveAuthRequired` if the homeserver asks for (more) authentication.
+    ///
+    /// This lets callers complete the stages the library can't answer itself, e.g. validating
+    /// an email address, and send the request again with the `auth` data of the next stage.
+    pub(crate) fn request_with_uiaa<E>(
+        &self,
+        request: E::Request,
+    ) -> impl Future<Item = E::Response, Error = Error>
+    where
+        E: Endpoint,
+    {
+        self.clone()
+            .send_request::<E>(request, Extensions::new())
+            .and_then(|response| {
+                if response.status() != StatusCode::UNAUTHORIZED {
+                    return Either::A(E::Response::future_from(response).map_err(Error::from));
+                }
+
+                Either::B(
+                    uiaa_info(response).and_then(|info| Err(Error::InteractiveAuthRequired(info))),
+                )
+            })
+    }
+}
+
+/// Read what the homeserver requires for authentication from a response with status code 401.
+///
+/// Responses that don't describe the authentication fail with the status code, like they would
+/// when converted to the endpoint's response.
+fn uiaa_info(response: http::Response<Body>) -> impl Future<Item = UiaaInfo, Error = Error> {
+    response
+        .into_body()
+        .concat2()
+        .map_err(Error::from)
+        .and_then(|body| {
+            serde_json::from_slice::<UiaaInfo>(&body)
+                .map_err(|_| Error::RumaApi(RumaApiError::StatusCode(StatusCode::UNAUTHORIZED)))
+        })
+}
@@ -0,0 +1,90 @@
+//! Upgrading rooms and following upgrades to the replacement rooms.
+
+use futures::future::{self, Either, Future};
+use hyper::client::connect::Connect;
+use ruma_events::EventType;
+use ruma_identifiers::{RoomId, RoomIdOrAliasId};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{Client, Error, Room};
+
+/// The type of the state event that marks a room as replaced by another one.
+pub const TOMBSTONE_EVENT_TYPE: &str = "m.room.tombstone";
+
+/// The content of an `m.room.tombstone` event, which `ruma_events` doesn't know about.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TombstoneEventContent {
+    /// A message for the members of the room explaining why it was replaced.
+    pub body: String,
+    /// The room that replaces this room.
+    pub replacement_room: RoomId,
+}
+
+impl<C> Room<C>
+where
+    C: Connect + 'static,
+{
+    /// Upgrade this room to the room version `new_version`.
+    ///
+    /// The homeserver creates a replacement room, copies the important state to it, and sends an
+    /// `m.room.tombstone` event to this room. The returned future resolves to the replacement
+    /// room.
+    pub fn upgrade(&self, new_version: String) -> impl Future<Item = Room<C>, Error = Error> {
+        use crate::api::r0::room::upgrade_room;
+
+        let client = self.client().clone();
+
+        upgrade_room::call(
+            client.clone(),
+            upgrade_room::Request {
+                room_id: self.room_id().clone(),
+                new_version,
+            },
+        )
+        .map(move |response| client.room(response.replacement_room))
+    }
+
+    /// Get this room's `m.room.tombstone` event from the homeserver.
+    ///
+    /// Resolves to `None` if the room hasn't been replaced.
+    pub fn tombstone(&self) -> impl Future<Item = Option<TombstoneEventContent>, Error = Error> {
+        self.state_event(EventType::from(TOMBSTONE_EVENT_TYPE), "")
+    }
+
+    /// Join the room that replaces this room, following further upgrades of the replacement.
+    ///
+    /// The tombstones are taken from the `Store`, so the rooms have to be synced. Resolves to
+    /// `None` if this room hasn't been replaced.
+    pub fn join_replacement(&self) -> impl Future<Item = Option<Room<C>>, Error = Error> {
+        use crate::api::r0::membership::join_room_by_id_or_alias;
+
+        let client = self.client().clone();
+        let latest = client.store().latest_room(self.room_id());
+
+        if latest == *self.room_id() {
+            return Either::A(future::ok(None));
+        }
+
+        Either::B(
+            join_room_by_id_or_alias::call(
+                client.clone(),
+                join_room_by_id_or_alias::Request {
+                    room_id_or_alias: RoomIdOrAliasId::RoomId(latest),
+                    third_party_signed: None,
+                },
+            )
+            .map(move |response| Some(client.room(response.room_id))),
+        )
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Connect + 'static,
+{
+    /// Get a handle for the room that replaces `room_id` after upgrades known to the `Store`, or
+    /// for `room_id` itself if it hasn't been replaced.
+    pub fn latest_room(&self, room_id: &RoomId) -> Room<C> {
+        self.room(self.store().latest_room(room_id))
+    }
+}
@@ -0,0 +1,369 @@
+//! A small HTTP server relaying JSON webhooks into rooms.
+
+use std::{collections::HashMap, net::SocketAddr, rc::Rc, time::Duration};
+
+use futures::{
+    future::{self, Either, ExecuteError, ExecuteErrorKind, Executor},
+    Future, Stream,
+};
+use http::{
+    header::{AUTHORIZATION, CONTENT_LENGTH},
+    Method, Request, Response, StatusCode,
+};
+use hyper::{client::connect::Connect, server::conn::Http, service::service_fn, Body};
+use log::warn;
+use ruma_events::room::message::{MessageEventContent, MessageType, NoticeMessageEventContent};
+use ruma_identifiers::RoomIdOrAliasId;
+use serde_json::{json, Value};
+use tokio_tcp::TcpListener;
+use tokio_timer::Timeout;
+
+use crate::{message::render_template, Client, Error};
+
+/// The largest body of a webhook a `WebhookServer` accepts, in bytes.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// How long a `WebhookServer` waits for the body of a webhook.
+const BODY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a connection to a `WebhookServer` may stay open, including relaying the webhook.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The future of a connection to a `WebhookServer`.
+type Connection = Box<dyn Future<Item = (), Error = ()>>;
+
+/// Where the webhooks posted to one path of a `WebhookServer` are relayed to.
+#[derive(Clone, Debug)]
+pub struct WebhookRoute {
+    /// The room the messages are sent to.
+    pub room: RoomIdOrAliasId,
+    /// The template of the messages.
+    ///
+    /// Placeholders like `{{alert.name}}` are replaced by the value at that path of the posted
+    /// JSON object. Strings are inserted as they are and other values as JSON. Placeholders for
+    /// missing values are removed.
+    pub template: String,
+}
+
+/// An HTTP server that accepts JSON webhooks and posts them as notices to rooms.
+///
+/// Webhooks have to be `POST` requests to one of the configured paths, authenticated with the
+/// secret token either as an `Authorization: Bearer <token>` header or as a `token` query
+/// parameter. Only HTTP/1 is supported, and the server doesn't speak TLS, so it should be run
+/// behind a reverse proxy when exposed to the internet.
+#[derive(Debug)]
+pub struct WebhookServer<C: Connect> {
+    client: Client<C>,
+    secret: String,
+    routes: HashMap<String, WebhookRoute>,
+}
+
+/// Why reading the body of a webhook failed.
+enum ReadError {
+    Hyper(hyper::Error),
+    /// The body is larger than `MAX_BODY_SIZE`.
+    TooLarge,
+}
+
+/// The executor for HTTP/2 streams, which are never created because only HTTP/1 is served.
+#[derive(Clone, Copy, Debug)]
+struct NoHttp2;
+
+impl<F> Executor<F> for NoHttp2
+where
+    F: Future<Item = (), Error = ()>,
+{
+    fn execute(&self, future: F) -> Result<(), ExecuteError<F>> {
+        Err(ExecuteError::new(ExecuteErrorKind::NoCapacity, future))
+    }
+}
+
+impl<C> WebhookServer<C>
+where
+    C: Connect + 'static,
+{
+    /// Creates a server relaying webhooks authenticated with `secret` through `client`.
+    ///
+    /// The client has to be logged in as a member of the rooms of all routes. A client in
+    /// lightweight mode is enough. Fails with `Error::EmptyWebhookSecret` if `secret` is empty.
+    pub fn new(client: Client<C>, secret: String) -> Result<Self, Error> {
+        if secret.is_empty() {
+            return Err(Error::EmptyWebhookSecret);
+        }
+
+        Ok(WebhookServer {
+            client,
+            secret,
+            routes: HashMap::new(),
+        })
+    }
+
+    /// Relay the webhooks posted to `path`, e.g. `/alerts`, to a room.
+    pub fn route(mut self, path: String, room: RoomIdOrAliasId, template: String) -> Self {
+        self.routes.insert(path, WebhookRoute { room, template });
+        self
+    }
+
+    /// Listen for webhooks on the given address, handling each connection as a task spawned on
+    /// `executor`, e.g. a `tokio_core::reactor::Handle`.
+    ///
+    /// Each connection serves a single webhook, and is closed after a minute at the latest.
+    /// Bodies larger than 64 KiB are refused. The returned future only resolves if accepting
+    /// connections fails. It has to be run on a runtime providing a Tokio reactor.
+    pub fn serve<E>(self, addr: &SocketAddr, executor: E) -> impl Future<Item = (), Error = Error>
+    where
+        E: Executor<Connection>,
+    {
+        let listener = match TcpListener::bind(addr) {
+            Ok(listener) => listener,
+            Err(error) => return Either::A(future::err(Error::from(error))),
+        };
+
+        let server = Rc::new(self);
+        let mut http = Http::new();
+        http.http1_only(true).keep_alive(false);
+        let http = http.with_executor(NoHttp2);
+
+        Either::B(
+            listener
+                .incoming()
+                .map_err(Error::from)
+                .for_each(move |socket| {
+                    let server = server.clone();
+                    let connection = http.serve_connection(
+                        socket,
+                        service_fn(move |request| server.handle(request)),
+                    );
+                    let connection: Connection =
+                        Box::new(Timeout::new(connection, CONNECTION_TIMEOUT).then(|result| {
+                            match result {
+                                Ok(()) => {}
+                                Err(ref error) if error.is_elapsed() => {
+                                    warn!("Webhook connection timed out");
+                                }
+                                Err(error) => match error.into_inner() {
+                                    Some(error) => warn!("Webhook connection failed: {}", error),
+                                    None => warn!("Webhook connection timer failed"),
+                                },
+                            }
+
+                            Ok(())
+                        }));
+
+                    if executor.execute(connection).is_err() {
+                        warn!("Dropped a webhook connection the executor didn't accept");
+                    }
+
+                    Ok(())
+                }),
+        )
+    }
+
+    /// Answer a single webhook request.
+    fn handle(
+        &self,
+        request: Request<Body>,
+    ) -> impl Future<Item = Response<Body>, Error = hyper::Error> {
+        if request.method() != Method::POST {
+            return Either::A(future::ok(respond(StatusCode::METHOD_NOT_ALLOWED, None)));
+        }
+
+        if !self.is_authorized(&request) {
+            return Either::A(future::ok(respond(StatusCode::UNAUTHORIZED, None)));
+        }
+
+        let route = match self.routes.get(request.uri().path()) {
+            Some(route) => route.clone(),
+            None => return Either::A(future::ok(respond(StatusCode::NOT_FOUND, None))),
+        };
+        let declared_length = request
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok());
+
+        if matches!(declared_length, Some(length) if length > MAX_BODY_SIZE) {
+            return Either::A(future::ok(respond(StatusCode::PAYLOAD_TOO_LARGE, None)));
+        }
+
+        let client = self.client.clone();
+        let body =
+            request
+                .into_body()
+                .map_err(ReadError::Hyper)
+                .fold(Vec::new(), |mut body, chunk| {
+                    if body.len() + chunk.len() > MAX_BODY_SIZE {
+                        return Err(ReadError::TooLarge);
+                    }
+
+                    body.extend_from_slice(&chunk);
+                    Ok(body)
+                });
+
+        Either::B(Timeout::new(body, BODY_TIMEOUT).then(move |result| {
+            let body = match result {
+                Ok(body) => body,
+                Err(ref error) if error.is_elapsed() => {
+                    return Either::A(future::ok(respond(StatusCode::REQUEST_TIMEOUT, None)));
+                }
+                Err(error) => match error.into_inner() {
+                    Some(ReadError::TooLarge) => {
+                        return Either::A(future::ok(respond(StatusCode::PAYLOAD_TOO_LARGE, None)));
+                    }
+                    Some(ReadError::Hyper(error)) => return Either::A(future::err(error)),
+                    None => {
+                        return Either::A(future::ok(respond(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            None,
+                        )));
+                    }
+                },
+            };
+            let payload = match serde_json::from_slice::<Value>(&body) {
+                Ok(payload) => payload,
+                Err(_) => return Either::A(future::ok(respond(StatusCode::BAD_REQUEST, None))),
+            };
+            let content = MessageEventContent::Notice(NoticeMessageEventContent {
+                body: render_template(&route.template, &payload),
+                msgtype: MessageType::Notice,
+            });
+
+            Either::B(client.send_to(route.room, content).then(|result| {
+                Ok(match result {
+                    Ok(event_id) => respond(
+                        StatusCode::OK,
+                        Some(json!({ "event_id": event_id.to_string() })),
+                    ),
+                    Err(error) => {
+                        warn!("Failed to relay webhook: {:?}", error);
+                        respond(StatusCode::BAD_GATEWAY, None)
+                    }
+                })
+            }))
+        }))
+    }
+
+    /// Whether the request carries the secret token.
+    fn is_authorized(&self, request: &Request<Body>) -> bool {
+        let header = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim().to_owned());
+        let query = request.uri().query().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "token")
+                .map(|(_, value)| value.into_owned())
+        });
+
+        header
+            .into_iter()
+            .chain(query)
+            .any(|token| constant_time_eq(token.as_bytes(), self.secret.as_bytes()))
+    }
+}
+
+/// Compare two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Create a response with an optional JSON body.
+fn respond(status: StatusCode, body: Option<Value>) -> Response<Body> {
+    let body = match body {
+        Some(body) => Body::from(body.to_string()),
+        None => Body::empty(),
+    };
+    let mut response = Response::new(body);
+
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use futures::Future;
+    use http::{header::AUTHORIZATION, Request, StatusCode};
+    use hyper::{client::HttpConnector, Body};
+    use ruma_identifiers::RoomIdOrAliasId;
+    use url::Url;
+
+    use super::{WebhookServer, MAX_BODY_SIZE};
+    use crate::{ClientBuilder, Error};
+
+    fn server(secret: &str) -> Result<WebhookServer<HttpConnector>, Error> {
+        let client = ClientBuilder::new(Url::parse("http://127.0.0.1:1").unwrap()).build();
+
+        WebhookServer::new(client, secret.to_owned()).map(|server| {
+            server.route(
+                "/alerts".to_owned(),
+                RoomIdOrAliasId::try_from("!room:example.org").unwrap(),
+                "{{alert}}".to_owned(),
+            )
+        })
+    }
+
+    fn request(uri: &str, authorization: Option<&str>) -> Request<Body> {
+        let mut request = Request::post(uri);
+
+        if let Some(authorization) = authorization {
+            request.header(AUTHORIZATION, authorization);
+        }
+
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn empty_secrets_are_rejected() {
+        assert!(matches!(server(""), Err(Error::EmptyWebhookSecret)));
+    }
+
+    #[test]
+    fn requests_need_the_secret_as_a_header_or_query_parameter() {
+        let server = server("secret").unwrap();
+
+        assert!(!server.is_authorized(&request("/alerts", None)));
+        assert!(!server.is_authorized(&request("/alerts?token=wrong", None)));
+        assert!(!server.is_authorized(&request("/alerts?token=", None)));
+        assert!(!server.is_authorized(&request("/alerts", Some("Bearer "))));
+        assert!(!server.is_authorized(&request("/alerts", Some("Bearer wrong"))));
+        assert!(!server.is_authorized(&request("/alerts", Some("Basic secret"))));
+        assert!(server.is_authorized(&request("/alerts", Some("Bearer secret"))));
+        assert!(server.is_authorized(&request("/alerts", Some("bearer  secret "))));
+        assert!(server.is_authorized(&request("/alerts?token=secret", None)));
+    }
+
+    #[test]
+    fn unauthorized_requests_are_refused() {
+        let server = server("secret").unwrap();
+        let response = server
+            .handle(request("/alerts?token=wrong", None))
+            .wait()
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn large_bodies_are_refused() {
+        let server = server("secret").unwrap();
+        let body = vec![b' '; MAX_BODY_SIZE + 1];
+
+        let declared = Request::post("/alerts?token=secret")
+            .header("content-length", body.len().to_string())
+            .body(Body::empty())
+            .unwrap();
+        let response = server.handle(declared).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let streamed = Request::post("/alerts?token=secret")
+            .body(Body::from(body))
+            .unwrap();
+        let response = server.handle(streamed).wait().unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}